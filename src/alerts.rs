@@ -0,0 +1,215 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, TimeDelta, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use logger::log::warn;
+use serde::Serialize;
+use tokio::{
+    select,
+    sync::broadcast::{self, error::RecvError},
+};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::{
+    config::{AlertCondition, AlertMetric, AlertRule},
+    measurements::{self, Measurements},
+    state::AppState,
+};
+
+/// A change of state of an alert, as sent to subscribers.
+#[derive(Debug, Clone)]
+pub(crate) enum AlertEvent {
+    Fired(Alert),
+    Cleared(Alert),
+}
+
+/// Number of cleared alerts kept for `GET /alerts`.
+const HISTORY_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct Alert {
+    pub name: String,
+    /// Tank whose measurements fired the alert.
+    pub tank: String,
+    pub metric: AlertMetric,
+    pub condition: AlertCondition,
+    pub threshold: f64,
+    pub critical: bool,
+    /// Latest value while active, and the last one past the clear level once cleared.
+    pub value: f64,
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub started_at: DateTime<Utc>,
+    /// Milliseconds since the Unix epoch. `None` while active.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub cleared_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct RuleState {
+    /// When the value first went past the threshold, while waiting for the minimum duration.
+    pending_since: Option<DateTime<Utc>>,
+    active: Option<Alert>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    rules: Vec<RuleState>,
+    history: VecDeque<Alert>,
+}
+
+/// The alerts firing and cleared, kept in the [`AppState`] for the API and the notifiers.
+pub(crate) struct Alerts {
+    state: Mutex<State>,
+    events: broadcast::Sender<AlertEvent>,
+}
+
+impl Alerts {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    /// Subscribes to alerts firing and clearing.
+    pub fn subscribe(&self) -> broadcast::Receiver<AlertEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns the alerts that are currently firing.
+    pub fn active(&self) -> Vec<Alert> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.rules.iter().filter_map(|rule| rule.active.clone()).collect()
+    }
+
+    /// Returns whether any alert is currently firing.
+    #[cfg(feature = "display")]
+    pub fn any_active(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.rules.iter().any(|rule| rule.active.is_some())
+    }
+
+    /// Returns cleared alerts, the most recent last.
+    pub fn history(&self) -> Vec<Alert> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.history.iter().cloned().collect()
+    }
+
+    /// Evaluates the rules watching `tank` against its measurements `m`, the rate of change of its temperature, and
+    /// the days the water change is overdue by.
+    fn evaluate(
+        &self,
+        rules: &[AlertRule],
+        default_tank: &str,
+        tank: &str,
+        m: &Measurements,
+        trend: Option<f64>,
+        overdue_days: Option<f64>,
+    ) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let State { rules: states, history } = &mut *state;
+        states.resize_with(rules.len(), RuleState::default);
+
+        for (rule, state) in rules.iter().zip(states.iter_mut()) {
+            if rule.tank.as_deref().unwrap_or(default_tank) != tank {
+                continue;
+            }
+            let value = match rule.metric {
+                AlertMetric::Temperature => Some(m.temperature),
+                AlertMetric::TemperatureTrend => trend,
+                AlertMetric::Tds => Some(m.tds),
+                AlertMetric::Ph => m.ph,
+                AlertMetric::WaterLevel => m.water_level_ok.map(|ok| f64::from(u8::from(ok))),
+                AlertMetric::Flow => m.flow_rate,
+                AlertMetric::WaterChange => overdue_days,
+            };
+            let Some(value) = value else {
+                continue;
+            };
+            let (breached, cleared) = match rule.condition {
+                AlertCondition::Above => (value > rule.threshold, value <= rule.clear),
+                AlertCondition::Below => (value < rule.threshold, value >= rule.clear),
+            };
+
+            if let Some(mut alert) = state.active.take() {
+                alert.value = value;
+                if cleared {
+                    warn!("Alert cleared: {} (value {value})", alert.name);
+                    alert.cleared_at = Some(m.timestamp);
+                    let _ = self.events.send(AlertEvent::Cleared(alert.clone()));
+                    while history.len() >= HISTORY_CAPACITY {
+                        history.pop_front();
+                    }
+                    history.push_back(alert);
+                } else {
+                    state.active = Some(alert);
+                }
+                continue;
+            }
+
+            if !breached {
+                state.pending_since = None;
+                continue;
+            }
+            let since = *state.pending_since.get_or_insert(m.timestamp);
+            if m.timestamp - since >= TimeDelta::from_std(rule.min_duration()).unwrap_or(TimeDelta::MAX) {
+                let name = rule.name();
+                warn!("Alert fired: {name} (value {value})");
+                state.pending_since = None;
+                let alert = Alert {
+                    name,
+                    tank: tank.to_owned(),
+                    metric: rule.metric,
+                    condition: rule.condition,
+                    threshold: rule.threshold,
+                    critical: rule.critical,
+                    value,
+                    started_at: since,
+                    cleared_at: None,
+                };
+                let _ = self.events.send(AlertEvent::Fired(alert.clone()));
+                state.active = Some(alert);
+            }
+        }
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    if config.alerts.is_empty() {
+        return Ok(());
+    }
+
+    let default_tank = state.default_tank().name.clone();
+    let watches_trend = config
+        .alerts
+        .iter()
+        .any(|rule| rule.metric == AlertMetric::TemperatureTrend);
+    let mut updates = state.subscribe_tanks();
+    loop {
+        select! {
+            (tank, m) = updates.recv() => match m {
+                Ok(m) => {
+                    // The trend takes a pass over the history, so it is only worked out when a rule watches it
+                    let trend = match state.tank(&tank) {
+                        Some(t) if watches_trend => measurements::temperature_trend(t, &config.measurements.trend).await,
+                        _ => None,
+                    };
+                    let overdue_days = state.water_change.overdue_days(m.timestamp);
+                    state.alerts.evaluate(&config.alerts, &default_tank, &tank, &m, trend, overdue_days);
+                }
+                Err(RecvError::Lagged(n)) => warn!("Skipped {n} measurements of tank {tank} while evaluating alerts"),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
@@ -3,31 +3,2270 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    fs, io,
+    net::SocketAddr,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{self, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
 use anyhow::anyhow;
-use axum::{Json, Router, http::StatusCode, routing::get};
-use tokio::net::TcpListener;
+use axum::{
+    Extension, Router,
+    body::Body,
+    extract::{
+        Path, Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use chrono::{DateTime, SecondsFormat, Utc, serde::ts_milliseconds_option};
+use futures_util::{FutureExt, Stream, StreamExt, future, stream};
+use logger::log::{error, info, warn};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use tokio::{
+    net::{TcpListener, UnixListener},
+    select,
+    sync::broadcast::{Receiver, error::RecvError},
+    task,
+};
+use tokio_util::sync::CancellationToken;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa::{IntoParams, ToSchema};
 
+use self::{
+    error::ApiError,
+    extract::{Json, Query},
+    model::{MeasurementsBody, SignalBody, SystemBody, TimestampFormat, TimestampParams},
+    negotiate::Format,
+};
+#[cfg(feature = "display")]
+use crate::display::{self, DisplayState};
 use crate::{
+    alerts::Alert,
+    calibration::LinearCalibration,
+    config::{ApiAuth, ApiConfig, Config, TemperatureUnit},
+    errors,
+    hardware::HardwareReport,
+    health::{self, Freshness, HealthSnapshot},
+    maintenance::Maintenance,
     measurements::{self, Measurements},
-    signal::{self, Signal},
+    metrics,
+    signal::Signal,
+    state::{AppState, Tank},
+    storage, thermostat,
+    water_change::WaterChange,
 };
 
-const ENDPOINT: &str = "0.0.0.0:8888";
+mod access_log;
+mod error;
+mod etag;
+mod extract;
+mod limit;
+mod mdns;
+mod model;
+mod negotiate;
+mod openapi;
+mod probes;
+mod tls;
+mod v1;
+
+pub(crate) use limit::RejectionCounter;
 
-pub(crate) async fn worker() -> anyhow::Result<()> {
-    let listener = TcpListener::bind(ENDPOINT).await?;
+/// The sockets the API is served on. They are bound once at startup, so that an address that is taken or invalid
+/// fails it, and every run of the worker serves on them again.
+pub(crate) struct Listeners {
+    tcp: Vec<std::net::TcpListener>,
+    tls: Vec<std::net::TcpListener>,
+    /// The socket file is removed when this is dropped, once serving has stopped for good.
+    unix: Option<(std::os::unix::net::UnixListener, SocketFile)>,
+}
+
+/// Binds every address and the socket configured, failing on the first that cannot be.
+pub(crate) fn bind(config: &ApiConfig) -> anyhow::Result<Listeners> {
+    let bind_tcp = |addr: &SocketAddr| {
+        let listener = std::net::TcpListener::bind(addr).map_err(|e| anyhow!("Failed to listen on {addr}: {e}"))?;
+        listener.set_nonblocking(true)?;
+        anyhow::Ok(listener)
+    };
+
+    let mut tcp = Vec::with_capacity(config.listen.len());
+    for addr in &config.listen {
+        tcp.push(bind_tcp(addr)?);
+        info!("Listening on {addr}");
+    }
+    let mut tls = Vec::new();
+    for addr in config.tls.iter().flat_map(|tls| &tls.listen) {
+        tls.push(bind_tcp(addr)?);
+        info!("Listening on {addr} with TLS");
+    }
+    let unix = match &config.socket {
+        Some(path) => Some(bind_socket(path, config.socket_mode)?),
+        None => None,
+    };
+
+    Ok(Listeners { tcp, tls, unix })
+}
+
+pub(crate) async fn worker(
+    state: AppState,
+    listeners: Arc<Listeners>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let tls_config = match &config.api.tls {
+        Some(config) => Some(tls::load(config).await?),
+        None => None,
+    };
+    let tcp_listeners = listeners
+        .tcp
+        .iter()
+        .map(|listener| TcpListener::from_std(listener.try_clone()?))
+        .collect::<io::Result<Vec<_>>>()?;
+    let tls_listeners = listeners
+        .tls
+        .iter()
+        .map(std::net::TcpListener::try_clone)
+        .collect::<io::Result<Vec<_>>>()?;
+    let unix_listener = match &listeners.unix {
+        Some((listener, _)) => Some(UnixListener::from_std(listener.try_clone()?)?),
+        None => None,
+    };
+
+    let tank = state.default_tank().name.clone();
+    let app = router(state)?;
+    let mut servers: Vec<_> = tcp_listeners
+        .into_iter()
+        .map(|listener| {
+            axum::serve(
+                listener,
+                app.clone().into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+            .into_future()
+            .boxed()
+        })
+        .collect();
+    if let Some(listener) = unix_listener {
+        servers.push(
+            axum::serve(listener, app.clone())
+                .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+                .into_future()
+                .boxed(),
+        );
+    }
+    if let Some((config, rustls)) = config.api.tls.clone().zip(tls_config) {
+        for listener in tls_listeners {
+            let handle = axum_server::Handle::new();
+            servers.push(
+                axum_server::from_tcp_rustls(listener, rustls.clone())?
+                    .handle(handle.clone())
+                    .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>())
+                    .boxed(),
+            );
+            let shutdown = shutdown.clone();
+            servers.push(
+                async move {
+                    shutdown.cancelled().await;
+                    handle.graceful_shutdown(None);
+                    Ok(())
+                }
+                .boxed(),
+            );
+        }
+        servers.push(tls::watch(config, rustls, shutdown.clone()).map(Ok).boxed());
+    }
+    if let Some((mdns, addr)) = config.api.mdns.clone().zip(config.api.mdns_listen()) {
+        servers.push(mdns::advertise(mdns, addr, tank, shutdown.clone()).map(Ok).boxed());
+    }
+    future::try_join_all(servers)
+        .await
+        .map_err(|e| anyhow!("Axum error: {e:?}"))?;
+
+    Ok(())
+}
+
+/// Builds the whole API around `state`, with everything but the listeners.
+fn router(state: AppState) -> anyhow::Result<Router> {
+    let config = state.config.clone();
     let app = Router::new()
-        .route("/measurements", get(get_measurements))
-        .route("/signal", get(get_signal));
-    axum::serve(listener, app)
+        .route("/", get(get_dashboard))
+        .merge(v1::router())
+        .merge(openapi::router())
+        .merge(probes::router())
+        .fallback(async || ApiError::NotFound)
+        .route_layer(middleware::from_fn_with_state(config.clone(), authenticate))
+        .with_state(state.clone());
+    // Outside of the authentication, so that preflight requests are answered without a token.
+    let app = match cors(&config.api)? {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
+    Ok(app
+        .layer(middleware::from_fn_with_state(
+            Arc::new(limit::Limiter::new(&config.api, state.api_rejections.clone())),
+            limit::layer,
+        ))
+        .layer(middleware::from_fn_with_state(state, access_log::layer)))
+}
+
+/// Removes the socket file once the listener is gone.
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            warn!("Failed to remove socket {}: {e}", self.0.display());
+        }
+    }
+}
+
+/// Listens on a Unix domain socket at `path`, replacing one left behind by an earlier run.
+fn bind_socket(path: &path::Path, mode: u32) -> anyhow::Result<(std::os::unix::net::UnixListener, SocketFile)> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => return Err(anyhow!("{} exists and is not a socket", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .map_err(|e| anyhow!("Failed to listen on {}: {e}", path.display()))?;
+    listener.set_nonblocking(true)?;
+    let file = SocketFile(path.to_owned());
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    info!("Listening on {}", path.display());
+
+    Ok((listener, file))
+}
+
+fn cors(config: &ApiConfig) -> anyhow::Result<Option<CorsLayer>> {
+    if config.cors_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let origins = if config.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = config
+            .cors_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin).map_err(|_| anyhow!("Invalid CORS origin {origin:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
+    ))
+}
+
+/// Rejects requests without a valid bearer token when tokens are configured.
+async fn authenticate(State(config): State<Arc<Config>>, request: Request, next: Next) -> Response {
+    let tokens = &config.api.tokens;
+    // The dashboard and the API description hold no data of their own, and the former asks for the token itself.
+    // Probes come from service managers, which have no token to give.
+    let exempt = is_public(request.uri().path())
+        || config.api.auth == ApiAuth::Mutating
+            && matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if tokens.is_empty() || exempt {
+        return next.run(request).await;
+    }
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        None => ApiError::Unauthorized("Missing bearer token").into_response(),
+        Some(token) if !tokens.iter().any(|t| constant_time_eq(t.as_bytes(), token.as_bytes())) => {
+            warn!("Rejected a request to {} with an invalid token", request.uri().path());
+            ApiError::Unauthorized("Invalid bearer token").into_response()
+        }
+        Some(_) => next.run(request).await,
+    }
+}
+
+fn is_public(path: &str) -> bool {
+    #[cfg(feature = "docs")]
+    if path == openapi::DOCS_PATH || path.starts_with(&format!("{}/", openapi::DOCS_PATH)) {
+        return true;
+    }
+
+    ["/", openapi::SPEC_PATH, probes::HEALTHZ_PATH, probes::READYZ_PATH].contains(&path)
+}
+
+/// Compares without bailing out at the first difference, so that the time taken gives nothing away about a token.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A page showing the latest values, for those who would rather not read JSON.
+async fn get_dashboard() -> Html<&'static str> {
+    Html(include_str!("../web/dashboard.html"))
+}
+
+/// A latest value together with how old it is, by the thresholds configured for its source.
+#[derive(Debug, Serialize, ToSchema)]
+struct Latest<T> {
+    #[serde(flatten)]
+    value: T,
+    /// Whether the value is older than `stale_after_secs`, so that it is better taken as missing.
+    is_stale: bool,
+    /// Whole seconds since the value was read, present once it is older than `fresh_for_secs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_seconds: Option<i64>,
+}
+
+impl<T> Latest<T> {
+    /// Wraps `value`, read at `timestamp`.
+    fn new(value: T, timestamp: DateTime<Utc>, fresh_for: Duration, stale_after: Duration) -> Self {
+        let now = Utc::now();
+        let freshness = health::freshness(timestamp, now, fresh_for, stale_after);
+
+        Self {
+            value,
+            is_stale: freshness == Freshness::Stale,
+            age_seconds: (freshness != Freshness::Fresh).then(|| (now - timestamp).num_seconds()),
+        }
+    }
+}
+
+/// Query of the latest measurements. Temperatures are given in Celsius unless asked otherwise.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MeasurementsParams {
+    #[serde(default)]
+    #[param(inline)]
+    unit: TemperatureUnit,
+    /// Adds the values the measurements were worked out from, such as raw ADC counts, with `1` or `true`.
+    #[serde(default, deserialize_with = "flag")]
+    #[param(value_type = Option<String>)]
+    debug: bool,
+}
+
+/// Reads a query flag given as `1` or `true`, or `0` or `false`.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match String::deserialize(deserializer)?.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(de::Error::custom(format!(
+            "invalid flag `{other}`, expected 1, 0, true or false"
+        ))),
+    }
+}
+
+/// Latest measurements.
+#[utoipa::path(
+    get,
+    path = "/measurements",
+    tag = "measurements",
+    params(MeasurementsParams, TimestampParams),
+    responses(
+        (status = 200, description = "Latest measurements", content(
+            (Latest<MeasurementsBody> = "application/json"),
+            (Latest<MeasurementsBody> = "application/cbor"),
+            (Latest<MeasurementsBody> = "application/msgpack"),
+        )),
+        (status = 304, description = "Unchanged since the version in `If-None-Match`"),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 400, description = "Unknown unit", body = error::Body),
+        (status = 503, description = "No measurement recorded yet", body = error::Body),
+    ),
+)]
+async fn get_measurements(
+    State(state): State<AppState>,
+    Query(params): Query<MeasurementsParams>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    respond_measurements(&state, state.default_tank(), &params, timestamp, format, &headers).await
+}
+
+/// Responds with the latest measurements of `tank`.
+async fn respond_measurements(
+    state: &AppState,
+    tank: &Tank,
+    params: &MeasurementsParams,
+    timestamps: TimestampFormat,
+    format: Format,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let value = tank
+        .measurements
+        .get()
+        .await
+        .ok_or(ApiError::NoData("No measurement recorded yet"))?;
+    let config = &state.config.measurements;
+    let debug = params.debug || config.expose_debug;
+    let timestamp = value.timestamp;
+    let latest = Latest::new(
+        MeasurementsBody::new(value.in_unit(params.unit), timestamps, debug),
+        timestamp,
+        config.fresh_for(),
+        config.stale_after(),
+    );
+    // The value changes with a new reading, and as it ages.
+    let etag = etag::weak(&[
+        &timestamp.timestamp_millis(),
+        &u8::from(latest.is_stale),
+        &latest.age_seconds.unwrap_or_default(),
+        &u8::from(debug),
+        &params.unit,
+        &timestamps,
+        &format.content_type(),
+    ]);
+
+    Ok(etag::respond(headers, &etag, format.respond(&latest)?))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TankSummary {
+    name: String,
+    /// Whether the tank is the one served at `/measurements`.
+    is_default: bool,
+}
+
+/// Tanks read by this service, in the order configured.
+#[utoipa::path(
+    get,
+    path = "/tanks",
+    tag = "measurements",
+    responses((status = 200, description = "Configured tanks", body = Vec<TankSummary>)),
+)]
+async fn get_tanks(State(state): State<AppState>) -> Json<Vec<TankSummary>> {
+    let default_tank = &state.default_tank().name;
+    let tanks = state
+        .tanks
+        .iter()
+        .map(|tank| TankSummary {
+            name: tank.name.clone(),
+            is_default: &tank.name == default_tank,
+        })
+        .collect();
+
+    Json(tanks)
+}
+
+/// Latest measurements of a tank.
+#[utoipa::path(
+    get,
+    path = "/tanks/{name}/measurements",
+    tag = "measurements",
+    params(("name" = String, Path, description = "Name of the tank"), MeasurementsParams, TimestampParams),
+    responses(
+        (status = 200, description = "Latest measurements", content(
+            (Latest<MeasurementsBody> = "application/json"),
+            (Latest<MeasurementsBody> = "application/cbor"),
+            (Latest<MeasurementsBody> = "application/msgpack"),
+        )),
+        (status = 304, description = "Unchanged since the version in `If-None-Match`"),
+        (status = 404, description = "No such tank", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 400, description = "Unknown unit", body = error::Body),
+        (status = 503, description = "No measurement recorded yet", body = error::Body),
+    ),
+)]
+async fn get_tank_measurements(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<MeasurementsParams>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let tank = state.tank(&name).ok_or(ApiError::NotConfigured("No such tank"))?;
+
+    respond_measurements(&state, tank, &params, timestamp, format, &headers).await
+}
+
+/// Measurements of a tank, served like those at `/measurements/history`.
+#[utoipa::path(
+    get,
+    path = "/tanks/{name}/measurements/history",
+    tag = "measurements",
+    params(("name" = String, Path, description = "Name of the tank"), HistoryParams, TimestampParams),
+    responses(
+        (status = 200, description = "Measurements, oldest first", content(
+            (Vec<MeasurementsBody> = "application/json"),
+            (Vec<MeasurementsBody> = "application/cbor"),
+            (Vec<MeasurementsBody> = "application/msgpack"),
+        )),
+        (status = 304, description = "Unchanged since the version in `If-None-Match`"),
+        (status = 404, description = "No such tank", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 400, description = "Malformed query", body = error::Body),
+    ),
+)]
+async fn get_tank_measurements_history(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<HistoryParams>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let tank = state.tank(&name).ok_or(ApiError::NotConfigured("No such tank"))?;
+    let history = tank_history(&state, tank, &params).await?;
+
+    respond_history(history, params.unit, timestamp, format, &headers)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryParams {
+    /// Only entries after this time, in milliseconds since the Unix epoch.
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[param(value_type = Option<i64>)]
+    since: Option<DateTime<Utc>>,
+    /// Start of the range, in milliseconds since the Unix epoch. Read from storage when enabled.
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[param(value_type = Option<i64>)]
+    from: Option<DateTime<Utc>>,
+    /// End of the range, in milliseconds since the Unix epoch. Read from storage when enabled.
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[param(value_type = Option<i64>)]
+    to: Option<DateTime<Utc>>,
+    /// At most this many of the newest entries.
+    limit: Option<usize>,
+    #[serde(default)]
+    #[param(inline)]
+    unit: TemperatureUnit,
+}
+
+/// Serves `from`/`to` queries from the database when storage is enabled, and everything else from memory.
+#[utoipa::path(
+    get,
+    path = "/measurements/history",
+    tag = "measurements",
+    params(HistoryParams, TimestampParams),
+    responses(
+        (status = 200, description = "Measurements, oldest first", content(
+            (Vec<MeasurementsBody> = "application/json"),
+            (Vec<MeasurementsBody> = "application/cbor"),
+            (Vec<MeasurementsBody> = "application/msgpack"),
+        )),
+        (status = 304, description = "Unchanged since the version in `If-None-Match`"),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 400, description = "Malformed query", body = error::Body),
+    ),
+)]
+async fn get_measurements_history(
+    State(state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let history = tank_history(&state, state.default_tank(), &params).await?;
+
+    respond_history(history, params.unit, timestamp, format, &headers)
+}
+
+/// Reads the history of `tank` from the database for `from`/`to` queries when storage is enabled, and from memory
+/// otherwise.
+async fn tank_history(state: &AppState, tank: &Tank, params: &HistoryParams) -> Result<Vec<Measurements>, ApiError> {
+    let mut history = None;
+    if params.from.is_some() || params.to.is_some() {
+        history = storage::history(&state.database, &tank.name, params.from, params.to, params.limit)
+            .await
+            .map_err(|e| ApiError::internal("Failed to query stored history", e))?;
+    }
+
+    Ok(match history {
+        Some(history) => history,
+        None => memory_history(tank, params).await,
+    })
+}
+
+fn respond_history(
+    history: Vec<Measurements>,
+    unit: TemperatureUnit,
+    timestamps: TimestampFormat,
+    format: Format,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    // Entries are only ever appended or dropped, so the newest one and the count tell versions apart.
+    let newest = history.last().map_or(0, |m| m.timestamp.timestamp_millis());
+    let etag = etag::weak(&[&newest, &history.len(), &unit, &timestamps, &format.content_type()]);
+    let history: Vec<_> = history
+        .into_iter()
+        .map(|m| MeasurementsBody::new(m.in_unit(unit), timestamps, false))
+        .collect();
+
+    Ok(etag::respond(headers, &etag, format.respond(&history)?))
+}
+
+async fn memory_history(tank: &Tank, params: &HistoryParams) -> Vec<Measurements> {
+    let since = params.since.max(params.from);
+    let mut history = measurements::history(tank, since, None).await;
+    if let Some(to) = params.to {
+        history.retain(|m| m.timestamp <= to);
+    }
+    if let Some(limit) = params.limit {
+        history.drain(..history.len().saturating_sub(limit));
+    }
+
+    history
+}
+
+/// Rows of the CSV export, read from the database when storage is enabled and from memory otherwise.
+struct CsvRows {
+    source: CsvSource,
+    unit: TemperatureUnit,
+}
+
+enum CsvSource {
+    Stored(storage::HistoryPages),
+    Memory(std::vec::IntoIter<Measurements>),
+}
+
+impl CsvRows {
+    const CHUNK_SIZE: usize = 1000;
+
+    /// Returns the next chunk of rows, or `None` at the end.
+    async fn next_chunk(&mut self) -> anyhow::Result<Option<String>> {
+        let rows = match &mut self.source {
+            CsvSource::Stored(pages) => pages.next().await?,
+            CsvSource::Memory(history) => history.by_ref().take(Self::CHUNK_SIZE).collect(),
+        };
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let mut chunk = String::new();
+        for m in rows {
+            let m = m.in_unit(self.unit);
+            let timestamp = m.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true);
+            let _ = writeln!(chunk, "{timestamp},{},{},{}", m.temperature, m.tds, m.ec);
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
+/// Streams the history as CSV a chunk at a time, so that a long range is never built up in memory.
+#[utoipa::path(
+    get,
+    path = "/measurements/history.csv",
+    tag = "measurements",
+    params(HistoryParams),
+    responses(
+        (status = 200, description = "`timestamp,temperature,tds,ec` rows, oldest first", body = String, content_type = "text/csv"),
+        (status = 400, description = "Malformed query", body = error::Body),
+    ),
+)]
+async fn get_measurements_history_csv(State(state): State<AppState>, Query(params): Query<HistoryParams>) -> Response {
+    export_history(&state, state.default_tank(), &params).await
+}
+
+/// Streams the history of a tank as CSV, like `/measurements/history.csv`.
+#[utoipa::path(
+    get,
+    path = "/tanks/{name}/measurements/history.csv",
+    tag = "measurements",
+    params(("name" = String, Path, description = "Name of the tank"), HistoryParams),
+    responses(
+        (status = 200, description = "`timestamp,temperature,tds,ec` rows, oldest first", body = String, content_type = "text/csv"),
+        (status = 404, description = "No such tank", body = error::Body),
+        (status = 400, description = "Malformed query", body = error::Body),
+    ),
+)]
+async fn get_tank_measurements_history_csv(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Response, ApiError> {
+    let tank = state.tank(&name).ok_or(ApiError::NotConfigured("No such tank"))?;
+
+    Ok(export_history(&state, tank, &params).await)
+}
+
+async fn export_history(state: &AppState, tank: &Tank, params: &HistoryParams) -> Response {
+    let source = if state.database.is_enabled() {
+        CsvSource::Stored(storage::HistoryPages::new(
+            state.database.clone(),
+            &tank.name,
+            params.since.max(params.from),
+            params.to,
+            params.limit,
+        ))
+    } else {
+        CsvSource::Memory(memory_history(tank, params).await.into_iter())
+    };
+    let rows = CsvRows {
+        source,
+        unit: params.unit,
+    };
+
+    // The body is streamed after the handler has returned, so the ID is taken along.
+    let request_id = access_log::request_id();
+    let header = stream::once(async { Ok("timestamp,temperature,tds,ec\n".to_owned()) });
+    let body = stream::try_unfold(rows, move |mut rows| {
+        let request_id = request_id.clone();
+        async move {
+            match rows.next_chunk().await {
+                Ok(chunk) => Ok(chunk.map(|chunk| (chunk, rows))),
+                Err(e) => {
+                    error!("Failed to export history [{request_id}]: {e:?}");
+                    Err(io::Error::other(e))
+                }
+            }
+        }
+    });
+
+    let date = |t: Option<DateTime<Utc>>| t.map_or_else(|| "start".to_owned(), |t| t.format("%Y%m%d").to_string());
+    // Named after the tank only when there is more than one, so that the name of a single tank's export stays.
+    let tank = if state.tanks.len() > 1 {
+        format!("{}-", tank.name)
+    } else {
+        String::new()
+    };
+    let filename = format!(
+        "cobitis-{tank}{}-{}.csv",
+        date(params.since.max(params.from)),
+        params.to.unwrap_or_else(Utc::now).format("%Y%m%d")
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        Body::from_stream(header.chain(body)),
+    )
+        .into_response()
+}
+
+/// Statistics of a tank.
+#[derive(Debug, Serialize, ToSchema)]
+struct MeasurementsStats {
+    #[serde(flatten)]
+    daily: measurements::DailyStatsPair,
+    /// Rate of change of the temperature in °C per hour over `measurements.trend.window_mins`. `None` until there
+    /// are enough readings in the window.
+    trend_c_per_hour: Option<f64>,
+}
+
+/// Statistics of today and yesterday, and how fast the temperature is changing, of the default tank.
+#[utoipa::path(
+    get,
+    path = "/measurements/stats",
+    tag = "measurements",
+    responses(
+        (status = 200, description = "Daily statistics", content(
+            (MeasurementsStats = "application/json"),
+            (MeasurementsStats = "application/cbor"),
+            (MeasurementsStats = "application/msgpack"),
+        )),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+    ),
+)]
+async fn get_measurements_stats(State(state): State<AppState>, format: Format) -> Result<Response, ApiError> {
+    respond_stats(&state, state.default_tank(), format).await
+}
+
+/// Statistics of a tank, like those at `/measurements/stats`.
+#[utoipa::path(
+    get,
+    path = "/tanks/{name}/measurements/stats",
+    tag = "measurements",
+    params(("name" = String, Path, description = "Name of the tank")),
+    responses(
+        (status = 200, description = "Daily statistics", content(
+            (MeasurementsStats = "application/json"),
+            (MeasurementsStats = "application/cbor"),
+            (MeasurementsStats = "application/msgpack"),
+        )),
+        (status = 404, description = "No such tank", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+    ),
+)]
+async fn get_tank_measurements_stats(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    format: Format,
+) -> Result<Response, ApiError> {
+    let tank = state.tank(&name).ok_or(ApiError::NotConfigured("No such tank"))?;
+
+    respond_stats(&state, tank, format).await
+}
+
+async fn respond_stats(state: &AppState, tank: &Tank, format: Format) -> Result<Response, ApiError> {
+    format.respond(&MeasurementsStats {
+        daily: measurements::daily_stats(tank),
+        trend_c_per_hour: measurements::temperature_trend(tank, &state.config.measurements.trend).await,
+    })
+}
+
+/// Takes a measurement right away instead of waiting for the next one, and returns it.
+#[utoipa::path(
+    post,
+    path = "/measurements/refresh",
+    tag = "measurements",
+    params(MeasurementsParams, TimestampParams),
+    responses(
+        (status = 200, description = "Fresh measurements", content(
+            (MeasurementsBody = "application/json"),
+            (MeasurementsBody = "application/cbor"),
+            (MeasurementsBody = "application/msgpack"),
+        )),
+        (status = 400, description = "Unknown unit", body = error::Body),
+        (status = 404, description = "Measurements are disabled", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 503, description = "The probes could not be read", body = error::Body),
+    ),
+)]
+async fn post_measurements_refresh(
+    State(state): State<AppState>,
+    Query(params): Query<MeasurementsParams>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+) -> Result<Response, ApiError> {
+    let config = &state.config;
+    if !config.measurements.enabled {
+        return Err(ApiError::NotConfigured("Measurements are disabled"));
+    }
+
+    let value = state
+        .sampling
+        .refresh()
+        .await
+        .map_err(|e| ApiError::Unavailable(format!("Failed to take measurements: {e:#}")))?;
+    let debug = params.debug || config.measurements.expose_debug;
+    format.respond(&MeasurementsBody::new(value.in_unit(params.unit), timestamp, debug))
+}
+
+/// Starts counting the volume through the flow sensor from zero again.
+#[utoipa::path(
+    post,
+    path = "/flow/reset",
+    tag = "measurements",
+    responses(
+        (status = 204, description = "Reset"),
+        (status = 404, description = "Flow sensor is not configured", body = error::Body),
+    ),
+)]
+async fn post_flow_reset(State(state): State<AppState>) -> Result<StatusCode, ApiError> {
+    if !state.flow.reset() {
+        return Err(ApiError::NotConfigured("Flow sensor is not configured"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Latest Wi-Fi signal reading.
+#[utoipa::path(
+    get,
+    path = "/signal",
+    tag = "signal",
+    params(TimestampParams),
+    responses(
+        (status = 200, description = "Latest signal reading", content(
+            (Latest<SignalBody> = "application/json"),
+            (Latest<SignalBody> = "application/cbor"),
+            (Latest<SignalBody> = "application/msgpack"),
+        )),
+        (status = 304, description = "Unchanged since the version in `If-None-Match`"),
+        (status = 400, description = "Malformed query", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 503, description = "No signal reading recorded yet", body = error::Body),
+    ),
+)]
+async fn get_signal(
+    State(state): State<AppState>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let value = state
+        .signal
+        .get()
+        .await
+        .ok_or(ApiError::NoData("No signal reading recorded yet"))?;
+    let config = &state.config.signal;
+    let read_at = value.timestamp;
+    let latest = Latest::new(
+        SignalBody::new(value, timestamp),
+        read_at,
+        config.fresh_for(),
+        config.stale_after(),
+    );
+    let etag = etag::weak(&[
+        &read_at.timestamp_millis(),
+        &u8::from(latest.is_stale),
+        &latest.age_seconds.unwrap_or_default(),
+        &timestamp,
+        &format.content_type(),
+    ]);
+
+    Ok(etag::respond(&headers, &etag, format.respond(&latest)?))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Alerts {
+    active: Vec<Alert>,
+    history: Vec<Alert>,
+}
+
+/// Active alerts, and those cleared recently.
+#[utoipa::path(
+    get,
+    path = "/alerts",
+    tag = "alerts",
+    responses((status = 200, body = Alerts)),
+)]
+async fn get_alerts(State(state): State<AppState>) -> Json<Alerts> {
+    Json(Alerts {
+        active: state.alerts.active(),
+        history: state.alerts.history(),
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SilenceParams {
+    /// Defaults to the configured duration.
+    duration_secs: Option<u64>,
+}
+
+/// Mutes the buzzer for the given duration, or the configured one.
+#[utoipa::path(
+    post,
+    path = "/alerts/silence",
+    tag = "alerts",
+    params(SilenceParams),
+    responses(
+        (status = 204, description = "Silenced"),
+        (status = 400, description = "Malformed query", body = error::Body),
+    ),
+)]
+async fn post_alerts_silence(State(state): State<AppState>, Query(params): Query<SilenceParams>) -> StatusCode {
+    let duration = params.duration_secs.map_or_else(
+        || state.config.buzzer.clone().unwrap_or_default().silence(),
+        Duration::from_secs,
+    );
+    state.buzzer.silence(duration);
+
+    StatusCode::NO_CONTENT
+}
+
+/// Vitals of the board: CPU temperature, load, memory and disk usage, and throttling. Each is `null` when it could
+/// not be read.
+#[utoipa::path(
+    get,
+    path = "/system",
+    tag = "status",
+    params(TimestampParams),
+    responses(
+        (status = 200, description = "Latest vitals", content(
+            (SystemBody = "application/json"),
+            (SystemBody = "application/cbor"),
+            (SystemBody = "application/msgpack"),
+        )),
+        (status = 400, description = "Malformed query", body = error::Body),
+        (status = 406, description = "None of the accepted types can be produced", body = error::Body),
+        (status = 503, description = "No vitals read yet", body = error::Body),
+    ),
+)]
+async fn get_system(
+    State(state): State<AppState>,
+    Query(TimestampParams { timestamp }): Query<TimestampParams>,
+    format: Format,
+) -> Result<Response, ApiError> {
+    let value = state.system.get().await.ok_or(ApiError::NoData("No vitals read yet"))?;
+    format.respond(&SystemBody::new(value, timestamp))
+}
+
+/// Metrics in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "status",
+    responses((status = 200, body = String, content_type = "text/plain; version=0.0.4")),
+)]
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&state).await,
+    )
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct Status {
+    version: &'static str,
+    uptime_secs: i64,
+    measurements: Option<Measurements>,
+    measurements_age_secs: Option<f64>,
+    measurements_stale: bool,
+    signal: Option<Signal>,
+    signal_age_secs: Option<f64>,
+    signal_stale: bool,
+    /// `None` unless in maintenance.
+    maintenance: Option<Maintenance>,
+    workers: WorkerStatuses,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct WorkerStatuses {
+    measurements: WorkerStatus,
+    signal: WorkerStatus,
+    display: WorkerStatus,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct WorkerStatus {
+    #[serde(flatten)]
+    health: HealthSnapshot,
+    last_success_age_secs: Option<f64>,
+}
+
+impl From<HealthSnapshot> for WorkerStatus {
+    fn from(health: HealthSnapshot) -> Self {
+        Self {
+            last_success_age_secs: health.last_success_age(),
+            health,
+        }
+    }
+}
+
+/// What the workers found when they last probed their devices, with what is missing spelled out.
+#[utoipa::path(
+    get,
+    path = "/hardware",
+    tag = "status",
+    responses((status = 200, body = HardwareReport)),
+)]
+async fn get_hardware(State(state): State<AppState>) -> Json<HardwareReport> {
+    Json(state.hardware.snapshot())
+}
+
+/// Version, uptime, latest values and the health of each worker.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "status",
+    responses((status = 200, body = Status)),
+)]
+async fn get_status(State(state): State<AppState>) -> Json<Status> {
+    let config = &state.config;
+    let now = Utc::now();
+    let measurements = state.default_tank().measurements.get().await;
+    let signal = state.signal.get().await;
+
+    Json(Status {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: (now - state.health.started_at).num_seconds(),
+        measurements_age_secs: measurements.as_ref().map(|m| (now - m.timestamp).as_seconds_f64()),
+        measurements_stale: measurements
+            .as_ref()
+            .is_none_or(|m| health::is_stale(m.timestamp, now, config.measurements.stale_after())),
+        measurements,
+        signal_age_secs: signal.as_ref().map(|s| (now - s.timestamp).as_seconds_f64()),
+        signal_stale: signal
+            .as_ref()
+            .is_none_or(|s| health::is_stale(s.timestamp, now, config.signal.stale_after())),
+        signal,
+        maintenance: state.maintenance.current(),
+        workers: WorkerStatuses {
+            measurements: state.health.measurements.snapshot().into(),
+            signal: state.health.signal.snapshot().into(),
+            display: state.health.display.snapshot().into(),
+        },
+    })
+}
+
+/// Recent errors and warnings from the workers, each repeated one counted in place of being listed again.
+#[utoipa::path(
+    get,
+    path = "/errors",
+    tag = "status",
+    responses((status = 200, body = [errors::Event])),
+)]
+async fn get_errors(State(state): State<AppState>) -> Json<Vec<errors::Event>> {
+    Json(state.errors.recent())
+}
+
+/// Forgets the recent errors and warnings.
+#[utoipa::path(
+    delete,
+    path = "/errors",
+    tag = "status",
+    responses((status = 204, description = "Cleared")),
+)]
+async fn delete_errors(State(state): State<AppState>) -> StatusCode {
+    state.errors.clear();
+
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct PollingInterval {
+    seconds: u64,
+}
+
+impl PollingInterval {
+    const MIN_SECS: u64 = 1;
+    const MAX_SECS: u64 = 3600;
+
+    fn to_duration(&self) -> Result<Duration, ApiError> {
+        if (Self::MIN_SECS..=Self::MAX_SECS).contains(&self.seconds) {
+            Ok(Duration::from_secs(self.seconds))
+        } else {
+            Err(ApiError::BadRequest(format!(
+                "seconds must be between {} and {}",
+                Self::MIN_SECS,
+                Self::MAX_SECS
+            )))
+        }
+    }
+}
+
+impl From<Duration> for PollingInterval {
+    fn from(interval: Duration) -> Self {
+        Self {
+            seconds: interval.as_secs(),
+        }
+    }
+}
+
+/// Interval between measurements.
+#[utoipa::path(
+    get,
+    path = "/config/measurement-interval",
+    tag = "config",
+    responses((status = 200, body = PollingInterval)),
+)]
+async fn get_measurement_interval(State(state): State<AppState>) -> Json<PollingInterval> {
+    Json(state.sampling.interval().into())
+}
+
+/// Changes the interval between measurements until the service restarts.
+#[utoipa::path(
+    put,
+    path = "/config/measurement-interval",
+    tag = "config",
+    request_body = PollingInterval,
+    responses(
+        (status = 200, body = PollingInterval),
+        (status = 400, description = "Out of range", body = error::Body),
+    ),
+)]
+async fn put_measurement_interval(
+    State(state): State<AppState>,
+    Json(body): Json<PollingInterval>,
+) -> Result<Json<PollingInterval>, ApiError> {
+    state.sampling.set_interval(body.to_duration()?);
+    Ok(Json(state.sampling.interval().into()))
+}
+
+/// Interval between signal readings.
+#[utoipa::path(
+    get,
+    path = "/config/signal-interval",
+    tag = "config",
+    responses((status = 200, body = PollingInterval)),
+)]
+async fn get_signal_interval(State(state): State<AppState>) -> Json<PollingInterval> {
+    Json(state.signal_polling.interval().into())
+}
+
+/// Changes the interval between signal readings until the service restarts.
+#[utoipa::path(
+    put,
+    path = "/config/signal-interval",
+    tag = "config",
+    request_body = PollingInterval,
+    responses(
+        (status = 200, body = PollingInterval),
+        (status = 400, description = "Out of range", body = error::Body),
+    ),
+)]
+async fn put_signal_interval(
+    State(state): State<AppState>,
+    Json(body): Json<PollingInterval>,
+) -> Result<Json<PollingInterval>, ApiError> {
+    state.signal_polling.set_interval(body.to_duration()?);
+    Ok(Json(state.signal_polling.interval().into()))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PhCalibration {
+    slope: f64,
+    offset: f64,
+    /// Latest raw probe voltage, to be read while the probe sits in a buffer solution.
+    voltage: Option<f64>,
+}
+
+impl PhCalibration {
+    fn current(state: &AppState) -> Option<Self> {
+        let ph = state.config.measurements.ph.as_ref()?;
+        let calibration = state.calibration.get().ph.unwrap_or(LinearCalibration {
+            slope: ph.slope,
+            offset: ph.offset,
+        });
+
+        Some(Self {
+            slope: calibration.slope,
+            offset: calibration.offset,
+            voltage: state.sampling.raw().ph_voltage,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct PhCalibrationRequest {
+    points: [PhCalibrationPoint; 2],
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct PhCalibrationPoint {
+    voltage: f64,
+    ph: f64,
+}
+
+/// Current pH calibration, along with the latest probe voltage.
+#[utoipa::path(
+    get,
+    path = "/calibrate/ph",
+    tag = "calibration",
+    responses(
+        (status = 200, body = PhCalibration),
+        (status = 404, description = "pH probe is not configured", body = error::Body),
+    ),
+)]
+async fn get_ph_calibration(State(state): State<AppState>) -> Result<Json<PhCalibration>, ApiError> {
+    PhCalibration::current(&state)
+        .map(Json)
+        .ok_or(ApiError::NotConfigured("pH probe is not configured"))
+}
+
+/// Calibrates the pH probe from two buffer solutions.
+#[utoipa::path(
+    put,
+    path = "/calibrate/ph",
+    tag = "calibration",
+    request_body = PhCalibrationRequest,
+    responses(
+        (status = 200, body = PhCalibration),
+        (status = 400, description = "The points do not make a usable line", body = error::Body),
+        (status = 404, description = "pH probe is not configured", body = error::Body),
+    ),
+)]
+async fn put_ph_calibration(
+    State(state): State<AppState>,
+    Json(body): Json<PhCalibrationRequest>,
+) -> Result<Json<PhCalibration>, ApiError> {
+    if state.config.measurements.ph.is_none() {
+        return Err(ApiError::NotConfigured("pH probe is not configured"));
+    }
+
+    let [a, b] = body.points;
+    let ph = LinearCalibration::from_points((a.voltage, a.ph), (b.voltage, b.ph))
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let (config, store) = (state.config.clone(), state.calibration.clone());
+    task::spawn_blocking(move || store.update(&config, |c| c.ph = Some(ph)))
         .await
-        .map_err(|e| anyhow!("Axum error: {e:?}"))
+        .map_err(|e| ApiError::internal("Failed to save pH calibration", e))?
+        .map_err(|e| ApiError::internal("Failed to save pH calibration", e))?;
+    info!(slope = ph.slope, offset = ph.offset; "pH calibration set to slope={} offset={}", ph.slope, ph.offset);
+
+    PhCalibration::current(&state)
+        .map(Json)
+        .ok_or(ApiError::NotConfigured("pH probe is not configured"))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TdsCalibrationRequest {
+    reference_ppm: f64,
 }
 
-async fn get_measurements() -> Result<Json<Measurements>, StatusCode> {
-    measurements::latest().await.map(Json).ok_or(StatusCode::NO_CONTENT)
+#[derive(Debug, Serialize, ToSchema)]
+struct TdsCalibration {
+    old_factor: f64,
+    new_factor: f64,
 }
 
-async fn get_signal() -> Result<Json<Signal>, StatusCode> {
-    signal::latest().await.map(Json).ok_or(StatusCode::NO_CONTENT)
+/// Derives the TDS factor from the current reading while the probe sits in a reference solution.
+#[utoipa::path(
+    post,
+    path = "/calibrate/tds",
+    tag = "calibration",
+    request_body = TdsCalibrationRequest,
+    responses(
+        (status = 200, body = TdsCalibration),
+        (status = 400, description = "Invalid reference", body = error::Body),
+        (status = 503, description = "No TDS reading to calibrate against yet", body = error::Body),
+    ),
+)]
+async fn post_tds_calibration(
+    State(state): State<AppState>,
+    Json(body): Json<TdsCalibrationRequest>,
+) -> Result<Json<TdsCalibration>, ApiError> {
+    if !(body.reference_ppm.is_finite() && body.reference_ppm > 0.0) {
+        return Err(ApiError::BadRequest("reference_ppm must be greater than 0".to_owned()));
+    }
+    let Some(uncalibrated) = state.sampling.raw().tds.filter(|tds| *tds > 0.0) else {
+        return Err(ApiError::NoData("No TDS reading to calibrate against yet"));
+    };
+
+    let factor = body.reference_ppm / uncalibrated;
+    set_tds_factor(&state, Some(factor)).await
+}
+
+/// Goes back to the configured TDS factor.
+#[utoipa::path(
+    delete,
+    path = "/calibrate/tds",
+    tag = "calibration",
+    responses((status = 200, body = TdsCalibration)),
+)]
+async fn delete_tds_calibration(State(state): State<AppState>) -> Result<Json<TdsCalibration>, ApiError> {
+    set_tds_factor(&state, None).await
+}
+
+async fn set_tds_factor(state: &AppState, factor: Option<f64>) -> Result<Json<TdsCalibration>, ApiError> {
+    let old_factor = state.calibration.get().tds_factor();
+    let (config, store) = (state.config.clone(), state.calibration.clone());
+    let calibration = task::spawn_blocking(move || store.update(&config, |c| c.tds_factor = factor))
+        .await
+        .map_err(|e| ApiError::internal("Failed to save TDS calibration", e))?
+        .map_err(|e| ApiError::internal("Failed to save TDS calibration", e))?;
+    let new_factor = calibration.tds_factor();
+    info!(old_factor, new_factor; "TDS calibration factor changed from {old_factor} to {new_factor}");
+
+    Ok(Json(TdsCalibration { old_factor, new_factor }))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct TemperatureCalibration {
+    offset: f64,
+}
+
+impl TemperatureCalibration {
+    fn current(state: &AppState) -> Self {
+        Self {
+            offset: state
+                .calibration
+                .get()
+                .temperature_offset
+                .unwrap_or(state.config.measurements.temperature_offset),
+        }
+    }
+}
+
+/// Offset added to every temperature reading.
+#[utoipa::path(
+    get,
+    path = "/calibrate/temperature",
+    tag = "calibration",
+    responses((status = 200, body = TemperatureCalibration)),
+)]
+async fn get_temperature_calibration(State(state): State<AppState>) -> Json<TemperatureCalibration> {
+    Json(TemperatureCalibration::current(&state))
+}
+
+#[utoipa::path(
+    put,
+    path = "/calibrate/temperature",
+    tag = "calibration",
+    request_body = TemperatureCalibration,
+    responses(
+        (status = 200, body = TemperatureCalibration),
+        (status = 400, description = "Offset out of range", body = error::Body),
+    ),
+)]
+async fn put_temperature_calibration(
+    State(state): State<AppState>,
+    Json(body): Json<TemperatureCalibration>,
+) -> Result<Json<TemperatureCalibration>, ApiError> {
+    const MAX_OFFSET: f64 = 10.0;
+
+    if !(body.offset.is_finite() && body.offset.abs() <= MAX_OFFSET) {
+        return Err(ApiError::BadRequest(format!("offset must be within ±{MAX_OFFSET}")));
+    }
+
+    let (config, store) = (state.config.clone(), state.calibration.clone());
+    task::spawn_blocking(move || store.update(&config, |c| c.temperature_offset = Some(body.offset)))
+        .await
+        .map_err(|e| ApiError::internal("Failed to save temperature calibration", e))?
+        .map_err(|e| ApiError::internal("Failed to save temperature calibration", e))?;
+    info!(offset = body.offset; "Temperature offset set to {}", body.offset);
+
+    Ok(Json(TemperatureCalibration::current(&state)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/thermostat",
+    tag = "thermostat",
+    responses(
+        (status = 200, body = thermostat::Status),
+        (status = 404, description = "Thermostat is not configured", body = error::Body),
+    ),
+)]
+async fn get_thermostat(State(state): State<AppState>) -> Result<Json<thermostat::Status>, ApiError> {
+    state
+        .thermostat
+        .status()
+        .map(Json)
+        .ok_or(ApiError::NotConfigured("Thermostat is not configured"))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MaintenanceParams {
+    /// Ends maintenance by itself after this long. Lasts until ended when left out.
+    duration_secs: Option<u64>,
+}
+
+/// Starts maintenance, during which measurements are still taken but not recorded, sent out or alerted on.
+#[utoipa::path(
+    post,
+    path = "/maintenance/start",
+    tag = "maintenance",
+    params(MaintenanceParams),
+    responses(
+        (status = 200, body = Maintenance),
+        (status = 400, description = "Malformed query, or duration out of range", body = error::Body),
+    ),
+)]
+async fn post_maintenance_start(
+    State(state): State<AppState>,
+    Query(params): Query<MaintenanceParams>,
+) -> Result<Json<Maintenance>, ApiError> {
+    if params.duration_secs == Some(0) {
+        return Err(ApiError::BadRequest("duration_secs must be greater than 0".to_owned()));
+    }
+
+    state
+        .maintenance
+        .start(params.duration_secs.map(Duration::from_secs))
+        .map(Json)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+/// Ends maintenance, if in progress.
+#[utoipa::path(
+    post,
+    path = "/maintenance/end",
+    tag = "maintenance",
+    responses((status = 204, description = "Ended")),
+)]
+async fn post_maintenance_end(State(state): State<AppState>) -> StatusCode {
+    state.maintenance.end();
+
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MaintenanceStatus {
+    /// `None` unless in maintenance.
+    window: Option<Maintenance>,
+    /// `None` unless the water change reminder is configured.
+    water_change: Option<WaterChange>,
+}
+
+/// The maintenance in progress, and when the next water change is due.
+#[utoipa::path(
+    get,
+    path = "/maintenance",
+    tag = "maintenance",
+    responses((status = 200, body = MaintenanceStatus)),
+)]
+async fn get_maintenance(State(state): State<AppState>) -> Json<MaintenanceStatus> {
+    Json(MaintenanceStatus {
+        window: state.maintenance.current(),
+        water_change: state.water_change.status(),
+    })
+}
+
+/// Records a water change made now. The next one is due a full interval from now, even when this one was late.
+#[utoipa::path(
+    post,
+    path = "/maintenance/water-change",
+    tag = "maintenance",
+    responses(
+        (status = 200, body = WaterChange),
+        (status = 404, description = "Water change reminder is not configured", body = error::Body),
+    ),
+)]
+async fn post_maintenance_water_change(State(state): State<AppState>) -> Result<Json<WaterChange>, ApiError> {
+    let reminder = state.water_change.clone();
+    task::spawn_blocking(move || reminder.record())
+        .await
+        .map_err(|e| ApiError::internal("Failed to save water change", e))?
+        .map_err(|e| ApiError::internal("Failed to save water change", e))?
+        .map(Json)
+        .ok_or(ApiError::NotConfigured("Water change reminder is not configured"))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ThermostatSettings {
+    target: Option<f64>,
+    enabled: Option<bool>,
+}
+
+/// Changes the target temperature, or turns the thermostat on or off, until the service restarts.
+#[utoipa::path(
+    put,
+    path = "/thermostat",
+    tag = "thermostat",
+    request_body = ThermostatSettings,
+    responses(
+        (status = 200, body = thermostat::Status),
+        (status = 400, description = "Target out of range", body = error::Body),
+        (status = 404, description = "Thermostat is not configured", body = error::Body),
+    ),
+)]
+async fn put_thermostat(
+    State(state): State<AppState>,
+    Json(body): Json<ThermostatSettings>,
+) -> Result<Json<thermostat::Status>, ApiError> {
+    if body.target.is_some_and(|target| !(10.0..=35.0).contains(&target)) {
+        return Err(ApiError::BadRequest("target must be between 10 and 35".to_owned()));
+    }
+
+    state
+        .thermostat
+        .configure(body.target, body.enabled)
+        .map(Json)
+        .ok_or(ApiError::NotConfigured("Thermostat is not configured"))
+}
+
+/// What the display currently shows.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    get,
+    path = "/display.png",
+    tag = "display",
+    responses(
+        (status = 200, content(("image/png"))),
+        (status = 503, description = "No frame drawn yet", body = error::Body),
+    ),
+)]
+async fn get_display_png(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let frame = state.display.frame().ok_or(ApiError::NoData("No frame drawn yet"))?;
+    let png = frame
+        .to_png()
+        .map_err(|e| ApiError::internal("Failed to encode display snapshot", e))?;
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], png))
+}
+
+/// Whether the display is lit, and how brightly.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    get,
+    path = "/display",
+    tag = "display",
+    responses((status = 200, body = DisplayState)),
+)]
+async fn get_display(State(state): State<AppState>) -> Json<DisplayState> {
+    Json(state.display.state(&state.config.display))
+}
+
+#[cfg(feature = "display")]
+#[derive(Debug, Deserialize, ToSchema)]
+struct DisplayBrightness {
+    /// Contrast of the panel, from 0 to 255.
+    brightness: u64,
+}
+
+/// Changes the brightness of the display until the service restarts. A change during the night window lights the
+/// display at that brightness until the window ends instead.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    put,
+    path = "/display/brightness",
+    tag = "display",
+    request_body = DisplayBrightness,
+    responses(
+        (status = 200, body = DisplayState),
+        (status = 400, description = "Out of range", body = error::Body),
+    ),
+)]
+async fn put_display_brightness(
+    State(state): State<AppState>,
+    Json(body): Json<DisplayBrightness>,
+) -> Result<Json<DisplayState>, ApiError> {
+    let brightness = u8::try_from(body.brightness)
+        .map_err(|_| ApiError::BadRequest("brightness must be between 0 and 255".into()))?;
+    state.display.set_contrast(&state.config.display, brightness);
+
+    Ok(Json(state.display.state(&state.config.display)))
+}
+
+/// Turns the display on until the next boundary of the night schedule.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    post,
+    path = "/display/on",
+    tag = "display",
+    responses((status = 204, description = "Turned on")),
+)]
+async fn post_display_on(State(state): State<AppState>) -> StatusCode {
+    state.display.set_override(state.config.display.night.as_ref(), true);
+    StatusCode::NO_CONTENT
+}
+
+/// Turns the display off until the next boundary of the night schedule.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    post,
+    path = "/display/off",
+    tag = "display",
+    responses((status = 204, description = "Turned off")),
+)]
+async fn post_display_off(State(state): State<AppState>) -> StatusCode {
+    state.display.set_override(state.config.display.night.as_ref(), false);
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(feature = "display")]
+#[derive(Debug, Deserialize, ToSchema)]
+struct DisplayMessage {
+    /// Up to 64 characters. Those the fonts lack are shown as `?`, and those that do not fit scroll by.
+    text: String,
+    /// How long the message is shown for.
+    duration_secs: u64,
+}
+
+/// Shows a message over the bottom row of the display, in place of any previous one, until it expires.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    post,
+    path = "/display/message",
+    tag = "display",
+    request_body = DisplayMessage,
+    responses(
+        (status = 204, description = "Shown"),
+        (status = 400, description = "Empty, too long, or shown for no time", body = error::Body),
+    ),
+)]
+async fn post_display_message(
+    State(state): State<AppState>,
+    Json(body): Json<DisplayMessage>,
+) -> Result<StatusCode, ApiError> {
+    if body.text.trim().is_empty() {
+        return Err(ApiError::BadRequest("text must not be empty".into()));
+    }
+    if body.text.chars().count() > display::MESSAGE_MAX_CHARS {
+        return Err(ApiError::BadRequest(format!(
+            "text must be at most {} characters",
+            display::MESSAGE_MAX_CHARS
+        )));
+    }
+    if body.duration_secs == 0 {
+        return Err(ApiError::BadRequest("duration_secs must be greater than 0".into()));
+    }
+
+    state
+        .display
+        .set_message(&body.text, Duration::from_secs(body.duration_secs));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Takes the message down from the display before it expires.
+#[cfg(feature = "display")]
+#[utoipa::path(
+    delete,
+    path = "/display/message",
+    tag = "display",
+    responses((status = 204, description = "Taken down, or there was none")),
+)]
+async fn delete_display_message(State(state): State<AppState>) -> StatusCode {
+    state.display.clear_message();
+    StatusCode::NO_CONTENT
+}
+
+/// A new value pushed to streaming clients.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum Update {
+    Measurements(Measurements),
+    Signal(Signal),
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StreamParams {
+    /// Name of the tank whose measurements are pushed. The default tank when left out.
+    tank: Option<String>,
+}
+
+impl StreamParams {
+    fn tank<'a>(&self, state: &'a AppState) -> Result<&'a Tank, ApiError> {
+        match &self.tank {
+            Some(name) => state.tank(name).ok_or(ApiError::NotConfigured("No such tank")),
+            None => Ok(state.default_tank()),
+        }
+    }
+}
+
+/// Subscription to new values from all workers.
+struct Updates {
+    measurements: Receiver<Measurements>,
+    signal: Receiver<Signal>,
+}
+
+impl Updates {
+    fn subscribe(state: &AppState, tank: &Tank) -> Self {
+        Self {
+            measurements: tank.measurements.subscribe(),
+            signal: state.signal.subscribe(),
+        }
+    }
+
+    /// Waits for the next value, or returns `None` when the subscriber has fallen too far behind.
+    ///
+    /// The workers never wait for clients, so a client that cannot keep up is disconnected instead.
+    async fn next(&mut self) -> Option<Update> {
+        let update = select! {
+            m = self.measurements.recv() => m.map(Update::Measurements),
+            s = self.signal.recv() => s.map(Update::Signal),
+        };
+        match update {
+            Ok(update) => Some(update),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Dropping slow streaming client ({skipped} updates behind)");
+                None
+            }
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+/// Pushes new values as `{"type":"measurements"|"signal","data":...}` text messages.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "streaming",
+    params(StreamParams),
+    responses(
+        (status = 101, description = "Switched to WebSocket"),
+        (status = 404, description = "No such tank", body = error::Body),
+    ),
+)]
+async fn get_ws(
+    State(state): State<AppState>,
+    Query(params): Query<StreamParams>,
+    slot: Option<Extension<limit::Slot>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let updates = Updates::subscribe(&state, params.tank(&state)?);
+    Ok(ws.on_upgrade(|socket| async move {
+        // Counted among the open requests for as long as the socket is
+        let _slot = slot;
+        stream_updates(socket, updates).await;
+    }))
+}
+
+async fn stream_updates(mut socket: WebSocket, mut updates: Updates) {
+    loop {
+        let update = select! {
+            update = updates.next() => match update {
+                Some(update) => update,
+                None => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let Ok(text) = serde_json::to_string(&update) else {
+            continue;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Pushes the current values and then new ones as `measurements` and `signal` events.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "streaming",
+    params(StreamParams),
+    responses(
+        (status = 200, body = String, content_type = "text/event-stream"),
+        (status = 404, description = "No such tank", body = error::Body),
+    ),
+)]
+async fn get_events(
+    State(state): State<AppState>,
+    Query(params): Query<StreamParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let tank = params.tank(&state)?;
+    // Subscribe before reading the current values so that nothing stored in between is missed.
+    let updates = Updates::subscribe(&state, tank);
+    let current = [
+        tank.measurements.get().await.map(Update::Measurements),
+        state.signal.get().await.map(Update::Signal),
+    ];
+
+    let stream = stream::iter(current.into_iter().flatten())
+        .chain(stream::unfold(updates, |mut updates| async move {
+            updates.next().await.map(|update| (update, updates))
+        }))
+        .filter_map(|update| async move {
+            let event = match &update {
+                Update::Measurements(m) => Event::default().event("measurements").json_data(m),
+                Update::Signal(s) => Event::default().event("signal").json_data(s),
+            };
+            event.ok().map(Ok)
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Request, header::IF_NONE_MATCH};
+    use chrono::TimeDelta;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::{AdcChannel, TankConfig};
+
+    fn app(state: AppState) -> Router {
+        router(state).unwrap()
+    }
+
+    fn get(uri: &str, etag: Option<&HeaderValue>) -> Request<Body> {
+        let mut request = Request::get(uri);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        request.body(Body::empty()).unwrap()
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> Response {
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    fn etag_of(response: &Response) -> HeaderValue {
+        response.headers()[header::ETAG].clone()
+    }
+
+    fn loopback_config(listeners: usize) -> Config {
+        Config {
+            api: ApiConfig {
+                listen: vec![SocketAddr::from(([127, 0, 0, 1], 0)); listeners],
+                ..ApiConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_on_every_listener() {
+        let config = Arc::new(loopback_config(3));
+        let listeners = Arc::new(bind(&config.api).unwrap());
+        let addrs: Vec<_> = listeners.tcp.iter().map(|l| l.local_addr().unwrap()).collect();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(worker(AppState::new(config), listeners.clone(), shutdown.clone()));
+
+        let client = reqwest::Client::new();
+        for addr in &addrs {
+            let response = client
+                .get(format!("http://{addr}{}", probes::HEALTHZ_PATH))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{addr}");
+        }
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_again_on_the_same_listeners_after_a_restart() {
+        let config = Arc::new(loopback_config(1));
+        let listeners = Arc::new(bind(&config.api).unwrap());
+        let addr = listeners.tcp[0].local_addr().unwrap();
+        let client = reqwest::Client::new();
+
+        for _ in 0..2 {
+            let shutdown = CancellationToken::new();
+            let server = tokio::spawn(worker(
+                AppState::new(config.clone()),
+                listeners.clone(),
+                shutdown.clone(),
+            ));
+            let response = client
+                .get(format!("http://{addr}{}", probes::HEALTHZ_PATH))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            shutdown.cancel();
+            server.await.unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn fails_to_bind_an_address_in_use() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+        let config = ApiConfig {
+            listen: vec![SocketAddr::from(([127, 0, 0, 1], 0)), addr],
+            ..ApiConfig::default()
+        };
+
+        let e = bind(&config).err().unwrap();
+        assert!(e.to_string().starts_with(&format!("Failed to listen on {addr}")), "{e}");
+    }
+
+    #[tokio::test]
+    async fn measurements_are_not_modified_until_updated() {
+        let state = AppState::new(Arc::new(Config::default()));
+        let tank = state.default_tank();
+        let now = Utc::now();
+        tank.measurements.set(Measurements::sample(now, 24.5, 150.0)).await;
+        let app = app(state.clone());
+
+        let first = send(&app, get("/v1/measurements", None)).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[header::CACHE_CONTROL], "no-cache");
+        let etag = etag_of(&first);
+
+        let second = send(&app, get("/v1/measurements", Some(&etag))).await;
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+
+        let tank = state.default_tank();
+        tank.measurements
+            .set(Measurements::sample(now + TimeDelta::seconds(10), 24.6, 150.0))
+            .await;
+        let third = send(&app, get("/v1/measurements", Some(&etag))).await;
+        assert_eq!(third.status(), StatusCode::OK);
+        assert_ne!(etag_of(&third), etag);
+    }
+
+    #[tokio::test]
+    async fn measurements_etag_changes_with_the_unit() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(Utc::now(), 24.5, 150.0))
+            .await;
+        let app = app(state);
+
+        let celsius = send(&app, get("/v1/measurements?unit=celsius", None)).await;
+        assert_eq!(celsius.status(), StatusCode::OK);
+        let etag = etag_of(&celsius);
+        let again = send(&app, get("/v1/measurements?unit=celsius", Some(&etag))).await;
+        assert_eq!(again.status(), StatusCode::NOT_MODIFIED);
+
+        let fahrenheit = send(&app, get("/v1/measurements?unit=fahrenheit", Some(&etag))).await;
+        assert_eq!(fahrenheit.status(), StatusCode::OK);
+        assert_ne!(etag_of(&fahrenheit), etag);
+    }
+
+    #[tokio::test]
+    async fn history_etag_changes_with_the_unit() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state
+            .default_tank()
+            .history
+            .write()
+            .await
+            .push_back(Measurements::sample(Utc::now(), 24.5, 150.0));
+        let app = app(state);
+
+        let celsius = send(&app, get("/v1/measurements/history", None)).await;
+        assert_eq!(celsius.status(), StatusCode::OK);
+        let etag = etag_of(&celsius);
+        let again = send(&app, get("/v1/measurements/history", Some(&etag))).await;
+        assert_eq!(again.status(), StatusCode::NOT_MODIFIED);
+
+        let fahrenheit = send(&app, get("/v1/measurements/history?unit=fahrenheit", Some(&etag))).await;
+        assert_eq!(fahrenheit.status(), StatusCode::OK);
+        assert_ne!(etag_of(&fahrenheit), etag);
+    }
+
+    async fn text_of(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_the_dashboard() {
+        let app = app(AppState::new(Arc::new(Config::default())));
+
+        let response = send(&app, get("/", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        let body = text_of(response).await;
+        assert!(body.contains("<title>Cobitis</title>"), "{body}");
+        assert!(body.contains("/v1/measurements"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn serves_the_dashboard_without_a_token() {
+        let mut config = Config::default();
+        config.api.tokens = vec!["secret".to_owned()];
+        let app = app(AppState::new(Arc::new(config)));
+
+        let response = send(&app, get("/", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The data it fetches still asks for one
+        let response = send(&app, get("/v1/measurements", None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    fn accepting(uri: &str, accept: &str) -> Request<Body> {
+        Request::get(uri)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn binary_encodings_are_smaller_than_json() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(Utc::now(), 24.5, 150.0))
+            .await;
+        let app = app(state);
+
+        let mut sizes = [0; 3];
+        for (size, accept) in sizes
+            .iter_mut()
+            .zip(["application/json", "application/cbor", "application/msgpack"])
+        {
+            let response = send(&app, accepting("/v1/measurements", accept)).await;
+            assert_eq!(response.status(), StatusCode::OK, "{accept}");
+            assert_eq!(response.headers()[header::CONTENT_TYPE], accept);
+            assert_eq!(response.headers()[header::VARY], "accept");
+            *size = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .len();
+        }
+
+        let [json, cbor, msgpack] = sizes;
+        assert!(cbor < json, "CBOR {cbor} bytes, JSON {json} bytes");
+        assert!(msgpack < json, "MessagePack {msgpack} bytes, JSON {json} bytes");
+    }
+
+    #[tokio::test]
+    async fn unsupported_types_are_not_acceptable() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(Utc::now(), 24.5, 150.0))
+            .await;
+        let app = app(state);
+
+        let response = send(&app, accepting("/v1/measurements", "text/html")).await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        let body = text_of(response).await;
+        assert!(body.contains("application/cbor"), "{body}");
+    }
+
+    fn two_tanks() -> Config {
+        let tank = |name: &str, tds_channel| TankConfig {
+            name: name.to_owned(),
+            temperature_sensor: Some(format!("28-{name}")),
+            tds_channel,
+        };
+        let mut config = Config::default();
+        config.measurements.tanks = vec![tank("main", AdcChannel::A0), tank("shrimp", AdcChannel::A2)];
+
+        config
+    }
+
+    #[tokio::test]
+    async fn serves_the_history_and_stats_of_each_tank() {
+        let state = AppState::new(Arc::new(two_tanks()));
+        let now = Utc::now();
+        for (name, temperature) in [("main", 24.5), ("shrimp", 22.0)] {
+            let tank = state.tank(name).unwrap();
+            tank.history
+                .write()
+                .await
+                .push_back(Measurements::sample(now, temperature, 150.0));
+        }
+        let app = app(state);
+
+        let response = send(&app, get("/v1/tanks/shrimp/measurements/history.csv", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let disposition = response.headers()[header::CONTENT_DISPOSITION]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert!(disposition.contains("cobitis-shrimp-"), "{disposition}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(body.lines().count(), 2, "{body}");
+        assert!(body.lines().nth(1).unwrap().contains(",22,"), "{body}");
+
+        let response = send(&app, get("/v1/tanks/shrimp/measurements/stats", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, get("/v1/tanks/nope/measurements/stats", None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn streams_the_requested_tank() {
+        let state = AppState::new(Arc::new(two_tanks()));
+        let now = Utc::now();
+        state
+            .tank("shrimp")
+            .unwrap()
+            .measurements
+            .set(Measurements::sample(now, 22.0, 150.0))
+            .await;
+        let app = app(state);
+
+        let response = send(&app, get("/v1/events?tank=nope", None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = send(&app, get("/v1/events?tank=shrimp", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        let event = body.next().await.unwrap().unwrap();
+        let event = String::from_utf8(event.to_vec()).unwrap();
+        assert!(event.starts_with("event: measurements\n"), "{event}");
+        assert!(event.contains("\"temperature\":22.0"), "{event}");
+    }
+
+    #[tokio::test]
+    async fn unprefixed_paths_are_deprecated_aliases() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(Utc::now(), 24.5, 150.0))
+            .await;
+        let app = app(state);
+
+        for path in [
+            "/measurements",
+            "/config/measurement-interval",
+            "/tanks",
+            "/maintenance",
+        ] {
+            let current = send(&app, get(&format!("/v1{path}"), None)).await;
+            assert_eq!(current.status(), StatusCode::OK, "{path}");
+            assert!(!current.headers().contains_key("deprecation"), "{path}");
+
+            let old = send(&app, get(path, None)).await;
+            assert_eq!(old.status(), StatusCode::OK, "{path}");
+            assert_eq!(old.headers()["deprecation"], "true", "{path}");
+            assert_eq!(
+                old.headers()[header::LINK],
+                format!("</v1{path}>; rel=\"successor-version\""),
+            );
+
+            assert_eq!(text_of(old).await, text_of(current).await, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_the_endpoints_of_v1() {
+        let app = app(AppState::new(Arc::new(Config::default())));
+
+        let response = send(&app, get("/v1", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let index: serde_json::Value = serde_json::from_str(&text_of(response).await).unwrap();
+        assert_eq!(index["version"], env!("CARGO_PKG_VERSION"));
+        let endpoints = index["endpoints"].as_array().unwrap();
+        let interval = endpoints
+            .iter()
+            .find(|e| e["path"] == "/v1/config/measurement-interval")
+            .unwrap();
+        assert_eq!(interval["methods"], serde_json::json!(["GET", "PUT"]));
+        assert!(
+            endpoints
+                .iter()
+                .all(|e| e["path"].as_str().unwrap().starts_with("/v1/"))
+        );
+    }
+
+    #[tokio::test]
+    async fn measurements_report_their_age_once_no_longer_fresh() {
+        // Fresh for 20 seconds and stale after 60 by default.
+        let state = AppState::new(Arc::new(Config::default()));
+        let app = app(state.clone());
+
+        for (age, age_seconds, is_stale) in [(5, None, false), (30, Some(30), false), (90, Some(90), true)] {
+            let timestamp = Utc::now() - TimeDelta::seconds(age);
+            state
+                .default_tank()
+                .measurements
+                .set(Measurements::sample(timestamp, 24.5, 150.0))
+                .await;
+
+            let response = send(&app, get("/v1/measurements", None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_str(&text_of(response).await).unwrap();
+            assert_eq!(body["is_stale"], is_stale, "{age}");
+            assert_eq!(body.get("age_seconds").and_then(|a| a.as_i64()), age_seconds, "{age}");
+        }
+    }
+
+    #[tokio::test]
+    async fn an_open_event_stream_holds_a_slot() {
+        let config = Config {
+            api: ApiConfig {
+                max_open_requests: Some(1),
+                ..ApiConfig::default()
+            },
+            ..Config::default()
+        };
+        let state = AppState::new(Arc::new(config));
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(Utc::now(), 24.5, 150.0))
+            .await;
+        let app = app(state);
+
+        // A body sent whole gives its slot back right away
+        let measurements = send(&app, get("/v1/measurements", None)).await;
+        assert_eq!(measurements.status(), StatusCode::OK);
+        let events = send(&app, get("/v1/events", None)).await;
+        assert_eq!(events.status(), StatusCode::OK);
+
+        let response = send(&app, get("/v1/measurements", None)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        drop((measurements, events));
+        let response = send(&app, get("/v1/measurements", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
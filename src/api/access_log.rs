@@ -0,0 +1,83 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use logger::log::{debug, info};
+
+use crate::state::AppState;
+
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// IDs handed over by a proxy longer than this are replaced rather than logged.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns the ID of the request being handled, for tying log lines to it.
+pub(super) fn request_id() -> String {
+    REQUEST_ID.try_with(Clone::clone).unwrap_or_else(|_| "-".to_owned())
+}
+
+/// Makes an ID unique within this run, prefixed with the start time to tell runs apart.
+fn new_request_id(started_at: DateTime<Utc>) -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+
+    format!("{:x}-{}", started_at.timestamp(), NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Tags each request with an ID, which is taken over from `x-request-id` when a proxy supplies one, and logs it
+/// once handled.
+pub(super) async fn layer(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic()))
+        .map_or_else(|| new_request_id(state.health.started_at), ToOwned::to_owned);
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let remote = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(|| "-".to_owned(), |ConnectInfo(addr)| addr.to_string());
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
+
+    let status = response.status();
+    let config = &state.config;
+    let quiet = !config.api.access_log
+        || !(status.is_client_error() || status.is_server_error())
+            && config.api.access_log_quiet_paths.contains(&path);
+    let line = format!(
+        "{remote} {method} {path} {} {}ms [{id}]",
+        status.as_u16(),
+        started.elapsed().as_millis()
+    );
+    if quiet {
+        debug!("{line}");
+    } else {
+        info!("{line}");
+    }
+
+    response
+}
@@ -0,0 +1,110 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::Debug;
+
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use logger::log::error;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::access_log;
+
+/// Clients are asked to come back after this many seconds when there is no data yet.
+const RETRY_AFTER_SECS: u32 = 10;
+
+/// An error returned by a route, rendered as a JSON body such as
+/// `{"error":"no_data","message":"No measurement recorded yet"}`.
+#[derive(Debug)]
+pub(super) enum ApiError {
+    /// Nothing has been recorded yet.
+    NoData(&'static str),
+    /// There is no such route.
+    NotFound,
+    /// The feature behind the route is not configured.
+    NotConfigured(&'static str),
+    BadRequest(String),
+    Unauthorized(&'static str),
+    /// None of the types the client accepts can be produced.
+    NotAcceptable,
+    /// The value could not be produced right now, for the reason given.
+    Unavailable(String),
+    /// The client is asked to come back after the given number of seconds.
+    TooManyRequests(u64),
+    /// The cause has already been logged, so it is not given away to the client.
+    Internal,
+}
+
+impl ApiError {
+    /// Logs `e` along with the request it failed, and hides it from the client.
+    pub fn internal(context: &str, e: impl Debug) -> Self {
+        error!("{context} [{}]: {e:?}", access_log::request_id());
+        Self::Internal
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = Error)]
+pub(super) struct Body<'a> {
+    /// Machine-readable code, such as `no_data` or `bad_request`.
+    error: &'static str,
+    message: &'a str,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error, message) = match &self {
+            Self::NoData(message) => (StatusCode::SERVICE_UNAVAILABLE, "no_data", *message),
+            Self::NotFound => (StatusCode::NOT_FOUND, "not_found", "No such route"),
+            Self::NotConfigured(message) => (StatusCode::NOT_FOUND, "not_configured", *message),
+            Self::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message.as_str()),
+            Self::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "unauthorized", *message),
+            Self::Unavailable(message) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable", message.as_str()),
+            Self::NotAcceptable => (
+                StatusCode::NOT_ACCEPTABLE,
+                "not_acceptable",
+                "Supported types are application/json, application/cbor, and application/msgpack",
+            ),
+            Self::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many requests"),
+            Self::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal", "Internal error"),
+        };
+
+        let mut response = (status, axum::Json(Body { error, message })).into_response();
+        match self {
+            Self::NoData(_) => {
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, RETRY_AFTER_SECS.into());
+            }
+            Self::TooManyRequests(secs) => {
+                response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+            }
+            Self::Unauthorized(_) => {
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+            }
+            _ => {}
+        }
+
+        response
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::Display;
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Makes a weak entity tag out of whatever the representation changes with.
+pub(super) fn weak(parts: &[&dyn Display]) -> String {
+    let parts: Vec<String> = parts.iter().map(ToString::to_string).collect();
+    format!("W/\"{}\"", parts.join("-"))
+}
+
+/// Responds with `body` tagged with `etag`, or with 304 Not Modified when the client already has that version.
+///
+/// The response is marked `no-cache`, so that caches on the way check back every time rather than serving a value
+/// that has moved on.
+pub(super) fn respond(headers: &HeaderMap, etag: &str, body: impl IntoResponse) -> Response {
+    let cache_headers = [
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        (
+            header::ETAG,
+            HeaderValue::from_str(etag).unwrap_or_else(|_| HeaderValue::from_static("W/\"\"")),
+        ),
+    ];
+
+    if matches(headers, etag) {
+        (StatusCode::NOT_MODIFIED, cache_headers).into_response()
+    } else {
+        (cache_headers, body).into_response()
+    }
+}
+
+/// Whether `If-None-Match` lists `etag`, compared weakly as the RFC requires for this header.
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let etag = opaque(etag);
+
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
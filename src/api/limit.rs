@@ -0,0 +1,159 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::error::ApiError;
+use crate::config::ApiConfig;
+
+/// Buckets are swept of idle clients once there are this many.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// Counts of requests turned away since the start.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rejections {
+    /// By the per-client rate limit.
+    pub rate_limited: u64,
+    /// By the cap on requests open at once.
+    pub overloaded: u64,
+}
+
+/// Counts the requests turned away, for the metrics to report.
+pub(crate) struct RejectionCounter {
+    rate_limited: AtomicU64,
+    overloaded: AtomicU64,
+}
+
+impl RejectionCounter {
+    pub fn new() -> Self {
+        Self {
+            rate_limited: AtomicU64::new(0),
+            overloaded: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Rejections {
+        Rejections {
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            overloaded: self.overloaded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub(super) struct Limiter {
+    /// Tokens added per second, and the most a bucket holds.
+    rate: Option<(f64, f64)>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    open: Option<Arc<Semaphore>>,
+    rejections: Arc<RejectionCounter>,
+}
+
+impl Limiter {
+    pub fn new(config: &ApiConfig, rejections: Arc<RejectionCounter>) -> Self {
+        Self {
+            rate: config
+                .rate_limit_per_sec
+                .map(|rate| (rate, f64::from(config.rate_limit_burst))),
+            buckets: Mutex::new(HashMap::new()),
+            open: config.max_open_requests.map(|max| Arc::new(Semaphore::new(max))),
+            rejections,
+        }
+    }
+
+    /// Takes a token from the bucket of `ip`. Returns the seconds until one is available when it is empty.
+    fn take(&self, ip: IpAddr) -> Result<(), u64> {
+        let Some((rate, burst)) = self.rate else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // A bucket that would be full again is no different from a fresh one.
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate).min(burst);
+        bucket.updated_at = now;
+        if bucket.tokens < 1.0 {
+            #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            return Err(((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64);
+        }
+        bucket.tokens -= 1.0;
+
+        Ok(())
+    }
+}
+
+/// A place among the requests open at once, held until the response has been sent in full, or the WebSocket it was
+/// upgraded to is closed.
+#[derive(Clone)]
+pub(super) struct Slot {
+    _permit: Arc<OwnedSemaphorePermit>,
+}
+
+/// Turns away clients exceeding their rate, and everyone while too many requests are open.
+pub(super) async fn layer(State(limiter): State<Arc<Limiter>>, mut request: Request, next: Next) -> Response {
+    // Requests over the Unix socket come from a local proxy, which is left to do its own limiting.
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>()
+        && let Err(retry_after_secs) = limiter.take(addr.ip())
+    {
+        limiter.rejections.rate_limited.fetch_add(1, Ordering::Relaxed);
+        return ApiError::TooManyRequests(retry_after_secs).into_response();
+    }
+
+    let Some(open) = &limiter.open else {
+        return next.run(request).await;
+    };
+    let Ok(permit) = open.clone().try_acquire_owned() else {
+        limiter.rejections.overloaded.fetch_add(1, Ordering::Relaxed);
+        return ApiError::TooManyRequests(1).into_response();
+    };
+    let slot = Slot {
+        _permit: Arc::new(permit),
+    };
+    // For the WebSocket handler to hold on to
+    request.extensions_mut().insert(slot.clone());
+
+    let response = next.run(request).await;
+    // A body of a known size is already complete, whereas a stream keeps the slot until it ends.
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+    response.map(|body| {
+        Body::from_stream(stream::unfold(
+            (body.into_data_stream(), slot),
+            |(mut body, slot)| async move { body.next().await.map(|chunk| (chunk, (body, slot))) },
+        ))
+    })
+}
@@ -0,0 +1,138 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{fs, net::SocketAddr, path::Path, time::Duration};
+
+use logger::log::{error, info, warn};
+use mdns_sd::{DaemonEvent, IfKind, ServiceDaemon, ServiceInfo};
+use tokio::{select, time::timeout};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::MdnsConfig;
+
+/// Service types announced, along with the path each one points at.
+const SERVICES: [(&str, &str); 2] = [("_cobitis._tcp.local.", "/v1"), ("_http._tcp.local.", "/")];
+
+/// Exists while avahi-daemon is running.
+const AVAHI_PID_FILE: &str = "/run/avahi-daemon/pid";
+
+/// How long the goodbye packets are given to go out when stopping.
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Announces the API served on `addr` until `shutdown`, then withdraws it. The addresses announced follow those of
+/// the host as they change. Failing to start is logged without stopping the API.
+pub(super) async fn advertise(config: MdnsConfig, addr: SocketAddr, tank: String, shutdown: CancellationToken) {
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .and_then(|s| s.trim().split('.').next().map(str::to_owned))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "cobitis".into());
+    let host_name = config.host_name.unwrap_or_else(|| format!("cobitis-{hostname}"));
+    let instance = config.instance.unwrap_or_else(|| format!("Cobitis {hostname}"));
+
+    if Path::new(AVAHI_PID_FILE).exists() {
+        if host_name.eq_ignore_ascii_case(&hostname) {
+            warn!(
+                "avahi-daemon is running and answers for {host_name}.local too, so one of them will rename it. Set \
+                 api.mdns.host_name to another name, or remove [api.mdns] to advertise through avahi-daemon instead"
+            );
+        } else {
+            info!("avahi-daemon is running, advertising alongside it as {host_name}.local");
+        }
+    }
+
+    let (daemon, fullnames) = match start(&instance, &host_name, addr, &tank) {
+        Ok(started) => started,
+        Err(e) => {
+            error!("Failed to advertise the API over mDNS: {e:?}");
+            return;
+        }
+    };
+    info!(
+        "Advertising the API over mDNS as {instance:?} on {host_name}.local:{}",
+        addr.port()
+    );
+
+    match daemon.monitor() {
+        Ok(events) => loop {
+            let event = select! {
+                event = events.recv_async() => event,
+                () = shutdown.cancelled() => break,
+            };
+            match event {
+                Ok(DaemonEvent::IpAdd(ip)) => info!("Advertising the API over mDNS on {ip}"),
+                Ok(DaemonEvent::IpDel(ip)) => info!("No longer advertising the API over mDNS on {ip}"),
+                Ok(DaemonEvent::NameChange(change)) => {
+                    warn!(
+                        "mDNS name {} is taken, using {} instead",
+                        change.original, change.new_name
+                    );
+                }
+                Ok(DaemonEvent::Error(e)) => warn!("mDNS error: {e}"),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        },
+        Err(e) => {
+            warn!("Failed to watch the mDNS responder: {e}");
+            shutdown.cancelled().await;
+        }
+    }
+
+    stop(&daemon, &fullnames).await;
+}
+
+/// Registers every service, returning the responder along with their full names.
+fn start(
+    instance: &str,
+    host_name: &str,
+    addr: SocketAddr,
+    tank: &str,
+) -> anyhow::Result<(ServiceDaemon, Vec<String>)> {
+    let daemon = ServiceDaemon::new()?;
+    // Only addresses the API can be reached on are announced
+    if !addr.ip().is_unspecified() {
+        daemon.disable_interface(IfKind::All)?;
+        daemon.enable_interface(IfKind::Addr(addr.ip()))?;
+    } else if addr.is_ipv4() {
+        daemon.disable_interface(IfKind::IPv6)?;
+    }
+    daemon.disable_interface(vec![IfKind::LoopbackV4, IfKind::LoopbackV6])?;
+
+    let mut fullnames = Vec::with_capacity(SERVICES.len());
+    for (service_type, path) in SERVICES {
+        let properties = [("version", env!("CARGO_PKG_VERSION")), ("tank", tank), ("path", path)];
+        let service = ServiceInfo::new(
+            service_type,
+            instance,
+            &format!("{host_name}.local."),
+            (),
+            addr.port(),
+            &properties[..],
+        )?
+        .enable_addr_auto();
+        fullnames.push(service.get_fullname().to_owned());
+        daemon.register(service)?;
+    }
+
+    Ok((daemon, fullnames))
+}
+
+async fn stop(daemon: &ServiceDaemon, fullnames: &[String]) {
+    for fullname in fullnames {
+        match daemon.unregister(fullname) {
+            Ok(status) => {
+                let _ = timeout(GOODBYE_TIMEOUT, status.recv_async()).await;
+            }
+            Err(e) => warn!("Failed to withdraw {fullname} from mDNS: {e}"),
+        }
+    }
+    match daemon.shutdown() {
+        Ok(status) => {
+            let _ = timeout(GOODBYE_TIMEOUT, status.recv_async()).await;
+        }
+        Err(e) => warn!("Failed to stop the mDNS responder: {e}"),
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{collections::BTreeMap, fmt};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    measurements::{AuxValue, DebugValues, Measurements},
+    signal::Signal,
+    system::{LoadAverage, System, Throttled, Usage},
+    timezone,
+};
+
+/// How the timestamps of a response are written.
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum TimestampFormat {
+    /// Milliseconds since the Unix epoch.
+    #[default]
+    Millis,
+    /// RFC 3339 in UTC, such as `2025-06-01T12:00:00.000Z`.
+    Rfc3339,
+    /// RFC 3339 in the configured `timezone`, or that of the system, such as `2025-06-01T21:00:00.000+09:00`.
+    Local,
+}
+
+impl TimestampFormat {
+    pub fn format(self, timestamp: DateTime<Utc>) -> Timestamp {
+        match self {
+            Self::Millis => Timestamp::Millis(timestamp.timestamp_millis()),
+            Self::Rfc3339 => Timestamp::Text(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            Self::Local => {
+                Timestamp::Text(timezone::localize(timestamp).to_rfc3339_opts(SecondsFormat::Millis, false))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Millis => "millis",
+            Self::Rfc3339 => "rfc3339",
+            Self::Local => "local",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct TimestampParams {
+    /// Milliseconds since the Unix epoch unless asked otherwise.
+    #[serde(default)]
+    #[param(inline)]
+    pub timestamp: TimestampFormat,
+}
+
+/// A timestamp written as asked for with `timestamp`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub(super) enum Timestamp {
+    /// Milliseconds since the Unix epoch.
+    Millis(i64),
+    /// RFC 3339.
+    Text(String),
+}
+
+/// Measurements as served, which the exporters write in their own formats from the same readings.
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct MeasurementsBody {
+    timestamp: Timestamp,
+    /// Temperature of the primary thermal sensor, smoothed when configured.
+    temperature: f64,
+    /// Temperature as read, present only while it is smoothed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature_raw: Option<f64>,
+    /// Temperatures of every thermal sensor that could be read, keyed by sensor ID.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    temperatures: BTreeMap<String, f64>,
+    /// Smoothed when configured.
+    tds: f64,
+    /// TDS as read, present only while it is smoothed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tds_raw: Option<f64>,
+    /// Electrical conductivity at 25 °C in µS/cm, which `tds` is derived from. Smoothed along with it.
+    ec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tds_voltage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ph: Option<f64>,
+    /// Flow rate in liters per minute over the interval since the previous measurement. Absent without a flow sensor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flow_rate: Option<f64>,
+    /// Liters through the flow sensor since the start, or since `POST /flow/reset`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flow_volume: Option<f64>,
+    /// Whether the water is up to the float switch. Absent without a switch, or until it has settled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    water_level_ok: Option<bool>,
+    /// Auxiliary inputs of the ADC that could be read, keyed by their configured name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    aux: BTreeMap<String, AuxValue>,
+    /// Values the measurements were worked out from. Absent unless asked for with `debug=1`, or
+    /// `measurements.expose_debug` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<Box<DebugValues>>,
+}
+
+impl MeasurementsBody {
+    pub fn new(m: Measurements, timestamps: TimestampFormat, debug: bool) -> Self {
+        Self {
+            timestamp: timestamps.format(m.timestamp),
+            temperature: m.temperature,
+            temperature_raw: m.temperature_raw,
+            temperatures: m.temperatures,
+            tds: m.tds,
+            tds_raw: m.tds_raw,
+            ec: m.ec,
+            tds_voltage: m.tds_voltage,
+            ph: m.ph,
+            flow_rate: m.flow_rate,
+            flow_volume: m.flow_volume,
+            water_level_ok: m.water_level_ok,
+            aux: m.aux,
+            debug: m.debug.filter(|_| debug),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct SignalBody {
+    timestamp: Timestamp,
+    /// Whether the interface is connected to an access point. The quality is 0 when not.
+    associated: bool,
+    quality: f64,
+    /// Received signal strength in dBm, when the driver reports it.
+    rssi: Option<i32>,
+    /// Network the interface is associated with. `None` for a hidden SSID.
+    ssid: Option<String>,
+    /// Negotiated transmit bitrate in Mbit/s.
+    bitrate: Option<f64>,
+    /// Channel frequency in MHz.
+    frequency: Option<f64>,
+}
+
+impl SignalBody {
+    pub fn new(signal: Signal, timestamps: TimestampFormat) -> Self {
+        Self {
+            timestamp: timestamps.format(signal.timestamp),
+            associated: signal.associated,
+            quality: signal.quality,
+            rssi: signal.rssi,
+            ssid: signal.ssid,
+            bitrate: signal.bitrate,
+            frequency: signal.frequency,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct SystemBody {
+    timestamp: Timestamp,
+    /// CPU temperature in °C.
+    cpu_temperature: Option<f64>,
+    load: Option<LoadAverage>,
+    memory: Option<Usage>,
+    /// Usage of the filesystem the state directory is on.
+    disk: Option<Usage>,
+    /// Only available on a Raspberry Pi.
+    throttled: Option<Throttled>,
+}
+
+impl SystemBody {
+    pub fn new(system: System, timestamps: TimestampFormat) -> Self {
+        Self {
+            timestamp: timestamps.format(system.timestamp),
+            cpu_temperature: system.cpu_temperature,
+            load: system.load,
+            memory: system.memory,
+            disk: system.disk,
+            throttled: system.throttled,
+        }
+    }
+}
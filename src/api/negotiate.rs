@@ -0,0 +1,152 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use super::error::ApiError;
+
+/// Encoding of a data response, picked from the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Format {
+    Json,
+    /// Compact binary encodings for constrained clients.
+    Cbor,
+    MessagePack,
+}
+
+impl Format {
+    /// In order of preference when the client accepts several equally.
+    const ALL: [Self; 3] = [Self::Json, Self::Cbor, Self::MessagePack];
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Cbor => "application/cbor",
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+
+    /// Picks the format the client likes best, JSON when it does not say. `None` when it accepts none of them.
+    fn negotiate(accept: &str) -> Option<Self> {
+        if accept.trim().is_empty() {
+            return Some(Self::Json);
+        }
+
+        let mut best: Option<(Self, f64)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = match media_type.as_str() {
+                "*/*" | "application/*" => Self::Json,
+                media_type => match Self::ALL.into_iter().find(|f| f.content_type() == media_type) {
+                    Some(format) => format,
+                    // Also seen in the wild for MessagePack
+                    None if media_type == "application/x-msgpack" => Self::MessagePack,
+                    None => continue,
+                },
+            };
+            if best.is_none_or(|(_, best)| quality > best) {
+                best = Some((format, quality));
+            }
+        }
+
+        best.map(|(format, _)| format)
+    }
+
+    /// Serializes `value` into a response of this format.
+    pub fn respond<T: Serialize>(self, value: &T) -> Result<Response, ApiError> {
+        let body = match self {
+            Self::Json => serde_json::to_vec(value).map_err(anyhow::Error::from),
+            Self::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(value, &mut body)
+                    .map(|()| body)
+                    .map_err(anyhow::Error::from)
+            }
+            // Named fields, so that the structure matches the JSON one
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(anyhow::Error::from),
+        }
+        .map_err(|e| ApiError::internal(&format!("Failed to encode a {} response", self.content_type()), e))?;
+
+        Ok((
+            [
+                (header::CONTENT_TYPE, HeaderValue::from_static(self.content_type())),
+                (header::VARY, HeaderValue::from_static("accept")),
+            ],
+            body,
+        )
+            .into_response())
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Self::negotiate(&accept).ok_or(ApiError::NotAcceptable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_without_an_accept_header() {
+        assert_eq!(Format::negotiate(""), Some(Format::Json));
+        assert_eq!(Format::negotiate("*/*"), Some(Format::Json));
+    }
+
+    #[test]
+    fn binary_encodings_when_asked_for() {
+        assert_eq!(Format::negotiate("application/cbor"), Some(Format::Cbor));
+        assert_eq!(Format::negotiate("application/msgpack"), Some(Format::MessagePack));
+        assert_eq!(Format::negotiate("application/x-msgpack"), Some(Format::MessagePack));
+    }
+
+    #[test]
+    fn the_most_preferred_type() {
+        assert_eq!(
+            Format::negotiate("application/json;q=0.5, application/cbor"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(
+            Format::negotiate("application/cbor;q=0.2, application/msgpack;q=0.9, */*;q=0.1"),
+            Some(Format::MessagePack)
+        );
+        // The first of equally preferred types
+        assert_eq!(
+            Format::negotiate("application/msgpack, application/cbor"),
+            Some(Format::MessagePack)
+        );
+    }
+
+    #[test]
+    fn none_of_the_supported_types() {
+        assert_eq!(Format::negotiate("text/html"), None);
+        assert_eq!(Format::negotiate("application/json;q=0"), None);
+    }
+}
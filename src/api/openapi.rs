@@ -0,0 +1,93 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use axum::{Router, routing::get};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self, ContentBuilder, Ref, ResponseBuilder,
+        security::{Http, HttpAuthScheme, SecurityRequirement, SecurityScheme},
+    },
+};
+
+use super::{extract::Json, probes, v1};
+use crate::state::AppState;
+
+/// Where the description is served. Left out of authentication along with the UI, as neither holds any data.
+pub(super) const SPEC_PATH: &str = "/openapi.json";
+#[cfg(feature = "docs")]
+pub(super) const DOCS_PATH: &str = "/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Cobitis", description = "Aquarium tank monitor", license(name = "MIT")),
+    paths(probes::get_healthz, probes::get_readyz)
+)]
+struct ApiDoc;
+
+/// Puts the description together. The versions are nested in by hand, as what they contain depends on features.
+fn spec() -> openapi::OpenApi {
+    let mut spec = ApiDoc::openapi().nest(v1::PREFIX, v1::doc());
+    Common.modify(&mut spec);
+
+    spec
+}
+
+/// Adds what every endpoint has in common, rather than repeating it on each of them.
+struct Common;
+
+impl Modify for Common {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        openapi.security = Some(vec![SecurityRequirement::new("bearer", Vec::<String>::new())]);
+
+        let error = |description: &str| {
+            ResponseBuilder::new()
+                .description(description)
+                .content(
+                    "application/json",
+                    ContentBuilder::new()
+                        .schema(Some(Ref::from_schema_name("Error")))
+                        .build(),
+                )
+                .build()
+        };
+        for (path, item) in &mut openapi.paths.paths {
+            let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete];
+            for operation in operations.into_iter().flatten() {
+                if super::is_public(path) {
+                    // An empty requirement makes authentication optional.
+                    operation.security = Some(vec![SecurityRequirement::default()]);
+                } else {
+                    operation.responses.responses.insert(
+                        "401".to_owned(),
+                        error("Missing or invalid bearer token, when tokens are configured").into(),
+                    );
+                }
+                operation.responses.responses.insert(
+                    "429".to_owned(),
+                    error("Rate limited, or too many requests at once. See `Retry-After`").into(),
+                );
+            }
+        }
+    }
+}
+
+/// The OpenAPI description of the API, and Swagger UI to browse it when built with the `docs` feature.
+pub(super) fn router() -> Router<AppState> {
+    let router = Router::new().route(SPEC_PATH, get(get_spec));
+    #[cfg(feature = "docs")]
+    let router =
+        router.merge(utoipa_swagger_ui::SwaggerUi::new(DOCS_PATH).config(utoipa_swagger_ui::Config::from(SPEC_PATH)));
+
+    router
+}
+
+async fn get_spec() -> Json<openapi::OpenApi> {
+    Json(spec())
+}
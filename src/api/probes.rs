@@ -0,0 +1,162 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::Utc;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::extract::Json;
+use crate::{health::Health, state::AppState};
+
+pub(super) const HEALTHZ_PATH: &str = "/healthz";
+pub(super) const READYZ_PATH: &str = "/readyz";
+
+/// Liveness and readiness checks for service managers and container runtimes, outside of the versioned API.
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route(HEALTHZ_PATH, get(get_healthz))
+        .route(READYZ_PATH, get(get_readyz))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct Liveness {
+    status: &'static str,
+}
+
+/// Answers as long as the process runs and the runtime gets around to handling requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "probes",
+    responses((status = 200, body = Liveness)),
+)]
+pub(super) async fn get_healthz() -> Json<Liveness> {
+    Json(Liveness { status: "ok" })
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct Readiness {
+    ready: bool,
+    measurements: SourceReadiness,
+    signal: SourceReadiness,
+}
+
+/// Readiness of a single source, judged by when its worker last succeeded.
+#[derive(Debug, Serialize, ToSchema)]
+struct SourceReadiness {
+    ready: bool,
+    /// `None` when the worker has not succeeded yet.
+    last_success_age_secs: Option<f64>,
+    max_age_secs: u64,
+    /// Why the source is not ready, or that it is disabled and so not waited for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+impl SourceReadiness {
+    fn of(health: &Health, enabled: bool, max_age: Duration) -> Self {
+        if !enabled {
+            return Self {
+                ready: true,
+                last_success_age_secs: None,
+                max_age_secs: max_age.as_secs(),
+                reason: Some("Disabled".to_owned()),
+            };
+        }
+
+        let snapshot = health.snapshot();
+        let ready = snapshot.succeeded_within(Utc::now(), max_age);
+        let last_success_age_secs = snapshot.last_success_age();
+        let reason = match last_success_age_secs {
+            _ if ready => None,
+            None => Some("No successful reading yet".to_owned()),
+            Some(age) => Some(format!(
+                "Last successful reading {age:.0}s ago, more than {}s",
+                max_age.as_secs()
+            )),
+        };
+
+        Self {
+            ready,
+            last_success_age_secs,
+            max_age_secs: max_age.as_secs(),
+            reason,
+        }
+    }
+}
+
+/// Ready once both a measurement and a signal reading have succeeded recently enough, unless their worker is disabled.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Ready", body = Readiness),
+        (status = 503, description = "At least one source is stale, as told by its `reason`", body = Readiness),
+    ),
+)]
+pub(super) async fn get_readyz(State(state): State<AppState>) -> Response {
+    let config = &state.config;
+    let max_age = config.api.readiness_max_age();
+    let measurements = SourceReadiness::of(
+        &state.health.measurements,
+        config.measurements.enabled,
+        max_age.unwrap_or_else(|| config.measurements.stale_after()),
+    );
+    // Without a wireless interface, as on an Ethernet-only install, there is no signal to wait for.
+    let signal = SourceReadiness::of(
+        &state.health.signal,
+        config.signal.enabled && !state.signal_polling.is_disabled(),
+        max_age.unwrap_or_else(|| config.signal.stale_after()),
+    );
+    let ready = measurements.ready && signal.ready;
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            measurements,
+            signal,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn ready_without_a_wireless_interface() {
+        let state = AppState::new(Arc::new(Config::default()));
+        state.health.measurements.initialized();
+        state.health.measurements.success();
+        state.health.signal.initialized();
+
+        let response = get_readyz(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        state.signal_polling.disable();
+        let response = get_readyz(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
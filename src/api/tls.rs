@@ -0,0 +1,68 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{path::Path, time::SystemTime};
+
+use anyhow::anyhow;
+use axum_server::tls_rustls::RustlsConfig;
+use logger::log::{error, info};
+use tokio::{
+    fs, select,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::TlsConfig;
+
+/// Loads the certificate and the key, which must be readable and belong together.
+pub(super) async fn load(config: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&config.cert, &config.key)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Failed to load TLS certificate {} with key {}: {e}",
+                config.cert.display(),
+                config.key.display()
+            )
+        })
+}
+
+/// Swaps in a renewed certificate whenever either file changes, until `shutdown`. A renewal that fails to load
+/// leaves the current certificate in place.
+pub(super) async fn watch(config: TlsConfig, rustls: RustlsConfig, shutdown: CancellationToken) {
+    let mut tick = interval(config.reload_interval());
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut loaded = modified(&config).await;
+
+    loop {
+        select! {
+            _ = tick.tick() => {}
+            () = shutdown.cancelled() => return,
+        }
+
+        let current = modified(&config).await;
+        if current == loaded {
+            continue;
+        }
+        match rustls.reload_from_pem_file(&config.cert, &config.key).await {
+            Ok(()) => info!("Reloaded TLS certificate {}", config.cert.display()),
+            // Files being replaced one at a time may not match for a moment, so this is tried again on the next
+            // tick.
+            Err(e) => {
+                error!("Failed to reload TLS certificate {}: {e}", config.cert.display());
+                continue;
+            }
+        }
+        loaded = current;
+    }
+}
+
+async fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    async fn mtime(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).await.and_then(|m| m.modified()).ok()
+    }
+
+    Some((mtime(&config.cert).await?, mtime(&config.key).await?))
+}
@@ -0,0 +1,227 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+#[cfg(feature = "display")]
+use axum::routing::put;
+use axum::{
+    Router,
+    extract::Request,
+    http::{HeaderValue, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+};
+use serde::Serialize;
+use utoipa::{OpenApi, openapi};
+
+#[cfg(feature = "display")]
+use super::{
+    delete_display_message, get_display, get_display_png, post_display_message, post_display_off, post_display_on,
+    put_display_brightness,
+};
+use super::{
+    delete_errors, delete_tds_calibration, extract::Json, get_alerts, get_errors, get_events, get_hardware,
+    get_maintenance, get_measurement_interval, get_measurements, get_measurements_history,
+    get_measurements_history_csv, get_measurements_stats, get_metrics, get_ph_calibration, get_signal,
+    get_signal_interval, get_status, get_system, get_tank_measurements, get_tank_measurements_history,
+    get_tank_measurements_history_csv, get_tank_measurements_stats, get_tanks, get_temperature_calibration,
+    get_thermostat, get_ws, post_alerts_silence, post_flow_reset, post_maintenance_end, post_maintenance_start,
+    post_maintenance_water_change, post_measurements_refresh, post_tds_calibration, put_measurement_interval,
+    put_ph_calibration, put_signal_interval, put_temperature_calibration, put_thermostat,
+};
+use crate::state::AppState;
+
+pub(super) const PREFIX: &str = "/v1";
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    super::get_measurements,
+    super::get_measurements_history,
+    super::get_measurements_history_csv,
+    super::get_measurements_stats,
+    super::post_measurements_refresh,
+    super::get_tanks,
+    super::get_tank_measurements,
+    super::get_tank_measurements_history,
+    super::get_tank_measurements_history_csv,
+    super::get_tank_measurements_stats,
+    super::post_flow_reset,
+    super::get_signal,
+    super::get_system,
+    super::get_metrics,
+    super::get_alerts,
+    super::post_alerts_silence,
+    super::get_status,
+    super::get_hardware,
+    super::get_errors,
+    super::delete_errors,
+    super::get_ws,
+    super::get_events,
+    super::get_measurement_interval,
+    super::put_measurement_interval,
+    super::get_signal_interval,
+    super::put_signal_interval,
+    super::get_ph_calibration,
+    super::put_ph_calibration,
+    super::post_tds_calibration,
+    super::delete_tds_calibration,
+    super::get_temperature_calibration,
+    super::put_temperature_calibration,
+    super::get_thermostat,
+    super::put_thermostat,
+    super::post_maintenance_start,
+    super::post_maintenance_end,
+    super::get_maintenance,
+    super::post_maintenance_water_change,
+))]
+struct Doc;
+
+/// The display endpoints, which are only there when built with the `display` feature.
+#[cfg(feature = "display")]
+#[derive(OpenApi)]
+#[openapi(paths(
+    super::get_display,
+    super::put_display_brightness,
+    super::get_display_png,
+    super::post_display_on,
+    super::post_display_off,
+    super::post_display_message,
+    super::delete_display_message,
+))]
+struct DisplayDoc;
+
+/// Describes the endpoints of version 1, relative to where they are mounted.
+pub(super) fn doc() -> openapi::OpenApi {
+    let doc = Doc::openapi();
+    #[cfg(feature = "display")]
+    let doc = doc.merge_from(DisplayDoc::openapi());
+
+    doc
+}
+
+/// Version 1 under its prefix, along with the unprefixed paths it used to be served at.
+///
+/// Further versions get their own prefix, so that they can be shaped differently without their routes colliding.
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .nest(PREFIX, routes().route("/", get(get_index)))
+        .merge(routes().layer(middleware::from_fn(deprecated)))
+}
+
+/// The endpoints of version 1, relative to wherever they are mounted.
+fn routes() -> Router<AppState> {
+    let router = Router::new()
+        .route("/measurements", get(get_measurements))
+        .route("/measurements/history", get(get_measurements_history))
+        .route("/measurements/history.csv", get(get_measurements_history_csv))
+        .route("/measurements/stats", get(get_measurements_stats))
+        .route("/measurements/refresh", post(post_measurements_refresh))
+        .route("/tanks", get(get_tanks))
+        .route("/tanks/{name}/measurements", get(get_tank_measurements))
+        .route("/tanks/{name}/measurements/history", get(get_tank_measurements_history))
+        .route(
+            "/tanks/{name}/measurements/history.csv",
+            get(get_tank_measurements_history_csv),
+        )
+        .route("/tanks/{name}/measurements/stats", get(get_tank_measurements_stats))
+        .route("/flow/reset", post(post_flow_reset))
+        .route("/signal", get(get_signal))
+        .route("/system", get(get_system))
+        .route("/metrics", get(get_metrics))
+        .route("/alerts", get(get_alerts))
+        .route("/alerts/silence", post(post_alerts_silence))
+        .route("/status", get(get_status))
+        .route("/hardware", get(get_hardware))
+        .route("/errors", get(get_errors).delete(delete_errors))
+        .route("/ws", get(get_ws))
+        .route("/events", get(get_events))
+        .route(
+            "/config/measurement-interval",
+            get(get_measurement_interval).put(put_measurement_interval),
+        )
+        .route(
+            "/config/signal-interval",
+            get(get_signal_interval).put(put_signal_interval),
+        )
+        .route("/calibrate/ph", get(get_ph_calibration).put(put_ph_calibration))
+        .route(
+            "/calibrate/tds",
+            post(post_tds_calibration).delete(delete_tds_calibration),
+        )
+        .route(
+            "/calibrate/temperature",
+            get(get_temperature_calibration).put(put_temperature_calibration),
+        )
+        .route("/thermostat", get(get_thermostat).put(put_thermostat))
+        .route("/maintenance/start", post(post_maintenance_start))
+        .route("/maintenance/end", post(post_maintenance_end))
+        .route("/maintenance", get(get_maintenance))
+        .route("/maintenance/water-change", post(post_maintenance_water_change));
+    #[cfg(feature = "display")]
+    let router = router
+        .route("/display", get(get_display))
+        .route("/display/brightness", put(put_display_brightness))
+        .route("/display.png", get(get_display_png))
+        .route("/display/on", post(post_display_on))
+        .route("/display/off", post(post_display_off))
+        .route(
+            "/display/message",
+            post(post_display_message).delete(delete_display_message),
+        );
+
+    router
+}
+
+#[derive(Serialize)]
+struct Index {
+    version: &'static str,
+    endpoints: Vec<Endpoint>,
+}
+
+#[derive(Serialize)]
+struct Endpoint {
+    path: String,
+    methods: Vec<&'static str>,
+}
+
+/// Lists the endpoints from the OpenAPI description, so that the two never disagree.
+async fn get_index() -> Json<Index> {
+    let endpoints = doc()
+        .paths
+        .paths
+        .into_iter()
+        .map(|(path, item)| Endpoint {
+            path: format!("{PREFIX}{path}"),
+            methods: [
+                ("GET", item.get.is_some()),
+                ("PUT", item.put.is_some()),
+                ("POST", item.post.is_some()),
+                ("DELETE", item.delete.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(method, present)| present.then_some(method))
+            .collect(),
+        })
+        .collect();
+
+    Json(Index {
+        version: env!("CARGO_PKG_VERSION"),
+        endpoints,
+    })
+}
+
+/// Marks responses from the unprefixed paths as deprecated, pointing at their `/v1` counterpart.
+async fn deprecated(request: Request, next: Next) -> Response {
+    let successor = format!("<{PREFIX}{}>; rel=\"successor-version\"", request.uri().path());
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(value) = HeaderValue::from_str(&successor) {
+        response.headers_mut().insert(header::LINK, value);
+    }
+
+    response
+}
@@ -0,0 +1,142 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{future, thread, time::Duration};
+
+use anyhow::anyhow;
+use logger::log::{error, info};
+use tokio::{
+    select,
+    sync::mpsc,
+    task,
+    time::{Instant, sleep_until},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{gpio, state::AppState};
+
+/// What a press of the button turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Press {
+    Short,
+    Long,
+}
+
+/// Where the button is, once it has settled.
+#[derive(Debug, Clone, Copy)]
+enum Button {
+    Released,
+    /// Pressed since then, not long enough yet to be a long press.
+    Pressed(Instant),
+    /// Held past a long press, which has been acted on already, until it is released.
+    Held,
+}
+
+impl Button {
+    /// Returns the state once the button has settled pressed or not at `now`, along with the press it ended, if any.
+    fn settle(self, pressed: bool, now: Instant) -> (Self, Option<Press>) {
+        match (self, pressed) {
+            (Self::Released, true) => (Self::Pressed(now), None),
+            (Self::Pressed(_), false) => (Self::Released, Some(Press::Short)),
+            (Self::Held, false) => (Self::Released, None),
+            (state, _) => (state, None),
+        }
+    }
+
+    /// Returns when the press in progress becomes a long one, if there is one.
+    fn long_press_at(self, long_press: Duration) -> Option<Instant> {
+        match self {
+            Self::Pressed(since) => Some(since + long_press),
+            Self::Released | Self::Held => None,
+        }
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(button) = &config.button else {
+        return Ok(());
+    };
+    // There is nothing to press when simulated.
+    if config.simulate {
+        return Ok(());
+    }
+
+    let mut edges = {
+        let c = button.clone();
+        task::spawn_blocking(move || gpio::Edges::open(&c.gpio_chip, c.pin, c.active_low, c.pull_up, "cobitis-button"))
+            .await??
+    };
+
+    // A plain thread for the same reason as the flow sensor has one: a wait for the next edge cannot be cancelled.
+    let (edges_tx, mut edges_rx) = mpsc::unbounded_channel();
+    thread::Builder::new().name("button".to_owned()).spawn(move || {
+        loop {
+            let edge = edges.wait();
+            let failed = edge.is_err();
+            if edges_tx.send(edge).is_err() || failed {
+                return;
+            }
+        }
+    })?;
+
+    let mut position = Button::Released;
+    // A level the line changed to, and since when, until it has stayed there for the debounce time.
+    let mut pending: Option<(bool, Instant)> = None;
+
+    loop {
+        let settle_at = pending.map(|(_, since)| since + button.debounce());
+        let long_press_at = position.long_press_at(button.long_press());
+
+        // Edges are taken as they come rather than on a tick, so that the display responds to a press right away.
+        let press = select! {
+            edge = edges_rx.recv() => match edge {
+                Some(Ok(active)) => {
+                    pending = Some((active, Instant::now()));
+                    None
+                }
+                Some(Err(e)) => return Err(e.context("Failed to read the button")),
+                None => return Err(anyhow!("Button reading stopped")),
+            },
+            () = sleep_until_some(settle_at) => pending.take().and_then(|(active, _)| {
+                let press;
+                (position, press) = position.settle(active, Instant::now());
+                press
+            }),
+            () = sleep_until_some(long_press_at) => {
+                position = Button::Held;
+                Some(Press::Long)
+            }
+            () = shutdown.cancelled() => return Ok(()),
+        };
+
+        match press {
+            Some(Press::Short) => {
+                info!("Button pressed");
+                #[cfg(feature = "display")]
+                state.display.show_next_page(&config.display, button.wake());
+            }
+            Some(Press::Long) if state.maintenance.is_active() => {
+                info!("Button held, ending maintenance");
+                state.maintenance.end();
+            }
+            Some(Press::Long) => {
+                info!("Button held, starting maintenance");
+                if let Err(e) = state.maintenance.start(button.maintenance()) {
+                    error!("Failed to start maintenance: {e:?}");
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// Sleeps until `at`, or forever without it.
+async fn sleep_until_some(at: Option<Instant>) {
+    match at {
+        Some(at) => sleep_until(at).await,
+        None => future::pending().await,
+    }
+}
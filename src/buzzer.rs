@@ -0,0 +1,108 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use logger::log::{error, info};
+use tokio::{
+    select, task,
+    time::{MissedTickBehavior, interval, sleep},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::BuzzerConfig, gpio, state::AppState};
+
+/// Whether the buzzer has been muted through the API.
+pub(crate) struct Buzzer {
+    silenced_until: Mutex<Option<Instant>>,
+}
+
+impl Buzzer {
+    pub fn new() -> Self {
+        Self {
+            silenced_until: Mutex::new(None),
+        }
+    }
+
+    /// Mutes the buzzer for `duration`, leaving the alerts themselves active.
+    pub fn silence(&self, duration: Duration) {
+        *self.silenced_until.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now() + duration);
+        info!("Buzzer silenced for {}s", duration.as_secs());
+    }
+
+    fn is_silenced(&self) -> bool {
+        self.silenced_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some_and(|until| Instant::now() < until)
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.buzzer else {
+        return Ok(());
+    };
+
+    // Without the pin there is nothing to retry, so the buzzer is left disabled.
+    let opened = {
+        let config = config.clone();
+        task::spawn_blocking(move || gpio::Output::open(&config.gpio_chip, config.pin, false, "cobitis-buzzer"))
+            .await?
+    };
+    let output = match opened {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Buzzer disabled: {e:?}");
+            return Ok(());
+        }
+    };
+
+    let mut tick = interval(Duration::from_secs(1));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut last_burst: Option<Instant> = None;
+
+    loop {
+        select! {
+            _ = tick.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        let sounding = state.alerts.active().iter().any(|alert| alert.critical) && !state.buzzer.is_silenced();
+        if !sounding {
+            last_burst = None;
+            continue;
+        }
+        if last_burst.is_some_and(|at| at.elapsed() < config.repeat()) {
+            continue;
+        }
+
+        last_burst = Some(Instant::now());
+        select! {
+            result = burst(&output, config) => if let Err(e) = result {
+                error!("Failed to sound the buzzer: {e:?}");
+            },
+            () = shutdown.cancelled() => break,
+        }
+    }
+
+    let _ = output.set(false);
+
+    Ok(())
+}
+
+async fn burst(output: &gpio::Output, config: &BuzzerConfig) -> anyhow::Result<()> {
+    for _ in 0..config.beeps {
+        output.set(true)?;
+        sleep(config.beep()).await;
+        output.set(false)?;
+        sleep(config.beep()).await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use anyhow::{Context as _, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+const FILE_NAME: &str = "calibration.toml";
+
+/// Calibration values set at runtime, which take precedence over the config file and survive restarts.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Calibration {
+    pub ph: Option<LinearCalibration>,
+    /// Multiplier applied to the computed TDS to correct for the probe.
+    pub tds_factor: Option<f64>,
+    /// Degrees Celsius added to the thermal sensor reading.
+    pub temperature_offset: Option<f64>,
+}
+
+impl Calibration {
+    pub fn tds_factor(&self) -> f64 {
+        self.tds_factor.unwrap_or(1.0)
+    }
+}
+
+/// Maps a probe voltage to a value as `slope * voltage + offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LinearCalibration {
+    pub slope: f64,
+    pub offset: f64,
+}
+
+impl LinearCalibration {
+    /// Derives the calibration from two `(voltage, value)` reference points.
+    pub fn from_points(a: (f64, f64), b: (f64, f64)) -> anyhow::Result<Self> {
+        let slope = (b.1 - a.1) / (b.0 - a.0);
+        if !slope.is_finite() {
+            return Err(anyhow!("Calibration points must have different voltages"));
+        }
+
+        Ok(Self {
+            slope,
+            offset: a.1 - slope * a.0,
+        })
+    }
+
+    pub fn apply(&self, voltage: f64) -> f64 {
+        self.slope * voltage + self.offset
+    }
+}
+
+fn path(config: &Config) -> PathBuf {
+    config.state_dir.join(FILE_NAME)
+}
+
+/// The calibration the readings are corrected with, which the API changes.
+pub(crate) struct Store {
+    current: RwLock<Calibration>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self {
+            current: RwLock::new(Calibration::default()),
+        }
+    }
+
+    /// Loads the persisted calibration, if any. Must be called once at startup.
+    pub fn load(&self, config: &Config) -> anyhow::Result<()> {
+        let path = path(config);
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let raw = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let calibration =
+            toml::from_str(&raw).with_context(|| format!("Malformed calibration file {}", path.display()))?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = calibration;
+
+        Ok(())
+    }
+
+    pub fn get(&self) -> Calibration {
+        *self.current.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Modifies the calibration and writes it to disk.
+    pub fn update(&self, config: &Config, f: impl FnOnce(&mut Calibration)) -> anyhow::Result<Calibration> {
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        let mut calibration = *current;
+        f(&mut calibration);
+        save(&path(config), &calibration)?;
+        *current = calibration;
+
+        Ok(calibration)
+    }
+}
+
+fn save(path: &Path, calibration: &Calibration) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Write to a temporary file first so that a power cut never leaves a truncated file behind.
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, toml::to_string(calibration)?).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
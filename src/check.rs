@@ -0,0 +1,77 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::anyhow;
+use linux_embedded_hal::I2cdev;
+use tokio::task;
+
+#[cfg(feature = "display")]
+use crate::display;
+use crate::{config::Config, measurements};
+
+/// What probing a single piece of hardware came to, with what it read on success.
+pub(crate) struct Outcome {
+    pub name: String,
+    pub result: anyhow::Result<String>,
+}
+
+impl Outcome {
+    pub fn new(name: impl Into<String>, result: anyhow::Result<String>) -> Self {
+        Self {
+            name: name.into(),
+            result,
+        }
+    }
+}
+
+/// Probes the hardware the config asks for, printing a line for each piece. Fails when any of them did.
+///
+/// The real hardware is probed even when the config says to simulate it, since checking the wiring is the point.
+pub(crate) async fn run(config: &Config) -> anyhow::Result<()> {
+    let config = config.clone();
+    let outcomes = task::spawn_blocking(move || probe(&config)).await?;
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(detail) if detail.is_empty() => println!("PASS  {}", outcome.name),
+            Ok(detail) => println!("PASS  {}: {detail}", outcome.name),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  {}: {e:#}", outcome.name);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} checks failed", outcomes.len()));
+    }
+    println!("All {} checks passed", outcomes.len());
+
+    Ok(())
+}
+
+fn probe(config: &Config) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+
+    if config.measurements.enabled {
+        let i2c_bus = &config.measurements.i2c_bus;
+        outcomes.push(Outcome::new(
+            format!("I2C bus {}", i2c_bus.display()),
+            I2cdev::new(i2c_bus).map(|_| String::new()).map_err(Into::into),
+        ));
+        outcomes.extend(measurements::probe(&config.measurements));
+    }
+
+    #[cfg(feature = "display")]
+    if config.display.enabled {
+        outcomes.push(Outcome::new(
+            format!("Display on {}", config.display.i2c_bus.display()),
+            display::probe(&config.display).map(|()| String::new()),
+        ));
+    }
+
+    outcomes
+}
@@ -0,0 +1,40 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::config::LogLevel;
+
+/// Aquarium monitor: water temperature, TDS, and more, served over HTTP and on an OLED panel.
+///
+/// Flags take precedence over the environment, which takes precedence over the config file.
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    /// Config file to read instead of /etc/cobitis/config.toml.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Addresses to serve the API on instead of `api.listen`. Repeat the flag or separate them with commas.
+    #[arg(long, value_name = "ADDR", value_delimiter = ',')]
+    pub listen: Vec<SocketAddr>,
+    /// Level below which messages are dropped, instead of `log_level` and `RUST_LOG`.
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+    /// Makes up readings and draws the display into a file, for development without the hardware.
+    #[arg(long)]
+    pub simulate: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Probes the configured hardware once and reports what answered, without starting the service.
+    ///
+    /// Exits with a non-zero status when anything failed, for use from installer scripts.
+    Check,
+}
@@ -0,0 +1,2356 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Write as _},
+    fs, iter,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context as _, anyhow};
+use chrono::{NaiveDate, NaiveTime};
+#[cfg(feature = "display")]
+use chrono::{NaiveDateTime, TimeDelta};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use lettre::message::Mailbox;
+use reqwest::{
+    Url,
+    header::{HeaderName, HeaderValue},
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+
+use crate::cli::Cli;
+
+const DEFAULT_PATH: &str = "/etc/cobitis/config.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Directory for state that must survive restarts, such as calibration.
+    pub state_dir: PathBuf,
+    /// Overridden by the `COBITIS_LOG_FORMAT` environment variable.
+    pub log_format: LogFormat,
+    /// Level below which messages are dropped. Overridden by `RUST_LOG`, and by the `--log-level` flag.
+    pub log_level: Option<LogLevel>,
+    /// How many recent errors and warnings to keep for `GET /errors`, or 0 to keep none.
+    pub recent_errors: usize,
+    /// IANA name of the zone the display clock and the daily statistics go by, such as `Asia/Tokyo`. That of the
+    /// system when unset. Timestamps served and stored are in UTC either way.
+    #[serde(deserialize_with = "timezone")]
+    pub timezone: Option<Tz>,
+    /// Makes up readings and draws the display into a file, for development without the hardware. Also set by the
+    /// `--simulate` flag.
+    pub simulate: bool,
+    pub api: ApiConfig,
+    pub measurements: MeasurementsConfig,
+    pub signal: SignalConfig,
+    pub display: DisplayConfig,
+    pub system: SystemConfig,
+    pub mqtt: Option<MqttConfig>,
+    pub storage: Option<StorageConfig>,
+    pub readings_log: Option<ReadingsLogConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub alerts: Vec<AlertRule>,
+    pub notifications: NotificationsConfig,
+    pub buzzer: Option<BuzzerConfig>,
+    pub thermostat: Option<ThermostatConfig>,
+    pub water_level: Option<WaterLevelConfig>,
+    pub flow: Option<FlowConfig>,
+    pub button: Option<ButtonConfig>,
+    pub water_change: Option<WaterChangeConfig>,
+    pub heartbeat: Option<HeartbeatConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            state_dir: "/var/lib/cobitis".into(),
+            log_format: LogFormat::default(),
+            log_level: None,
+            recent_errors: 100,
+            timezone: None,
+            simulate: false,
+            api: ApiConfig::default(),
+            measurements: MeasurementsConfig::default(),
+            signal: SignalConfig::default(),
+            display: DisplayConfig::default(),
+            system: SystemConfig::default(),
+            mqtt: None,
+            storage: None,
+            readings_log: None,
+            webhook: None,
+            alerts: Vec::new(),
+            notifications: NotificationsConfig::default(),
+            buzzer: None,
+            thermostat: None,
+            water_level: None,
+            flow: None,
+            button: None,
+            water_change: None,
+            heartbeat: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ApiConfig {
+    /// Addresses to serve on, either a single one or a list, such as `["[::]:8888"]` for dual-stack. Overridden by
+    /// the comma-separated `COBITIS_LISTEN` environment variable.
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen: Vec<SocketAddr>,
+    /// Unix domain socket to serve on as well, such as `/run/cobitis/api.sock`. Set `listen = []` to serve on it
+    /// alone.
+    pub socket: Option<PathBuf>,
+    /// Permissions of the socket, such as `0o660`.
+    pub socket_mode: u32,
+    /// HTTPS, served alongside the plain HTTP listeners.
+    pub tls: Option<TlsConfig>,
+    /// Advertises the plain HTTP API on the local network over mDNS.
+    pub mdns: Option<MdnsConfig>,
+    /// Logs every request handled. When off, requests are still logged at the debug level.
+    pub access_log: bool,
+    /// Paths whose successful requests are logged at the debug level only, such as those a dashboard polls.
+    pub access_log_quiet_paths: Vec<String>,
+    /// Requests per second allowed for each client address, with bursts of up to `rate_limit_burst`. Unlimited when
+    /// unset.
+    pub rate_limit_per_sec: Option<f64>,
+    pub rate_limit_burst: u32,
+    /// Requests open at once across all clients, counting WebSockets, event streams and downloads until they end.
+    /// Unlimited when unset.
+    pub max_open_requests: Option<usize>,
+    /// Bearer tokens accepted in the `Authorization` header. The API is open to anyone when empty.
+    pub tokens: Vec<String>,
+    /// Requests that need a token when `tokens` is set.
+    pub auth: ApiAuth,
+    /// Origins allowed to call the API from a browser, or `["*"]` for any. No CORS headers are sent when empty.
+    pub cors_origins: Vec<String>,
+    /// How recently both the measurement and the signal worker must have succeeded for `/readyz` to report ready.
+    /// Each source's `stale_after_secs` when unset.
+    pub readiness_max_age_secs: Option<u64>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 8888))],
+            socket: None,
+            socket_mode: 0o660,
+            tls: None,
+            mdns: None,
+            access_log: true,
+            access_log_quiet_paths: Vec::new(),
+            rate_limit_per_sec: None,
+            rate_limit_burst: 20,
+            max_open_requests: None,
+            tokens: Vec::new(),
+            auth: ApiAuth::default(),
+            cors_origins: Vec::new(),
+            readiness_max_age_secs: None,
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn readiness_max_age(&self) -> Option<Duration> {
+        self.readiness_max_age_secs.map(Duration::from_secs)
+    }
+
+    /// Returns the address advertised over mDNS, the first in `listen` that is reachable from other hosts.
+    pub fn mdns_listen(&self) -> Option<SocketAddr> {
+        self.listen.iter().copied().find(|addr| !addr.ip().is_loopback())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TlsConfig {
+    /// PEM file with the certificate, followed by any intermediate ones.
+    pub cert: PathBuf,
+    /// PEM file with the private key.
+    pub key: PathBuf,
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen: Vec<SocketAddr>,
+    /// How often the files are checked for a renewed certificate.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: PathBuf::new(),
+            key: PathBuf::new(),
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 8443))],
+            reload_interval_secs: 60,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn reload_interval(&self) -> Duration {
+        Duration::from_secs(self.reload_interval_secs)
+    }
+}
+
+/// Announces the API as `_cobitis._tcp` and `_http._tcp`, with the version and the default tank in the TXT record.
+///
+/// Runs its own responder, which shares the mDNS port with avahi-daemon when that is running too. Leave this out to
+/// advertise through avahi-daemon instead.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MdnsConfig {
+    /// Instance name shown when browsing. Defaults to `Cobitis <hostname>`.
+    pub instance: Option<String>,
+    /// Host name answered for under `.local`. Defaults to `cobitis-<hostname>`, so as not to clash with avahi-daemon
+    /// answering for `<hostname>.local`.
+    pub host_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ApiAuth {
+    /// Every request.
+    #[default]
+    All,
+    /// Only requests that change something, leaving the readings open to anyone.
+    Mutating,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MeasurementsConfig {
+    /// Whether to run the worker at all.
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub i2c_bus: PathBuf,
+    pub w1_devices: PathBuf,
+    /// ID of the DS18B20 to read, e.g. `28-0316a279xxxx`. The first one found is used when unset.
+    pub temperature_sensor: Option<String>,
+    pub history_capacity: usize,
+    /// Measurements older than this are shown along with their age, and older than `stale_after_secs`, as missing.
+    pub fresh_for_secs: u64,
+    pub stale_after_secs: u64,
+    /// Degrees Celsius added to the thermal sensor reading. A calibration made through the API takes precedence.
+    pub temperature_offset: f64,
+    /// Number of ADC conversions averaged into one TDS reading.
+    pub tds_samples: usize,
+    pub tds_sample_spacing_ms: u64,
+    /// Includes the filtered probe voltage in the measurements as `tds_voltage`.
+    pub expose_tds_voltage: bool,
+    /// Includes the values the measurements were worked out from, such as raw ADC counts, in every response of the
+    /// latest measurements, as `debug=1` does.
+    pub expose_debug: bool,
+    /// Factor converting the conductivity in µS/cm into TDS in ppm: 0.5 for the NaCl scale, 0.64 for the 442 scale,
+    /// or 0.7 for the KCl scale.
+    pub tds_factor: f64,
+    pub adc: AdcConfig,
+    /// pH probe on the `ph_channel` of the ADC. Disabled when absent.
+    pub ph: Option<PhConfig>,
+    pub spike_filter: SpikeFilterConfig,
+    pub smoothing: SmoothingConfig,
+    pub trend: TrendConfig,
+    /// Tanks read by this one process, each with a thermal sensor and a TDS probe of its own. When empty, there is a
+    /// single tank named `main`, read from `temperature_sensor` and `adc.tds_channel`.
+    pub tanks: Vec<TankConfig>,
+    /// Name of the tank served at `/measurements`, which the pH probe, flow sensor, float switch, auxiliary inputs,
+    /// thermostat and calibrations belong to. The first tank when unset.
+    pub default_tank: Option<String>,
+}
+
+impl Default for MeasurementsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 10,
+            i2c_bus: "/dev/i2c-1".into(),
+            w1_devices: "/sys/bus/w1/devices".into(),
+            temperature_sensor: None,
+            // 24 hours at the default interval
+            history_capacity: 24 * 60 * 6,
+            fresh_for_secs: 20,
+            stale_after_secs: 60,
+            temperature_offset: 0.0,
+            tds_samples: 10,
+            tds_sample_spacing_ms: 5,
+            expose_tds_voltage: false,
+            expose_debug: false,
+            tds_factor: 0.5,
+            adc: AdcConfig::default(),
+            ph: None,
+            spike_filter: SpikeFilterConfig::default(),
+            smoothing: SmoothingConfig::default(),
+            trend: TrendConfig::default(),
+            tanks: Vec::new(),
+            default_tank: None,
+        }
+    }
+}
+
+impl MeasurementsConfig {
+    /// Name of the tank there is when none are configured.
+    pub const IMPLICIT_TANK: &str = "main";
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn fresh_for(&self) -> Duration {
+        Duration::from_secs(self.fresh_for_secs)
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        Duration::from_secs(self.stale_after_secs)
+    }
+
+    /// Returns the configured tanks, or the implicit one when there are none.
+    pub fn tanks(&self) -> Vec<TankConfig> {
+        if !self.tanks.is_empty() {
+            return self.tanks.clone();
+        }
+
+        vec![TankConfig {
+            name: Self::IMPLICIT_TANK.to_owned(),
+            temperature_sensor: self.temperature_sensor.clone(),
+            tds_channel: self.adc.tds_channel,
+        }]
+    }
+
+    /// Returns the index of the default tank in `tanks()`.
+    pub fn default_tank_index(&self) -> usize {
+        self.default_tank
+            .as_ref()
+            .and_then(|name| self.tanks.iter().position(|tank| &tank.name == name))
+            .unwrap_or(0)
+    }
+
+    /// Time the ADC conversions of a single measurement take at least, with the spacing between TDS samples.
+    pub fn adc_burst_time(&self) -> Duration {
+        let tanks = self.tanks.len().max(1);
+        let conversions = self.tds_samples * tanks + usize::from(self.ph.is_some()) + self.adc.aux.len();
+        let spacing_ms = self.tds_sample_spacing_ms as f64 * (self.tds_samples.saturating_sub(1) * tanks) as f64;
+
+        self.adc.conversion_time().mul_f64(conversions as f64) + Duration::from_secs_f64(spacing_ms / 1000.0)
+    }
+}
+
+/// The ADC the TDS and pH probes are wired to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AdcConfig {
+    pub chip: AdcChip,
+    /// I2C address, from 0x48 to 0x4b as the ADDR pin is tied to GND, VDD, SDA or SCL.
+    pub address: u8,
+    /// Full-scale range in volts: 6.144, 4.096, 2.048, 1.024, 0.512 or 0.256.
+    pub range: f64,
+    /// Conversions per second: 8, 16, 32, 64, 128, 250, 475 or 860 on an ADS1115, and 128, 250, 490, 920, 1600,
+    /// 2400 or 3300 on an ADS1015.
+    pub data_rate: u16,
+    pub mode: AdcMode,
+    pub tds_channel: AdcChannel,
+    pub ph_channel: AdcChannel,
+    /// Highest voltage the TDS board puts out, which the range must cover.
+    pub tds_max_voltage: f64,
+    /// Other inputs read along with the probes, reported under `aux` in the measurements.
+    pub aux: Vec<AuxChannelConfig>,
+}
+
+impl Default for AdcConfig {
+    fn default() -> Self {
+        Self {
+            chip: AdcChip::default(),
+            address: 0x48,
+            range: 4.096,
+            data_rate: 128,
+            mode: AdcMode::default(),
+            tds_channel: AdcChannel::A0,
+            ph_channel: AdcChannel::A1,
+            tds_max_voltage: 2.3,
+            aux: Vec::new(),
+        }
+    }
+}
+
+impl AdcConfig {
+    pub const RANGES: [f64; 6] = [6.144, 4.096, 2.048, 1.024, 0.512, 0.256];
+
+    /// Time a single conversion takes at the configured data rate.
+    pub fn conversion_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / f64::from(self.data_rate.max(1)))
+    }
+}
+
+/// A tank with a thermal sensor and a TDS probe of its own.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TankConfig {
+    /// Shown on the display and used in paths, topics and labels. Letters, digits, `_` and `-` only.
+    pub name: String,
+    /// ID of the DS18B20 in the tank. Required when there is more than one tank.
+    #[serde(default)]
+    pub temperature_sensor: Option<String>,
+    pub tds_channel: AdcChannel,
+}
+
+/// An input of the ADC with something other than a probe on it, such as a photoresistor divider.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AuxChannelConfig {
+    /// Key of the value in the measurements and label of the metrics. Letters, digits, `_` and `-` only.
+    pub name: String,
+    pub channel: AdcChannel,
+    /// The value is `scale * voltage + offset`.
+    #[serde(default = "AuxChannelConfig::default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl AuxChannelConfig {
+    fn default_scale() -> f64 {
+        1.0
+    }
+}
+
+/// The ADS1015 is the 12-bit sibling of the 16-bit ADS1115, on the same breakouts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AdcChip {
+    #[default]
+    Ads1115,
+    Ads1015,
+}
+
+impl AdcChip {
+    pub fn data_rates(self) -> &'static [u16] {
+        match self {
+            Self::Ads1115 => &[8, 16, 32, 64, 128, 250, 475, 860],
+            Self::Ads1015 => &[128, 250, 490, 920, 1600, 2400, 3300],
+        }
+    }
+
+    /// The raw value read at the full-scale voltage.
+    #[cfg(feature = "tds")]
+    pub fn max_raw_value(self) -> i16 {
+        match self {
+            Self::Ads1115 => 32767,
+            Self::Ads1015 => 2047,
+        }
+    }
+}
+
+impl fmt::Display for AdcChip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ads1115 => "ADS1115",
+            Self::Ads1015 => "ADS1015",
+        })
+    }
+}
+
+/// How the ADC is made to convert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AdcMode {
+    /// A conversion is started for every read, which then polls the chip over I2C until it is done: at 128 SPS, about
+    /// 8 ms of a busy thread per sample, or 80 ms for the default burst of 10 TDS samples.
+    #[default]
+    OneShot,
+    /// The chip keeps converting the TDS channel, and a read only fetches the latest result, sleeping rather than
+    /// polling until a new one is due. The burst takes as long, but leaves the CPU idle in between. Only used when
+    /// the TDS channel is the only one read, since switching channels would cost a conversion each time anyway.
+    Continuous,
+}
+
+/// A single-ended input of the ADC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) enum AdcChannel {
+    A0,
+    A1,
+    A2,
+    A3,
+}
+
+impl fmt::Display for AdcChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Linear calibration of the pH probe, `pH = slope * voltage + offset`.
+///
+/// A calibration made through the API takes precedence over these values.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PhConfig {
+    pub slope: f64,
+    pub offset: f64,
+}
+
+impl Default for PhConfig {
+    fn default() -> Self {
+        Self {
+            slope: -5.7,
+            offset: 21.34,
+        }
+    }
+}
+
+/// Rejects a value that jumped further from the previous one than the water plausibly can, unless the next reading
+/// confirms it. The previous value is kept in its place, along with the rest of the reading.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SpikeFilterConfig {
+    pub enabled: bool,
+    /// Largest plausible change of the temperature in °C per 10 seconds.
+    pub max_temperature_change: f64,
+    /// Largest plausible change of the TDS in ppm per 10 seconds.
+    pub max_tds_change: f64,
+}
+
+impl Default for SpikeFilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_temperature_change: 2.0,
+            max_tds_change: 50.0,
+        }
+    }
+}
+
+/// Exponential smoothing of the published values, `alpha * value + (1 - alpha) * previous`, where a smaller alpha
+/// smooths more. Each value is left as read when its alpha is unset.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SmoothingConfig {
+    pub temperature_alpha: Option<f64>,
+    /// Applies to the conductivity as well, which the TDS is derived from.
+    pub tds_alpha: Option<f64>,
+}
+
+/// Rate of change of the temperature, worked out from a straight line fitted through the history over a window.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TrendConfig {
+    pub window_mins: u64,
+    /// Readings there must be in the window for a rate, so that a gap in the history does not leave it to a few.
+    pub min_samples: usize,
+}
+
+impl Default for TrendConfig {
+    fn default() -> Self {
+        Self {
+            window_mins: 30,
+            min_samples: 10,
+        }
+    }
+}
+
+impl TrendConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_mins.saturating_mul(60))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SignalConfig {
+    /// Whether to run the worker at all.
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Wireless interface to monitor. The first one found is used when unset.
+    pub interface: Option<String>,
+    /// A signal reading older than this is shown along with its age, and older than `stale_after_secs`, as missing.
+    pub fresh_for_secs: u64,
+    pub stale_after_secs: u64,
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 30,
+            interface: None,
+            fresh_for_secs: 60,
+            stale_after_secs: 120,
+        }
+    }
+}
+
+impl SignalConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn fresh_for(&self) -> Duration {
+        Duration::from_secs(self.fresh_for_secs)
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        Duration::from_secs(self.stale_after_secs)
+    }
+}
+
+/// Vitals of the board itself, such as its CPU temperature and disk usage.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SystemConfig {
+    /// Whether to run the worker at all.
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 30,
+        }
+    }
+}
+
+impl SystemConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DisplayConfig {
+    /// Whether to run the worker at all. Ignored when built without the `display` feature.
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub i2c_bus: PathBuf,
+    pub driver: DisplayDriver,
+    pub size: PanelSize,
+    /// Rotation in degrees, either 0 or 180 for a panel mounted upside down.
+    pub rotation: u16,
+    /// Contrast (0-255) the panel is lit at outside the night window. That of the driver when unset.
+    pub contrast: Option<u8>,
+    /// Daily window during which the panel is dimmed or blanked.
+    pub night: Option<NightConfig>,
+    /// Moves the whole layout by a pixel every `pixel_shift_secs` to spread OLED wear.
+    pub pixel_shift: bool,
+    pub pixel_shift_secs: u64,
+    /// Interface whose IP address is shown. The first one with an address other than loopback is used when unset.
+    pub address_interface: Option<String>,
+    /// Pages shown in turn, each for `page_secs`.
+    pub pages: Vec<PageKind>,
+    pub page_secs: u64,
+    /// How far back the trend page graphs the temperature.
+    pub trend_window_mins: u64,
+    /// Unit temperatures are shown in. Measurements are still taken and stored in Celsius.
+    pub temperature_unit: TemperatureUnit,
+    /// Whether the water is shown as TDS or as conductivity.
+    pub tds_unit: TdsUnit,
+    /// How the header tells the date and time, as a preset or a `strftime` format such as `%a %H:%M`.
+    pub datetime_format: DatetimeFormat,
+    /// Bar under the TDS on the main page telling where it stands in a target range. Not drawn when unset.
+    pub tds_bar: Option<TdsBarConfig>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 1,
+            i2c_bus: "/dev/i2c-1".into(),
+            driver: DisplayDriver::default(),
+            size: PanelSize::default(),
+            rotation: 0,
+            contrast: None,
+            night: None,
+            pixel_shift: false,
+            pixel_shift_secs: 180,
+            address_interface: None,
+            pages: vec![PageKind::Main, PageKind::Range, PageKind::Network],
+            page_secs: 5,
+            trend_window_mins: 120,
+            temperature_unit: TemperatureUnit::default(),
+            tds_unit: TdsUnit::default(),
+            datetime_format: DatetimeFormat::default(),
+            tds_bar: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+pub(crate) struct NightConfig {
+    /// Local time the window starts, as `HH:MM`.
+    #[serde(deserialize_with = "hh_mm")]
+    pub start: NaiveTime,
+    /// Local time the window ends, as `HH:MM`. May be earlier than `start` for a window spanning midnight.
+    #[serde(deserialize_with = "hh_mm")]
+    pub end: NaiveTime,
+    /// Contrast (0-255) during the window. The panel is blanked when unset.
+    pub contrast: Option<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+pub(crate) struct TdsBarConfig {
+    /// TDS in ppm at the left end of the bar, whichever unit the value is shown in.
+    pub min: f64,
+    /// TDS in ppm at the right end of the bar.
+    pub max: f64,
+}
+
+#[cfg(feature = "display")]
+impl NightConfig {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Returns the first start or end of the window after `now`.
+    pub fn next_boundary(&self, now: NaiveDateTime) -> NaiveDateTime {
+        [self.start, self.end]
+            .into_iter()
+            .map(|time| {
+                let today = now.date().and_time(time);
+                if today > now { today } else { today + TimeDelta::days(1) }
+            })
+            .min()
+            .unwrap_or(now)
+    }
+}
+
+/// Accepts either a single value or a list of them.
+fn one_or_many<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+fn timezone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Tz>, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    raw.parse().map(Some).map_err(|_| {
+        serde::de::Error::custom(format!(
+            "unknown timezone {raw:?}, expected an IANA name such as Asia/Tokyo"
+        ))
+    })
+}
+
+fn hh_mm<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&raw, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+fn hh_mm_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|raw| NaiveTime::parse_from_str(&raw, "%H:%M").map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum DatetimeFormat {
+    Preset(DatetimePreset),
+    /// A `strftime` format, which is cut short when it does not fit in the header.
+    Custom(String),
+}
+
+impl Default for DatetimeFormat {
+    fn default() -> Self {
+        Self::Preset(DatetimePreset::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub(crate) enum DatetimePreset {
+    /// `06·01 21:30`
+    #[default]
+    #[serde(rename = "month_first")]
+    MonthFirst,
+    /// `01·06 21:30`
+    #[serde(rename = "day_first")]
+    DayFirst,
+    /// `06·01 9:30pm`
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+impl DatetimeFormat {
+    /// Returns the `strftime` format of the date and time.
+    pub fn datetime(&self) -> &str {
+        match self {
+            Self::Preset(DatetimePreset::MonthFirst) => "%m·%d %H:%M",
+            Self::Preset(DatetimePreset::DayFirst) => "%d·%m %H:%M",
+            Self::Preset(DatetimePreset::TwelveHour) => "%m·%d %-I:%M%P",
+            Self::Custom(format) => format,
+        }
+    }
+
+    /// Returns the `strftime` format of the time, shown in place of the date when the header has other things to tell.
+    /// A custom format is shown whole, as far as it fits.
+    pub fn time(&self) -> &str {
+        match self {
+            Self::Preset(DatetimePreset::MonthFirst | DatetimePreset::DayFirst) => "%H:%M",
+            Self::Preset(DatetimePreset::TwelveHour) => "%-I:%M%P",
+            Self::Custom(format) => format,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PageKind {
+    /// Temperature and TDS, shown for each tank in turn.
+    Main,
+    /// Lowest and highest values since midnight, and the days left until the water change when it is reminded of.
+    Range,
+    /// Wi-Fi network, IP address, and link quality.
+    Network,
+    /// Temperature over the last `trend_window_mins` as a line graph.
+    Trend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Converts a temperature in Celsius into this unit.
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    #[cfg(feature = "display")]
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+}
+
+impl fmt::Display for TemperatureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Celsius => "celsius",
+            Self::Fahrenheit => "fahrenheit",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the values logged as fields of their own, for log collectors.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TdsUnit {
+    /// TDS in ppm.
+    #[default]
+    Ppm,
+    /// Electrical conductivity in µS/cm.
+    Ec,
+}
+
+impl TdsUnit {
+    #[cfg(feature = "display")]
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Ppm => "ppm",
+            Self::Ec => "µS/cm",
+        }
+    }
+}
+
+/// Controller of the OLED panel. Many 1.3" modules are SH1106 rather than SSD1306.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DisplayDriver {
+    #[default]
+    Ssd1306,
+    Sh1106,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub(crate) enum PanelSize {
+    #[default]
+    #[serde(rename = "128x64")]
+    Size128x64,
+    #[serde(rename = "128x32")]
+    Size128x32,
+}
+
+impl DisplayConfig {
+    /// Whether the display worker runs, which takes the `display` feature as well.
+    pub fn is_enabled(&self) -> bool {
+        cfg!(feature = "display") && self.enabled
+    }
+
+    #[cfg(feature = "display")]
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    #[cfg(feature = "display")]
+    pub fn page_dwell(&self) -> Duration {
+        Duration::from_secs(self.page_secs)
+    }
+
+    #[cfg(feature = "display")]
+    pub fn trend_window(&self) -> Duration {
+        Duration::from_secs(self.trend_window_mins.saturating_mul(60))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Defaults to `cobitis-<hostname>`.
+    pub client_id: Option<String>,
+    /// Defaults to `cobitis/<hostname>`.
+    pub topic_prefix: Option<String>,
+    pub homeassistant_discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".into(),
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: None,
+            topic_prefix: None,
+            homeassistant_discovery: false,
+            discovery_prefix: "homeassistant".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct StorageConfig {
+    /// SQLite database file, created when missing.
+    pub path: PathBuf,
+    /// Rows older than this are deleted.
+    pub retention_days: u64,
+    /// Rows older than this are replaced by hourly averages. Never when unset.
+    pub downsample_after_days: Option<u64>,
+    /// How often pruning and downsampling run.
+    pub maintenance_interval_secs: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: "/var/lib/cobitis/history.db".into(),
+            retention_days: 365,
+            downsample_after_days: None,
+            maintenance_interval_secs: 3600,
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_days.saturating_mul(86_400))
+    }
+
+    pub fn downsample_after(&self) -> Option<Duration> {
+        self.downsample_after_days
+            .map(|days| Duration::from_secs(days.saturating_mul(86_400)))
+    }
+
+    pub fn maintenance_interval(&self) -> Duration {
+        Duration::from_secs(self.maintenance_interval_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ReadingsLogConfig {
+    /// JSON Lines file that every measurement is appended to.
+    pub path: PathBuf,
+    /// The file is rotated to `<path>.1` once it grows past this size.
+    pub max_size_bytes: u64,
+    /// Number of rotated files kept, as `<path>.1` to `<path>.<keep_files>`.
+    pub keep_files: usize,
+}
+
+impl Default for ReadingsLogConfig {
+    fn default() -> Self {
+        Self {
+            path: "/var/lib/cobitis/readings.jsonl".into(),
+            max_size_bytes: 10 * 1024 * 1024,
+            keep_files: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WebhookConfig {
+    pub url: String,
+    /// Sent as `Authorization: Bearer <token>`.
+    pub bearer_token: Option<String>,
+    pub timeout_secs: u64,
+    /// Further attempts after a failed POST before the reading is dropped.
+    pub retries: u32,
+    /// Whether signal readings are posted as well as measurements.
+    pub include_signal: bool,
+    /// Only post measurements that differ from the last posted one by more than the deltas below.
+    pub on_change: bool,
+    pub temperature_delta: f64,
+    pub tds_delta: f64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            bearer_token: None,
+            timeout_secs: 10,
+            retries: 3,
+            include_signal: false,
+            on_change: false,
+            temperature_delta: 0.2,
+            tds_delta: 10.0,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// Fires when a value stays past `threshold` for `min_duration_secs`, and clears once it is back past `clear`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AlertRule {
+    /// Defaults to a description of the rule, such as `temperature below 23`.
+    pub name: Option<String>,
+    pub metric: AlertMetric,
+    pub condition: AlertCondition,
+    pub threshold: f64,
+    /// Level the value must return to before the alert clears, so that it does not flap around the threshold.
+    pub clear: f64,
+    #[serde(default = "AlertRule::default_min_duration_secs")]
+    pub min_duration_secs: u64,
+    /// Whether the buzzer sounds while the alert is active.
+    #[serde(default)]
+    pub critical: bool,
+    /// Tank whose measurements the rule watches, the default one when unset.
+    #[serde(default)]
+    pub tank: Option<String>,
+}
+
+impl AlertRule {
+    fn default_min_duration_secs() -> u64 {
+        60
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| match &self.tank {
+            Some(tank) => format!("{tank} {} {} {}", self.metric, self.condition, self.threshold),
+            None => format!("{} {} {}", self.metric, self.condition, self.threshold),
+        })
+    }
+
+    pub fn min_duration(&self) -> Duration {
+        Duration::from_secs(self.min_duration_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AlertMetric {
+    Temperature,
+    /// Rate of change of the temperature in °C per hour over `measurements.trend`. Rising and falling too fast each
+    /// take a rule of their own, one `above` a positive threshold and one `below` a negative one.
+    TemperatureTrend,
+    Tds,
+    Ph,
+    /// 1 while the water is up to the float switch and 0 while it is low, so that a rule `below` a threshold of 0.5
+    /// fires when the level drops.
+    WaterLevel,
+    /// Flow rate in liters per minute. A rule `below` a small threshold with a `min_duration_secs` catches a pump
+    /// that stopped.
+    Flow,
+    /// Days past the due date of the water change, negative until then, so that a rule `above` a threshold of 0
+    /// fires once it is overdue and clears when the change is recorded.
+    WaterChange,
+}
+
+impl fmt::Display for AlertMetric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Temperature => "temperature",
+            Self::TemperatureTrend => "temperature_trend",
+            Self::Tds => "tds",
+            Self::Ph => "ph",
+            Self::WaterLevel => "water_level",
+            Self::Flow => "flow",
+            Self::WaterChange => "water_change",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AlertCondition {
+    Above,
+    Below,
+}
+
+impl fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Above => "above",
+            Self::Below => "below",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct NotificationsConfig {
+    /// An alert that fires again within this long of its last notification is not notified again.
+    pub cooldown_secs: u64,
+    pub telegram: Option<TelegramConfig>,
+    pub webhook: Option<AlertWebhookConfig>,
+    pub email: Option<EmailConfig>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            cooldown_secs: 900,
+            telegram: None,
+            webhook: None,
+            email: None,
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.cooldown_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+/// Email sent through an SMTP server as alerts fire and clear, and once a day with a summary when asked for.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct EmailConfig {
+    pub server: String,
+    /// 465 with `tls`, 587 with `starttls`, and 25 with `none` when unset.
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    /// Logs in when set, along with `password`.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Such as `Cobitis <cobitis@example.com>`.
+    pub from: String,
+    /// Addresses to send to, either a single one or a list.
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+    /// Time of day in the configured zone to send the statistics of the day and the alerts that fired, such as
+    /// `21:00`. No summary is sent when unset.
+    #[serde(deserialize_with = "hh_mm_option")]
+    pub summary_at: Option<NaiveTime>,
+    pub timeout_secs: u64,
+    /// Further attempts after failing to reach the server before the email is dropped.
+    pub retries: u32,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            server: String::new(),
+            port: None,
+            tls: SmtpTls::default(),
+            username: None,
+            password: None,
+            from: String::new(),
+            to: Vec::new(),
+            summary_at: None,
+            timeout_secs: 30,
+            retries: 3,
+        }
+    }
+}
+
+impl EmailConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            SmtpTls::Tls => 465,
+            SmtpTls::StartTls => 587,
+            SmtpTls::None => 25,
+        })
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SmtpTls {
+    /// TLS from the start of the connection.
+    Tls,
+    /// Upgraded to TLS by the `STARTTLS` command, which the server must support.
+    #[default]
+    StartTls,
+    /// Plain text, for a relay on the same host or network only.
+    None,
+}
+
+/// HTTP endpoint that alerts are sent to as they fire and clear, such as an ntfy.sh topic.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AlertWebhookConfig {
+    pub url: String,
+    pub method: WebhookMethod,
+    /// Sent with every request, such as an `Authorization` header, or a `Title` for ntfy.sh.
+    pub headers: BTreeMap<String, String>,
+    /// JSON body, where `{{name}}`, `{{tank}}`, `{{metric}}`, `{{value}}`, `{{threshold}}` and `{{state}}` are filled
+    /// in. `state` is `fired` or `cleared`. An object with all of them when unset.
+    pub body: Option<BodyTemplate>,
+    pub timeout_secs: u64,
+}
+
+impl Default for AlertWebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: WebhookMethod::default(),
+            headers: BTreeMap::new(),
+            body: None,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl AlertWebhookConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum WebhookMethod {
+    #[default]
+    Post,
+    Put,
+    Patch,
+}
+
+/// Text with `{{placeholder}}`s, checked when the config is loaded to give valid JSON whatever is filled in.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct BodyTemplate(Vec<TemplatePart>);
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Text(String),
+    Field(TemplateField),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TemplateField {
+    Name,
+    Tank,
+    Metric,
+    Value,
+    Threshold,
+    State,
+}
+
+impl TemplateField {
+    const ALL: [(&str, Self); 6] = [
+        ("name", Self::Name),
+        ("tank", Self::Tank),
+        ("metric", Self::Metric),
+        ("value", Self::Value),
+        ("threshold", Self::Threshold),
+        ("state", Self::State),
+    ];
+
+    /// Whether it is filled in as a number rather than as the contents of a string.
+    pub fn is_number(self) -> bool {
+        matches!(self, Self::Value | Self::Threshold)
+    }
+}
+
+impl BodyTemplate {
+    /// Fills in each placeholder with what `value` returns for it, as it is.
+    pub fn render(&self, mut value: impl FnMut(TemplateField) -> String) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Field(field) => value(*field),
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<String> for BodyTemplate {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        let mut parts = Vec::new();
+        let mut rest = raw.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                return Err(format!("unclosed placeholder in {raw:?}"));
+            };
+            let name = rest[start + 2..start + 2 + len].trim();
+            let Some(&(_, field)) = TemplateField::ALL.iter().find(|(n, _)| *n == name) else {
+                return Err(format!(
+                    "unknown placeholder {{{{{name}}}}}, expected one of {}",
+                    TemplateField::ALL.map(|(n, _)| n).join(", ")
+                ));
+            };
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_owned()));
+            }
+            parts.push(TemplatePart::Field(field));
+            rest = &rest[start + 2 + len + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_owned()));
+        }
+        let template = Self(parts);
+
+        // Numbers are filled in as they are and the rest escaped, so a string placeholder outside of quotes fails here
+        let sample = template.render(|field| if field.is_number() { "1.5" } else { "x" }.to_owned());
+        serde_json::from_str::<serde_json::Value>(&sample)
+            .map_err(|e| format!("body does not make valid JSON: {e}"))?;
+
+        Ok(template)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BuzzerConfig {
+    pub gpio_chip: PathBuf,
+    pub pin: u32,
+    /// Short beeps in each burst.
+    pub beeps: u32,
+    pub beep_ms: u64,
+    /// A burst is repeated this often while a critical alert is active.
+    pub repeat_secs: u64,
+    /// How long `POST /alerts/silence` mutes the buzzer by default.
+    pub silence_secs: u64,
+}
+
+impl Default for BuzzerConfig {
+    fn default() -> Self {
+        Self {
+            gpio_chip: "/dev/gpiochip0".into(),
+            pin: 18,
+            beeps: 3,
+            beep_ms: 150,
+            repeat_secs: 30,
+            silence_secs: 3600,
+        }
+    }
+}
+
+impl BuzzerConfig {
+    pub fn beep(&self) -> Duration {
+        Duration::from_millis(self.beep_ms)
+    }
+
+    pub fn repeat(&self) -> Duration {
+        Duration::from_secs(self.repeat_secs)
+    }
+
+    pub fn silence(&self) -> Duration {
+        Duration::from_secs(self.silence_secs)
+    }
+}
+
+/// Heater relay control. The heater is switched on below `target - hysteresis` and off above `target + hysteresis`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ThermostatConfig {
+    pub enabled: bool,
+    pub gpio_chip: PathBuf,
+    pub pin: u32,
+    /// Whether the relay module switches on when the pin is driven low, as many do.
+    pub active_low: bool,
+    pub target: f64,
+    pub hysteresis: f64,
+    /// The heater is switched off and kept off after running this long without a break.
+    pub max_on_secs: u64,
+    /// The heater is switched off when there has been no valid reading for this long.
+    pub stale_after_secs: u64,
+}
+
+impl Default for ThermostatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gpio_chip: "/dev/gpiochip0".into(),
+            pin: 17,
+            active_low: false,
+            target: 25.0,
+            hysteresis: 0.2,
+            max_on_secs: 3600,
+            stale_after_secs: 120,
+        }
+    }
+}
+
+impl ThermostatConfig {
+    pub fn max_on(&self) -> Duration {
+        Duration::from_secs(self.max_on_secs)
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        Duration::from_secs(self.stale_after_secs)
+    }
+}
+
+/// Float switch telling whether the water is up to the level it is mounted at.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WaterLevelConfig {
+    pub gpio_chip: PathBuf,
+    pub pin: u32,
+    /// Whether the pin reads low while the water is up to the switch. The pin reads high then otherwise.
+    pub active_low: bool,
+    /// Enables the internal pull-up of the pin, for a switch that connects it to ground.
+    pub pull_up: bool,
+    /// The switch must stay in a new position this long before the level is taken to have changed, so that ripples
+    /// do not toggle it.
+    pub debounce_secs: u64,
+    pub poll_interval_ms: u64,
+}
+
+impl Default for WaterLevelConfig {
+    fn default() -> Self {
+        Self {
+            gpio_chip: "/dev/gpiochip0".into(),
+            pin: 27,
+            active_low: true,
+            pull_up: true,
+            debounce_secs: 3,
+            poll_interval_ms: 200,
+        }
+    }
+}
+
+impl WaterLevelConfig {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce_secs)
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+/// Hall-effect flow sensor, such as a YF-S201, giving a pulse for each bit of water through it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FlowConfig {
+    pub gpio_chip: PathBuf,
+    pub pin: u32,
+    /// Enables the internal pull-up of the pin, for a sensor with an open-collector output.
+    pub pull_up: bool,
+    /// 450 for a YF-S201.
+    pub pulses_per_liter: f64,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            gpio_chip: "/dev/gpiochip0".into(),
+            pin: 22,
+            pull_up: true,
+            pulses_per_liter: 450.0,
+        }
+    }
+}
+
+/// URL pinged while the measurements are coming in, such as a check on healthchecks.io, which raises the alarm once
+/// the pings stop, whether the sensors are stuck or the board is down altogether.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HeartbeatConfig {
+    pub url: String,
+    /// The latest temperature goes in the query either way, and a `POST` carries the TDS as well in a JSON body.
+    pub method: HeartbeatMethod,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: HeartbeatMethod::default(),
+            interval_secs: 60,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum HeartbeatMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+/// Reminder to change part of the water every so often, with the changes recorded through the API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WaterChangeConfig {
+    pub interval_days: u32,
+}
+
+impl Default for WaterChangeConfig {
+    fn default() -> Self {
+        Self { interval_days: 7 }
+    }
+}
+
+/// Push button on the enclosure. A short press wakes the display and shows the next page, and a long one turns
+/// maintenance on or off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ButtonConfig {
+    pub gpio_chip: PathBuf,
+    pub pin: u32,
+    /// Whether the pin reads low while the button is pressed. The pin reads high then otherwise.
+    pub active_low: bool,
+    /// Enables the internal pull-up of the pin, for a button that connects it to ground.
+    pub pull_up: bool,
+    /// The button must stay pressed or released this long before it is taken to have been, so that its contacts
+    /// bouncing do not count as presses.
+    pub debounce_ms: u64,
+    /// Presses held at least this long are long presses.
+    pub long_press_ms: u64,
+    /// How long a press keeps the display on while it is blanked.
+    pub wake_secs: u64,
+    /// Maintenance started with a long press ends by itself after this long. It lasts until ended when unset.
+    pub maintenance_secs: Option<u64>,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            gpio_chip: "/dev/gpiochip0".into(),
+            pin: 17,
+            active_low: true,
+            pull_up: true,
+            debounce_ms: 30,
+            long_press_ms: 2000,
+            wake_secs: 30,
+            maintenance_secs: None,
+        }
+    }
+}
+
+impl ButtonConfig {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+
+    pub fn long_press(&self) -> Duration {
+        Duration::from_millis(self.long_press_ms)
+    }
+
+    #[cfg(feature = "display")]
+    pub fn wake(&self) -> Duration {
+        Duration::from_secs(self.wake_secs)
+    }
+
+    pub fn maintenance(&self) -> Option<Duration> {
+        self.maintenance_secs.map(Duration::from_secs)
+    }
+}
+
+impl Config {
+    /// Reads the config file given by `--config`, or the one at the default location, then applies the environment
+    /// and then the flags of `cli` over it, each taking precedence over the one before.
+    ///
+    /// A missing file at the default location is not an error; all settings fall back to their defaults.
+    pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Self::from_file(Path::new(DEFAULT_PATH))?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        config.apply_cli(cli);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let raw =
+            fs::read_to_string(path).with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        toml::from_str(&raw).with_context(|| format!("Malformed config file {}", path.display()))
+    }
+
+    /// Overrides settings with those given through the environment, looked up with `var`.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        if let Some(listen) = var("COBITIS_LISTEN") {
+            self.api.listen = parse_listen(&listen)?;
+        }
+        // A filter finer than a single level, such as `cobitis::api=debug`, is left to the logger to read as it is.
+        if let Some(filter) = var("RUST_LOG").filter(|filter| !filter.trim().is_empty()) {
+            self.log_level = LogLevel::from_str(filter.trim(), true).ok();
+        }
+        if let Some(format) = var("COBITIS_LOG_FORMAT") {
+            self.log_format = match format.trim() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => return Err(anyhow!("Invalid COBITIS_LOG_FORMAT {format:?}, expected text or json")),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Overrides settings with those given as flags.
+    fn apply_cli(&mut self, cli: &Cli) {
+        self.simulate |= cli.simulate;
+        if !cli.listen.is_empty() {
+            self.api.listen.clone_from(&cli.listen);
+        }
+        if cli.log_level.is_some() {
+            self.log_level = cli.log_level;
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, secs) in [
+            ("measurements.interval_secs", self.measurements.interval_secs),
+            ("signal.interval_secs", self.signal.interval_secs),
+            ("display.interval_secs", self.display.interval_secs),
+            ("display.pixel_shift_secs", self.display.pixel_shift_secs),
+            ("display.page_secs", self.display.page_secs),
+            ("system.interval_secs", self.system.interval_secs),
+            ("measurements.tds_samples", self.measurements.tds_samples as u64),
+        ] {
+            if secs == 0 {
+                return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+            }
+        }
+        for (source, fresh_for, stale_after) in [
+            (
+                "measurements",
+                self.measurements.fresh_for_secs,
+                self.measurements.stale_after_secs,
+            ),
+            ("signal", self.signal.fresh_for_secs, self.signal.stale_after_secs),
+        ] {
+            if fresh_for > stale_after {
+                return Err(anyhow!(
+                    "Invalid config: {source}.fresh_for_secs must not exceed {source}.stale_after_secs"
+                ));
+            }
+        }
+        if self.measurements.tds_factor.is_nan() || self.measurements.tds_factor <= 0.0 {
+            return Err(anyhow!(
+                "Invalid config: measurements.tds_factor must be greater than 0"
+            ));
+        }
+        for (name, change) in [
+            (
+                "measurements.spike_filter.max_temperature_change",
+                self.measurements.spike_filter.max_temperature_change,
+            ),
+            (
+                "measurements.spike_filter.max_tds_change",
+                self.measurements.spike_filter.max_tds_change,
+            ),
+        ] {
+            if change.is_nan() || change <= 0.0 {
+                return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+            }
+        }
+        for (name, alpha) in [
+            (
+                "measurements.smoothing.temperature_alpha",
+                self.measurements.smoothing.temperature_alpha,
+            ),
+            (
+                "measurements.smoothing.tds_alpha",
+                self.measurements.smoothing.tds_alpha,
+            ),
+        ] {
+            if alpha.is_some_and(|alpha| !(alpha > 0.0 && alpha <= 1.0)) {
+                return Err(anyhow!("Invalid config: {name} must be greater than 0 and at most 1"));
+            }
+        }
+        if self.measurements.trend.window_mins == 0 {
+            return Err(anyhow!(
+                "Invalid config: measurements.trend.window_mins must be greater than 0"
+            ));
+        }
+        if self.measurements.trend.min_samples < 2 {
+            return Err(anyhow!(
+                "Invalid config: measurements.trend.min_samples must be at least 2"
+            ));
+        }
+        let adc = &self.measurements.adc;
+        if !(0x48..=0x4b).contains(&adc.address) {
+            return Err(anyhow!(
+                "Invalid config: measurements.adc.address must be between 0x48 and 0x4b"
+            ));
+        }
+        if !AdcConfig::RANGES.contains(&adc.range) {
+            return Err(anyhow!(
+                "Invalid config: measurements.adc.range must be one of {:?}",
+                AdcConfig::RANGES
+            ));
+        }
+        if !adc.chip.data_rates().contains(&adc.data_rate) {
+            return Err(anyhow!(
+                "Invalid config: measurements.adc.data_rate must be one of {:?} on an {}",
+                adc.chip.data_rates(),
+                adc.chip
+            ));
+        }
+        if adc.tds_max_voltage > adc.range {
+            return Err(anyhow!(
+                "Invalid config: measurements.adc.range must cover measurements.adc.tds_max_voltage"
+            ));
+        }
+        let tanks = &self.measurements.tanks;
+        for (i, tank) in tanks.iter().enumerate() {
+            if !is_name(&tank.name) {
+                return Err(anyhow!(
+                    "Invalid config: name of measurements.tanks entry {:?} must be letters, digits, _ and - only",
+                    tank.name
+                ));
+            }
+            if tanks[..i].iter().any(|other| other.name == tank.name) {
+                return Err(anyhow!(
+                    "Invalid config: measurements.tanks entry {:?} is listed twice",
+                    tank.name
+                ));
+            }
+            if tanks.len() > 1 && tank.temperature_sensor.is_none() {
+                return Err(anyhow!(
+                    "Invalid config: temperature_sensor of measurements.tanks entry {:?} must be set when there is \
+                     more than one tank",
+                    tank.name
+                ));
+            }
+            if tank.temperature_sensor.is_some()
+                && tanks[..i]
+                    .iter()
+                    .any(|other| other.temperature_sensor == tank.temperature_sensor)
+            {
+                return Err(anyhow!(
+                    "Invalid config: temperature_sensor of measurements.tanks entry {:?} is already in use",
+                    tank.name
+                ));
+            }
+            if tanks[..i].iter().any(|other| other.tds_channel == tank.tds_channel) {
+                return Err(anyhow!(
+                    "Invalid config: {} of measurements.tanks entry {:?} is already in use",
+                    tank.tds_channel,
+                    tank.name
+                ));
+            }
+        }
+        if let Some(name) = &self.measurements.default_tank {
+            let known = if tanks.is_empty() {
+                name == MeasurementsConfig::IMPLICIT_TANK
+            } else {
+                tanks.iter().any(|tank| &tank.name == name)
+            };
+            if !known {
+                return Err(anyhow!(
+                    "Invalid config: measurements.default_tank {name:?} is not one of measurements.tanks"
+                ));
+            }
+        }
+        let mut channels: Vec<_> = self.measurements.tanks().iter().map(|tank| tank.tds_channel).collect();
+        if self.measurements.ph.is_some() && channels.contains(&adc.ph_channel) {
+            return Err(anyhow!(
+                "Invalid config: measurements.adc.ph_channel must differ from the TDS channels"
+            ));
+        }
+        channels.extend(self.measurements.ph.as_ref().map(|_| adc.ph_channel));
+        for (i, aux) in adc.aux.iter().enumerate() {
+            if !is_name(&aux.name) {
+                return Err(anyhow!(
+                    "Invalid config: name of measurements.adc.aux channel {:?} must be letters, digits, _ and - only",
+                    aux.name
+                ));
+            }
+            if adc.aux[..i].iter().any(|other| other.name == aux.name) {
+                return Err(anyhow!(
+                    "Invalid config: measurements.adc.aux channel {:?} is listed twice",
+                    aux.name
+                ));
+            }
+            if channels.contains(&aux.channel) {
+                return Err(anyhow!(
+                    "Invalid config: {} of measurements.adc.aux channel {:?} is already in use",
+                    aux.channel,
+                    aux.name
+                ));
+            }
+            if !aux.scale.is_finite() || !aux.offset.is_finite() {
+                return Err(anyhow!(
+                    "Invalid config: scale and offset of measurements.adc.aux channel {:?} must be finite",
+                    aux.name
+                ));
+            }
+            channels.push(aux.channel);
+        }
+        // Every conversion blocks until the chip is done, so a slow data rate with many samples can eat up the
+        // interval.
+        let burst = self.measurements.adc_burst_time();
+        if burst * 2 > self.measurements.interval() {
+            return Err(anyhow!(
+                "Invalid config: the ADC takes {}ms per measurement, which must be under half of \
+                 measurements.interval_secs",
+                burst.as_millis()
+            ));
+        }
+        if self.api.listen.is_empty() && self.api.socket.is_none() {
+            return Err(anyhow!(
+                "Invalid config: api.listen must not be empty unless api.socket is set"
+            ));
+        }
+        if let Some(tls) = &self.api.tls {
+            if tls.cert.as_os_str().is_empty() || tls.key.as_os_str().is_empty() {
+                return Err(anyhow!("Invalid config: api.tls.cert and api.tls.key must be set"));
+            }
+            if tls.listen.is_empty() {
+                return Err(anyhow!("Invalid config: api.tls.listen must not be empty"));
+            }
+            if tls.reload_interval_secs == 0 {
+                return Err(anyhow!(
+                    "Invalid config: api.tls.reload_interval_secs must be greater than 0"
+                ));
+            }
+        }
+        if let Some(mdns) = &self.api.mdns {
+            if self.api.mdns_listen().is_none() {
+                return Err(anyhow!(
+                    "Invalid config: api.mdns needs an address in api.listen other than loopback"
+                ));
+            }
+            if mdns
+                .instance
+                .as_ref()
+                .is_some_and(|name| name.is_empty() || name.len() > 63)
+            {
+                return Err(anyhow!("Invalid config: api.mdns.instance must be 1 to 63 bytes long"));
+            }
+            if mdns.host_name.as_ref().is_some_and(|name| {
+                name.is_empty()
+                    || name.len() > 63
+                    || name.starts_with('-')
+                    || name.ends_with('-')
+                    || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }) {
+                return Err(anyhow!(
+                    "Invalid config: api.mdns.host_name must be a single label of letters, digits and hyphens"
+                ));
+            }
+        }
+        if self
+            .api
+            .rate_limit_per_sec
+            .is_some_and(|rate| rate.is_nan() || rate <= 0.0)
+        {
+            return Err(anyhow!("Invalid config: api.rate_limit_per_sec must be greater than 0"));
+        }
+        if self.api.rate_limit_burst == 0 {
+            return Err(anyhow!("Invalid config: api.rate_limit_burst must be greater than 0"));
+        }
+        if self.api.max_open_requests == Some(0) {
+            return Err(anyhow!("Invalid config: api.max_open_requests must be greater than 0"));
+        }
+        if self.api.readiness_max_age_secs == Some(0) {
+            return Err(anyhow!(
+                "Invalid config: api.readiness_max_age_secs must be greater than 0"
+            ));
+        }
+        if self.api.socket_mode > 0o777 {
+            return Err(anyhow!("Invalid config: api.socket_mode must be at most 0o777"));
+        }
+        if self.api.tokens.iter().any(String::is_empty) {
+            return Err(anyhow!("Invalid config: api.tokens must not contain empty tokens"));
+        }
+        if self.display.pages.is_empty() {
+            return Err(anyhow!("Invalid config: display.pages must not be empty"));
+        }
+        if let Some(bar) = &self.display.tds_bar
+            && bar.min >= bar.max
+        {
+            return Err(anyhow!(
+                "Invalid config: display.tds_bar.min must be less than display.tds_bar.max"
+            ));
+        }
+        if let DatetimeFormat::Custom(format) = &self.display.datetime_format {
+            // Formatting fails rather than the parsing of the format when it has an unknown specifier
+            let sample = NaiveDate::from_ymd_opt(2025, 6, 1)
+                .and_then(|date| date.and_hms_opt(21, 30, 0))
+                .unwrap_or_default();
+            if format.is_empty() || write!(String::new(), "{}", sample.format(format)).is_err() {
+                return Err(anyhow!(
+                    "Invalid config: display.datetime_format is not a valid strftime format: {format:?}"
+                ));
+            }
+        }
+        if self.display.trend_window_mins == 0 {
+            return Err(anyhow!(
+                "Invalid config: display.trend_window_mins must be greater than 0"
+            ));
+        }
+        if !matches!(self.display.rotation, 0 | 180) {
+            return Err(anyhow!("Invalid config: display.rotation must be 0 or 180"));
+        }
+        if self.readings_log.as_ref().is_some_and(|log| log.max_size_bytes == 0) {
+            return Err(anyhow!(
+                "Invalid config: readings_log.max_size_bytes must be greater than 0"
+            ));
+        }
+        let tank_names: Vec<_> = self.measurements.tanks().into_iter().map(|tank| tank.name).collect();
+        for rule in &self.alerts {
+            if let Some(tank) = rule.tank.as_ref().filter(|tank| !tank_names.contains(tank)) {
+                return Err(anyhow!(
+                    "Invalid config: tank {tank:?} of alert \"{}\" is not one of measurements.tanks",
+                    rule.name()
+                ));
+            }
+            let ordered = match rule.condition {
+                AlertCondition::Above => rule.clear <= rule.threshold,
+                AlertCondition::Below => rule.clear >= rule.threshold,
+            };
+            if !ordered {
+                return Err(anyhow!(
+                    "Invalid config: clear level of alert \"{}\" must be on the safe side of the threshold",
+                    rule.name()
+                ));
+            }
+            if rule.metric == AlertMetric::WaterChange && self.water_change.is_none() {
+                return Err(anyhow!(
+                    "Invalid config: alert \"{}\" watches the water change, but water_change is not set",
+                    rule.name()
+                ));
+            }
+        }
+        if let Some(buzzer) = &self.buzzer {
+            for (name, value) in [
+                ("buzzer.beep_ms", buzzer.beep_ms),
+                ("buzzer.repeat_secs", buzzer.repeat_secs),
+            ] {
+                if value == 0 {
+                    return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+                }
+            }
+        }
+        if let Some(thermostat) = &self.thermostat {
+            for (name, value) in [
+                ("thermostat.max_on_secs", thermostat.max_on_secs),
+                ("thermostat.stale_after_secs", thermostat.stale_after_secs),
+            ] {
+                if value == 0 {
+                    return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+                }
+            }
+            if thermostat.hysteresis.is_nan() || thermostat.hysteresis < 0.0 {
+                return Err(anyhow!("Invalid config: thermostat.hysteresis must not be negative"));
+            }
+        }
+        if self
+            .water_level
+            .as_ref()
+            .is_some_and(|water_level| water_level.poll_interval_ms == 0)
+        {
+            return Err(anyhow!(
+                "Invalid config: water_level.poll_interval_ms must be greater than 0"
+            ));
+        }
+        if self
+            .flow
+            .as_ref()
+            .is_some_and(|flow| flow.pulses_per_liter.is_nan() || flow.pulses_per_liter <= 0.0)
+        {
+            return Err(anyhow!("Invalid config: flow.pulses_per_liter must be greater than 0"));
+        }
+        if self
+            .water_change
+            .as_ref()
+            .is_some_and(|water_change| water_change.interval_days == 0)
+        {
+            return Err(anyhow!(
+                "Invalid config: water_change.interval_days must be greater than 0"
+            ));
+        }
+        if let Some(button) = &self.button {
+            if button.long_press_ms <= button.debounce_ms {
+                return Err(anyhow!(
+                    "Invalid config: button.long_press_ms must be greater than button.debounce_ms"
+                ));
+            }
+            if button.maintenance_secs == Some(0) {
+                return Err(anyhow!(
+                    "Invalid config: button.maintenance_secs must be greater than 0"
+                ));
+            }
+        }
+        if let Some(webhook) = &self.notifications.webhook {
+            if webhook.url.is_empty() {
+                return Err(anyhow!("Invalid config: notifications.webhook.url must be set"));
+            }
+            if webhook.timeout_secs == 0 {
+                return Err(anyhow!(
+                    "Invalid config: notifications.webhook.timeout_secs must be greater than 0"
+                ));
+            }
+            for (name, value) in &webhook.headers {
+                if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err() {
+                    return Err(anyhow!(
+                        "Invalid config: notifications.webhook.headers has an invalid header {name:?}"
+                    ));
+                }
+            }
+        }
+        if let Some(email) = &self.notifications.email {
+            if email.server.is_empty() {
+                return Err(anyhow!("Invalid config: notifications.email.server must be set"));
+            }
+            if email.username.is_some() != email.password.is_some() {
+                return Err(anyhow!(
+                    "Invalid config: notifications.email.username and password must be set together"
+                ));
+            }
+            if email.timeout_secs == 0 {
+                return Err(anyhow!(
+                    "Invalid config: notifications.email.timeout_secs must be greater than 0"
+                ));
+            }
+            if email.to.is_empty() {
+                return Err(anyhow!("Invalid config: notifications.email.to must be set"));
+            }
+            for address in iter::once(&email.from).chain(&email.to) {
+                if address.parse::<Mailbox>().is_err() {
+                    return Err(anyhow!(
+                        "Invalid config: notifications.email has an invalid address {address:?}"
+                    ));
+                }
+            }
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            if Url::parse(&heartbeat.url).is_err() {
+                return Err(anyhow!("Invalid config: heartbeat.url must be a valid URL"));
+            }
+            for (name, value) in [
+                ("heartbeat.interval_secs", heartbeat.interval_secs),
+                ("heartbeat.timeout_secs", heartbeat.timeout_secs),
+            ] {
+                if value == 0 {
+                    return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+                }
+            }
+            if !self.measurements.enabled {
+                return Err(anyhow!(
+                    "Invalid config: heartbeat needs measurements.enabled, as it is only sent while they come in"
+                ));
+            }
+        }
+        if let Some(webhook) = &self.webhook {
+            if webhook.url.is_empty() {
+                return Err(anyhow!("Invalid config: webhook.url must be set"));
+            }
+            if webhook.timeout_secs == 0 {
+                return Err(anyhow!("Invalid config: webhook.timeout_secs must be greater than 0"));
+            }
+        }
+        if let Some(storage) = &self.storage {
+            for (name, value) in [
+                ("storage.retention_days", storage.retention_days),
+                ("storage.maintenance_interval_secs", storage.maintenance_interval_secs),
+            ] {
+                if value == 0 {
+                    return Err(anyhow!("Invalid config: {name} must be greater than 0"));
+                }
+            }
+            if storage
+                .downsample_after_days
+                .is_some_and(|days| days >= storage.retention_days)
+            {
+                return Err(anyhow!(
+                    "Invalid config: storage.downsample_after_days must be less than storage.retention_days"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether `name` is made of letters, digits, `_` and `-` only, so that it fits in paths, topics and labels.
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parses a comma-separated list of addresses, such as `[::]:8888,10.0.0.2:8888`. A port alone stands for every IPv4
+/// address, as in the default.
+fn parse_listen(raw: &str) -> anyhow::Result<Vec<SocketAddr>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| match addr.parse::<u16>() {
+            Ok(port) => Ok(SocketAddr::from(([0, 0, 0, 0], port))),
+            Err(_) => addr
+                .parse()
+                .with_context(|| format!("Invalid address {addr:?} in COBITIS_LISTEN")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const FILE: &str = r#"
+        log_level = "warn"
+
+        [api]
+        listen = "127.0.0.1:1000"
+    "#;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: BTreeMap<String, String> = vars.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(iter::once("cobitis").chain(args.iter().copied())).unwrap()
+    }
+
+    /// Resolves the config the way `load` does, from `FILE` rather than a file on disk.
+    fn resolve(vars: &[(&str, &str)], args: &[&str]) -> Config {
+        let mut config: Config = toml::from_str(FILE).unwrap();
+        config.apply_env(env(vars)).unwrap();
+        config.apply_cli(&cli(args));
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn file_overrides_the_defaults() {
+        let config = resolve(&[], &[]);
+        assert_eq!(config.log_level, Some(LogLevel::Warn));
+        assert_eq!(config.api.listen, [SocketAddr::from(([127, 0, 0, 1], 1000))]);
+        assert_eq!(Config::default().log_level, None);
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let config = resolve(&[("RUST_LOG", "debug"), ("COBITIS_LISTEN", "127.0.0.1:2000")], &[]);
+        assert_eq!(config.log_level, Some(LogLevel::Debug));
+        assert_eq!(config.api.listen, [SocketAddr::from(([127, 0, 0, 1], 2000))]);
+    }
+
+    #[test]
+    fn flags_override_the_environment() {
+        let config = resolve(
+            &[("RUST_LOG", "debug"), ("COBITIS_LISTEN", "127.0.0.1:2000")],
+            &["--log-level", "error", "--listen", "127.0.0.1:3000,[::1]:3000"],
+        );
+        assert_eq!(config.log_level, Some(LogLevel::Error));
+        assert_eq!(
+            config.api.listen,
+            [
+                SocketAddr::from(([127, 0, 0, 1], 3000)),
+                SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 3000))
+            ]
+        );
+    }
+
+    #[test]
+    fn a_finer_rust_log_filter_is_left_to_the_logger() {
+        let config = resolve(&[("RUST_LOG", "cobitis::api=debug")], &[]);
+        assert_eq!(config.log_level, None);
+    }
+
+    #[test]
+    fn an_empty_rust_log_leaves_the_file_level() {
+        let config = resolve(&[("RUST_LOG", " ")], &[]);
+        assert_eq!(config.log_level, Some(LogLevel::Warn));
+    }
+
+    #[test]
+    fn parses_an_ipv4_listen_address() {
+        assert_eq!(
+            parse_listen("127.0.0.1:8888").unwrap(),
+            [SocketAddr::from(([127, 0, 0, 1], 8888))]
+        );
+    }
+
+    #[test]
+    fn parses_an_ipv6_listen_address_in_brackets() {
+        assert_eq!(
+            parse_listen("[::]:8888").unwrap(),
+            [SocketAddr::from(([0u16; 8], 8888))]
+        );
+        assert_eq!(
+            parse_listen("[::1]:80").unwrap(),
+            [SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 80))]
+        );
+    }
+
+    #[test]
+    fn parses_a_port_alone_as_every_ipv4_address() {
+        assert_eq!(parse_listen("9000").unwrap(), [SocketAddr::from(([0, 0, 0, 0], 9000))]);
+    }
+
+    #[test]
+    fn parses_a_list_of_listen_addresses() {
+        assert_eq!(
+            parse_listen(" [::]:8888, 10.0.0.2:8888 ,").unwrap(),
+            [
+                SocketAddr::from(([0u16; 8], 8888)),
+                SocketAddr::from(([10, 0, 0, 2], 8888))
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_garbage_listen_addresses() {
+        for raw in [
+            "localhost:8888",
+            "::1:8888",
+            "127.0.0.1",
+            "127.0.0.1:99999",
+            "70000",
+            "garbage",
+        ] {
+            let e = parse_listen(raw).unwrap_err();
+            assert!(e.to_string().contains("COBITIS_LISTEN"), "{raw}: {e}");
+        }
+    }
+
+    #[test]
+    fn fresh_for_must_not_exceed_stale_after() {
+        let mut config = Config::default();
+        config.measurements.fresh_for_secs = config.measurements.stale_after_secs;
+        config.validate().unwrap();
+
+        config.measurements.fresh_for_secs += 1;
+        let e = config.validate().unwrap_err();
+        assert!(e.to_string().contains("measurements.fresh_for_secs"), "{e}");
+
+        let mut config = Config::default();
+        config.signal.fresh_for_secs = config.signal.stale_after_secs + 1;
+        let e = config.validate().unwrap_err();
+        assert!(e.to_string().contains("signal.fresh_for_secs"), "{e}");
+    }
+
+    #[test]
+    fn datetime_format_is_tried_on_a_sample_date() {
+        let mut config = Config::default();
+        config.display.datetime_format = DatetimeFormat::Custom("%a %-I:%M%P".to_owned());
+        config.validate().unwrap();
+
+        for format in ["", "%Q", "%H:%"] {
+            config.display.datetime_format = DatetimeFormat::Custom(format.to_owned());
+            let e = config.validate().unwrap_err();
+            assert!(e.to_string().contains("display.datetime_format"), "{format:?}: {e}");
+        }
+    }
+}
@@ -4,60 +4,667 @@
 // https://opensource.org/licenses/MIT
 
 use std::{
-    borrow::Cow,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use anyhow::anyhow;
-use chrono::Local;
+use anyhow::{Context as _, anyhow};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc, serde::ts_milliseconds_option};
 use eg_bdf::BdfTextStyle;
 use eg_font_converter::{EgBdfOutput, FontConverter, Mapping};
 use embedded_graphics::{
     pixelcolor::BinaryColor,
     prelude::*,
-    primitives::{Line, PrimitiveStyleBuilder},
-    text::{Baseline, Text},
+    primitives::{Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, Triangle},
+    text::{Baseline, Text, renderer::TextRenderer},
 };
+use embedded_hal_compat::{Reverse, ReverseCompat};
 use linux_embedded_hal::I2cdev;
-use logger::log::error;
-use ssd1306::{I2CDisplayInterface, Ssd1306, prelude::*, size::DisplaySize128x64};
+use logger::log::{error, info, warn};
+use serde::Serialize;
+use ssd1306::{
+    I2CDisplayInterface, Ssd1306,
+    mode::BufferedGraphicsMode,
+    prelude::*,
+    size::{DisplaySize128x32, DisplaySize128x64},
+};
 use tokio::{
+    select,
+    sync::Notify,
     task,
     time::{MissedTickBehavior, interval},
 };
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+pub(crate) use self::framebuffer::Framebuffer;
+use self::pages::{Canvas, Page, Snapshot};
+use crate::{
+    config::{
+        self, Config, DatetimeFormat, DisplayDriver, NightConfig, PageKind, PanelSize, TdsUnit, TemperatureUnit,
+    },
+    errors::ErrorLog,
+    hardware::{Device, Hardware},
+    health::{self, Freshness},
+    measurements, network, simulation,
+    state::AppState,
+    timezone,
+};
+
+mod framebuffer;
+mod pages;
+
+type BufferedSsd1306<S> = Ssd1306<I2CInterface<I2cdev>, S, BufferedGraphicsMode<S>>;
+type Sh1106 = sh1106::mode::GraphicsMode<sh1106::interface::I2cInterface<Reverse<I2cdev>>>;
+
+/// A display of any of the supported controllers and sizes.
+enum Display {
+    Ssd1306x64(Box<BufferedSsd1306<DisplaySize128x64>>),
+    Ssd1306x32(Box<BufferedSsd1306<DisplaySize128x32>>),
+    Sh1106(Box<Sh1106>),
+}
+
+macro_rules! with_display {
+    ($display:expr, $d:ident => $ssd1306:expr, $sh:ident => $sh1106:expr) => {
+        match $display {
+            Display::Ssd1306x64($d) => $ssd1306,
+            Display::Ssd1306x32($d) => $ssd1306,
+            Display::Sh1106($sh) => $sh1106,
+        }
+    };
+}
+
+impl Display {
+    fn new(i2c_bus: &Path, driver: DisplayDriver, size: PanelSize, rotation: u16) -> anyhow::Result<Self> {
+        let i2c = I2cdev::new(i2c_bus)?;
+        let upside_down = rotation == 180;
+
+        Ok(match driver {
+            DisplayDriver::Ssd1306 => {
+                let iface = I2CDisplayInterface::new(i2c);
+                let rotation = if upside_down {
+                    DisplayRotation::Rotate180
+                } else {
+                    DisplayRotation::Rotate0
+                };
+                match size {
+                    PanelSize::Size128x64 => Self::Ssd1306x64(Box::new(
+                        Ssd1306::new(iface, DisplaySize128x64, rotation).into_buffered_graphics_mode(),
+                    )),
+                    PanelSize::Size128x32 => Self::Ssd1306x32(Box::new(
+                        Ssd1306::new(iface, DisplaySize128x32, rotation).into_buffered_graphics_mode(),
+                    )),
+                }
+            }
+            DisplayDriver::Sh1106 => {
+                let rotation = if upside_down {
+                    sh1106::displayrotation::DisplayRotation::Rotate180
+                } else {
+                    sh1106::displayrotation::DisplayRotation::Rotate0
+                };
+                let size = match size {
+                    PanelSize::Size128x64 => sh1106::displaysize::DisplaySize::Display128x64,
+                    PanelSize::Size128x32 => sh1106::displaysize::DisplaySize::Display128x32,
+                };
+                // The SH1106 driver is still on embedded-hal 0.2.
+                let display: Sh1106 = sh1106::Builder::new()
+                    .with_size(size)
+                    .with_rotation(rotation)
+                    .connect_i2c(i2c.reverse())
+                    .into();
+                Self::Sh1106(Box::new(display))
+            }
+        })
+    }
+
+    fn init(&mut self) -> anyhow::Result<()> {
+        let driver = match self {
+            Self::Ssd1306x64(_) | Self::Ssd1306x32(_) => "SSD1306",
+            Self::Sh1106(_) => "SH1106",
+        };
+        with_display!(
+            self,
+            d => d.init().map_err(|e| anyhow!("{e:?}")),
+            d => d.init().map_err(|e| anyhow!("{e:?}"))
+        )
+        .with_context(|| format!("Failed to initialize display, is this really an {driver}?"))
+    }
+
+    fn clear_buffer(&mut self) {
+        with_display!(self, d => d.clear_buffer(), d => d.clear());
+    }
+
+    fn set_power(&mut self, power: Power) -> anyhow::Result<()> {
+        if power == Power::Off {
+            // The SH1106 driver cannot switch the panel off, so it is left blank instead.
+            self.clear_buffer();
+            self.flush()?;
+        }
+        match self {
+            Self::Ssd1306x64(d) => set_ssd1306_power(&mut **d, power),
+            Self::Ssd1306x32(d) => set_ssd1306_power(&mut **d, power),
+            Self::Sh1106(d) => match power {
+                Power::On(contrast) | Power::Dim(contrast) => d.set_contrast(contrast).map_err(|e| anyhow!("{e:?}")),
+                Power::Off => Ok(()),
+            },
+        }
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        with_display!(
+            self,
+            d => d.flush().map_err(|e| anyhow!("{e:?}")),
+            d => d.flush().map_err(|e| anyhow!("{e:?}"))
+        )
+    }
+}
+
+fn set_ssd1306_power<S: DisplaySize>(display: &mut BufferedSsd1306<S>, power: Power) -> anyhow::Result<()> {
+    let result = match power {
+        Power::On(contrast) => display
+            .set_display_on(true)
+            .and_then(|()| display.set_brightness(Brightness::custom(2, contrast))),
+        Power::Dim(contrast) => display
+            .set_display_on(true)
+            .and_then(|()| display.set_brightness(Brightness::custom(1, contrast))),
+        Power::Off => display.set_display_on(false),
+    };
+
+    result.map_err(|e| anyhow!("{e:?}"))
+}
+
+impl OriginDimensions for Display {
+    fn size(&self) -> Size {
+        with_display!(self, d => d.size(), d => d.size())
+    }
+}
+
+impl DrawTarget for Display {
+    type Color = BinaryColor;
+    type Error = <BufferedSsd1306<DisplaySize128x64> as DrawTarget>::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        with_display!(self, d => d.draw_iter(pixels), d => d.draw_iter(pixels).map_err(|e| match e {}))
+    }
+}
+
+/// Initializes the panel once and leaves it blank, to tell whether it is wired up.
+pub(crate) fn probe(config: &config::DisplayConfig) -> anyhow::Result<()> {
+    let mut display = Display::new(&config.i2c_bus, config.driver, config.size, config.rotation)?;
+    display.init()?;
+    display.clear_buffer();
+    display.flush()
+}
+
+/// What the panel should be doing, according to the night schedule and any manual override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Power {
+    /// Lit at the contrast.
+    On(u8),
+    /// Lit at the contrast, with a shorter precharge on the SSD1306 to go dimmer than contrast alone can.
+    Dim(u8),
+    Off,
+}
+
+/// Returns the contrast the panel is lit at outside the night window, unless changed through the API.
+fn configured_contrast(config: &config::DisplayConfig) -> u8 {
+    config.contrast.unwrap_or(match config.driver {
+        // Those the drivers initialize the panels with.
+        DisplayDriver::Ssd1306 => 0x5f,
+        DisplayDriver::Sh1106 => 0x80,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Override {
+    on: bool,
+    until: Option<NaiveDateTime>,
+}
+
+/// Longest message accepted, in characters.
+pub(crate) const MESSAGE_MAX_CHARS: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Message {
+    text: String,
+    until: DateTime<Utc>,
+}
+
+/// Height of the row a message takes, that of the small font.
+const MESSAGE_ROW_HEIGHT: u32 = 14;
+
+/// Contrast set through the API.
+#[derive(Debug, Clone, Copy)]
+struct ManualContrast {
+    contrast: u8,
+    /// Set when changed during the night window, which it then outlasts until its next boundary.
+    until: Option<NaiveDateTime>,
+}
+
+/// What the API and the button change about the display, along with the latest frame drawn for the API to show.
+pub(crate) struct Controls {
+    frame: Mutex<Option<Framebuffer>>,
+    r#override: Mutex<Option<Override>>,
+    message: Mutex<Option<Message>>,
+    manual_contrast: Mutex<Option<ManualContrast>>,
+    /// Signalled by the button, so that the next page is shown without waiting for the next draw.
+    pressed: Notify,
+    /// Signalled when the contrast is changed, so that it is applied without waiting for the next draw.
+    contrast_changed: Notify,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Self {
+            frame: Mutex::new(None),
+            r#override: Mutex::new(None),
+            message: Mutex::new(None),
+            manual_contrast: Mutex::new(None),
+            pressed: Notify::new(),
+            contrast_changed: Notify::new(),
+        }
+    }
+
+    /// Returns a copy of the latest frame, which is blank while the panel is switched off.
+    pub fn frame(&self) -> Option<Framebuffer> {
+        self.frame.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forces the display on or off until the next boundary of the night schedule, or indefinitely without one.
+    pub fn set_override(&self, night: Option<&NightConfig>, on: bool) {
+        let until = night.map(|night| night.next_boundary(timezone::now().naive_local()));
+        match until {
+            Some(until) => info!("Display forced {} until {until}", if on { "on" } else { "off" }),
+            None => info!("Display forced {}", if on { "on" } else { "off" }),
+        }
+
+        *self.r#override.lock().unwrap_or_else(|e| e.into_inner()) = Some(Override { on, until });
+    }
+
+    /// Shows `text` along the bottom of the panel for `duration`, in place of any previous message. Characters the
+    /// fonts lack are replaced with `?`, as they would be when drawn.
+    pub fn set_message(&self, text: &str, duration: Duration) {
+        let text: String = text
+            .chars()
+            .map(|c| {
+                if u32::from(c) <= 0xff && !c.is_control() {
+                    c
+                } else {
+                    '?'
+                }
+            })
+            .collect();
+        let until = Utc::now() + TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX);
+        info!("Display showing \"{text}\" until {until}");
+
+        *self.message.lock().unwrap_or_else(|e| e.into_inner()) = Some(Message { text, until });
+    }
+
+    /// Takes the message down before it expires.
+    pub fn clear_message(&self) {
+        *self.message.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Returns the message to show at `now`, dropping it once it has expired.
+    fn message(&self, now: DateTime<Utc>) -> Option<String> {
+        let mut guard = self.message.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.as_ref().is_some_and(|m| now >= m.until) {
+            *guard = None;
+        }
+
+        guard.as_ref().map(|m| m.text.clone())
+    }
+
+    /// Shows the next page right away, keeping the panel on for `wake` first when it is blanked.
+    pub fn show_next_page(&self, config: &config::DisplayConfig, wake: Duration) {
+        let now = timezone::now().naive_local();
+        if self.power(config.night.as_ref(), configured_contrast(config), now) == Power::Off {
+            let until = TimeDelta::from_std(wake)
+                .ok()
+                .and_then(|wake| now.checked_add_signed(wake));
+            match until {
+                Some(until) => info!("Display woken until {until}"),
+                None => info!("Display woken"),
+            }
+            *self.r#override.lock().unwrap_or_else(|e| e.into_inner()) = Some(Override { on: true, until });
+        }
+
+        self.pressed.notify_one();
+    }
+
+    /// Changes the contrast the panel is lit at until the service restarts. A change during the night window lights
+    /// the panel at `contrast` until the window ends instead.
+    pub fn set_contrast(&self, config: &config::DisplayConfig, contrast: u8) {
+        let now = timezone::now().naive_local();
+        let until = config
+            .night
+            .as_ref()
+            .filter(|night| night.contains(now.time()))
+            .map(|night| night.next_boundary(now));
+        match until {
+            Some(until) => info!("Display contrast set to {contrast} until {until}"),
+            None => info!("Display contrast set to {contrast}"),
+        }
+
+        *self.manual_contrast.lock().unwrap_or_else(|e| e.into_inner()) = Some(ManualContrast { contrast, until });
+        self.contrast_changed.notify_one();
+    }
+
+    /// Returns what the panel is doing, or would be when simulated.
+    pub fn state(&self, config: &config::DisplayConfig) -> DisplayState {
+        let now = timezone::now().naive_local();
+        let (on, brightness) = match self.power(config.night.as_ref(), configured_contrast(config), now) {
+            Power::On(contrast) | Power::Dim(contrast) => (true, Some(contrast)),
+            Power::Off => (false, None),
+        };
+        let brightness_until = self
+            .manual_contrast(now)
+            .and_then(|manual| manual.until)
+            .and_then(timezone::to_utc);
+
+        DisplayState {
+            on,
+            brightness,
+            brightness_until,
+        }
+    }
+
+    /// Returns the contrast set through the API, dropping it once it has expired.
+    fn manual_contrast(&self, now: NaiveDateTime) -> Option<ManualContrast> {
+        let mut guard = self.manual_contrast.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.and_then(|manual| manual.until).is_some_and(|until| now >= until) {
+            *guard = None;
+        }
+
+        *guard
+    }
+
+    fn power(&self, night: Option<&NightConfig>, contrast: u8, now: NaiveDateTime) -> Power {
+        let manual = self.manual_contrast(now);
+        let contrast = manual.map_or(contrast, |manual| manual.contrast);
 
-use crate::{measurements, signal};
+        let mut guard = self.r#override.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(o) = *guard {
+            if o.until.is_none_or(|until| now < until) {
+                return if o.on { Power::On(contrast) } else { Power::Off };
+            }
+            *guard = None;
+        }
+
+        // A contrast set during the night window holds until the window ends
+        if manual.is_some_and(|manual| manual.until.is_some()) {
+            return Power::On(contrast);
+        }
+        match night {
+            Some(night) if night.contains(now.time()) => night.contrast.map_or(Power::Off, Power::Dim),
+            _ => Power::On(contrast),
+        }
+    }
+}
+
+/// Whether the panel is lit, and how brightly.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct DisplayState {
+    /// Whether the panel is lit, rather than blanked by the night window or turned off.
+    pub on: bool,
+    /// Contrast (0-255) the panel is lit at. Absent while it is not.
+    pub brightness: Option<u8>,
+    /// When a brightness set during the night window gives way to the window again. Milliseconds since the Unix
+    /// epoch.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub brightness_until: Option<DateTime<Utc>>,
+}
+
+/// Room for the datetime in the header, between the alert indicator and the signal level.
+const HEADER_TEXT_WIDTH: u32 = 97;
+
+/// Cuts `text` short at a character, so that it is at most `width` pixels wide in `style`.
+fn fit<'a>(text: &'a str, style: &BdfTextStyle<BinaryColor>, width: u32) -> &'a str {
+    let mut end = text.len();
+    while end > 0
+        && style
+            .measure_string(&text[..end], Point::zero(), Baseline::Top)
+            .bounding_box
+            .size
+            .width
+            > width
+    {
+        end = text[..end].char_indices().next_back().map_or(0, |(i, _)| i);
+    }
 
-type Display = Ssd1306<
-    I2CInterface<linux_embedded_hal::I2cdev>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
->;
+    text[..end].trim_end()
+}
+
+/// Pixels scrolling text moves each frame.
+const MARQUEE_STEP: i32 = 4;
+
+/// Frames scrolling text rests at either end before moving on.
+const MARQUEE_PAUSE_FRAMES: u32 = 3;
+
+/// How far text wider than its region has scrolled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Scroll {
+    offset: i32,
+    /// Frames left to rest where it is.
+    rest: u32,
+}
+
+impl Default for Scroll {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            rest: MARQUEE_PAUSE_FRAMES,
+        }
+    }
+}
+
+impl Scroll {
+    /// Returns the position a frame later, for text `overflow` pixels wider than its region. Once the end of the text
+    /// has come into view and rested, it goes back to the start.
+    fn next(self, overflow: i32) -> Self {
+        if self.rest > 0 {
+            return Self {
+                rest: self.rest - 1,
+                ..self
+            };
+        }
+        if self.offset >= overflow {
+            return Self::default();
+        }
+
+        let offset = (self.offset + MARQUEE_STEP).min(overflow);
+        let rest = if offset == overflow { MARQUEE_PAUSE_FRAMES } else { 0 };
+        Self { offset, rest }
+    }
+}
+
+/// Scroll positions of the text drawn with `marquee`, keyed by the text, along with the frame each was last drawn in.
+#[derive(Default)]
+struct Marquees {
+    frame: u64,
+    scrolls: BTreeMap<String, (Scroll, u64)>,
+}
+
+impl Marquees {
+    /// Moves on to the next frame, forgetting text that was not drawn in the last one so that it starts over when it
+    /// comes back.
+    fn next_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.scrolls.retain(|_, (_, drawn)| *drawn + 1 == frame);
+    }
+}
+
+/// Draws `text` within `region`, scrolling it a step each frame when it is wider than the region.
+fn marquee(
+    frame: &mut Framebuffer,
+    marquees: &Mutex<Marquees>,
+    text: &str,
+    style: BdfTextStyle<BinaryColor>,
+    region: Rectangle,
+) {
+    let width = style
+        .measure_string(text, Point::zero(), Baseline::Top)
+        .bounding_box
+        .size
+        .width;
+    let overflow = i32::try_from(width.saturating_sub(region.size.width)).unwrap_or(i32::MAX);
+    let offset = if overflow > 0 {
+        let mut marquees = marquees.lock().unwrap_or_else(|e| e.into_inner());
+        let current = marquees.frame;
+        let (scroll, drawn) = marquees
+            .scrolls
+            .entry(text.to_owned())
+            .or_insert((Scroll::default(), current));
+        // Text drawn more than once in a frame stays put
+        if *drawn != current {
+            *scroll = scroll.next(overflow);
+            *drawn = current;
+        }
+        scroll.offset
+    } else {
+        0
+    };
+
+    Text::with_baseline(text, region.top_left - Point::new(offset, 0), style, Baseline::Top)
+        .draw(&mut frame.clipped(&region))
+        .unwrap();
+}
+
+/// Offsets the layout cycles through when pixel shifting, staying within a pixel of its home position.
+const PIXEL_SHIFTS: [(i32, i32); 9] = [
+    (0, 0),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// Returns the offset of the whole layout at `timestamp`, or the origin when pixel shifting is disabled.
+fn pixel_shift(period: Option<Duration>, timestamp: i64) -> Point {
+    let Some(period) = period else {
+        return Point::zero();
+    };
+
+    let step = timestamp / i64::try_from(period.as_secs().max(1)).unwrap_or(i64::MAX);
+    let (x, y) = PIXEL_SHIFTS[usize::try_from(step).unwrap_or_default() % PIXEL_SHIFTS.len()];
+    Point::new(x, y)
+}
 
 struct Context {
-    display: Mutex<Display>,
+    /// `None` when simulated, in which case frames are written to a file instead.
+    panel: Option<Mutex<Panel>>,
     fonts: (EgBdfOutput, EgBdfOutput),
+    measurements_fresh_for: Duration,
+    measurements_stale_after: Duration,
+    /// Whether the signal is monitored at all, without which no missing signal is shown.
+    signal_enabled: bool,
+    signal_fresh_for: Duration,
+    signal_stale_after: Duration,
+    night: Option<NightConfig>,
+    /// Contrast configured, which the API may change.
+    contrast: u8,
+    datetime_format: DatetimeFormat,
+    pixel_shift: Option<Duration>,
+    size: Size,
+    /// Each page along with the index of the tank it shows.
+    pages: Vec<(Box<dyn Page>, usize)>,
+    page: Mutex<PageState>,
+    marquees: Mutex<Marquees>,
+    /// Whether there is more than one tank, in which case the pages name the one they show.
+    multiple_tanks: bool,
+    page_dwell: Duration,
+    /// Interface whose address is shown, or the first one with an address when `None`.
+    address_interface: Option<String>,
+    temperature_unit: TemperatureUnit,
+    tds_unit: TdsUnit,
+}
+
+struct PageState {
+    index: usize,
+    shown_at: Instant,
 }
 
 impl Context {
-    async fn new() -> anyhow::Result<Arc<Self>> {
+    async fn new(config: &Config, hardware: Arc<Hardware>, errors: Arc<ErrorLog>) -> anyhow::Result<Arc<Self>> {
+        let simulate = config.simulate;
+        let i2c_bus = config.display.i2c_bus.clone();
+        let size = config.display.size;
+        let driver = config.display.driver;
+        let rotation = config.display.rotation;
+        let night = config.display.night.clone();
+        let contrast = configured_contrast(&config.display);
+        let datetime_format = config.display.datetime_format.clone();
+        let pixel_shift = config
+            .display
+            .pixel_shift
+            .then(|| Duration::from_secs(config.display.pixel_shift_secs));
+        let tanks = config.measurements.tanks().len();
+        let default_tank = config.measurements.default_tank_index();
+        // The readings and the daily statistics are repeated for each tank, while the trend is of the default tank.
+        let pages = config
+            .display
+            .pages
+            .iter()
+            .flat_map(|&kind| {
+                let shown: Vec<_> = if matches!(kind, PageKind::Main | PageKind::Range) {
+                    (0..tanks).collect()
+                } else {
+                    vec![default_tank]
+                };
+                shown
+                    .into_iter()
+                    .map(move |tank| (pages::new(kind, config, tank), tank))
+            })
+            .collect();
+        let page_dwell = config.display.page_dwell();
+        let address_interface = config.display.address_interface.clone();
+        let temperature_unit = config.display.temperature_unit;
+        let tds_unit = config.display.tds_unit;
+        let measurements_fresh_for = config.measurements.fresh_for();
+        let measurements_stale_after = config.measurements.stale_after();
+        let signal_enabled = config.signal.enabled;
+        let signal_fresh_for = config.signal.fresh_for();
+        let signal_stale_after = config.signal.stale_after();
         task::spawn_blocking(move || {
-            let display = {
-                let iwc = I2cdev::new("/dev/i2c-1")?;
-                let iface = I2CDisplayInterface::new(iwc);
-                let mut display =
-                    Ssd1306::new(iface, DisplaySize128x64, DisplayRotation::Rotate0).into_buffered_graphics_mode();
-                display.init().map_err(|e| anyhow!("{e:?}"))?;
-                display.clear_buffer();
-                display.flush().map_err(|e| anyhow!("{e:?}"))?;
-
-                Mutex::new(display)
+            // The display itself is opened on the first draw, so that a missing panel is retried like a failing one.
+            let panel = if simulate {
+                info!(
+                    "Simulating the display, writing frames to {}",
+                    simulation::frame_path().display()
+                );
+                None
+            } else {
+                Some(Mutex::new(Panel {
+                    i2c_bus,
+                    driver,
+                    size,
+                    rotation,
+                    display: None,
+                    consecutive_failures: 0,
+                    retry_at: Instant::now(),
+                    backoff: Panel::MIN_BACKOFF,
+                    power: Power::On(contrast),
+                    contrast,
+                    hardware,
+                    errors,
+                }))
             };
 
             let fonts = (
                 FontConverter::with_string(include_str!("../fonts/ter-u14b.bdf"), "ter_u14b")
                     .glyphs(Mapping::Iso8859_1)
+                    .glyphs('↑'..='↓')
                     .missing_glyph_substitute('?')
                     .convert_eg_bdf()
                     .unwrap(),
@@ -68,110 +675,543 @@ impl Context {
                     .unwrap(),
             );
 
-            Ok(Arc::new(Self { display, fonts }))
+            Ok(Arc::new(Self {
+                panel,
+                fonts,
+                measurements_fresh_for,
+                measurements_stale_after,
+                signal_enabled,
+                signal_fresh_for,
+                signal_stale_after,
+                night,
+                contrast,
+                datetime_format,
+                pixel_shift,
+                size: match size {
+                    PanelSize::Size128x64 => Size::new(128, 64),
+                    PanelSize::Size128x32 => Size::new(128, 32),
+                },
+                pages,
+                page: Mutex::new(PageState {
+                    index: 0,
+                    shown_at: Instant::now(),
+                }),
+                marquees: Mutex::new(Marquees::default()),
+                multiple_tanks: tanks > 1,
+                page_dwell,
+                address_interface,
+                temperature_unit,
+                tds_unit,
+            }))
         })
         .await?
     }
+
+    /// Returns the index of the page to show, moving on to the next one once the current one has been shown long
+    /// enough.
+    fn current_page(&self) -> usize {
+        let mut page = self.page.lock().unwrap_or_else(|e| e.into_inner());
+        if page.shown_at.elapsed() >= self.page_dwell {
+            page.index = (page.index + 1) % self.pages.len();
+            page.shown_at = Instant::now();
+        }
+
+        page.index
+    }
+
+    fn next_page(&self) {
+        let mut page = self.page.lock().unwrap_or_else(|e| e.into_inner());
+        page.index = (page.index + 1) % self.pages.len();
+        page.shown_at = Instant::now();
+    }
+}
+
+/// The display together with the state needed to rebuild it after I2C errors, e.g. when its cable is reseated.
+struct Panel {
+    i2c_bus: PathBuf,
+    driver: DisplayDriver,
+    size: PanelSize,
+    rotation: u16,
+    display: Option<Display>,
+    consecutive_failures: u32,
+    retry_at: Instant,
+    backoff: Duration,
+    power: Power,
+    /// Contrast configured, which the panel is lit at once initialized.
+    contrast: u8,
+    hardware: Arc<Hardware>,
+    errors: Arc<ErrorLog>,
+}
+
+impl Panel {
+    const MIN_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    /// The display is torn down and rebuilt after this many consecutive failed flushes.
+    const REOPEN_AFTER: u32 = 3;
+
+    fn open(&self) -> anyhow::Result<Display> {
+        let mut display = Display::new(&self.i2c_bus, self.driver, self.size, self.rotation)?;
+        display.init()?;
+        display.set_power(Power::On(self.contrast))?;
+        display.clear_buffer();
+        display.flush()?;
+
+        Ok(display)
+    }
+
+    /// Returns the display, opening it first if needed. Returns `None` while waiting to retry a failed open.
+    fn get(&mut self) -> anyhow::Result<Option<&mut Display>> {
+        if self.display.is_none() {
+            if Instant::now() < self.retry_at {
+                return Ok(None);
+            }
+
+            match self.open() {
+                Ok(display) => {
+                    info!("Display initialized");
+                    let detail = format!("display: initialized on {}", self.i2c_bus.display());
+                    self.hardware.record(|report| report.display = Device::found(detail));
+                    self.display = Some(display);
+                    self.backoff = Self::MIN_BACKOFF;
+                    self.power = Power::On(self.contrast);
+                }
+                Err(e) => {
+                    let detail = format!("display: not responding on {} ({e:#})", self.i2c_bus.display());
+                    self.hardware.record(|report| report.display = Device::missing(detail));
+                    let delay = self.backoff;
+                    self.retry_at = Instant::now() + delay;
+                    self.backoff = (self.backoff * 2).min(Self::MAX_BACKOFF);
+                    return Err(e.context(format!(
+                        "Failed to initialize display, retrying in {}s",
+                        delay.as_secs()
+                    )));
+                }
+            }
+        }
+
+        Ok(self.display.as_mut())
+    }
+
+    /// Switches the display to `power` unless it is already there.
+    fn set_power(&mut self, power: Power) -> anyhow::Result<()> {
+        if power == self.power {
+            return Ok(());
+        }
+        let Some(display) = self.display.as_mut() else {
+            return Ok(());
+        };
+
+        let result = display.set_power(power);
+        self.record(result)?;
+        self.power = power;
+
+        Ok(())
+    }
+
+    /// Records the result of a write to the display, tearing the display down after repeated failures.
+    fn record(&mut self, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        if result.is_ok() {
+            self.consecutive_failures = 0;
+            return result;
+        }
+
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= Self::REOPEN_AFTER {
+            warn!(
+                consecutive_failures = self.consecutive_failures;
+                "Display failed {} times in a row, re-initializing",
+                self.consecutive_failures
+            );
+            self.errors
+                .warning("display", "Display failed repeatedly, re-initializing");
+            let detail = format!(
+                "display: failed repeatedly on {}, re-initializing",
+                self.i2c_bus.display()
+            );
+            self.hardware.record(|report| report.display = Device::missing(detail));
+            self.display = None;
+            self.consecutive_failures = 0;
+            self.retry_at = Instant::now();
+        }
+
+        result
+    }
 }
 
-pub(crate) async fn worker() -> anyhow::Result<()> {
-    let mut interval = interval(Duration::from_secs(1));
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let mut interval = interval(config.display.interval());
     interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-    let ctx = Context::new().await?;
+    let ctx = Context::new(&config, state.hardware.clone(), state.errors.clone())
+        .await
+        .inspect_err(|_| state.health.display.failure())?;
+    state.health.display.initialized();
 
     loop {
-        interval.tick().await;
+        select! {
+            _ = interval.tick() => {}
+            () = state.display.pressed.notified() => ctx.next_page(),
+            () = state.display.contrast_changed.notified() => {}
+            () = shutdown.cancelled() => break,
+        }
 
-        if let Err(e) = draw(&ctx).await {
-            error!("Failed to update measurements: {e:?}");
+        match draw(&state, &ctx).await {
+            Ok(true) => state.health.display.success(),
+            Ok(false) => {}
+            Err(e) => {
+                state.health.display.failure();
+                let consecutive_failures = state.health.display.snapshot().consecutive_failures;
+                error!(consecutive_failures; "Failed to update display: {e:?}");
+                state
+                    .errors
+                    .error("display", format!("Failed to update display: {e:#}"));
+            }
         }
     }
+
+    // Leave a blank panel rather than a frozen frame burning into the OLED.
+    clear(&ctx).await
 }
 
-async fn draw(ctx: &Arc<Context>) -> anyhow::Result<()> {
-    let signal = signal::latest().await;
-    let measurements = measurements::latest().await;
+/// Draws the current values. Returns `false` when the display is unavailable and nothing was drawn.
+async fn draw(state: &AppState, ctx: &Arc<Context>) -> anyhow::Result<bool> {
+    // Stale values are drawn as missing so that a dead sensor is not mistaken for a live one.
+    let now = Utc::now();
+    let (signal, signal_age) = shown(
+        state.signal.get().await,
+        |s| s.timestamp,
+        now,
+        ctx.signal_fresh_for,
+        ctx.signal_stale_after,
+    );
+    let page = ctx.current_page();
+    let tank_index = ctx.pages[page].1;
+    let tank = &state.tanks[tank_index];
+    let (measurements, measurements_age) = shown(
+        tank.measurements.get().await,
+        |m| m.timestamp,
+        now,
+        ctx.measurements_fresh_for,
+        ctx.measurements_stale_after,
+    );
+    let history = match ctx.pages[page].0.history_window() {
+        Some(window) => {
+            let since = now.checked_sub_signed(TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX));
+            measurements::history(tank, since, None)
+                .await
+                .into_iter()
+                .map(|m| (m.timestamp, m.temperature))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let local_now = timezone::now();
+    let today = Some(measurements::daily_stats(tank).today);
+    let tank = ctx.multiple_tanks.then(|| tank.name.clone());
+    let cpu_temperature = state.system.get().await.and_then(|s| s.cpu_temperature);
+    let alert = state.alerts.any_active();
+    let maintenance = state.maintenance.is_active();
+    let water_low = state.water_level.is_ok() == Some(false);
+    let water_change = state.water_change.status();
+    let signal_disabled = state.signal_polling.is_disabled();
 
+    let controls = state.display.clone();
     let ctx = ctx.clone();
     task::spawn_blocking(move || {
-        let mut display = ctx.display.lock().map_err(|e| anyhow!("{e:?}"))?;
-        display.clear_buffer();
+        let power = controls.power(ctx.night.as_ref(), ctx.contrast, local_now.naive_local());
 
-        let font_refs = (ctx.fonts.0.as_font(), ctx.fonts.1.as_font());
-        let text_styles = (
-            BdfTextStyle::new(&font_refs.0, BinaryColor::On),
-            BdfTextStyle::new(&font_refs.1, BinaryColor::On),
-        );
-        let line_style = PrimitiveStyleBuilder::new()
-            .stroke_width(1)
-            .stroke_color(BinaryColor::On)
-            .build();
-
-        // Draw current datetime
-        let datetime = Local::now().format("%m·%d %H:%M").to_string();
-        Text::with_baseline(&datetime, Point::new(10, 0), text_styles.0, Baseline::Top)
-            .draw(&mut *display)
-            .unwrap();
-
-        // Draw signal level
-        if let Some(signal) = signal {
-            assert!(signal.quality.is_finite() && signal.quality <= 1.0);
-
-            let level = match signal.quality {
-                q if q < 0.2 => 0,
-                q if q < 0.4 => 1,
-                q if q < 0.6 => 2,
-                q if q < 0.8 => 3,
-                _ => 4,
+        // Nothing is rendered while blanked, sparing the I2C bus.
+        let mut frame = Framebuffer::new(ctx.size);
+        if power != Power::Off {
+            let snapshot = Snapshot {
+                now: local_now,
+                tank,
+                measurements,
+                measurements_age,
+                signal,
+                signal_age,
+                today,
+                address: network::ipv4_address(ctx.address_interface.as_deref()),
+                alert,
+                maintenance,
+                water_low,
+                water_change,
+                signal_disabled,
+                cpu_temperature,
+                history,
+                message: controls.message(now),
             };
-            for i in 1..=level {
-                let x = 107 + i * 2;
-                let y = 12 - i * 2;
-                Line::new(Point::new(x, y), Point::new(x, 11))
-                    .into_styled(line_style)
-                    .draw(&mut *display)
-                    .unwrap();
-            }
+            render(&ctx, &mut frame, ctx.pages[page].0.as_ref(), &snapshot);
         }
+        *controls.frame.lock().unwrap_or_else(|e| e.into_inner()) = Some(frame.clone());
 
-        // Draw temperature
-        let temp: Cow<_> = if let Some(v) = measurements.map(|m| m.temperature) {
-            format!("{v:>7.1}").into()
-        } else {
-            "    -.-".into()
+        let Some(panel) = &ctx.panel else {
+            simulation::write_frame(&frame)?;
+            return Ok(true);
         };
+        let mut panel = panel.lock().unwrap_or_else(|e| e.into_inner());
+        if panel.get()?.is_none() {
+            return Ok(false);
+        }
 
-        Text::with_baseline(&temp, Point::new(0, 16), text_styles.1, Baseline::Top)
-            .draw(&mut *display)
-            .unwrap();
-        Text::with_baseline(&temp, Point::new(1, 16), text_styles.1, Baseline::Top)
-            .draw(&mut *display)
-            .unwrap();
-        Text::with_baseline("°C", Point::new(89, 23), text_styles.0, Baseline::Top)
-            .draw(&mut *display)
-            .unwrap();
-
-        // Draw TDS
-        let tds: Cow<_> = if let Some(v) = measurements.map(|m| m.tds) {
-            format!("{v:>7.0}").into()
-        } else {
-            "      -".into()
+        panel.set_power(power)?;
+        if power == Power::Off {
+            return Ok(true);
+        }
+        let Some(display) = panel.display.as_mut() else {
+            return Ok(false);
         };
+        display.clear_buffer();
+        frame.blit(display).map_err(|e| anyhow!("{e:?}"))?;
+
+        let result = display.flush();
+        panel.record(result)?;
+
+        Ok(true)
+    })
+    .await?
+}
 
-        Text::with_baseline(&tds, Point::new(0, 40), text_styles.1, Baseline::Top)
-            .draw(&mut *display)
+/// Returns `value` unless it is stale, along with its age once it is no longer fresh.
+fn shown<T>(
+    value: Option<T>,
+    timestamp: impl Fn(&T) -> DateTime<Utc>,
+    now: DateTime<Utc>,
+    fresh_for: Duration,
+    stale_after: Duration,
+) -> (Option<T>, Option<Duration>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+
+    match health::freshness(timestamp(&value), now, fresh_for, stale_after) {
+        Freshness::Fresh => (Some(value), None),
+        Freshness::Aged(age) => (Some(value), Some(age)),
+        Freshness::Stale => (None, None),
+    }
+}
+
+/// Draws the header and `page` into `frame`.
+fn render(ctx: &Context, frame: &mut Framebuffer, page: &dyn Page, snapshot: &Snapshot) {
+    ctx.marquees.lock().unwrap_or_else(|e| e.into_inner()).next_frame();
+    let font_refs = (ctx.fonts.0.as_font(), ctx.fonts.1.as_font());
+    let canvas = Canvas {
+        small: BdfTextStyle::new(&font_refs.0, BinaryColor::On),
+        large: BdfTextStyle::new(&font_refs.1, BinaryColor::On),
+        base: pixel_shift(ctx.pixel_shift, snapshot.now.timestamp()),
+        temperature_unit: ctx.temperature_unit,
+        tds_unit: ctx.tds_unit,
+        marquees: &ctx.marquees,
+    };
+    let line_style = PrimitiveStyleBuilder::new()
+        .stroke_width(1)
+        .stroke_color(BinaryColor::On)
+        .build();
+    let right_edge = i32::try_from(frame.size().width).unwrap_or(i32::MAX) - 1;
+    let base = canvas.base;
+
+    // Draw current datetime, with the date giving way to a banner during maintenance, or to the name of the tank
+    let format = &ctx.datetime_format;
+    let datetime = match &snapshot.tank {
+        _ if snapshot.maintenance => format!("MAINT {}", snapshot.now.format(format.time())),
+        Some(tank) => format!("{tank:<5.5} {}", snapshot.now.format(format.time())),
+        None => snapshot.now.format(format.datetime()).to_string(),
+    };
+    // Cut it short rather than running into the signal level
+    let datetime = fit(&datetime, &canvas.small, HEADER_TEXT_WIDTH);
+    Text::with_baseline(datetime, base + Point::new(10, 0), canvas.small, Baseline::Top)
+        .draw(frame)
+        .unwrap();
+
+    // Draw an alert indicator in front of it
+    if snapshot.alert {
+        Text::with_baseline("!", base + Point::new(2, 0), canvas.small, Baseline::Top)
+            .draw(frame)
             .unwrap();
-        Text::with_baseline(&tds, Point::new(1, 40), text_styles.1, Baseline::Top)
-            .draw(&mut *display)
+    }
+
+    // Draw a downward triangle left of the signal level when the water is low
+    if snapshot.water_low {
+        let left = (base.x + 96).min(right_edge - 18);
+        Triangle::new(
+            Point::new(left, base.y + 4),
+            Point::new(left + 6, base.y + 4),
+            Point::new(left + 3, base.y + 10),
+        )
+        .into_styled(line_style)
+        .draw(frame)
+        .unwrap();
+    }
+
+    // Draw signal level, or a cross when there is no recent poll
+    if let Some(signal) = &snapshot.signal {
+        let level = match signal.quality {
+            q if q < 0.2 => 0,
+            q if q < 0.4 => 1,
+            q if q < 0.6 => 2,
+            q if q < 0.8 => 3,
+            _ => 4,
+        };
+        for i in 1..=level {
+            let x = (base.x + 107 + i * 2).min(right_edge);
+            let y = base.y + 12 - i * 2;
+            Line::new(Point::new(x, y), Point::new(x, base.y + 11))
+                .into_styled(line_style)
+                .draw(frame)
+                .unwrap();
+        }
+    } else if ctx.signal_enabled && !snapshot.signal_disabled {
+        let left = (base.x + 109).min(right_edge - 6);
+        for (from, to) in [((0, 4), (6, 10)), ((0, 10), (6, 4))] {
+            Line::new(
+                Point::new(left + from.0, base.y + from.1),
+                Point::new(left + to.0, base.y + to.1),
+            )
+            .into_styled(line_style)
+            .draw(frame)
             .unwrap();
-        Text::with_baseline("ppm", Point::new(90, 47), text_styles.0, Baseline::Top)
-            .draw(&mut *display)
+        }
+    }
+
+    page.render(frame, &canvas, snapshot);
+
+    // Draw the message over the bottom row
+    if let Some(message) = &snapshot.message {
+        let size = frame.size();
+        let top = i32::try_from(size.height.saturating_sub(MESSAGE_ROW_HEIGHT)).unwrap_or_default();
+        let region = Rectangle::new(Point::new(0, top), Size::new(size.width, MESSAGE_ROW_HEIGHT));
+        region
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+            .draw(frame)
             .unwrap();
 
-        display.flush().map_err(|e| anyhow!("{e:?}"))?;
+        marquee(frame, &ctx.marquees, message, canvas.small, region);
+    }
+}
+
+async fn clear(ctx: &Arc<Context>) -> anyhow::Result<()> {
+    let ctx = ctx.clone();
+    task::spawn_blocking(move || {
+        let Some(panel) = &ctx.panel else {
+            return Ok(());
+        };
+        let mut panel = panel.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(display) = panel.display.as_mut() {
+            display.clear_buffer();
+            display.flush()?;
+        }
 
         Ok(())
     })
     .await?
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::config::DatetimePreset;
+
+    /// The font of the header.
+    fn small_font() -> EgBdfOutput {
+        FontConverter::with_string(include_str!("../fonts/ter-u14b.bdf"), "ter_u14b")
+            .glyphs(Mapping::Iso8859_1)
+            .missing_glyph_substitute('?')
+            .convert_eg_bdf()
+            .unwrap()
+    }
+
+    /// Renders 2025-06-01 21:30 in `format` as the header does, returning the text drawn and the column right of the
+    /// last lit pixel, relative to where the text starts.
+    fn render_datetime(format: &DatetimeFormat) -> (String, i32) {
+        let font = small_font();
+        let font = font.as_font();
+        let style = BdfTextStyle::new(&font, BinaryColor::On);
+        let sample = NaiveDate::from_ymd_opt(2025, 6, 1)
+            .and_then(|date| date.and_hms_opt(21, 30, 0))
+            .unwrap();
+        let datetime = sample.format(format.datetime()).to_string();
+        let text = fit(&datetime, &style, HEADER_TEXT_WIDTH);
+
+        let mut frame = Framebuffer::new(Size::new(256, 16));
+        Text::with_baseline(text, Point::zero(), style, Baseline::Top)
+            .draw(&mut frame)
+            .unwrap();
+        let right = (0..256)
+            .filter(|&x| (0..16).any(|y| frame.is_on(Point::new(x, y))))
+            .max()
+            .map_or(0, |x| x + 1);
+
+        (text.to_owned(), right)
+    }
+
+    #[test]
+    fn presets_fit_in_the_header_whole() {
+        for (preset, expected) in [
+            (DatetimePreset::MonthFirst, "06·01 21:30"),
+            (DatetimePreset::DayFirst, "01·06 21:30"),
+            (DatetimePreset::TwelveHour, "06·01 9:30pm"),
+        ] {
+            let (text, right) = render_datetime(&DatetimeFormat::Preset(preset));
+            assert_eq!(text, expected);
+            assert!(right <= HEADER_TEXT_WIDTH as i32, "{text}: {right}");
+        }
+    }
+
+    #[test]
+    fn a_custom_format_that_fits_is_shown_whole() {
+        let (text, right) = render_datetime(&DatetimeFormat::Custom("%a %H:%M".to_owned()));
+        assert_eq!(text, "Sun 21:30");
+        assert!(right <= HEADER_TEXT_WIDTH as i32, "{text}: {right}");
+    }
+
+    #[test]
+    fn a_custom_format_too_wide_is_cut_short() {
+        let (text, right) = render_datetime(&DatetimeFormat::Custom("%A %d %B %Y %H:%M".to_owned()));
+        assert_eq!(text, "Sunday 01 Jun");
+        assert!(right <= HEADER_TEXT_WIDTH as i32, "{text}: {right}");
+    }
+
+    /// The positions text `overflow` pixels too wide goes through in `frames` frames, as offsets.
+    fn offsets(overflow: i32, frames: usize) -> Vec<i32> {
+        let mut scroll = Scroll::default();
+        (0..frames)
+            .map(|_| {
+                scroll = scroll.next(overflow);
+                scroll.offset
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scroll_rests_at_the_start_then_steps_to_the_end() {
+        assert_eq!(offsets(10, 6), [0, 0, 0, 4, 8, 10]);
+    }
+
+    #[test]
+    fn scroll_rests_at_the_end_then_starts_over() {
+        assert_eq!(offsets(10, 14)[5..], [10, 10, 10, 10, 0, 0, 0, 0, 4]);
+    }
+
+    #[test]
+    fn scroll_stops_exactly_at_the_end() {
+        assert_eq!(offsets(8, 7), [0, 0, 0, 4, 8, 8, 8]);
+        assert_eq!(offsets(1, 5), [0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn marquees_forget_text_not_drawn_in_the_last_frame() {
+        let mut marquees = Marquees::default();
+        marquees.scrolls.insert("drawn".to_owned(), (Scroll::default(), 0));
+        marquees.scrolls.insert("gone".to_owned(), (Scroll::default(), 0));
+
+        marquees.next_frame();
+        marquees.scrolls.get_mut("drawn").unwrap().1 = 1;
+        marquees.next_frame();
+
+        assert_eq!(marquees.scrolls.keys().collect::<Vec<_>>(), ["drawn"]);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::convert::Infallible;
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+/// Off-screen copy of what the panel shows, which every frame is rendered into before being sent to the panel.
+#[derive(Debug, Clone)]
+pub(crate) struct Framebuffer {
+    size: Size,
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            pixels: vec![false; (size.width * size.height) as usize],
+        }
+    }
+
+    /// Copies the frame onto `target`.
+    pub fn blit<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        target.fill_contiguous(
+            &self.bounding_box(),
+            self.pixels.iter().map(|&on| BinaryColor::from(on)),
+        )
+    }
+
+    /// Encodes the frame as a grayscale PNG.
+    pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.size.width, self.size.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.pixels.iter().map(|&on| if on { 0xff } else { 0x00 }).collect();
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+
+        Ok(png)
+    }
+
+    /// Whether the pixel at `point` is lit, which is off for one outside the frame.
+    #[cfg(test)]
+    pub fn is_on(&self, point: Point) -> bool {
+        match (u32::try_from(point.x), u32::try_from(point.y)) {
+            (Ok(x), Ok(y)) if x < self.size.width && y < self.size.height => {
+                self.pixels[(y * self.size.width + x) as usize]
+            }
+            _ => false,
+        }
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y))
+                && x < self.size.width
+                && y < self.size.height
+            {
+                self.pixels[(y * self.size.width + x) as usize] = color.is_on();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use embedded_graphics::{
+        mock_display::MockDisplay,
+        primitives::{Line, PrimitiveStyle},
+    };
+
+    use super::*;
+
+    /// A 6×3 frame with a pixel in its top left corner and a line along its bottom row.
+    fn frame() -> Framebuffer {
+        let mut frame = Framebuffer::new(Size::new(6, 3));
+        Pixel(Point::new(0, 0), BinaryColor::On).draw(&mut frame).unwrap();
+        Line::new(Point::new(1, 2), Point::new(4, 2))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut frame)
+            .unwrap();
+        // Off the frame, and so dropped
+        Pixel(Point::new(6, 0), BinaryColor::On).draw(&mut frame).unwrap();
+        Pixel(Point::new(-1, 1), BinaryColor::On).draw(&mut frame).unwrap();
+
+        frame
+    }
+
+    #[test]
+    fn blits_the_pixels_drawn() {
+        let mut display = MockDisplay::new();
+        frame().blit(&mut display).unwrap();
+
+        #[rustfmt::skip]
+        display.assert_pattern(&[
+            "#.....",
+            "......",
+            ".####.",
+        ]);
+    }
+
+    #[test]
+    fn encodes_the_pixels_drawn() {
+        let png = frame().to_png().unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (6, 3));
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        #[rustfmt::skip]
+        assert_eq!(data, [
+            0xff, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0xff, 0xff, 0xff, 0xff, 0x00,
+        ]);
+    }
+}
@@ -0,0 +1,616 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{borrow::Cow, net::Ipv4Addr, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use eg_bdf::BdfTextStyle;
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text, renderer::TextRenderer},
+};
+
+use super::{Framebuffer, Marquees, marquee};
+use crate::{
+    config::{AlertMetric, Config, PageKind, TdsUnit, TemperatureUnit},
+    measurements::{DailyStats, Measurements},
+    signal::Signal,
+    water_change::WaterChange,
+};
+
+/// Values that do not fit on the panel at once are cycled through at this period.
+const ALTERNATE_PERIOD_SECS: i64 = 5;
+
+/// Drawing parameters shared by all pages.
+pub(super) struct Canvas<'a> {
+    pub small: BdfTextStyle<'a, BinaryColor>,
+    pub large: BdfTextStyle<'a, BinaryColor>,
+    /// Origin of the layout, which moves around when pixel shifting.
+    pub base: Point,
+    pub temperature_unit: TemperatureUnit,
+    pub tds_unit: TdsUnit,
+    /// Scroll positions of the text too wide for its region.
+    pub marquees: &'a Mutex<Marquees>,
+}
+
+/// Everything a page may show, gathered once per draw. Stale values have already been dropped.
+pub(super) struct Snapshot {
+    /// In the configured zone.
+    pub now: DateTime<FixedOffset>,
+    /// Name of the tank the page shows, only when there is more than one.
+    pub tank: Option<String>,
+    pub measurements: Option<Measurements>,
+    /// Age of the measurements once they are no longer fresh.
+    pub measurements_age: Option<Duration>,
+    pub signal: Option<Signal>,
+    /// Age of the signal reading once it is no longer fresh.
+    pub signal_age: Option<Duration>,
+    /// Statistics of the tank since local midnight.
+    pub today: Option<DailyStats>,
+    pub address: Option<Ipv4Addr>,
+    /// Whether any alert is firing.
+    pub alert: bool,
+    pub maintenance: bool,
+    /// Whether the float switch tells the water is low.
+    pub water_low: bool,
+    /// `None` unless the water change reminder is configured.
+    pub water_change: Option<WaterChange>,
+    /// Whether there is no wireless interface to monitor, in which case no missing signal is shown.
+    pub signal_disabled: bool,
+    /// CPU temperature of the board in °C.
+    pub cpu_temperature: Option<f64>,
+    /// Temperatures of the tank over the window of the page, oldest first. Only gathered for pages that ask for it.
+    pub history: Vec<(DateTime<Utc>, f64)>,
+    /// Text pushed through the API to be shown over the bottom row.
+    pub message: Option<String>,
+}
+
+/// The area below the header, which shows one page at a time.
+pub(super) trait Page: Send + Sync {
+    fn render(&self, frame: &mut Framebuffer, canvas: &Canvas, snapshot: &Snapshot);
+
+    /// How far back the history of the tank is wanted in the snapshot, if at all.
+    fn history_window(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Returns the page of `kind` showing the tank at index `tank`.
+pub(super) fn new(kind: PageKind, config: &Config, tank: usize) -> Box<dyn Page> {
+    match kind {
+        PageKind::Main => Box::new(Main {
+            tds_bar: config.display.tds_bar.as_ref().map(|bar| TdsBar {
+                min: bar.min,
+                max: bar.max,
+                thresholds: tds_thresholds(config, tank),
+            }),
+        }),
+        PageKind::Range => Box::new(DailyRange),
+        PageKind::Network => Box::new(Network),
+        PageKind::Trend => Box::new(Trend {
+            window: config.display.trend_window(),
+            max_gap: config.measurements.stale_after(),
+        }),
+    }
+}
+
+/// Temperature and TDS in the large font.
+struct Main {
+    tds_bar: Option<TdsBar>,
+}
+
+/// Returns `temperature` in °C as shown in `unit` on the main page, right-aligned to the same width whether or not
+/// it takes three digits, so that the label after it stays put.
+fn temperature_column(unit: TemperatureUnit, temperature: Option<f64>) -> Cow<'static, str> {
+    match temperature {
+        Some(v) => format!("{:>7.1}", unit.convert(v)).into(),
+        None => "    -.-".into(),
+    }
+}
+
+/// Where the TDS stands in a target range, with ticks at the thresholds of the alerts on it. In ppm.
+struct TdsBar {
+    min: f64,
+    max: f64,
+    thresholds: Vec<f64>,
+}
+
+impl Page for Main {
+    fn render(&self, frame: &mut Framebuffer, canvas: &Canvas, snapshot: &Snapshot) {
+        let unit = canvas.temperature_unit;
+        let temp = temperature_column(unit, snapshot.measurements.as_ref().map(|m| m.temperature));
+        // The conductivity label is wider, so its value takes a column less.
+        let tds: Cow<_> = match (canvas.tds_unit, &snapshot.measurements) {
+            (TdsUnit::Ppm, Some(m)) => format!("{:>7.0}", m.tds).into(),
+            (TdsUnit::Ppm, None) => "      -".into(),
+            (TdsUnit::Ec, Some(m)) => format!("{:>6.0}", m.ec).into(),
+            (TdsUnit::Ec, None) => "     -".into(),
+        };
+        let tds_unit_x = match canvas.tds_unit {
+            TdsUnit::Ppm => 90,
+            TdsUnit::Ec => 78,
+        };
+        let values = [(temp, unit.symbol(), 89), (tds, canvas.tds_unit.symbol(), tds_unit_x)];
+
+        // Take turns when the panel has room for only one of them
+        let tall = frame.size().height >= 64;
+        let rows: &[i32] = if tall { &[11, 42] } else { &[9] };
+        let first = if rows.len() < values.len() {
+            usize::try_from(snapshot.now.timestamp() / ALTERNATE_PERIOD_SECS).unwrap_or_default()
+        } else {
+            0
+        };
+        for (i, &y) in rows.iter().enumerate() {
+            let shown = (first + i) % values.len();
+            let (value, unit, unit_x) = &values[shown];
+            Text::with_baseline(value, canvas.base + Point::new(0, y), canvas.large, Baseline::Top)
+                .draw(frame)
+                .unwrap();
+            Text::with_baseline(value, canvas.base + Point::new(1, y), canvas.large, Baseline::Top)
+                .draw(frame)
+                .unwrap();
+            Text::with_baseline(
+                unit,
+                canvas.base + Point::new(*unit_x, y + 7),
+                canvas.small,
+                Baseline::Top,
+            )
+            .draw(frame)
+            .unwrap();
+
+            // The bar sits right below the digits, clear of the descenders of the unit
+            if let Some(bar) = self.tds_bar.as_ref().filter(|_| shown == 1) {
+                let tds = snapshot.measurements.as_ref().map(|m| m.tds);
+                bar.draw(
+                    frame,
+                    canvas.base + Point::new(0, y + 19),
+                    tds,
+                    snapshot.now.timestamp(),
+                );
+            }
+
+            // Both values are of the same reading, so the age is told once, in the room the first one leaves
+            if let Some(age) = snapshot.measurements_age.filter(|_| i == 0) {
+                Text::with_baseline(
+                    &format_age(age),
+                    canvas.base + Point::new(0, y + 7),
+                    canvas.small,
+                    Baseline::Top,
+                )
+                .draw(frame)
+                .unwrap();
+            }
+        }
+
+        // Squeeze today's range of the temperature in between
+        if let Some(today) = snapshot.today.as_ref().filter(|_| tall) {
+            let today = &today.temperature;
+            let range = match today.min.zip(today.max) {
+                Some((min, max)) => format!("↓{:.1} ↑{:.1}", unit.convert(min), unit.convert(max)),
+                None => "↓-.- ↑-.-".to_owned(),
+            };
+            Text::with_baseline(&range, canvas.base + Point::new(20, 31), canvas.small, Baseline::Top)
+                .draw(frame)
+                .unwrap();
+        }
+    }
+}
+
+impl TdsBar {
+    /// Width of the bar, which spans the digits of the value.
+    const WIDTH: i32 = 85;
+
+    /// Draws the bar with its top left at `origin`. The marker blinks while `tds` is out of the range.
+    fn draw(&self, frame: &mut Framebuffer, origin: Point, tds: Option<f64>, now: i64) {
+        let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        Line::new(origin + Point::new(0, 1), origin + Point::new(Self::WIDTH - 1, 1))
+            .into_styled(style)
+            .draw(frame)
+            .unwrap();
+        for &threshold in &self.thresholds {
+            let (x, _) = self.position(threshold);
+            Line::new(origin + Point::new(x, 0), origin + Point::new(x, 2))
+                .into_styled(style)
+                .draw(frame)
+                .unwrap();
+        }
+
+        let Some(tds) = tds else {
+            return;
+        };
+        let (x, clamped) = self.position(tds);
+        if clamped && now % 2 != 0 {
+            return;
+        }
+        Rectangle::new(origin + Point::new(x - 1, 0), Size::new(3, 3))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(frame)
+            .unwrap();
+    }
+
+    /// Returns the column of `tds` on the bar, clamped to the ends, and whether it had to be.
+    fn position(&self, tds: f64) -> (i32, bool) {
+        let fraction = (tds - self.min) / (self.max - self.min);
+        let clamped = !(0.0..=1.0).contains(&fraction);
+        // The marker is three pixels wide, so it stops a pixel short of either end
+        let x = 1 + scale(fraction.clamp(0.0, 1.0), Self::WIDTH - 3);
+
+        (x, clamped)
+    }
+}
+
+/// Returns the thresholds of the alerts watching the TDS of the tank at index `tank`.
+fn tds_thresholds(config: &Config, tank: usize) -> Vec<f64> {
+    let tanks = config.measurements.tanks();
+    let default_tank = &tanks[config.measurements.default_tank_index()].name;
+    let name = &tanks[tank].name;
+
+    config
+        .alerts
+        .iter()
+        .filter(|rule| rule.metric == AlertMetric::Tds && rule.tank.as_ref().unwrap_or(default_tank) == name)
+        .map(|rule| rule.threshold)
+        .collect()
+}
+
+/// Lowest and highest values since midnight, with the water change counting down beside the title.
+struct DailyRange;
+
+impl Page for DailyRange {
+    fn render(&self, frame: &mut Framebuffer, canvas: &Canvas, snapshot: &Snapshot) {
+        let (temperature, tds, ec) = snapshot
+            .today
+            .map(|today| (today.temperature, today.tds, today.ec))
+            .unwrap_or_default();
+        let unit = canvas.temperature_unit;
+        let temperature = match temperature.min.zip(temperature.max) {
+            Some((min, max)) => format!(
+                "{:>5.1} -{:>5.1} {}",
+                unit.convert(min),
+                unit.convert(max),
+                unit.symbol()
+            ),
+            None => format!("  -.- -  -.- {}", unit.symbol()),
+        };
+        let tds = match canvas.tds_unit {
+            TdsUnit::Ppm => match tds.min.zip(tds.max) {
+                Some((min, max)) => format!("{min:>5.0} -{max:>5.0} ppm"),
+                None => "    - -    - ppm".to_owned(),
+            },
+            TdsUnit::Ec => match ec.min.zip(ec.max) {
+                Some((min, max)) => format!("{min:>4.0} -{max:>4.0} µS/cm"),
+                None => "   - -   - µS/cm".to_owned(),
+            },
+        };
+
+        draw_lines(frame, canvas, 0, &["Today".to_owned(), temperature, tds]);
+
+        let label = snapshot
+            .water_change
+            .as_ref()
+            .and_then(|water_change| water_change_label(water_change, snapshot.now.timestamp()));
+        if let Some(label) = label {
+            let width = canvas
+                .small
+                .measure_string(&label, Point::zero(), Baseline::Top)
+                .bounding_box
+                .size
+                .width;
+            let x = i32::try_from(frame.size().width.saturating_sub(width + 4)).unwrap_or_default();
+            let origin = Point::new(canvas.base.x + x, line_origin(canvas, 0).y);
+            Text::with_baseline(&label, origin, canvas.small, Baseline::Top)
+                .draw(frame)
+                .unwrap();
+        }
+    }
+}
+
+/// Returns the water change countdown, such as `WC in 3d`, or `WC due` once overdue, which blinks. `None` while it
+/// is blinked off.
+fn water_change_label(water_change: &WaterChange, now: i64) -> Option<String> {
+    match water_change.days_remaining {
+        _ if water_change.overdue => (now % 2 == 0).then(|| "WC due".to_owned()),
+        ..=0 => Some("WC today".to_owned()),
+        days => Some(format!("WC in {days}d")),
+    }
+}
+
+/// Wi-Fi network, IP address, link quality, and the CPU temperature.
+struct Network;
+
+impl Page for Network {
+    fn render(&self, frame: &mut Framebuffer, canvas: &Canvas, snapshot: &Snapshot) {
+        let ssid = match &snapshot.signal {
+            Some(s) if !s.associated => "not connected".to_owned(),
+            s => s
+                .as_ref()
+                .and_then(|s| s.ssid.clone())
+                .unwrap_or_else(|| "-".to_owned()),
+        };
+        let ssid = match snapshot.signal_age {
+            Some(age) => format!("{ssid} ({})", format_age(age)),
+            None => ssid,
+        };
+        let address = snapshot.address.map_or_else(|| "no ip".to_owned(), |a| a.to_string());
+        let quality = snapshot
+            .signal
+            .as_ref()
+            .map_or_else(|| "-".to_owned(), |s| format!("{:.0}%", s.quality * 100.0));
+
+        let unit = canvas.temperature_unit;
+        let cpu = snapshot.cpu_temperature.map_or_else(
+            || "-".to_owned(),
+            |t| format!("{:.0}{}", unit.convert(t), unit.symbol()),
+        );
+
+        // Long SSIDs scroll by, while the rest are short enough to fit
+        let region = Rectangle::new(
+            line_origin(canvas, 0),
+            Size::new(frame.size().width.saturating_sub(4), 14),
+        );
+        marquee(frame, canvas.marquees, &ssid, canvas.small, region);
+        draw_lines(frame, canvas, 1, &[address, format!("Signal {quality} CPU {cpu}")]);
+    }
+}
+
+/// Temperature over a window as a line graph, scaled to its lowest and highest values.
+struct Trend {
+    window: Duration,
+    /// Readings further apart than this are not joined.
+    max_gap: Duration,
+}
+
+impl Page for Trend {
+    fn render(&self, frame: &mut Framebuffer, canvas: &Canvas, snapshot: &Snapshot) {
+        let unit = canvas.temperature_unit;
+        let current = snapshot.measurements.as_ref().map_or_else(
+            || format!("-.-{}", unit.symbol()),
+            |m| format!("{:.1}{}", unit.convert(m.temperature), unit.symbol()),
+        );
+        let temperatures = snapshot.history.iter().map(|&(_, t)| t);
+        let min = temperatures.clone().reduce(f64::min);
+        let max = temperatures.reduce(f64::max);
+        let range = match min.zip(max) {
+            Some((min, max)) => format!("↓{:.1} ↑{:.1}", unit.convert(min), unit.convert(max)),
+            None => "↓-.- ↑-.-".to_owned(),
+        };
+        Text::with_baseline(&current, canvas.base + Point::new(4, 18), canvas.small, Baseline::Top)
+            .draw(frame)
+            .unwrap();
+        Text::with_baseline(&range, canvas.base + Point::new(52, 18), canvas.small, Baseline::Top)
+            .draw(frame)
+            .unwrap();
+
+        // A single point makes no line
+        let (Some(min), Some(max)) = (min, max) else {
+            return;
+        };
+        if snapshot.history.len() < 2 {
+            return;
+        }
+
+        let size = frame.size();
+        let width = i32::try_from(size.width).unwrap_or(i32::MAX);
+        let top = 31;
+        let bottom = i32::try_from(size.height).unwrap_or(i32::MAX) - 2;
+        if bottom <= top {
+            return;
+        }
+
+        let columns = trend_columns(&snapshot.history, snapshot.now.to_utc(), self.window, size.width);
+        let span = max - min;
+        let y_of = |t: f64| {
+            // A flat line is drawn across the middle
+            let level = if span > 0.0 { (t - min) / span } else { 0.5 };
+            bottom - scale(level, bottom - top)
+        };
+        let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        // Readings too far apart to be told apart from a dead sensor leave a gap rather than being bridged
+        let max_gap = TimeDelta::from_std(self.max_gap).unwrap_or(TimeDelta::MAX);
+        let mut previous: Option<(Point, DateTime<Utc>)> = None;
+        for (x, column) in (0..width).zip(columns) {
+            let Some(column) = column else {
+                continue;
+            };
+            let point = canvas.base + Point::new(x, y_of(column.mean));
+            let from = previous
+                .filter(|&(_, last)| column.first - last <= max_gap)
+                .map_or(point, |(from, _)| from);
+            Line::new(from, point).into_styled(style).draw(frame).unwrap();
+            previous = Some((point, column.last));
+        }
+    }
+
+    fn history_window(&self) -> Option<Duration> {
+        Some(self.window)
+    }
+}
+
+/// Readings averaged into a column of the trend graph.
+struct TrendColumn {
+    mean: f64,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+}
+
+/// Averages `history` into `width` columns spanning `window` up to `now`. Columns no reading falls in are `None`.
+fn trend_columns(
+    history: &[(DateTime<Utc>, f64)],
+    now: DateTime<Utc>,
+    window: Duration,
+    width: u32,
+) -> Vec<Option<TrendColumn>> {
+    // Sum and count of the readings in each column, along with the first and the last of them
+    let mut columns = vec![None; usize::try_from(width).unwrap_or_default()];
+    let window_millis = i64::try_from(window.as_millis()).unwrap_or(i64::MAX).max(1);
+    let start = now.timestamp_millis().saturating_sub(window_millis);
+    for &(timestamp, t) in history {
+        let offset = timestamp
+            .timestamp_millis()
+            .saturating_sub(start)
+            .clamp(0, window_millis - 1);
+        let index = i128::from(offset) * i128::from(width) / i128::from(window_millis);
+        if let Some(column) = usize::try_from(index).ok().and_then(|i| columns.get_mut(i)) {
+            *column = Some(match *column {
+                Some((sum, count, first, _)) => (sum + t, count + 1, first, timestamp),
+                None => (t, 1, timestamp, timestamp),
+            });
+        }
+    }
+
+    columns
+        .into_iter()
+        .map(|column| {
+            column.map(|(sum, count, first, last)| TrendColumn {
+                mean: sum / f64::from(count),
+                first,
+                last,
+            })
+        })
+        .collect()
+}
+
+/// Returns `fraction` of `length` rounded to the nearest pixel, for a `fraction` between 0 and 1.
+fn scale(fraction: f64, length: i32) -> i32 {
+    let scaled = fraction * f64::from(length);
+    (0..=length).rfind(|&i| f64::from(i) <= scaled + 0.5).unwrap_or(0)
+}
+
+/// Formats `age` in a few characters, such as `45s` or `12m`, to fit beside a value.
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs @ ..100 => format!("{secs}s"),
+        secs @ ..6000 => format!("{}m", secs / 60),
+        secs => format!("{}h", secs / 3600),
+    }
+}
+
+/// Draws lines of small text below the header from line `first` on, as many as fit on the panel.
+fn draw_lines(frame: &mut Framebuffer, canvas: &Canvas, first: usize, lines: &[String]) {
+    for (i, line) in lines.iter().enumerate() {
+        Text::with_baseline(line, line_origin(canvas, first + i), canvas.small, Baseline::Top)
+            .draw(frame)
+            .unwrap();
+    }
+}
+
+/// Returns where line `index` of small text below the header starts.
+fn line_origin(canvas: &Canvas, index: usize) -> Point {
+    let y = i32::try_from(index).map_or(i32::MAX, |i| 18 + i * 14);
+    canvas.base + Point::new(4, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fahrenheit_is_converted_exactly() {
+        assert_eq!(TemperatureUnit::Fahrenheit.convert(0.0), 32.0);
+        assert_eq!(TemperatureUnit::Fahrenheit.convert(100.0), 212.0);
+        assert_eq!(TemperatureUnit::Fahrenheit.convert(-40.0), -40.0);
+        assert_eq!(TemperatureUnit::Celsius.convert(25.6), 25.6);
+    }
+
+    #[test]
+    fn temperature_is_rounded_to_a_decimal() {
+        // 78.08 °F
+        assert_eq!(temperature_column(TemperatureUnit::Fahrenheit, Some(25.6)), "   78.1");
+        assert_eq!(temperature_column(TemperatureUnit::Celsius, Some(24.46)), "   24.5");
+    }
+
+    #[test]
+    fn temperature_column_keeps_its_width_past_100_fahrenheit() {
+        // 99.896 °F and 100.004 °F
+        let below = temperature_column(TemperatureUnit::Fahrenheit, Some(37.72));
+        let above = temperature_column(TemperatureUnit::Fahrenheit, Some(37.78));
+        let missing = temperature_column(TemperatureUnit::Fahrenheit, None);
+
+        assert_eq!(below, "   99.9");
+        assert_eq!(above, "  100.0");
+        assert_eq!(below.len(), above.len());
+        assert_eq!(missing.len(), above.len());
+    }
+
+    #[test]
+    fn age_is_told_in_seconds_then_minutes_then_hours() {
+        assert_eq!(format_age(Duration::from_secs(0)), "0s");
+        assert_eq!(format_age(Duration::from_secs(99)), "99s");
+        assert_eq!(format_age(Duration::from_secs(100)), "1m");
+        assert_eq!(format_age(Duration::from_secs(5999)), "99m");
+        assert_eq!(format_age(Duration::from_secs(6000)), "1h");
+    }
+
+    /// A bar over 100-300 ppm, with ticks at 150 and 250 ppm.
+    fn tds_bar() -> TdsBar {
+        TdsBar {
+            min: 100.0,
+            max: 300.0,
+            thresholds: vec![150.0, 250.0],
+        }
+    }
+
+    /// Draws `tds_bar` into a frame of its own size, at the given second.
+    fn draw_bar(tds: Option<f64>, now: i64) -> Framebuffer {
+        let mut frame = Framebuffer::new(Size::new(TdsBar::WIDTH as u32, 3));
+        tds_bar().draw(&mut frame, Point::zero(), tds, now);
+        frame
+    }
+
+    /// Columns lit in row `y` of `frame`.
+    fn lit_columns(frame: &Framebuffer, y: i32) -> Vec<i32> {
+        (0..TdsBar::WIDTH).filter(|&x| frame.is_on(Point::new(x, y))).collect()
+    }
+
+    #[test]
+    fn bar_positions_span_the_range() {
+        let bar = tds_bar();
+        assert_eq!(bar.position(100.0), (1, false));
+        assert_eq!(bar.position(200.0), (42, false));
+        assert_eq!(bar.position(300.0), (TdsBar::WIDTH - 2, false));
+    }
+
+    #[test]
+    fn bar_positions_clamp_to_the_ends() {
+        let bar = tds_bar();
+        assert_eq!(bar.position(99.0), (1, true));
+        assert_eq!(bar.position(0.0), (1, true));
+        assert_eq!(bar.position(301.0), (TdsBar::WIDTH - 2, true));
+    }
+
+    #[test]
+    fn bar_has_a_line_with_ticks_at_the_thresholds() {
+        let frame = draw_bar(None, 0);
+
+        assert_eq!(lit_columns(&frame, 1), (0..TdsBar::WIDTH).collect::<Vec<_>>());
+        let ticks = vec![tds_bar().position(150.0).0, tds_bar().position(250.0).0];
+        assert_eq!(ticks, [22, 63]);
+        assert_eq!(lit_columns(&frame, 0), ticks);
+        assert_eq!(lit_columns(&frame, 2), ticks);
+    }
+
+    #[test]
+    fn bar_marker_is_three_pixels_around_the_value() {
+        let frame = draw_bar(Some(200.0), 1);
+
+        for y in [0, 2] {
+            assert_eq!(lit_columns(&frame, y), [22, 41, 42, 43, 63], "{y}");
+        }
+    }
+
+    #[test]
+    fn bar_marker_blinks_out_of_the_range() {
+        let end = TdsBar::WIDTH - 2;
+
+        let shown = draw_bar(Some(400.0), 0);
+        assert_eq!(lit_columns(&shown, 0), [22, 63, end - 1, end, end + 1]);
+
+        let hidden = draw_bar(Some(400.0), 1);
+        assert_eq!(lit_columns(&hidden, 0), [22, 63]);
+    }
+}
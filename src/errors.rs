@@ -0,0 +1,110 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, Utc, serde::ts_milliseconds};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct Event {
+    pub severity: Severity,
+    /// Worker or module the event comes from, such as `measurements`.
+    pub source: &'static str,
+    pub message: String,
+    /// First occurrence, in milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub timestamp: DateTime<Utc>,
+    /// Latest occurrence, in milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub last_timestamp: DateTime<Utc>,
+    /// Number of times the event has occurred in a row.
+    pub count: u64,
+}
+
+/// Recent errors and warnings, kept for looking into problems without access to the journal.
+///
+/// Guarded by a plain mutex held only to push or copy, so that workers can record from anywhere without awaiting.
+pub(crate) struct ErrorLog {
+    recent: Mutex<VecDeque<Event>>,
+    /// Nothing is kept when 0.
+    capacity: usize,
+}
+
+impl ErrorLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            recent: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    pub fn error(&self, source: &'static str, message: impl Into<String>) {
+        self.record(Severity::Error, source, message.into());
+    }
+
+    pub fn warning(&self, source: &'static str, message: impl Into<String>) {
+        self.record(Severity::Warning, source, message.into());
+    }
+
+    /// Records an event, or counts it as a repeat when it is the same as the latest one from `source`.
+    ///
+    /// Repeats are told apart per source, since the workers fail independently and their events interleave.
+    fn record(&self, severity: Severity, source: &'static str, message: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let now = Utc::now();
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = recent.iter().rposition(|event| event.source == source)
+            && recent[i].severity == severity
+            && recent[i].message == message
+        {
+            // Moved to the back, so that the events stay in the order they last occurred in.
+            let mut latest = recent.remove(i).expect("index found above");
+            latest.count += 1;
+            latest.last_timestamp = now;
+            recent.push_back(latest);
+            return;
+        }
+
+        if recent.len() >= self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(Event {
+            severity,
+            source,
+            message,
+            timestamp: now,
+            last_timestamp: now,
+            count: 1,
+        });
+    }
+
+    /// Returns the events kept, least recently occurred first.
+    pub fn recent(&self) -> Vec<Event> {
+        self.recent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.recent.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use logger::log::info;
+use tokio::{select, sync::oneshot, task, time::interval};
+use tokio_util::sync::CancellationToken;
+
+use crate::{gpio, state::AppState};
+
+/// The pulses of the flow sensor, counted by the worker and sampled along with the measurements.
+pub(crate) struct FlowMeter {
+    /// Pulses counted since the start. Differences are taken with wrapping arithmetic, so that even a wrapped
+    /// counter gives the right rate and volume.
+    pulses: AtomicU64,
+    /// Value of `pulses` when the volume was last reset.
+    reset_at: AtomicU64,
+    /// Set once pulses are being counted, so that nothing is reported before.
+    pulses_per_liter: OnceLock<f64>,
+    /// Value of `pulses` at the previous [`sample`](Self::sample), and when it was taken.
+    last_sample: Mutex<Option<(u64, Instant)>>,
+}
+
+/// Water through the flow sensor.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Flow {
+    /// Liters per minute since the previous sample.
+    pub rate: f64,
+    /// Liters since the start, or since last reset.
+    pub volume: f64,
+}
+
+impl FlowMeter {
+    pub fn new() -> Self {
+        Self {
+            pulses: AtomicU64::new(0),
+            reset_at: AtomicU64::new(0),
+            pulses_per_liter: OnceLock::new(),
+            last_sample: Mutex::new(None),
+        }
+    }
+
+    /// Returns the flow since the previous call, or `None` when there is no flow sensor.
+    pub fn sample(&self) -> Option<Flow> {
+        let pulses_per_liter = *self.pulses_per_liter.get()?;
+        let pulses = self.pulses.load(Ordering::Relaxed);
+        let now = Instant::now();
+
+        let (last_pulses, last_at) = self
+            .last_sample
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace((pulses, now))?;
+        let minutes = (now - last_at).as_secs_f64() / 60.0;
+        let rate = if minutes > 0.0 {
+            pulses.wrapping_sub(last_pulses) as f64 / pulses_per_liter / minutes
+        } else {
+            0.0
+        };
+        let volume = pulses.wrapping_sub(self.reset_at.load(Ordering::Relaxed)) as f64 / pulses_per_liter;
+
+        Some(Flow {
+            rate: (rate * 100.0).round() / 100.0,
+            volume: (volume * 100.0).round() / 100.0,
+        })
+    }
+
+    /// Starts counting the volume from zero again. Returns `false` when there is no flow sensor.
+    pub fn reset(&self) -> bool {
+        if self.pulses_per_liter.get().is_none() {
+            return false;
+        }
+
+        self.reset_at
+            .store(self.pulses.load(Ordering::Relaxed), Ordering::Relaxed);
+        info!("Flow volume reset");
+        true
+    }
+
+    fn start(&self, pulses_per_liter: f64) {
+        *self.last_sample.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((self.pulses.load(Ordering::Relaxed), Instant::now()));
+        let _ = self.pulses_per_liter.set(pulses_per_liter);
+    }
+
+    fn count(&self) {
+        self.pulses.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(flow) = &config.flow else {
+        return Ok(());
+    };
+    if config.simulate {
+        return simulate(&state.flow, flow.pulses_per_liter, shutdown).await;
+    }
+
+    let mut edges = {
+        let flow = flow.clone();
+        task::spawn_blocking(move || gpio::RisingEdges::open(&flow.gpio_chip, flow.pin, flow.pull_up, "cobitis-flow"))
+            .await??
+    };
+    state.flow.start(flow.pulses_per_liter);
+
+    // A plain thread rather than a blocking task, since a wait for the next edge cannot be cancelled and would
+    // otherwise hold up the shutdown of the runtime while the pump is off.
+    let (failed_tx, failed_rx) = oneshot::channel();
+    let meter = state.flow.clone();
+    thread::Builder::new().name("flow".to_owned()).spawn(move || {
+        loop {
+            if let Err(e) = edges.wait() {
+                let _ = failed_tx.send(e);
+                return;
+            }
+            meter.count();
+        }
+    })?;
+
+    select! {
+        Ok(e) = failed_rx => Err(e.context("Failed to count flow pulses")),
+        () = shutdown.cancelled() => Ok(()),
+        else => Err(anyhow!("Flow pulse counting stopped")),
+    }
+}
+
+/// Counts made-up pulses of about 8 L/min.
+async fn simulate(meter: &FlowMeter, pulses_per_liter: f64, shutdown: CancellationToken) -> anyhow::Result<()> {
+    meter.start(pulses_per_liter);
+    let mut tick = interval(Duration::from_secs_f64(60.0 / (8.0 * pulses_per_liter)));
+    loop {
+        select! {
+            _ = tick.tick() => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+
+        meter.count();
+    }
+}
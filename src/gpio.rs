@@ -0,0 +1,124 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use linux_embedded_hal::gpio_cdev::{Chip, EventRequestFlags, LineEventHandle, LineHandle, LineRequestFlags};
+
+/// `GPIOHANDLE_REQUEST_BIAS_PULL_UP`, which the kernel has supported since 5.5 but `gpio_cdev` does not name.
+const BIAS_PULL_UP: u32 = 1 << 5;
+
+/// A GPIO line driven as an output, which the kernel releases when dropped.
+pub(crate) struct Output {
+    line: LineHandle,
+}
+
+impl Output {
+    /// Requests `pin` of `chip` as an output, starting out at `initial`.
+    pub fn open(chip: &Path, pin: u32, initial: bool, consumer: &str) -> anyhow::Result<Self> {
+        let line = Chip::new(chip)
+            .and_then(|mut chip| chip.get_line(pin))
+            .and_then(|line| line.request(LineRequestFlags::OUTPUT, u8::from(initial), consumer))
+            .with_context(|| format!("Failed to request GPIO {pin} of {}", chip.display()))?;
+
+        Ok(Self { line })
+    }
+
+    pub fn set(&self, high: bool) -> anyhow::Result<()> {
+        self.line.set_value(u8::from(high))?;
+
+        Ok(())
+    }
+}
+
+/// A GPIO line read as an input, which the kernel releases when dropped.
+pub(crate) struct Input {
+    line: LineHandle,
+}
+
+impl Input {
+    /// Requests `pin` of `chip` as an input, which reads as active while low when `active_low`, and is pulled up
+    /// internally when `pull_up`.
+    pub fn open(chip: &Path, pin: u32, active_low: bool, pull_up: bool, consumer: &str) -> anyhow::Result<Self> {
+        let mut flags = LineRequestFlags::INPUT;
+        if active_low {
+            flags |= LineRequestFlags::ACTIVE_LOW;
+        }
+        if pull_up {
+            flags |= LineRequestFlags::from_bits_retain(BIAS_PULL_UP);
+        }
+        let line = Chip::new(chip)
+            .and_then(|mut chip| chip.get_line(pin))
+            .and_then(|line| line.request(flags, 0, consumer))
+            .with_context(|| format!("Failed to request GPIO {pin} of {}", chip.display()))?;
+
+        Ok(Self { line })
+    }
+
+    /// Returns whether the line is active.
+    pub fn get(&self) -> anyhow::Result<bool> {
+        Ok(self.line.get_value()? != 0)
+    }
+}
+
+/// Changes of a GPIO input, queued by the kernel as they happen.
+pub(crate) struct Edges {
+    events: LineEventHandle,
+}
+
+impl Edges {
+    /// Requests `pin` of `chip` as an input reporting both edges. See [`Input::open`] for the flags.
+    pub fn open(chip: &Path, pin: u32, active_low: bool, pull_up: bool, consumer: &str) -> anyhow::Result<Self> {
+        let mut flags = LineRequestFlags::INPUT;
+        if active_low {
+            flags |= LineRequestFlags::ACTIVE_LOW;
+        }
+        if pull_up {
+            flags |= LineRequestFlags::from_bits_retain(BIAS_PULL_UP);
+        }
+        let events = Chip::new(chip)
+            .and_then(|mut chip| chip.get_line(pin))
+            .and_then(|line| line.events(flags, EventRequestFlags::BOTH_EDGES, consumer))
+            .with_context(|| format!("Failed to request GPIO {pin} of {}", chip.display()))?;
+
+        Ok(Self { events })
+    }
+
+    /// Blocks until the next edge, and returns whether the line is active after it. A bouncing contact may have
+    /// moved on by then, so the level is read rather than told by the kind of edge.
+    pub fn wait(&mut self) -> anyhow::Result<bool> {
+        self.events.get_event()?;
+
+        Ok(self.events.get_value()? != 0)
+    }
+}
+
+/// Rising edges of a GPIO input, queued by the kernel as they happen so that none is missed between reads.
+pub(crate) struct RisingEdges {
+    events: LineEventHandle,
+}
+
+impl RisingEdges {
+    pub fn open(chip: &Path, pin: u32, pull_up: bool, consumer: &str) -> anyhow::Result<Self> {
+        let mut flags = LineRequestFlags::INPUT;
+        if pull_up {
+            flags |= LineRequestFlags::from_bits_retain(BIAS_PULL_UP);
+        }
+        let events = Chip::new(chip)
+            .and_then(|mut chip| chip.get_line(pin))
+            .and_then(|line| line.events(flags, EventRequestFlags::RISING_EDGE, consumer))
+            .with_context(|| format!("Failed to request GPIO {pin} of {}", chip.display()))?;
+
+        Ok(Self { events })
+    }
+
+    /// Blocks until the next edge.
+    pub fn wait(&mut self) -> anyhow::Result<()> {
+        self.events.get_event()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc, serde::ts_milliseconds_option};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+
+/// What the workers found when they last opened their devices, recorded as they start and whenever they retry.
+pub(crate) struct Hardware(Mutex<HardwareReport>);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct HardwareReport {
+    /// The 1-Wire bus the thermal sensors are on.
+    pub w1_bus: Device,
+    /// IDs of the thermal sensors present on the 1-Wire bus when it was last scanned.
+    pub w1_sensors: Vec<String>,
+    /// The ADC the TDS and pH probes are wired to.
+    pub adc: Device,
+    pub display: Device,
+    /// The wireless interface polled for the signal.
+    pub wireless: Device,
+    /// Name of the interface polled, if one is.
+    pub wireless_interface: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct Device {
+    pub status: DeviceStatus,
+    /// What was found, or what is missing, such as `ADS1115: not responding at 0x48`.
+    pub detail: String,
+    /// When the device was last probed. Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub probed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeviceStatus {
+    /// The device answered.
+    Found,
+    /// The device was looked for and did not answer.
+    Missing,
+    /// Not probed yet, as its worker has not got that far.
+    Pending,
+    /// Made up, as the hardware is simulated.
+    Simulated,
+    /// Not used, as configured or built.
+    Disabled,
+}
+
+impl Device {
+    pub fn found(detail: impl Into<String>) -> Self {
+        Self::probed(DeviceStatus::Found, detail)
+    }
+
+    pub fn missing(detail: impl Into<String>) -> Self {
+        Self::probed(DeviceStatus::Missing, detail)
+    }
+
+    fn probed(status: DeviceStatus, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+            probed_at: Some(Utc::now()),
+        }
+    }
+
+    /// Returns the device as it stands before its worker gets to it.
+    fn initial(enabled: bool, simulate: bool, name: &str) -> Self {
+        let (status, detail) = if !enabled {
+            (DeviceStatus::Disabled, format!("{name}: disabled"))
+        } else if simulate {
+            (DeviceStatus::Simulated, format!("{name}: simulated"))
+        } else {
+            (DeviceStatus::Pending, format!("{name}: not probed yet"))
+        };
+
+        Self {
+            status,
+            detail,
+            probed_at: None,
+        }
+    }
+}
+
+impl Hardware {
+    pub fn new(config: &Config) -> Self {
+        let measurements = config.measurements.enabled;
+        let adc_chip = config.measurements.adc.chip.to_string();
+
+        Self(Mutex::new(HardwareReport {
+            w1_bus: Device::initial(measurements, config.simulate, "1-Wire bus"),
+            w1_sensors: Vec::new(),
+            adc: Device::initial(measurements, config.simulate, &adc_chip),
+            display: Device::initial(config.display.is_enabled(), config.simulate, "display"),
+            wireless: Device::initial(config.signal.enabled, config.simulate, "wireless interface"),
+            wireless_interface: None,
+        }))
+    }
+
+    /// Records what a worker found, under the lock.
+    pub fn record(&self, f: impl FnOnce(&mut HardwareReport)) {
+        f(&mut self.0.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    pub fn snapshot(&self) -> HardwareReport {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc, serde::ts_milliseconds_option};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The health of every worker, kept in the [`AppState`](crate::state::AppState) for the API and systemd to report.
+pub(crate) struct Workers {
+    pub started_at: DateTime<Utc>,
+    pub measurements: Health,
+    pub signal: Health,
+    pub display: Health,
+}
+
+impl Workers {
+    pub fn new() -> Self {
+        Self {
+            started_at: Utc::now(),
+            measurements: Health::new(),
+            signal: Health::new(),
+            display: Health::new(),
+        }
+    }
+}
+
+/// Success and failure bookkeeping of a single worker.
+pub(crate) struct Health(Mutex<HealthSnapshot>);
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub(crate) struct HealthSnapshot {
+    /// Whether the worker has got through its setup, such as opening its devices.
+    pub initialized: bool,
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub last_success: Option<DateTime<Utc>>,
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub last_failure: Option<DateTime<Utc>>,
+    pub consecutive_failures: u32,
+    pub total_failures: u64,
+}
+
+impl Health {
+    fn new() -> Self {
+        Self(Mutex::new(HealthSnapshot {
+            initialized: false,
+            last_success: None,
+            last_failure: None,
+            consecutive_failures: 0,
+            total_failures: 0,
+        }))
+    }
+
+    pub fn initialized(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).initialized = true;
+    }
+
+    pub fn success(&self) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.last_success = Some(Utc::now());
+        state.consecutive_failures = 0;
+    }
+
+    pub fn failure(&self) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.last_failure = Some(Utc::now());
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.total_failures += 1;
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl HealthSnapshot {
+    /// Seconds elapsed since the last successful cycle, if there has been one.
+    pub fn last_success_age(&self) -> Option<f64> {
+        self.last_success.map(|t| (Utc::now() - t).as_seconds_f64())
+    }
+
+    /// Whether the worker has succeeded no longer than `max_age` before `now`.
+    pub fn succeeded_within(&self, now: DateTime<Utc>, max_age: Duration) -> bool {
+        self.last_success.is_some_and(|t| !is_stale(t, now, max_age))
+    }
+}
+
+/// Returns whether a value recorded at `timestamp` is older than `max_age` as of `now`.
+pub(crate) fn is_stale(timestamp: DateTime<Utc>, now: DateTime<Utc>, max_age: Duration) -> bool {
+    now - timestamp > TimeDelta::from_std(max_age).unwrap_or(TimeDelta::MAX)
+}
+
+/// Where a value stands by its age, as far as showing it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Freshness {
+    /// Shown as it is.
+    Fresh,
+    /// Shown along with how old it is.
+    Aged(Duration),
+    /// Shown as missing.
+    Stale,
+}
+
+/// Returns where a value recorded at `timestamp` stands as of `now`: fresh up to `fresh_for` old, stale once older than
+/// `stale_after` as with [`is_stale`], and aged in between.
+pub(crate) fn freshness(
+    timestamp: DateTime<Utc>,
+    now: DateTime<Utc>,
+    fresh_for: Duration,
+    stale_after: Duration,
+) -> Freshness {
+    let age = (now - timestamp).to_std().unwrap_or_default();
+    if age <= fresh_for {
+        Freshness::Fresh
+    } else if age > stale_after {
+        Freshness::Stale
+    } else {
+        Freshness::Aged(age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRESH_FOR: Duration = Duration::from_secs(30);
+    const STALE_AFTER: Duration = Duration::from_secs(60);
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn not_stale_at_the_threshold() {
+        assert!(!is_stale(at(0), at(60), STALE_AFTER));
+    }
+
+    #[test]
+    fn stale_just_past_the_threshold() {
+        let just_past = at(60) + TimeDelta::milliseconds(1);
+        assert!(is_stale(at(0), just_past, STALE_AFTER));
+    }
+
+    #[test]
+    fn fresh_at_the_fresh_threshold() {
+        assert_eq!(freshness(at(0), at(30), FRESH_FOR, STALE_AFTER), Freshness::Fresh);
+    }
+
+    #[test]
+    fn aged_just_past_the_fresh_threshold() {
+        let just_past = at(30) + TimeDelta::milliseconds(1);
+        assert_eq!(
+            freshness(at(0), just_past, FRESH_FOR, STALE_AFTER),
+            Freshness::Aged(Duration::from_millis(30_001))
+        );
+    }
+
+    #[test]
+    fn aged_at_the_stale_threshold() {
+        assert_eq!(
+            freshness(at(0), at(60), FRESH_FOR, STALE_AFTER),
+            Freshness::Aged(STALE_AFTER)
+        );
+    }
+
+    #[test]
+    fn stale_just_past_the_stale_threshold() {
+        let just_past = at(60) + TimeDelta::milliseconds(1);
+        assert_eq!(freshness(at(0), just_past, FRESH_FOR, STALE_AFTER), Freshness::Stale);
+    }
+
+    #[test]
+    fn fresh_from_the_future() {
+        // A clock stepped back leaves the latest value ahead of now, which is taken as just read.
+        assert_eq!(freshness(at(10), at(0), FRESH_FOR, STALE_AFTER), Freshness::Fresh);
+        assert!(!is_stale(at(10), at(0), STALE_AFTER));
+    }
+}
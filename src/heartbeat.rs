@@ -0,0 +1,109 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::anyhow;
+use chrono::Utc;
+use logger::log::{info, warn};
+use reqwest::{Client, Url};
+use serde_json::json;
+use tokio::{
+    select,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::HeartbeatMethod, health, measurements::Measurements, state::AppState};
+
+/// What became of a ping, which is logged only when it changes from the last one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Sent,
+    /// Not sent, since there are no recent measurements.
+    Withheld,
+    Failed,
+}
+
+/// Pings the heartbeat URL at its interval while the measurements of the default tank are recent.
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(heartbeat) = &config.heartbeat else {
+        return Ok(());
+    };
+
+    let url = Url::parse(&heartbeat.url)?;
+    let client = Client::builder().timeout(heartbeat.timeout()).build()?;
+    let mut ticker = interval(heartbeat.interval());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The URL is left out of the logs, since it is all it takes to ping on behalf of the service.
+    info!("Pinging the heartbeat every {}s", heartbeat.interval_secs);
+    let mut last = None;
+
+    loop {
+        select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+
+        let measurements = state
+            .default_tank()
+            .measurements
+            .get()
+            .await
+            .filter(|m| !health::is_stale(m.timestamp, Utc::now(), config.measurements.stale_after()));
+        let outcome = match measurements {
+            Some(m) => {
+                let result = select! {
+                    result = ping(&client, url.clone(), heartbeat.method, &m) => result,
+                    () = shutdown.cancelled() => return Ok(()),
+                };
+                match result {
+                    Ok(()) => Outcome::Sent,
+                    Err(e) if last != Some(Outcome::Failed) => {
+                        warn!("Failed to ping the heartbeat, trying again every interval: {e:?}");
+                        Outcome::Failed
+                    }
+                    Err(_) => Outcome::Failed,
+                }
+            }
+            None => Outcome::Withheld,
+        };
+
+        match (last, outcome) {
+            // Nothing to tell before the first reading after startup
+            (None, Outcome::Withheld) => continue,
+            (Some(Outcome::Failed), Outcome::Sent) => info!("Heartbeat is reaching the URL again"),
+            (Some(Outcome::Withheld), Outcome::Sent) => {
+                info!("Measurements are coming in again, resumed the heartbeat");
+            }
+            (last, Outcome::Withheld) if last != Some(Outcome::Withheld) => {
+                warn!("No recent measurements, holding back the heartbeat until there are");
+            }
+            _ => {}
+        }
+        last = Some(outcome);
+    }
+}
+
+/// Pings the URL with the latest temperature in the query, so that the log of pings tells roughly how it went.
+async fn ping(client: &Client, mut url: Url, method: HeartbeatMethod, m: &Measurements) -> anyhow::Result<()> {
+    url.query_pairs_mut()
+        .append_pair("temperature", &format!("{:.1}", m.temperature));
+    let request = match method {
+        HeartbeatMethod::Get => client.get(url),
+        HeartbeatMethod::Post => client
+            .post(url)
+            .json(&json!({ "temperature": m.temperature, "tds": m.tds })),
+    };
+    let response = request
+        .send()
+        .await
+        // The error would contain the URL.
+        .map_err(|e| anyhow!("{}", e.without_url()))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Server returned {}", response.status()));
+    }
+
+    Ok(())
+}
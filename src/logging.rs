@@ -0,0 +1,75 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    env,
+    io::{self, Write},
+};
+
+use chrono::{SecondsFormat, Utc};
+use env_logger::{Env, WriteStyle, fmt::Formatter};
+use logger::log::{
+    Record,
+    kv::{self, Key, VisitSource},
+};
+use serde_json::{Map, Number, Value};
+
+use crate::config::{LogFormat, LogLevel};
+
+/// Sets up logging in `format`, filtered by `level` when given and by `RUST_LOG` otherwise.
+pub(crate) fn init(format: LogFormat, level: Option<LogLevel>) {
+    if let Some(level) = level {
+        // SAFETY: Logging is set up first thing in `main`, before any other thread is around to read the environment.
+        unsafe { env::set_var("RUST_LOG", level.to_string()) };
+    }
+
+    match format {
+        LogFormat::Text => logger::init(),
+        LogFormat::Json => env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+            .write_style(WriteStyle::Never)
+            .format(write_json)
+            .init(),
+    }
+}
+
+/// Writes `record` as a single line such as
+/// `{"consecutive_failures":3,"level":"ERROR","message":"...","target":"cobitis::measurements","timestamp":"..."}`.
+fn write_json(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let mut line = Map::new();
+    // Failing to take a field apart leaves the others in place, which is as good as it gets for a log line.
+    let _ = record.key_values().visit(&mut Fields(&mut line));
+    // Inserted last, so that a field of the same name cannot replace them.
+    line.insert(
+        "timestamp".to_owned(),
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+    );
+    line.insert("level".to_owned(), record.level().as_str().into());
+    line.insert("target".to_owned(), record.target().into());
+    line.insert("message".to_owned(), record.args().to_string().into());
+
+    writeln!(buf, "{}", Value::Object(line))
+}
+
+/// Collects the structured fields of a record, keeping numbers and booleans as such.
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            Value::Bool(b)
+        } else if let Some(n) = value.to_i64() {
+            n.into()
+        } else if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_f64() {
+            Number::from_f64(n).map_or(Value::Null, Value::Number)
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+
+        Ok(())
+    }
+}
@@ -3,24 +3,171 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
-use logger::log::info;
-use tokio::select;
+use std::{sync::Arc, time::Duration};
 
+use clap::Parser;
+use logger::log::{info, warn};
+use tokio::{
+    select,
+    signal::unix::{self, SignalKind},
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cli::{Cli, Command},
+    config::{Config, LogFormat},
+    state::AppState,
+};
+
+mod alerts;
 mod api;
+mod button;
+mod buzzer;
+mod calibration;
+mod check;
+mod cli;
+mod config;
+#[cfg(feature = "display")]
 mod display;
+mod errors;
+mod flow;
+mod gpio;
+mod hardware;
+mod health;
+mod heartbeat;
+mod logging;
+mod maintenance;
 mod measurements;
+mod metrics;
+mod mqtt;
+#[cfg(feature = "display")]
+mod network;
+mod notify;
+mod readings_log;
 mod signal;
+mod simulation;
+mod state;
+mod storage;
+mod supervisor;
+mod system;
+mod systemd;
+mod thermostat;
+mod timezone;
+mod water_change;
+mod water_level;
+mod webhook;
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
-    logger::init();
+    let cli = Cli::parse();
+
+    // Logging is set up before bailing out on a bad config, in the format asked for when there is one.
+    let config = Config::load(&cli);
+    match &config {
+        Ok(config) => logging::init(config.log_format, config.log_level),
+        Err(_) => logging::init(LogFormat::default(), cli.log_level),
+    }
+    let config = Arc::new(config?);
+    timezone::init(config.timezone);
+    if let Some(Command::Check) = cli.command {
+        return check::run(&config).await;
+    }
+    let state = AppState::new(config.clone());
+    state.calibration.load(&config)?;
+    state.water_change.load(&config)?;
+    // Bound before anything starts, so that an address that cannot be served on fails the startup.
+    let listeners = Arc::new(api::bind(&config.api)?);
 
     info!("Cobitis: tank monitor service started");
+    if config.simulate {
+        warn!("Simulating the hardware, none of the readings are real");
+    }
+
+    let shutdown = CancellationToken::new();
+    let mut workers = Vec::new();
+    if config.measurements.enabled {
+        workers.push(supervisor::spawn(
+            "measurements",
+            measurements::worker,
+            state.clone(),
+            shutdown.clone(),
+        ));
+    }
+    if config.signal.enabled {
+        workers.push(supervisor::spawn(
+            "signal",
+            signal::worker,
+            state.clone(),
+            shutdown.clone(),
+        ));
+    }
+    #[cfg(feature = "display")]
+    if config.display.enabled {
+        workers.push(supervisor::spawn(
+            "display",
+            display::worker,
+            state.clone(),
+            shutdown.clone(),
+        ));
+    }
+    if config.system.enabled {
+        workers.push(supervisor::spawn(
+            "system",
+            system::worker,
+            state.clone(),
+            shutdown.clone(),
+        ));
+    }
+    workers.extend([
+        supervisor::spawn("thermostat", thermostat::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("water_level", water_level::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("flow", flow::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("button", button::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("alerts", alerts::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("notify", notify::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("email", notify::email::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("buzzer", buzzer::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn(
+            "api",
+            move |state, shutdown| api::worker(state, listeners.clone(), shutdown),
+            state.clone(),
+            shutdown.clone(),
+        ),
+        supervisor::spawn("mqtt", mqtt::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("storage", storage::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("readings_log", readings_log::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("webhook", webhook::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("systemd", systemd::worker, state.clone(), shutdown.clone()),
+        supervisor::spawn("heartbeat", heartbeat::worker, state.clone(), shutdown.clone()),
+    ]);
 
+    wait_for_termination().await?;
+    info!("Cobitis: shutting down");
+    shutdown.cancel();
+
+    let stopped = timeout(SHUTDOWN_TIMEOUT, async {
+        for worker in workers {
+            let _ = worker.await;
+        }
+    })
+    .await;
+    if stopped.is_err() {
+        warn!("Workers did not stop within {}s", SHUTDOWN_TIMEOUT.as_secs());
+    }
+
+    Ok(())
+}
+
+async fn wait_for_termination() -> anyhow::Result<()> {
+    let mut sigterm = unix::signal(SignalKind::terminate())?;
+    let mut sigint = unix::signal(SignalKind::interrupt())?;
     select! {
-        result = measurements::worker() => result,
-        result = signal::worker() => result,
-        result = api::worker() => result,
-        result = display::worker() => result,
+        _ = sigterm.recv() => {}
+        _ = sigint.recv() => {}
     }
+
+    Ok(())
 }
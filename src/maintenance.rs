@@ -0,0 +1,102 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::anyhow;
+use chrono::{DateTime, TimeDelta, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use logger::log::info;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A maintenance window, during which the probes may be out of the water and their readings are not published.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct Maintenance {
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub started_at: DateTime<Utc>,
+    /// Milliseconds since the Unix epoch. `None` when it lasts until ended.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Whether maintenance is in progress. Kept in memory only, so that a restart never comes back up in a maintenance
+/// window that was long forgotten.
+pub(crate) struct Tracker {
+    state: Mutex<Option<Maintenance>>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Starts maintenance, ending by itself after `duration` when given. Starting it again while it is on only
+    /// changes when it ends.
+    pub fn start(&self, duration: Option<Duration>) -> anyhow::Result<Maintenance> {
+        let now = Utc::now();
+        let expires_at = match duration {
+            Some(duration) => Some(
+                TimeDelta::from_std(duration)
+                    .ok()
+                    .and_then(|duration| now.checked_add_signed(duration))
+                    .ok_or_else(|| anyhow!("Duration is out of range"))?,
+            ),
+            None => None,
+        };
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let started_at = active(&mut state).map_or(now, |m| m.started_at);
+        let maintenance = Maintenance { started_at, expires_at };
+        match duration {
+            Some(duration) => {
+                let duration_secs = duration.as_secs();
+                info!(duration_secs; "Maintenance started, ending in {duration_secs}s");
+            }
+            None => info!("Maintenance started"),
+        }
+        *state = Some(maintenance);
+
+        Ok(maintenance)
+    }
+
+    /// Ends maintenance. Returns the one that was ended, if any.
+    pub fn end(&self) -> Option<Maintenance> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let ended = active(&mut state);
+        if ended.is_some() {
+            info!("Maintenance ended");
+        }
+        *state = None;
+
+        ended
+    }
+
+    /// Returns the maintenance in progress, if any.
+    pub fn current(&self) -> Option<Maintenance> {
+        active(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Returns whether maintenance is in progress.
+    pub fn is_active(&self) -> bool {
+        self.current().is_some()
+    }
+}
+
+/// Ends an expired maintenance before returning the one in progress.
+fn active(state: &mut Option<Maintenance>) -> Option<Maintenance> {
+    if let Some(expires_at) = state.and_then(|m| m.expires_at)
+        && expires_at <= Utc::now()
+    {
+        info!("Maintenance ended as it expired");
+        *state = None;
+    }
+
+    *state
+}
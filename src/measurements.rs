@@ -4,151 +4,1054 @@
 // https://opensource.org/licenses/MIT
 
 use std::{
-    fs,
-    path::PathBuf,
-    sync::{Arc, LazyLock, Mutex},
+    collections::BTreeMap,
+    mem,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
     time::Duration,
 };
 
-use ads1x1x::{Ads1x1x, FullScaleRange, TargetAddr, channel};
 use anyhow::anyhow;
-use chrono::{DateTime, Utc, serde::ts_milliseconds};
-use linux_embedded_hal::{I2cdev, nb::block};
-use logger::log::error;
-use regex::Regex;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc, serde::ts_milliseconds};
+use futures_util::future::{self, join};
+use logger::log::{error, info, warn};
 use serde::Serialize;
 use tokio::{
-    sync::RwLock,
+    select,
+    sync::{Notify, oneshot, watch},
     task,
-    time::{MissedTickBehavior, interval},
+    time::{Interval, MissedTickBehavior, interval, sleep, timeout},
 };
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
 
-type Ads1115 = ads1x1x::Ads1x1x<
-    linux_embedded_hal::I2cdev,
-    ads1x1x::ic::Ads1115,
-    ads1x1x::ic::Resolution16Bit,
-    ads1x1x::mode::OneShot,
->;
+use crate::{
+    calibration::LinearCalibration,
+    check::Outcome,
+    config::{AuxChannelConfig, MeasurementsConfig, TemperatureUnit, TrendConfig},
+    errors::ErrorLog,
+    state::{AppState, Tank},
+    timezone,
+};
+
+use self::{
+    filters::{Smoother, SpikeFilter},
+    sensors::{Sensors, TdsSensor, TemperatureReading, TemperatureSensor},
+};
 
-#[derive(Debug, Clone, Copy, Serialize)]
+mod convert;
+mod filters;
+mod sensors;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub(crate) struct Measurements {
+    /// Milliseconds since the Unix epoch.
     #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
     pub timestamp: DateTime<Utc>,
+    /// Temperature of the primary thermal sensor, smoothed when configured.
     pub temperature: f64,
+    /// Temperature as read, present only while it is smoothed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_raw: Option<f64>,
+    /// Temperatures of every thermal sensor that could be read, keyed by sensor ID.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub temperatures: BTreeMap<String, f64>,
+    /// Smoothed when configured.
     pub tds: f64,
+    /// TDS as read, present only while it is smoothed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tds_raw: Option<f64>,
+    /// Electrical conductivity at 25 °C in µS/cm, which `tds` is derived from. Smoothed along with it.
+    pub ec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tds_voltage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ph: Option<f64>,
+    /// Flow rate in liters per minute over the interval since the previous measurement. Absent without a flow sensor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_rate: Option<f64>,
+    /// Liters through the flow sensor since the start, or since `POST /flow/reset`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_volume: Option<f64>,
+    /// Whether the water is up to the float switch. Absent without a switch, or until it has settled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub water_level_ok: Option<bool>,
+    /// Auxiliary inputs of the ADC that could be read, keyed by their configured name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub aux: BTreeMap<String, AuxValue>,
+    /// Kept out of everything but the API, which serves it when asked for.
+    #[serde(skip)]
+    pub debug: Option<Box<DebugValues>>,
+}
+
+/// Reading of an auxiliary input of the ADC.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct AuxValue {
+    /// The voltage with the configured scale and offset applied.
+    pub value: f64,
+    pub voltage: f64,
+}
+
+/// Values the measurements were worked out from, for telling a bad probe from a bad ADC or bad math.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct DebugValues {
+    /// Millidegrees Celsius as read from the thermal sensor. Absent when simulated.
+    pub temperature_millis: Option<i32>,
+    /// Offset added to the temperature as read, in °C.
+    pub temperature_offset: f64,
+    /// Each conversion of the TDS probe in the burst, as the ADC returned it. Empty when simulated.
+    pub tds_counts: Vec<i16>,
+    /// Voltage of the TDS probe, averaged over the burst with outliers discarded.
+    pub tds_voltage: f64,
+    /// What `tds_voltage` was divided by to compensate for the temperature.
+    pub temperature_coefficient: f64,
+    /// `tds_voltage` compensated to 25 °C, which the conductivity is worked out from.
+    pub tds_voltage_compensated: f64,
+    /// Factor from calibrating the TDS probe, which the conductivity was multiplied by.
+    pub tds_calibration_factor: f64,
+    /// Factor the conductivity was multiplied by to give TDS.
+    pub tds_factor: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ph_voltage: Option<f64>,
+    /// Slope of the pH calibration applied to `ph_voltage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ph_slope: Option<f64>,
+    /// Offset of the pH calibration applied to `ph_voltage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ph_offset: Option<f64>,
 }
 
 impl Measurements {
-    fn new(temperature: f64, tds: f64) -> Self {
+    /// Returns the measurements with the temperatures converted from Celsius into `unit`, for presentation only.
+    pub fn in_unit(mut self, unit: TemperatureUnit) -> Self {
+        self.temperature = unit.convert(self.temperature);
+        self.temperature_raw = self.temperature_raw.map(|t| unit.convert(t));
+        for t in self.temperatures.values_mut() {
+            *t = unit.convert(*t);
+        }
+
+        self
+    }
+
+    /// Returns measurements of a tank with nothing but the temperature and the TDS, for tests elsewhere.
+    #[cfg(test)]
+    pub fn sample(timestamp: DateTime<Utc>, temperature: f64, tds: f64) -> Self {
         Self {
-            timestamp: Utc::now(),
+            timestamp,
             temperature,
+            temperature_raw: None,
+            temperatures: BTreeMap::new(),
             tds,
+            tds_raw: None,
+            ec: tds * 2.0,
+            tds_voltage: None,
+            ph: None,
+            flow_rate: None,
+            flow_volume: None,
+            water_level_ok: None,
+            aux: BTreeMap::new(),
+            debug: None,
+        }
+    }
+}
+
+/// How long [`Sampling::refresh`] waits for the worker, which may be in the middle of a slow read already.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the measurements are taken, along with what the API reads back about them besides the values.
+pub(crate) struct Sampling {
+    interval: watch::Sender<Duration>,
+    /// Requests for an immediate reading, each waiting for the result.
+    refresh_waiters: Mutex<Vec<oneshot::Sender<Result<Measurements, String>>>>,
+    refresh: Notify,
+    raw: Mutex<RawValues>,
+    rejected_temperatures: AtomicU64,
+    rejected_tds: AtomicU64,
+}
+
+impl Sampling {
+    pub fn new(config: &MeasurementsConfig) -> Self {
+        Self {
+            interval: watch::channel(config.interval()).0,
+            refresh_waiters: Mutex::new(Vec::new()),
+            refresh: Notify::new(),
+            raw: Mutex::new(RawValues::default()),
+            rejected_temperatures: AtomicU64::new(0),
+            rejected_tds: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the effective polling interval, which starts out as the configured one.
+    pub fn interval(&self) -> Duration {
+        *self.interval.borrow()
+    }
+
+    /// Changes the polling interval of the running worker.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval.send_replace(interval);
+    }
+
+    /// Has the worker take a reading right away, and returns it once stored.
+    ///
+    /// Requests made while the worker is busy are all answered by the next reading, so that the probes are not read
+    /// over and over.
+    pub async fn refresh(&self) -> anyhow::Result<Measurements> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut waiters = self.refresh_waiters.lock().unwrap_or_else(|e| e.into_inner());
+            // Requests that timed out are left behind when the worker is not running.
+            waiters.retain(|tx| !tx.is_closed());
+            waiters.push(tx);
+        }
+        self.refresh.notify_one();
+
+        match timeout(REFRESH_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result.map_err(|e| anyhow!(e)),
+            Ok(Err(_)) => Err(anyhow!("Measurement worker stopped")),
+            Err(_) => Err(anyhow!("Timed out after {}s", REFRESH_TIMEOUT.as_secs())),
+        }
+    }
+
+    /// Returns the values of the latest read before calibration is applied.
+    pub fn raw(&self) -> RawValues {
+        *self.raw.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn rejections(&self) -> Rejections {
+        Rejections {
+            temperature: self.rejected_temperatures.load(Ordering::Relaxed),
+            tds: self.rejected_tds.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Running statistics of a single value.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub(crate) struct Stats {
+    pub count: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// `None` while there are no samples.
+    pub mean: Option<f64>,
+    #[serde(skip)]
+    sum: f64,
+}
+
+impl Stats {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        let mean = self.sum / self.count as f64;
+        self.mean = Some((mean * 100.0).round() / 100.0);
+    }
+}
+
+/// Statistics of the measurements taken on a single local day.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct DailyStats {
+    #[schema(value_type = String, format = Date)]
+    pub date: NaiveDate,
+    pub temperature: Stats,
+    pub tds: Stats,
+    pub ec: Stats,
+}
+
+impl DailyStats {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            temperature: Stats::default(),
+            tds: Stats::default(),
+            ec: Stats::default(),
         }
     }
 }
 
-static LATEST: LazyLock<RwLock<Option<Measurements>>> = LazyLock::new(|| RwLock::new(None));
+/// Statistics of today and of yesterday. They start over from zero when the service restarts.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct DailyStatsPair {
+    pub today: DailyStats,
+    pub yesterday: Option<DailyStats>,
+}
+
+/// Moves on to `date` when it is a new day, keeping the previous day only if it was yesterday.
+fn roll_over(stats: &mut Option<DailyStatsPair>, date: NaiveDate) -> &mut DailyStatsPair {
+    if let Some(pair) = stats
+        && pair.today.date != date
+    {
+        let yesterday = date.pred_opt();
+        *pair = DailyStatsPair {
+            today: DailyStats::new(date),
+            yesterday: [Some(pair.today), pair.yesterday]
+                .into_iter()
+                .flatten()
+                .find(|stats| Some(stats.date) == yesterday),
+        };
+    }
+
+    stats.get_or_insert_with(|| DailyStatsPair {
+        today: DailyStats::new(date),
+        yesterday: None,
+    })
+}
+
+/// Returns the statistics of `tank` since midnight in the configured zone, together with those of yesterday.
+pub(crate) fn daily_stats(tank: &Tank) -> DailyStatsPair {
+    let mut stats = tank.daily_stats.lock().unwrap_or_else(|e| e.into_inner());
+    *roll_over(&mut stats, timezone::now().date_naive())
+}
+
+fn record_daily_stats(tank: &Tank, m: &Measurements) {
+    let mut stats = tank.daily_stats.lock().unwrap_or_else(|e| e.into_inner());
+    let today = &mut roll_over(&mut stats, timezone::localize(m.timestamp).date_naive()).today;
+    today.temperature.add(m.temperature);
+    today.tds.add(m.tds);
+    today.ec.add(m.ec);
+}
+
+/// Values from the latest read before calibration is applied, needed for calibrating the probes.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RawValues {
+    pub tds: Option<f64>,
+    pub ph_voltage: Option<f64>,
+}
+
+/// Counts of readings rejected by the spike filter since the start.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rejections {
+    pub temperature: u64,
+    pub tds: u64,
+}
+
+/// Returns recorded measurements of a tank in chronological order.
+///
+/// Only entries newer than `since` are returned, and when `limit` is given, only the most recent `limit` of them.
+pub(crate) async fn history(tank: &Tank, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Vec<Measurements> {
+    let history = tank.history.read().await;
+    let start = since.map_or(0, |since| history.partition_point(|m| m.timestamp <= since));
+    let start = limit.map_or(start, |limit| start.max(history.len().saturating_sub(limit)));
+
+    history.range(start..).cloned().collect()
+}
+
+/// Returns the rate of change of the temperature of a tank in °C per hour, as the slope of a least-squares line
+/// through its history over the window up to now. `None` with fewer readings in the window than `config` asks for.
+pub(crate) async fn temperature_trend(tank: &Tank, config: &TrendConfig) -> Option<f64> {
+    let now = Utc::now();
+    let window = TimeDelta::from_std(config.window()).unwrap_or(TimeDelta::MAX);
+    let history = tank.history.read().await;
+    let start = history.partition_point(|m| now - m.timestamp > window);
+    if history.len() - start < config.min_samples {
+        return None;
+    }
+
+    // Hours before now, which keeps the sums small. Readings need not be evenly spaced, so gaps only weigh less.
+    let points: Vec<_> = history
+        .range(start..)
+        .map(|m| (-(now - m.timestamp).as_seconds_f64() / 3600.0, m.temperature))
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let (sxx, sxy) = points.iter().fold((0.0, 0.0), |(sxx, sxy), &(x, y)| {
+        (sxx + (x - mean_x).powi(2), sxy + (x - mean_x) * (y - mean_y))
+    });
+
+    // Readings all taken at once give no slope
+    (sxx > 0.0).then(|| sxy / sxx)
+}
+
+/// Reads every sensor once, without retrying, for `cobitis check`.
+pub(crate) fn probe(config: &MeasurementsConfig) -> Vec<Outcome> {
+    let mut outcomes = sensors::probe_thermometers(config);
+    outcomes.extend(sensors::probe_adc(config));
 
-pub(crate) async fn latest() -> Option<Measurements> {
-    *LATEST.read().await
+    outcomes
 }
 
 struct Context {
-    temperature_path: PathBuf,
-    rx_temperature: Regex,
-    tds_adc: Mutex<Ads1115>,
+    history_capacity: usize,
+    sensors: Sensors,
+    /// In the same order as the tanks of the sensors.
+    tanks: Vec<TankContext>,
+    /// Index of the tank the pH probe, the auxiliary inputs, and the calibrations belong to.
+    default_tank: usize,
+    temperature_offset: f64,
+    tds_samples: usize,
+    tds_sample_spacing: Duration,
+    expose_tds_voltage: bool,
+    tds_factor: f64,
+    ph: Option<LinearCalibration>,
+    /// In the same order as the auxiliary sensors.
+    aux: Vec<AuxChannelConfig>,
+    interval: watch::Receiver<Duration>,
 }
 
-impl Context {
-    async fn new() -> anyhow::Result<Arc<Self>> {
-        task::spawn_blocking(move || {
-            let temperature_path = {
-                let mut dir = fs::read_dir("/sys/bus/w1/devices")?.flatten();
-                loop {
-                    match dir.next() {
-                        Some(entry) => {
-                            let path = entry.path().join("w1_slave");
-                            if path.is_file() {
-                                break path;
-                            }
-                        }
-                        None => return Err(anyhow!("Thermal sensor not found")),
-                    }
-                }
-            };
-            let rx_temperature = Regex::new(r"t=\s*([0-9]+)").unwrap();
+/// The filters of a single tank, which carry over from one measurement to the next.
+struct TankContext {
+    name: String,
+    /// `None` when the spike filter is disabled.
+    temperature_spikes: Option<Mutex<SpikeFilter>>,
+    tds_spikes: Option<Mutex<SpikeFilter>>,
+    /// `None` when not smoothed.
+    temperature_smoother: Option<Mutex<Smoother>>,
+    tds_smoothers: Option<Mutex<TdsSmoothers>>,
+}
 
-            let tds_adc = {
-                let dev = I2cdev::new("/dev/i2c-1")?;
-                let mut adc = Ads1x1x::new_ads1115(dev, TargetAddr::default());
-                adc.set_full_scale_range(FullScaleRange::Within4_096V)
-                    .map_err(|e| anyhow!("{e:?}"))?;
+struct TdsSmoothers {
+    tds: Smoother,
+    ec: Smoother,
+}
 
-                Mutex::new(adc)
+impl Context {
+    async fn new(state: &AppState) -> anyhow::Result<Arc<Self>> {
+        let config = state.config.measurements.clone();
+        let simulate = state.config.simulate;
+        let hardware = state.hardware.clone();
+        let errors = state.errors.clone();
+        let interval = state.sampling.interval.subscribe();
+        task::spawn_blocking(move || {
+            let sensors = if simulate {
+                Sensors::simulated(&config)
+            } else {
+                Sensors::open(&config, &hardware, &errors)?
             };
 
-            Ok(Arc::new(Self {
-                temperature_path,
-                rx_temperature,
-                tds_adc,
-            }))
+            Ok(Arc::new(Self::with_sensors(&config, sensors, interval)))
         })
         .await?
     }
+
+    fn with_sensors(config: &MeasurementsConfig, sensors: Sensors, interval: watch::Receiver<Duration>) -> Self {
+        Self {
+            history_capacity: config.history_capacity,
+            sensors,
+            tanks: config
+                .tanks()
+                .into_iter()
+                .map(|tank| TankContext::new(config, tank.name))
+                .collect(),
+            default_tank: config.default_tank_index(),
+            temperature_offset: config.temperature_offset,
+            tds_samples: config.tds_samples,
+            tds_sample_spacing: Duration::from_millis(config.tds_sample_spacing_ms),
+            expose_tds_voltage: config.expose_tds_voltage,
+            tds_factor: config.tds_factor,
+            ph: config.ph.as_ref().map(|ph| LinearCalibration {
+                slope: ph.slope,
+                offset: ph.offset,
+            }),
+            aux: config.adc.aux.clone(),
+            interval,
+        }
+    }
 }
 
-pub(crate) async fn worker() -> anyhow::Result<()> {
-    let mut interval = interval(Duration::from_secs(10));
-    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+impl TankContext {
+    fn new(config: &MeasurementsConfig, name: String) -> Self {
+        Self {
+            name,
+            temperature_spikes: config.spike_filter.enabled.then(|| {
+                Mutex::new(SpikeFilter::new(
+                    "temperature",
+                    config.spike_filter.max_temperature_change,
+                ))
+            }),
+            tds_spikes: config
+                .spike_filter
+                .enabled
+                .then(|| Mutex::new(SpikeFilter::new("TDS", config.spike_filter.max_tds_change))),
+            temperature_smoother: config
+                .smoothing
+                .temperature_alpha
+                .map(|alpha| Mutex::new(Smoother::new(alpha))),
+            tds_smoothers: config.smoothing.tds_alpha.map(|alpha| {
+                Mutex::new(TdsSmoothers {
+                    tds: Smoother::new(alpha),
+                    ec: Smoother::new(alpha),
+                })
+            }),
+        }
+    }
+}
 
-    let ctx = Context::new().await?;
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let sampling = state.sampling.clone();
+    let mut interval_rx = sampling.interval.subscribe();
+    let mut interval = ticker(*interval_rx.borrow_and_update());
+
+    let ctx = Context::new(&state)
+        .await
+        .inspect_err(|_| state.health.measurements.failure())?;
+    state.health.measurements.initialized();
 
     loop {
-        interval.tick().await;
+        select! {
+            _ = interval.tick() => {}
+            () = sampling.refresh.notified() => {}
+            Ok(()) = interval_rx.changed() => {
+                let period = *interval_rx.borrow_and_update();
+                let interval_secs = period.as_secs();
+                info!(interval_secs; "Polling interval changed to {interval_secs}s");
+                interval = ticker(period);
+                continue;
+            }
+            () = shutdown.cancelled() => return Ok(()),
+        }
+
+        // Taken before reading, so that every waiter gets a value read after it asked.
+        let waiters = mem::take(&mut *sampling.refresh_waiters.lock().unwrap_or_else(|e| e.into_inner()));
 
-        if let Err(e) = update(&ctx).await {
-            error!("Failed to update measurements: {e:?}");
+        match update(&state, &ctx).await {
+            Ok(measurements) => {
+                state.health.measurements.success();
+                for tx in waiters {
+                    let _ = tx.send(Ok(measurements.clone()));
+                }
+            }
+            Err(e) => {
+                for tx in waiters {
+                    let _ = tx.send(Err(format!("{e:#}")));
+                }
+                state.health.measurements.failure();
+                state.thermostat.feed(None);
+                let consecutive_failures = state.health.measurements.snapshot().consecutive_failures;
+                error!(consecutive_failures; "Failed to update measurements: {e:?}");
+                state
+                    .errors
+                    .error("measurements", format!("Failed to update measurements: {e:#}"));
+            }
         }
     }
 }
 
-async fn update(ctx: &Arc<Context>) -> anyhow::Result<()> {
-    let measurements = read(ctx).await?;
-    *LATEST.write().await = Some(measurements);
+fn ticker(period: Duration) -> Interval {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+/// Reads every tank and passes the measurements on, returning those of the default tank.
+///
+/// A failure in another tank is logged without failing the update, so that the default tank is still served.
+async fn update(state: &AppState, ctx: &Arc<Context>) -> anyhow::Result<Measurements> {
+    let mut readings = read(state, ctx).await?;
+    // The bus is re-scanned from time to time while reading, so the sensors plugged in or out since show up here.
+    if let Some(w1_bus) = &ctx.sensors.w1_bus {
+        let sensor_ids = w1_bus.sensor_ids();
+        state.hardware.record(|report| report.w1_sensors = sensor_ids);
+    }
 
-    Ok(())
+    // The probes are likely out of the water, so the readings are kept out of everything downstream, and the heater
+    // goes into its fail-safe rather than acting on the temperature of the air.
+    if state.maintenance.is_active() {
+        state.thermostat.feed(None);
+        return readings.swap_remove(ctx.default_tank);
+    }
+
+    let mut result = Err(anyhow!("The default tank was not read"));
+    for (i, ((reading, tank_ctx), tank)) in readings.into_iter().zip(&ctx.tanks).zip(state.tanks.iter()).enumerate() {
+        let is_default = i == ctx.default_tank;
+        let updated = match reading {
+            Ok(measurements) => update_tank(state, ctx, tank_ctx, tank, measurements, is_default).await,
+            Err(e) => Err(e),
+        };
+        if is_default {
+            result = updated;
+        } else if let Err(e) = updated {
+            let tank = &tank_ctx.name;
+            error!(tank; "Failed to update measurements of tank {tank}: {e:?}");
+            state.errors.error(
+                "measurements",
+                format!("Failed to update measurements of tank {tank}: {e:#}"),
+            );
+        }
+    }
+
+    result
+}
+
+async fn update_tank(
+    state: &AppState,
+    ctx: &Context,
+    tank_ctx: &TankContext,
+    tank: &Tank,
+    mut measurements: Measurements,
+    is_default: bool,
+) -> anyhow::Result<Measurements> {
+    check_spikes(&state.sampling, ctx, tank_ctx, &mut measurements);
+
+    // The heater acts on the temperature as read, since smoothing only delays it.
+    if is_default {
+        state.thermostat.feed(Some(measurements.temperature));
+    }
+    let measurements = smooth(ctx, tank_ctx, measurements);
+    record_daily_stats(tank, &measurements);
+    tank.measurements.set(measurements.clone()).await;
+
+    let mut history = tank.history.write().await;
+    while history.len() >= ctx.history_capacity.max(1) {
+        history.pop_front();
+    }
+    // The debug values are of the latest measurements alone, so they are not worth the memory for every entry.
+    history.push_back(Measurements {
+        debug: None,
+        ..measurements.clone()
+    });
+
+    Ok(measurements)
+}
+
+/// Puts the last accepted value back in place of one that jumped implausibly far from it, so that the other values
+/// of the reading are kept. The rejected value is logged and counted.
+fn check_spikes(sampling: &Sampling, ctx: &Context, tank_ctx: &TankContext, m: &mut Measurements) {
+    if let Some(filter) = &tank_ctx.temperature_spikes
+        && let Some(accepted) = reject_spike(
+            filter,
+            m.temperature,
+            m.timestamp,
+            &tank_ctx.name,
+            &sampling.rejected_temperatures,
+        )
+    {
+        m.temperature = accepted;
+    }
+    if let Some(filter) = &tank_ctx.tds_spikes
+        && let Some(accepted) = reject_spike(filter, m.tds, m.timestamp, &tank_ctx.name, &sampling.rejected_tds)
+    {
+        m.tds = accepted;
+        m.ec = (accepted / ctx.tds_factor).round();
+    }
+}
+
+/// Returns the value to keep in place of `value` when `filter` rejects it.
+fn reject_spike(
+    filter: &Mutex<SpikeFilter>,
+    value: f64,
+    at: DateTime<Utc>,
+    tank: &str,
+    count: &AtomicU64,
+) -> Option<f64> {
+    let mut filter = filter.lock().unwrap_or_else(|e| e.into_inner());
+    let e = filter.check(value, at).err()?;
+    count.fetch_add(1, Ordering::Relaxed);
+    warn!(tank; "{e} in tank {tank}");
+
+    filter.accepted()
+}
+
+/// Smoothing starts over after this many intervals without a reading.
+const SMOOTHING_MAX_GAP_INTERVALS: u32 = 3;
+
+/// Smooths the values as configured, keeping the ones as read alongside.
+fn smooth(ctx: &Context, tank_ctx: &TankContext, mut m: Measurements) -> Measurements {
+    let max_gap = *ctx.interval.borrow() * SMOOTHING_MAX_GAP_INTERVALS;
+
+    if let Some(smoother) = &tank_ctx.temperature_smoother {
+        let smoothed = smoother
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .apply(m.temperature, m.timestamp, max_gap);
+        m.temperature_raw = Some(m.temperature);
+        m.temperature = convert::round_to(smoothed, 1);
+    }
+    if let Some(smoothers) = &tank_ctx.tds_smoothers {
+        let mut smoothers = smoothers.lock().unwrap_or_else(|e| e.into_inner());
+        m.tds_raw = Some(m.tds);
+        m.tds = smoothers.tds.apply(m.tds, m.timestamp, max_gap).round();
+        m.ec = smoothers.ec.apply(m.ec, m.timestamp, max_gap).round();
+    }
+
+    m
 }
 
-async fn read(ctx: &Arc<Context>) -> anyhow::Result<Measurements> {
-    const MAX_VOLTAGE: f64 = 4.096;
-    const MAX_RAW_VALUE: f64 = 32767.0;
+/// Reads the measurements of every tank, in the order of the tanks.
+///
+/// The pH, flow, water level and auxiliary inputs go into the default tank only, as do the thermal sensors that
+/// belong to no tank.
+async fn read(state: &AppState, ctx: &Arc<Context>) -> anyhow::Result<Vec<anyhow::Result<Measurements>>> {
+    let calibration = state.calibration.get();
+
+    let others = match &ctx.sensors.w1_bus {
+        Some(w1_bus) => {
+            let w1_bus = w1_bus.clone();
+            task::spawn_blocking(move || w1_bus.thermometers()).await?
+        }
+        None => Vec::new(),
+    };
+    let others = others
+        .into_iter()
+        .filter(|thermometer| {
+            !ctx.sensors
+                .tanks
+                .iter()
+                .any(|tank| tank.thermometer.id() == thermometer.id())
+        })
+        .map(|thermometer| async move {
+            let sensor = thermometer.id().to_owned();
+            (sensor, read_temperature(Arc::new(thermometer)).await)
+        });
+
+    // Every read blocks for a whole conversion, so the sensors are read all at once rather than one after another.
+    let (temperatures, others) = join(
+        future::join_all(
+            ctx.sensors
+                .tanks
+                .iter()
+                .map(|tank| read_temperature(tank.thermometer.clone())),
+        ),
+        future::join_all(others),
+    )
+    .await;
+
+    // A calibration made through the API is of the sensor in the default tank.
+    let temperatures: Vec<_> = temperatures
+        .into_iter()
+        .enumerate()
+        .map(|(i, reading)| {
+            let offset = if i == ctx.default_tank {
+                calibration.temperature_offset.unwrap_or(ctx.temperature_offset)
+            } else {
+                ctx.temperature_offset
+            };
+
+            reading.map(|reading| (reading, offset))
+        })
+        .collect();
+
+    // The offset is calibrated against the sensors in the tanks, so the other ones are reported as they are.
+    let mut other_temperatures = BTreeMap::new();
+    for (sensor, result) in others {
+        match result {
+            Ok(reading) => {
+                other_temperatures.insert(sensor, convert::round_to(reading.celsius, 1));
+            }
+            Err(e) => {
+                warn!(sensor; "Failed to read thermal sensor {sensor}: {e:?}");
+                state
+                    .errors
+                    .warning("measurements", format!("Failed to read thermal sensor {sensor}: {e:#}"));
+            }
+        }
+    }
+
+    let flow = state.flow.sample();
 
+    let state = state.clone();
     let ctx = ctx.clone();
     task::spawn_blocking(move || {
-        let temperature = {
-            let raw = fs::read_to_string(&ctx.temperature_path)?;
-            let Some(caps) = ctx.rx_temperature.captures(&raw) else {
-                return Err(anyhow!("Invalid format"));
-            };
-            let millis: i32 = caps[1].parse().unwrap();
+        let mut other_temperatures = Some(other_temperatures);
+        let readings = ctx
+            .sensors
+            .tanks
+            .iter()
+            .zip(temperatures)
+            .enumerate()
+            .map(|(i, (sensors, temperature))| {
+                let (reading, temperature_offset) = temperature?;
+                let temperature = convert::round_to(reading.celsius + temperature_offset, 1);
+                let is_default = i == ctx.default_tank;
+                let (tds_voltage, tds_counts) = read_tds_voltage(&ctx, sensors.tds.as_ref())?;
 
-            (f64::from(millis) / 100.0).round() / 10.0
-        };
+                let temperature_coefficient = convert::temperature_coefficient(temperature);
+                let compensated_voltage = tds_voltage / temperature_coefficient;
+                let tds_calibration_factor = if is_default { calibration.tds_factor() } else { 1.0 };
+                let uncalibrated_ec = convert::conductivity(compensated_voltage);
+                let ec = uncalibrated_ec * tds_calibration_factor;
+
+                let mut m = Measurements {
+                    timestamp: Utc::now(),
+                    temperature,
+                    temperature_raw: None,
+                    temperatures: BTreeMap::from([(sensors.thermometer.id().to_owned(), temperature)]),
+                    tds: convert::tds(ec, ctx.tds_factor),
+                    tds_raw: None,
+                    ec: ec.round(),
+                    tds_voltage: ctx.expose_tds_voltage.then_some(convert::round_to(tds_voltage, 4)),
+                    ph: None,
+                    flow_rate: None,
+                    flow_volume: None,
+                    water_level_ok: None,
+                    aux: BTreeMap::new(),
+                    debug: Some(Box::new(DebugValues {
+                        temperature_millis: reading.millis,
+                        temperature_offset,
+                        tds_counts,
+                        tds_voltage,
+                        temperature_coefficient,
+                        tds_voltage_compensated: compensated_voltage,
+                        tds_calibration_factor,
+                        tds_factor: ctx.tds_factor,
+                        ph_voltage: None,
+                        ph_slope: None,
+                        ph_offset: None,
+                    })),
+                };
+                if !is_default {
+                    return Ok(m);
+                }
 
-        let tds = {
-            let mut adc = ctx.tds_adc.lock().map_err(|e| anyhow!("{e:?}"))?;
-            let raw_value = block!(adc.read(channel::SingleA0)).map_err(|e| anyhow!("{e:?}"))?;
-            let voltage = f64::from(raw_value) * MAX_VOLTAGE / MAX_RAW_VALUE;
+                m.temperatures.extend(other_temperatures.take().unwrap_or_default());
 
-            let coefficient = 1.0 + 0.02 * (temperature - 25.0);
-            let voltage = voltage / coefficient;
-            let tds = (133.42 * voltage.powf(3.0) - 255.86 * voltage.powf(2.0) + 857.39 * voltage) * 0.5;
+                let ph_voltage = match ctx.ph {
+                    Some(_) => Some(ctx.sensors.ph.read_voltage()?),
+                    None => None,
+                };
+                let ph_calibration = ctx
+                    .ph
+                    .map(|config_calibration| calibration.ph.unwrap_or(config_calibration));
+                m.ph = ph_voltage
+                    .zip(ph_calibration)
+                    .map(|(voltage, ph_calibration)| convert::ph(voltage, ph_calibration));
+                if let Some(debug) = &mut m.debug {
+                    debug.ph_voltage = ph_voltage;
+                    debug.ph_slope = ph_calibration.map(|c| c.slope);
+                    debug.ph_offset = ph_calibration.map(|c| c.offset);
+                }
+                m.flow_rate = flow.map(|f| f.rate);
+                m.flow_volume = flow.map(|f| f.volume);
+                m.water_level_ok = state.water_level.is_ok();
+                m.aux = read_aux(&ctx, &state.errors);
 
-            tds.round()
-        };
+                *state.sampling.raw.lock().unwrap_or_else(|e| e.into_inner()) = RawValues {
+                    tds: Some(uncalibrated_ec * ctx.tds_factor),
+                    ph_voltage,
+                };
 
-        Ok(Measurements::new(temperature, tds))
+                Ok(m)
+            })
+            .collect();
+
+        Ok(readings)
     })
     .await?
 }
+
+/// Reads the voltage of a TDS probe. Pump noise makes single conversions jumpy, so a burst is taken and filtered.
+///
+/// Returns the voltage along with the counts of every conversion in the burst.
+fn read_tds_voltage(ctx: &Context, sensor: &dyn TdsSensor) -> anyhow::Result<(f64, Vec<i16>)> {
+    let mut samples = Vec::with_capacity(ctx.tds_samples);
+    let mut counts = Vec::with_capacity(ctx.tds_samples);
+    for i in 0..ctx.tds_samples {
+        if i > 0 {
+            thread::sleep(ctx.tds_sample_spacing);
+        }
+        let sample = sensor.read_sample()?;
+        samples.push(sample.voltage);
+        counts.extend(sample.counts);
+    }
+
+    let voltage = convert::filtered_mean(&mut samples, 2.0).ok_or_else(|| anyhow!("No TDS samples"))?;
+
+    Ok((voltage, counts))
+}
+
+/// Reads the auxiliary inputs. An input is not worth failing the measurements over, so it is left out when it cannot
+/// be read.
+fn read_aux(ctx: &Context, errors: &ErrorLog) -> BTreeMap<String, AuxValue> {
+    let mut aux = BTreeMap::new();
+    for (config, sensor) in ctx.aux.iter().zip(&ctx.sensors.aux) {
+        match sensor.read_voltage() {
+            Ok(voltage) => {
+                aux.insert(
+                    config.name.clone(),
+                    AuxValue {
+                        value: convert::round_to(config.scale * voltage + config.offset, 3),
+                        voltage: convert::round_to(voltage, 4),
+                    },
+                );
+            }
+            Err(e) => {
+                let name = &config.name;
+                warn!("Failed to read auxiliary input {name}: {e:?}");
+                errors.warning("measurements", format!("Failed to read auxiliary input {name}: {e:#}"));
+            }
+        }
+    }
+
+    aux
+}
+
+/// Reads a thermal sensor, retrying a few times when the bus returns garbage.
+///
+/// Each attempt gives up after a while, since a flaky 1-Wire bus can make reads hang.
+async fn read_temperature(sensor: Arc<dyn TemperatureSensor>) -> anyhow::Result<TemperatureReading> {
+    const RETRIES: u32 = 2;
+    const RETRY_DELAY: Duration = Duration::from_millis(500);
+    const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+    let mut attempt = 0;
+    loop {
+        let read = task::spawn_blocking({
+            let sensor = sensor.clone();
+            move || sensor.read()
+        });
+        let result = match timeout(READ_TIMEOUT, read).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(anyhow!("Timed out after {}s", READ_TIMEOUT.as_secs())),
+        };
+        match result {
+            Ok(reading) => return Ok(reading),
+            Err(e) if attempt < RETRIES => {
+                attempt += 1;
+                let sensor = sensor.id();
+                warn!(sensor, attempt; "Failed to read thermal sensor {sensor} (attempt {attempt}): {e:?}");
+                sleep(RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        sensors::{
+            TankSensors,
+            fakes::{FakePh, FakeTds, FakeThermometer},
+        },
+        *,
+    };
+    use crate::config::{Config, PhConfig};
+
+    struct Fixture {
+        ctx: Arc<Context>,
+        state: AppState,
+        thermometer: FakeThermometer,
+        tds: FakeTds,
+    }
+
+    fn fixture() -> Fixture {
+        let config = Config {
+            measurements: MeasurementsConfig {
+                tds_samples: 3,
+                tds_sample_spacing_ms: 0,
+                ph: Some(PhConfig::default()),
+                ..MeasurementsConfig::default()
+            },
+            ..Config::default()
+        };
+        let thermometer = FakeThermometer::new("28-000000000001", 24_500);
+        let tds = FakeTds::new(0.4);
+        let sensors = Sensors {
+            tanks: vec![TankSensors {
+                thermometer: Arc::new(thermometer.clone()),
+                tds: Box::new(tds.clone()),
+            }],
+            w1_bus: None,
+            ph: Box::new(FakePh::new(2.5)),
+            aux: Vec::new(),
+        };
+
+        let state = AppState::new(Arc::new(config));
+        let interval = state.sampling.interval.subscribe();
+
+        Fixture {
+            ctx: Arc::new(Context::with_sensors(&state.config.measurements, sensors, interval)),
+            state,
+            thermometer,
+            tds,
+        }
+    }
+
+    async fn read_default(fixture: &Fixture) -> anyhow::Result<Measurements> {
+        let ctx = &fixture.ctx;
+        read(&fixture.state, ctx).await.unwrap().swap_remove(ctx.default_tank)
+    }
+
+    #[tokio::test]
+    async fn reads_and_converts_the_fake_sensors() {
+        let fixture = fixture();
+        let m = read_default(&fixture).await.unwrap();
+
+        assert!((m.temperature - 24.5).abs() < 1e-9);
+        // 0.4 V compensated from 24.5 °C comes to 313.45 µS/cm
+        assert!((m.ec - 313.0).abs() < 1e-9);
+        assert!((m.tds - 157.0).abs() < 1e-9);
+        assert_eq!(m.ph, Some(7.09));
+        assert_eq!(m.temperatures.get("28-000000000001"), Some(&24.5));
+        let debug = m.debug.unwrap();
+        assert_eq!(debug.temperature_millis, Some(24_500));
+        assert!((debug.temperature_coefficient - 0.99).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn negative_tds_voltage_reads_as_zero() {
+        let fixture = fixture();
+        fixture.tds.set(-0.02);
+        let m = read_default(&fixture).await.unwrap();
+
+        assert!(m.ec.abs() < 1e-9);
+        assert!(m.tds.abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn failing_thermometer_fails_the_tank() {
+        let fixture = fixture();
+        fixture.thermometer.set(None);
+
+        assert!(read_default(&fixture).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn update_tank_stores_the_latest_and_the_history() {
+        let fixture = fixture();
+        let tank = fixture.state.default_tank();
+        let m = read_default(&fixture).await.unwrap();
+        update_tank(
+            &fixture.state,
+            &fixture.ctx,
+            &fixture.ctx.tanks[0],
+            tank,
+            m.clone(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        let latest = tank.measurements.get().await.unwrap();
+        assert_eq!(latest.timestamp, m.timestamp);
+        assert!(latest.debug.is_some());
+        let history = tank.history.read().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].timestamp, m.timestamp);
+        // Only the latest measurements carry the debug values
+        assert!(history[0].debug.is_none());
+    }
+
+    #[tokio::test]
+    async fn update_tank_keeps_the_last_value_in_place_of_a_spike() {
+        let fixture = fixture();
+        let tank = fixture.state.default_tank();
+        let tank_ctx = &fixture.ctx.tanks[0];
+        let m = read_default(&fixture).await.unwrap();
+        let spike = Measurements {
+            timestamp: m.timestamp + TimeDelta::seconds(10),
+            temperature: m.temperature + 5.0,
+            tds: m.tds + 1.0,
+            ..m.clone()
+        };
+
+        update_tank(&fixture.state, &fixture.ctx, tank_ctx, tank, m.clone(), true)
+            .await
+            .unwrap();
+        let updated = update_tank(&fixture.state, &fixture.ctx, tank_ctx, tank, spike.clone(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(updated.temperature, m.temperature);
+        // The TDS of the same reading is kept
+        assert_eq!(updated.tds, spike.tds);
+        assert_eq!(tank.history.read().await.len(), 2);
+        let rejections = fixture.state.sampling.rejections();
+        assert_eq!((rejections.temperature, rejections.tds), (1, 0));
+    }
+}
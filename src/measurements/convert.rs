@@ -0,0 +1,394 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::sync::LazyLock;
+
+use anyhow::anyhow;
+use regex::Regex;
+
+use crate::calibration::LinearCalibration;
+
+static RX_TEMPERATURE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"t=\s*(-?[0-9]+)").unwrap());
+
+/// Converts a raw conversion into volts, given the full-scale `range` the ADC is set up with and the raw value it
+/// reads at full scale, which depends on its resolution.
+#[cfg(feature = "tds")]
+pub(super) fn adc_voltage(raw_value: i16, range: f64, max_raw_value: i16) -> f64 {
+    f64::from(raw_value) * range / f64::from(max_raw_value)
+}
+
+/// Parses the contents of a DS18B20 `w1_slave` file into millidegrees Celsius.
+///
+/// The first line carries the CRC result of the scratchpad read, and the value on the second line is only
+/// trustworthy when it ends with `YES`.
+pub(super) fn parse_w1_slave(raw: &str) -> anyhow::Result<i32> {
+    let mut lines = raw.lines();
+    let (Some(crc_line), Some(value_line)) = (lines.next(), lines.next()) else {
+        return Err(anyhow!("Invalid format"));
+    };
+    if !crc_line.trim_end().ends_with("YES") {
+        return Err(anyhow!("CRC check failed"));
+    }
+    let Some(caps) = RX_TEMPERATURE.captures(value_line) else {
+        return Err(anyhow!("Invalid format"));
+    };
+    let millis: i32 = caps[1].parse()?;
+
+    check_w1_error_value(millis)
+}
+
+/// Parses the contents of the `temperature` attribute of a DS18B20 into millidegrees Celsius.
+///
+/// The kernel checks the CRC itself and fails the read when it does not match, so only the value is left.
+pub(super) fn parse_w1_temperature(raw: &str) -> anyhow::Result<i32> {
+    let millis: i32 = raw.trim().parse().map_err(|_| anyhow!("Invalid format"))?;
+
+    check_w1_error_value(millis)
+}
+
+fn check_w1_error_value(millis: i32) -> anyhow::Result<i32> {
+    // 85 °C is the power-on reset value of the scratchpad, and 127.9375 °C is what a sensor with a broken
+    // conversion reports. Neither is a plausible water temperature.
+    if millis == 85_000 || millis == 127_937 {
+        return Err(anyhow!("Sensor reported an error value: t={millis}"));
+    }
+
+    Ok(millis)
+}
+
+pub(super) fn millis_to_celsius(millis: i32) -> f64 {
+    f64::from(millis) / 1000.0
+}
+
+/// Returns what the voltage of the TDS probe is divided by to compensate for the water being at `temperature` °C
+/// rather than 25 °C.
+pub(super) fn temperature_coefficient(temperature: f64) -> f64 {
+    1.0 + 0.02 * (temperature - 25.0)
+}
+
+/// Converts the voltage of the TDS probe, compensated to 25 °C, into the electrical conductivity in µS/cm.
+///
+/// A negative voltage is only ever the noise of the ADC around a probe in air, so it counts as 0 V.
+pub(super) fn conductivity(voltage: f64) -> f64 {
+    let voltage = voltage.max(0.0);
+
+    133.42 * voltage.powf(3.0) - 255.86 * voltage.powf(2.0) + 857.39 * voltage
+}
+
+/// Converts the conductivity in µS/cm into TDS in whole ppm on the scale of `tds_factor`.
+pub(super) fn tds(ec: f64, tds_factor: f64) -> f64 {
+    (ec * tds_factor).round()
+}
+
+/// Converts the voltage of the pH probe into pH with `calibration`, rounded to two decimal places.
+///
+/// A negative voltage counts as 0 V like that of the TDS probe, and the result is kept on the 0 to 14 scale.
+pub(super) fn ph(voltage: f64, calibration: LinearCalibration) -> f64 {
+    round_to(calibration.apply(voltage.max(0.0)).clamp(0.0, 14.0), 2)
+}
+
+/// Rounds `value` to `decimals` decimal places.
+pub(super) fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10.0_f64.powi(decimals);
+
+    (value * scale).round() / scale
+}
+
+/// Averages `samples` after discarding those further than `max_deviations` median absolute deviations from the
+/// median. Returns `None` when there are no samples.
+pub(super) fn filtered_mean(samples: &mut [f64], max_deviations: f64) -> Option<f64> {
+    let center = median(samples)?;
+    let mut deviations: Vec<f64> = samples.iter().map(|v| (v - center).abs()).collect();
+    let mad = median(&mut deviations)?;
+
+    let (sum, count) = samples
+        .iter()
+        .filter(|v| (*v - center).abs() <= max_deviations * mad)
+        .fold((0.0, 0u32), |(sum, count), v| (sum + v, count + 1));
+
+    // At least half of the samples lie within one MAD of the median, so `count` is never zero here.
+    Some(sum / f64::from(count))
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tds")]
+    use crate::config::{AdcChip, AdcConfig};
+
+    const PH: LinearCalibration = LinearCalibration {
+        slope: -5.7,
+        offset: 21.34,
+    };
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[cfg(feature = "tds")]
+    fn voltage_of(raw_value: i16, range: f64, chip: AdcChip) -> f64 {
+        adc_voltage(raw_value, range, chip.max_raw_value())
+    }
+
+    #[cfg(feature = "tds")]
+    #[test]
+    fn ads1115_voltage_at_zero_and_full_scale() {
+        assert_close(voltage_of(0, 4.096, AdcChip::Ads1115), 0.0);
+        assert_close(voltage_of(32767, 4.096, AdcChip::Ads1115), 4.096);
+        assert_close(voltage_of(-32768, 4.096, AdcChip::Ads1115), -4.096_125);
+    }
+
+    #[cfg(feature = "tds")]
+    #[test]
+    fn ads1015_voltage_at_zero_and_full_scale() {
+        assert_close(voltage_of(0, 4.096, AdcChip::Ads1015), 0.0);
+        assert_close(voltage_of(2047, 4.096, AdcChip::Ads1015), 4.096);
+        assert_close(voltage_of(-2048, 4.096, AdcChip::Ads1015), -4.098_001);
+    }
+
+    #[cfg(feature = "tds")]
+    #[test]
+    fn both_chips_agree_on_the_same_voltage() {
+        // 16 counts of the ADS1115 make one of the ADS1015, give or take the rounding of the full scale.
+        for range in AdcConfig::RANGES {
+            let ads1115 = voltage_of(16 * 1000, range, AdcChip::Ads1115);
+            let ads1015 = voltage_of(1000, range, AdcChip::Ads1015);
+            assert!(
+                (ads1115 - ads1015).abs() < range / 2047.0,
+                "{range}: {ads1115} != {ads1015}"
+            );
+        }
+    }
+
+    #[test]
+    fn conductivity_is_zero_at_zero_volts() {
+        assert_close(conductivity(0.0), 0.0);
+    }
+
+    #[test]
+    fn conductivity_at_full_scale() {
+        assert_close(conductivity(4.096), 8_387.803_540_357);
+    }
+
+    #[test]
+    fn conductivity_clamps_negative_voltages() {
+        assert_close(conductivity(-0.01), 0.0);
+        assert_close(conductivity(-4.096), 0.0);
+    }
+
+    #[test]
+    fn temperature_coefficient_is_one_at_25_degrees() {
+        assert_close(temperature_coefficient(25.0), 1.0);
+    }
+
+    #[test]
+    fn compensation_raises_the_voltage_below_25_degrees() {
+        let coefficient = temperature_coefficient(15.0);
+        assert_close(coefficient, 0.8);
+        assert_close(conductivity(0.4 / coefficient), 381.4075);
+        assert!(conductivity(0.4 / coefficient) > conductivity(0.4));
+    }
+
+    #[test]
+    fn compensation_lowers_the_voltage_above_25_degrees() {
+        let coefficient = temperature_coefficient(35.0);
+        assert_close(coefficient, 1.2);
+        assert_close(conductivity(0.4 / coefficient), 262.309_259_259);
+        assert!(conductivity(0.4 / coefficient) < conductivity(0.4));
+    }
+
+    /// TDS of water whose probe reads `voltage` at `temperature` °C, on the scale of `tds_factor`.
+    fn tds_of(voltage: f64, temperature: f64, tds_factor: f64) -> f64 {
+        tds(conductivity(voltage / temperature_coefficient(temperature)), tds_factor)
+    }
+
+    // 0.4 V at 25 °C is 310.557 µS/cm, and so is 0.4 V at 15 °C once compensated to 0.5 V, 381.408 µS/cm.
+
+    #[test]
+    fn tds_on_the_nacl_scale() {
+        assert_eq!(tds_of(0.4, 25.0, 0.5), 155.0);
+    }
+
+    #[test]
+    fn tds_on_the_442_scale() {
+        assert_eq!(tds_of(0.4, 25.0, 0.64), 199.0);
+    }
+
+    #[test]
+    fn tds_on_the_kcl_scale() {
+        assert_eq!(tds_of(0.4, 25.0, 0.7), 217.0);
+    }
+
+    #[test]
+    fn compensated_tds_on_the_nacl_scale() {
+        assert_eq!(tds_of(0.4, 15.0, 0.5), 191.0);
+    }
+
+    #[test]
+    fn compensated_tds_on_the_442_scale() {
+        assert_eq!(tds_of(0.4, 15.0, 0.64), 244.0);
+    }
+
+    #[test]
+    fn compensated_tds_on_the_kcl_scale() {
+        assert_eq!(tds_of(0.4, 15.0, 0.7), 267.0);
+    }
+
+    #[test]
+    fn ph_at_zero_volts() {
+        assert_close(ph(0.0, PH), 14.0);
+        assert_close(
+            ph(
+                0.0,
+                LinearCalibration {
+                    slope: -5.7,
+                    offset: 7.0,
+                },
+            ),
+            7.0,
+        );
+    }
+
+    #[test]
+    fn ph_at_mid_and_full_scale() {
+        assert_close(ph(2.5, PH), 7.09);
+        // -2.0072 before being kept on the scale
+        assert_close(ph(4.096, PH), 0.0);
+    }
+
+    #[test]
+    fn ph_clamps_negative_voltages() {
+        let calibration = LinearCalibration {
+            slope: -5.7,
+            offset: 7.0,
+        };
+        assert_close(ph(-0.2, calibration), ph(0.0, calibration));
+    }
+
+    #[test]
+    fn rounds_to_decimals() {
+        assert_close(round_to(24.46, 1), 24.5);
+        assert_close(round_to(-0.125, 2), -0.13);
+        assert_close(round_to(310.557, 0), 311.0);
+    }
+
+    #[test]
+    fn millis_to_celsius_keeps_the_sign() {
+        assert_close(millis_to_celsius(24_500), 24.5);
+        assert_close(millis_to_celsius(-500), -0.5);
+    }
+
+    /// `w1_slave` as the driver writes it, with the CRC result on the first line.
+    fn w1_slave(crc: &str, millis: i32) -> String {
+        format!("72 01 4b 46 7f ff 0e 10 57 : crc=57 {crc}\n72 01 4b 46 7f ff 0e 10 57 t={millis}\n")
+    }
+
+    #[test]
+    fn w1_slave_below_zero() {
+        assert_eq!(parse_w1_slave(&w1_slave("YES", -500)).unwrap(), -500);
+    }
+
+    #[test]
+    fn w1_slave_at_zero() {
+        assert_eq!(parse_w1_slave(&w1_slave("YES", 0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn w1_slave_above_zero() {
+        assert_eq!(parse_w1_slave(&w1_slave("YES", 23_125)).unwrap(), 23_125);
+    }
+
+    #[test]
+    fn w1_slave_with_a_failed_crc() {
+        let e = parse_w1_slave(&w1_slave("NO", 23_125)).unwrap_err();
+        assert_eq!(e.to_string(), "CRC check failed");
+    }
+
+    #[test]
+    fn w1_slave_without_a_value() {
+        assert!(parse_w1_slave("72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n").is_err());
+    }
+
+    #[test]
+    fn w1_temperature_keeps_the_sign() {
+        assert_eq!(parse_w1_temperature("-500\n").unwrap(), -500);
+        assert_eq!(parse_w1_temperature("0\n").unwrap(), 0);
+        assert_eq!(parse_w1_temperature("23125\n").unwrap(), 23_125);
+    }
+
+    #[test]
+    fn power_on_reset_value_is_an_error() {
+        assert!(check_w1_error_value(85_000).is_err());
+        assert!(parse_w1_slave(&w1_slave("YES", 85_000)).is_err());
+        assert!(parse_w1_temperature("85000\n").is_err());
+    }
+
+    #[test]
+    fn broken_conversion_value_is_an_error() {
+        assert!(check_w1_error_value(127_937).is_err());
+        assert!(parse_w1_slave(&w1_slave("YES", 127_937)).is_err());
+        assert!(parse_w1_temperature("127937\n").is_err());
+    }
+
+    #[test]
+    fn values_next_to_the_error_values_are_kept() {
+        assert_eq!(check_w1_error_value(84_937).unwrap(), 84_937);
+        assert_eq!(check_w1_error_value(-500).unwrap(), -500);
+        assert_eq!(check_w1_error_value(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn median_of_an_odd_number_of_values() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), Some(2.0));
+    }
+
+    #[test]
+    fn median_of_an_even_number_of_values() {
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), Some(2.5));
+    }
+
+    #[test]
+    fn median_of_nothing() {
+        assert_eq!(median(&mut []), None);
+    }
+
+    #[test]
+    fn filtered_mean_rejects_an_outlier() {
+        let mut samples = [24.0, 24.1, 30.0, 24.2, 24.1];
+        assert_close(filtered_mean(&mut samples, 3.0).unwrap(), 24.1);
+    }
+
+    #[test]
+    fn filtered_mean_of_an_even_number_of_samples() {
+        let mut samples = [24.0, 24.2, 24.1, 24.3];
+        assert_close(filtered_mean(&mut samples, 3.0).unwrap(), 24.15);
+    }
+
+    #[test]
+    fn filtered_mean_keeps_identical_samples_around_an_outlier() {
+        let mut samples = [24.0, 24.0, 85.0, 24.0];
+        assert_close(filtered_mean(&mut samples, 3.0).unwrap(), 24.0);
+    }
+
+    #[test]
+    fn filtered_mean_of_a_single_sample() {
+        assert_eq!(filtered_mean(&mut [24.5], 3.0), Some(24.5));
+        assert_eq!(filtered_mean(&mut [], 3.0), None);
+    }
+}
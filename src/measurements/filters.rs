@@ -0,0 +1,233 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+
+/// The changes allowed by [`SpikeFilter`] are given per this many seconds.
+const SPIKE_PERIOD_SECS: f64 = 10.0;
+
+/// Rejects a reading that jumped further from the previous one than the water plausibly can.
+///
+/// A rejected reading is accepted after all once the next one agrees with it, so that a genuine fast change is held
+/// back for a single reading at most.
+pub(super) struct SpikeFilter {
+    /// What is filtered, for the errors.
+    name: &'static str,
+    /// Largest plausible change per [`SPIKE_PERIOD_SECS`].
+    max_change: f64,
+    accepted: Option<(f64, DateTime<Utc>)>,
+    rejected: Option<(f64, DateTime<Utc>)>,
+}
+
+impl SpikeFilter {
+    pub fn new(name: &'static str, max_change: f64) -> Self {
+        Self {
+            name,
+            max_change,
+            accepted: None,
+            rejected: None,
+        }
+    }
+
+    /// Checks `value` read at `at` against the previous readings, remembering it as the one to compare the next
+    /// reading against.
+    pub fn check(&mut self, value: f64, at: DateTime<Utc>) -> anyhow::Result<()> {
+        let Some((previous, previous_at)) = self.accepted else {
+            self.accepted = Some((value, at));
+            return Ok(());
+        };
+
+        let confirmed = self
+            .rejected
+            .take()
+            .is_some_and(|(rejected, rejected_at)| self.is_plausible(rejected, rejected_at, value, at));
+        if confirmed || self.is_plausible(previous, previous_at, value, at) {
+            self.accepted = Some((value, at));
+            return Ok(());
+        }
+
+        self.rejected = Some((value, at));
+        let secs = (at - previous_at).num_seconds();
+        Err(anyhow!(
+            "Rejected {} of {value}, jumping from {previous} in {secs}s",
+            self.name
+        ))
+    }
+
+    /// The value the last reading that passed was accepted with, if any.
+    pub fn accepted(&self) -> Option<f64> {
+        self.accepted.map(|(value, _)| value)
+    }
+
+    fn is_plausible(&self, from: f64, from_at: DateTime<Utc>, to: f64, to_at: DateTime<Utc>) -> bool {
+        // Readings closer together than the period are allowed the change of a whole period, as the noise of the
+        // probes does not shrink with the interval.
+        let periods = ((to_at - from_at).as_seconds_f64() / SPIKE_PERIOD_SECS).max(1.0);
+
+        (to - from).abs() <= self.max_change * periods
+    }
+}
+
+/// Exponential smoothing, which starts over after a gap so that values from either side of it are not blended.
+pub(super) struct Smoother {
+    /// Weight of the newest value, between 0 and 1.
+    alpha: f64,
+    last: Option<(f64, DateTime<Utc>)>,
+}
+
+impl Smoother {
+    pub fn new(alpha: f64) -> Self {
+        Self { alpha, last: None }
+    }
+
+    /// Returns the smoothed value after `value` read at `at`, which is `value` itself when the previous one is older
+    /// than `max_gap`.
+    pub fn apply(&mut self, value: f64, at: DateTime<Utc>, max_gap: Duration) -> f64 {
+        let smoothed = match self.last {
+            Some((last, last_at)) if (at - last_at).to_std().is_ok_and(|gap| gap <= max_gap) => {
+                self.alpha * value + (1.0 - self.alpha) * last
+            }
+            _ => value,
+        };
+        self.last = Some((smoothed, at));
+
+        smoothed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    const INTERVAL: TimeDelta = TimeDelta::seconds(10);
+    const MAX_GAP: Duration = Duration::from_secs(30);
+
+    /// A filter allowing 2 °C per period.
+    fn spikes() -> SpikeFilter {
+        SpikeFilter::new("temperature", 2.0)
+    }
+
+    #[test]
+    fn accepts_the_first_reading_whatever_it_is() {
+        let mut filter = spikes();
+        filter.check(85.0, Utc::now()).unwrap();
+        assert_eq!(filter.accepted(), Some(85.0));
+    }
+
+    #[test]
+    fn accepts_changes_up_to_the_limit() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.0, start).unwrap();
+        filter.check(27.0, start + INTERVAL).unwrap();
+        filter.check(25.0, start + INTERVAL * 2).unwrap();
+        assert_eq!(filter.accepted(), Some(25.0));
+    }
+
+    #[test]
+    fn rejects_a_spike_and_accepts_the_return_to_normal() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.4, start).unwrap();
+
+        let e = filter.check(3.2, start + INTERVAL).unwrap_err();
+        assert_eq!(e.to_string(), "Rejected temperature of 3.2, jumping from 25.4 in 10s");
+        assert_eq!(filter.accepted(), Some(25.4));
+
+        filter.check(25.5, start + INTERVAL * 2).unwrap();
+        assert_eq!(filter.accepted(), Some(25.5));
+    }
+
+    #[test]
+    fn accepts_two_consecutive_consistent_readings() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.0, start).unwrap();
+
+        filter.check(30.0, start + INTERVAL).unwrap_err();
+        filter.check(30.5, start + INTERVAL * 2).unwrap();
+        assert_eq!(filter.accepted(), Some(30.5));
+        filter.check(30.4, start + INTERVAL * 3).unwrap();
+    }
+
+    #[test]
+    fn a_rejected_reading_is_only_confirmed_by_the_next_one() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.0, start).unwrap();
+
+        filter.check(30.0, start + INTERVAL).unwrap_err();
+        filter.check(25.0, start + INTERVAL * 2).unwrap();
+        filter.check(30.0, start + INTERVAL * 3).unwrap_err();
+        assert_eq!(filter.accepted(), Some(25.0));
+    }
+
+    #[test]
+    fn readings_closer_than_the_period_are_allowed_a_whole_period() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.0, start).unwrap();
+        filter.check(26.9, start + TimeDelta::seconds(2)).unwrap();
+        filter.check(29.0, start + TimeDelta::seconds(3)).unwrap_err();
+        filter.check(25.0, start + TimeDelta::seconds(3)).unwrap();
+    }
+
+    #[test]
+    fn readings_further_apart_are_allowed_more() {
+        let mut filter = spikes();
+        let start = Utc::now();
+        filter.check(25.0, start).unwrap();
+        filter.check(36.0, start + TimeDelta::seconds(60)).unwrap();
+        filter.check(50.0, start + TimeDelta::seconds(120)).unwrap_err();
+    }
+
+    #[test]
+    fn follows_a_step_exponentially() {
+        let mut smoother = Smoother::new(0.25);
+        let start = Utc::now();
+        assert_eq!(smoother.apply(0.0, start, MAX_GAP), 0.0);
+
+        for n in 1..=20 {
+            let smoothed = smoother.apply(1.0, start + INTERVAL * n, MAX_GAP);
+            let expected = 1.0 - 0.75_f64.powi(n);
+            assert!((smoothed - expected).abs() < 1e-12, "{n}: {smoothed} != {expected}");
+        }
+    }
+
+    #[test]
+    fn passes_values_through_with_an_alpha_of_one() {
+        let mut smoother = Smoother::new(1.0);
+        let start = Utc::now();
+        smoother.apply(0.0, start, MAX_GAP);
+
+        assert_eq!(smoother.apply(1.0, start + INTERVAL, MAX_GAP), 1.0);
+    }
+
+    #[test]
+    fn starts_over_after_a_gap() {
+        let mut smoother = Smoother::new(0.5);
+        let start = Utc::now();
+        smoother.apply(0.0, start, MAX_GAP);
+        assert_eq!(smoother.apply(1.0, start + INTERVAL, MAX_GAP), 0.5);
+
+        let after_gap = start + INTERVAL + TimeDelta::seconds(31);
+        assert_eq!(smoother.apply(1.0, after_gap, MAX_GAP), 1.0);
+        assert_eq!(smoother.apply(0.0, after_gap + INTERVAL, MAX_GAP), 0.5);
+    }
+
+    #[test]
+    fn starts_over_when_the_clock_goes_back() {
+        let mut smoother = Smoother::new(0.5);
+        let start = Utc::now();
+        smoother.apply(0.0, start, MAX_GAP);
+
+        assert_eq!(smoother.apply(1.0, start - INTERVAL, MAX_GAP), 1.0);
+    }
+}
@@ -0,0 +1,561 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use logger::log::{info, warn};
+
+use super::convert;
+use crate::{
+    check::Outcome,
+    config::{AdcChannel, AdcConfig, AdcMode, MeasurementsConfig},
+    errors::ErrorLog,
+    hardware::{Device, Hardware},
+    simulation,
+};
+
+#[cfg(feature = "tds")]
+mod ads1x15;
+
+/// The sensors the measurements are read from.
+pub(super) struct Sensors {
+    /// The sensors of each tank, in the order configured.
+    pub tanks: Vec<TankSensors>,
+    /// Where the other thermal sensors are found. `None` when simulated.
+    pub w1_bus: Option<Arc<W1Bus>>,
+    /// In the default tank.
+    pub ph: Box<dyn PhSensor>,
+    /// The auxiliary inputs, in the order configured.
+    pub aux: Vec<Box<dyn AuxSensor>>,
+}
+
+/// The probes in a single tank.
+pub(super) struct TankSensors {
+    /// The thermal sensor the temperature of the tank is read from, and calibrated against in the default tank.
+    pub thermometer: Arc<dyn TemperatureSensor>,
+    pub tds: Box<dyn TdsSensor>,
+}
+
+impl Sensors {
+    /// Opens the sensors wired up as configured, recording what was found in `hardware` and the failures to read them
+    /// in `errors`.
+    pub fn open(config: &MeasurementsConfig, hardware: &Hardware, errors: &Arc<ErrorLog>) -> anyhow::Result<Self> {
+        let tanks = config.tanks();
+        let continuous = config.adc.mode == AdcMode::Continuous;
+        let single_channel = tanks.len() == 1 && config.ph.is_none() && config.adc.aux.is_empty();
+        if continuous && !single_channel {
+            warn!("Using the ADC in one-shot mode, as continuous mode only works with a single TDS channel alone");
+        }
+        let tds_channels: Vec<_> = tanks.iter().map(|tank| tank.tds_channel).collect();
+
+        // Both buses are probed before bailing out on either, so that the report covers them all.
+        let w1_bus = W1Bus::open(&config.w1_devices, errors.clone());
+        let probes = open_probes(
+            &config.i2c_bus,
+            &config.adc,
+            &tds_channels,
+            continuous && single_channel,
+            errors.clone(),
+        );
+        hardware.record(|report| {
+            let w1_devices = config.w1_devices.display();
+            match &w1_bus {
+                Ok(w1_bus) => {
+                    report.w1_sensors = w1_bus.sensor_ids();
+                    report.w1_bus = Device::found(format!(
+                        "1-Wire bus: {} sensors at {w1_devices}",
+                        report.w1_sensors.len()
+                    ));
+                }
+                Err(e) => {
+                    report.w1_sensors.clear();
+                    report.w1_bus = Device::missing(format!("1-Wire bus: not found at {w1_devices} ({e:#})"));
+                }
+            }
+
+            let (chip, address) = (config.adc.chip, config.adc.address);
+            report.adc = match &probes {
+                Ok(_) => Device::found(format!("{chip}: responding at {address:#04x}")),
+                Err(e) => Device::missing(format!("{chip}: not responding at {address:#04x} ({e:#})")),
+            };
+        });
+
+        let w1_bus = w1_bus?;
+        let thermometers = tanks
+            .iter()
+            .map(|tank| w1_bus.thermometer(tank.temperature_sensor.as_deref()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (tds, ph, aux) = probes?;
+
+        Ok(Self {
+            tanks: thermometers
+                .into_iter()
+                .zip(tds)
+                .map(|(thermometer, tds)| TankSensors {
+                    thermometer: Arc::new(thermometer),
+                    tds,
+                })
+                .collect(),
+            w1_bus: Some(Arc::new(w1_bus)),
+            ph,
+            aux,
+        })
+    }
+
+    /// Returns sensors that make up their readings, for development without the hardware.
+    pub fn simulated(config: &MeasurementsConfig) -> Self {
+        Self {
+            tanks: (0..config.tanks().len())
+                .map(|i| TankSensors {
+                    thermometer: Arc::new(SimulatedThermometer {
+                        id: simulation::sensor_id(i),
+                        base: 25.0 - i as f64,
+                    }),
+                    tds: Box::new(SimulatedTds),
+                })
+                .collect(),
+            w1_bus: None,
+            ph: Box::new(SimulatedPh),
+            aux: config
+                .adc
+                .aux
+                .iter()
+                .map(|_| Box::new(SimulatedAux) as Box<dyn AuxSensor>)
+                .collect(),
+        }
+    }
+}
+
+/// Reads the thermal sensor of every tank once, without retrying.
+pub(super) fn probe_thermometers(config: &MeasurementsConfig) -> Vec<Outcome> {
+    // Failures end up in the outcomes, so none are kept besides.
+    let w1_bus = match W1Bus::open(&config.w1_devices, Arc::new(ErrorLog::new(0))) {
+        Ok(w1_bus) => w1_bus,
+        Err(e) => {
+            return vec![Outcome::new(
+                format!("1-Wire bus {}", config.w1_devices.display()),
+                Err(e),
+            )];
+        }
+    };
+
+    config
+        .tanks()
+        .iter()
+        .map(|tank| {
+            let result = w1_bus
+                .thermometer(tank.temperature_sensor.as_deref())
+                .and_then(|sensor| Ok(format!("{} reads {:.1} °C", sensor.id(), sensor.read()?.celsius)));
+            Outcome::new(format!("Thermal sensor of tank {}", tank.name), result)
+        })
+        .collect()
+}
+
+/// Takes a single conversion of every input of the ADC in use.
+pub(super) fn probe_adc(config: &MeasurementsConfig) -> Vec<Outcome> {
+    let tanks = config.tanks();
+    let tds_channels: Vec<_> = tanks.iter().map(|tank| tank.tds_channel).collect();
+    let errors = Arc::new(ErrorLog::new(0));
+    let (tds, ph, aux) = match open_probes(&config.i2c_bus, &config.adc, &tds_channels, false, errors) {
+        Ok(probes) => probes,
+        Err(e) => {
+            return vec![Outcome::new(
+                format!("{} at {:#04x}", config.adc.chip, config.adc.address),
+                Err(e),
+            )];
+        }
+    };
+    let read = |voltage: anyhow::Result<f64>| voltage.map(|v| format!("{v:.4} V"));
+
+    let mut outcomes: Vec<_> = tanks
+        .iter()
+        .zip(&tds)
+        .map(|(tank, tds)| {
+            Outcome::new(
+                format!("TDS probe of tank {} on {}", tank.name, tank.tds_channel),
+                read(tds.read_sample().map(|sample| sample.voltage)),
+            )
+        })
+        .collect();
+    if config.ph.is_some() {
+        outcomes.push(Outcome::new(
+            format!("pH probe on {}", config.adc.ph_channel),
+            read(ph.read_voltage()),
+        ));
+    }
+    for (aux_config, sensor) in config.adc.aux.iter().zip(&aux) {
+        outcomes.push(Outcome::new(
+            format!("Auxiliary input {} on {}", aux_config.name, aux_config.channel),
+            read(sensor.read_voltage()),
+        ));
+    }
+
+    outcomes
+}
+
+/// A reading of a thermal sensor.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct TemperatureReading {
+    pub celsius: f64,
+    /// Millidegrees Celsius as the sensor reported them. `None` when simulated.
+    pub millis: Option<i32>,
+}
+
+/// A single conversion of the ADC.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct AdcSample {
+    pub voltage: f64,
+    /// The conversion as the chip returned it. `None` when simulated.
+    pub counts: Option<i16>,
+}
+
+/// A thermal sensor. Reads block, so they are made from `spawn_blocking`.
+pub(super) trait TemperatureSensor: Send + Sync {
+    /// ID the sensor is reported under, e.g. `28-0316a279xxxx`.
+    fn id(&self) -> &str;
+
+    /// Reads the temperature, without retrying.
+    fn read(&self) -> anyhow::Result<TemperatureReading>;
+}
+
+/// The TDS probe. Reads block, so they are made from `spawn_blocking`.
+pub(super) trait TdsSensor: Send + Sync {
+    /// Takes a single conversion of the voltage of the probe.
+    fn read_sample(&self) -> anyhow::Result<AdcSample>;
+}
+
+/// The pH probe. Reads block, so they are made from `spawn_blocking`.
+pub(super) trait PhSensor: Send + Sync {
+    /// Takes a single reading of the voltage of the probe.
+    fn read_voltage(&self) -> anyhow::Result<f64>;
+}
+
+/// An auxiliary input of the ADC. Reads block, so they are made from `spawn_blocking`.
+pub(super) trait AuxSensor: Send + Sync {
+    /// Takes a single reading of the voltage on the input.
+    fn read_voltage(&self) -> anyhow::Result<f64>;
+}
+
+/// A DS18B20 on the 1-Wire bus.
+pub(super) struct W1Thermometer {
+    id: String,
+    path: PathBuf,
+    /// Whether `path` is the `temperature` attribute of newer kernels rather than the `w1_slave` file.
+    is_attribute: bool,
+}
+
+impl W1Thermometer {
+    /// Takes `path` to be the `w1_slave` file of the sensor, reading the `temperature` attribute next to it instead
+    /// where there is one.
+    fn new(path: PathBuf) -> Self {
+        let id = sensor_id(&path);
+        let attribute = path.with_file_name("temperature");
+        if attribute.is_file() {
+            Self {
+                id,
+                path: attribute,
+                is_attribute: true,
+            }
+        } else {
+            Self {
+                id,
+                path,
+                is_attribute: false,
+            }
+        }
+    }
+}
+
+impl TemperatureSensor for W1Thermometer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Blocks for the whole conversion, about 750 ms, which the kernel makes during the read.
+    fn read(&self) -> anyhow::Result<TemperatureReading> {
+        let raw = fs::read_to_string(&self.path)?;
+        let millis = if self.is_attribute {
+            convert::parse_w1_temperature(&raw)?
+        } else {
+            convert::parse_w1_slave(&raw)?
+        };
+
+        Ok(TemperatureReading {
+            celsius: convert::millis_to_celsius(millis),
+            millis: Some(millis),
+        })
+    }
+}
+
+/// Sensors are re-scanned at most this often, so that a probe plugged in later is picked up.
+const SENSOR_RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The thermal sensors found on the 1-Wire bus.
+pub(super) struct W1Bus {
+    w1_devices: PathBuf,
+    scan: Mutex<Scan>,
+    errors: Arc<ErrorLog>,
+}
+
+struct Scan {
+    paths: Vec<PathBuf>,
+    scanned_at: Instant,
+}
+
+impl W1Bus {
+    pub fn open(w1_devices: &Path, errors: Arc<ErrorLog>) -> anyhow::Result<Self> {
+        Ok(Self {
+            w1_devices: w1_devices.to_owned(),
+            scan: Mutex::new(Scan {
+                paths: scan_sensors(w1_devices)?,
+                scanned_at: Instant::now(),
+            }),
+            errors,
+        })
+    }
+
+    /// Returns the sensor with the ID `id`, or the first one found without one.
+    pub fn thermometer(&self, id: Option<&str>) -> anyhow::Result<W1Thermometer> {
+        match id {
+            Some(id) => {
+                let path = self.w1_devices.join(id).join("w1_slave");
+                if !path.is_file() {
+                    return Err(anyhow!("Thermal sensor {id} not found"));
+                }
+                Ok(W1Thermometer::new(path))
+            }
+            None => {
+                let scan = self.scan.lock().unwrap_or_else(|e| e.into_inner());
+                let path = scan.paths.first().ok_or_else(|| anyhow!("Thermal sensor not found"))?;
+                let sensor = W1Thermometer::new(path.clone());
+                let id = sensor.id();
+                info!(sensor = id; "Using thermal sensor {id}");
+                Ok(sensor)
+            }
+        }
+    }
+
+    /// Returns the IDs of the sensors found when the bus was last scanned.
+    pub fn sensor_ids(&self) -> Vec<String> {
+        let scan = self.scan.lock().unwrap_or_else(|e| e.into_inner());
+        scan.paths.iter().map(|path| sensor_id(path)).collect()
+    }
+
+    /// Returns all thermal sensors, re-scanning the bus when the list is old.
+    pub fn thermometers(&self) -> Vec<W1Thermometer> {
+        let mut scan = self.scan.lock().unwrap_or_else(|e| e.into_inner());
+        if scan.scanned_at.elapsed() >= SENSOR_RESCAN_INTERVAL {
+            match scan_sensors(&self.w1_devices) {
+                Ok(paths) => {
+                    for path in paths.iter().filter(|p| !scan.paths.contains(p)) {
+                        let sensor = sensor_id(path);
+                        info!(sensor; "Found thermal sensor {sensor}");
+                    }
+                    scan.paths = paths;
+                }
+                Err(e) => {
+                    warn!("Failed to scan thermal sensors: {e:?}");
+                    self.errors
+                        .warning("measurements", format!("Failed to scan thermal sensors: {e:#}"));
+                }
+            }
+            scan.scanned_at = Instant::now();
+        }
+
+        scan.paths.iter().cloned().map(W1Thermometer::new).collect()
+    }
+}
+
+/// Lists the `w1_slave` files under `w1_devices`, ordered by sensor ID.
+fn scan_sensors(w1_devices: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(w1_devices)?
+        .flatten()
+        .map(|entry| entry.path().join("w1_slave"))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Returns the ID of the sensor a `w1_slave` path belongs to, e.g. `28-0316a279xxxx`.
+fn sensor_id(path: &Path) -> String {
+    path.parent()
+        .and_then(Path::file_name)
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+type Probes = (Vec<Box<dyn TdsSensor>>, Box<dyn PhSensor>, Vec<Box<dyn AuxSensor>>);
+
+/// Opens the ADC the TDS and pH probes and the auxiliary inputs are wired to, with a TDS probe on each of
+/// `tds_channels`.
+///
+/// In continuous mode, the ADC keeps converting the first TDS channel and nothing else can be read.
+#[cfg(feature = "tds")]
+fn open_probes(
+    i2c_bus: &Path,
+    config: &AdcConfig,
+    tds_channels: &[AdcChannel],
+    continuous: bool,
+    errors: Arc<ErrorLog>,
+) -> anyhow::Result<Probes> {
+    let (tds, ph, aux) = ads1x15::open(i2c_bus, config, tds_channels, continuous, errors)?;
+
+    Ok((
+        tds.into_iter().map(|tds| Box::new(tds) as Box<dyn TdsSensor>).collect(),
+        Box::new(ph),
+        aux.into_iter().map(|aux| Box::new(aux) as Box<dyn AuxSensor>).collect(),
+    ))
+}
+
+#[cfg(not(feature = "tds"))]
+fn open_probes(
+    _i2c_bus: &Path,
+    _config: &AdcConfig,
+    _tds_channels: &[AdcChannel],
+    _continuous: bool,
+    _errors: Arc<ErrorLog>,
+) -> anyhow::Result<Probes> {
+    Err(anyhow!("Built without the tds feature, so the probes cannot be read"))
+}
+
+/// Made-up water temperature, drifting by a degree either way around `base` over an hour so that changes show up
+/// without waiting for a day.
+struct SimulatedThermometer {
+    id: String,
+    base: f64,
+}
+
+impl TemperatureSensor for SimulatedThermometer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn read(&self) -> anyhow::Result<TemperatureReading> {
+        Ok(TemperatureReading {
+            celsius: self.base + simulation::wave(Duration::from_secs(60 * 60)) + simulation::noise() * 0.05,
+            millis: None,
+        })
+    }
+}
+
+/// Made-up TDS probe voltage, around 300 µS/cm with some pump noise.
+struct SimulatedTds;
+
+impl TdsSensor for SimulatedTds {
+    fn read_sample(&self) -> anyhow::Result<AdcSample> {
+        Ok(AdcSample {
+            voltage: 0.4 + simulation::wave(Duration::from_secs(6 * 60 * 60)) * 0.02 + simulation::noise() * 0.01,
+            counts: None,
+        })
+    }
+}
+
+/// Made-up pH probe voltage, around pH 7 with the default calibration.
+struct SimulatedPh;
+
+impl PhSensor for SimulatedPh {
+    fn read_voltage(&self) -> anyhow::Result<f64> {
+        Ok(2.5 + simulation::noise() * 0.005)
+    }
+}
+
+/// Made-up auxiliary input, wandering around the middle of the range over ten minutes.
+struct SimulatedAux;
+
+impl AuxSensor for SimulatedAux {
+    fn read_voltage(&self) -> anyhow::Result<f64> {
+        Ok(1.5 + simulation::wave(Duration::from_secs(10 * 60)) * 0.5 + simulation::noise() * 0.01)
+    }
+}
+
+/// Sensors reading back whatever a test sets them to, so that the measurements can be worked out without the
+/// hardware. Clones share the value, so a test can keep one to change it after handing the other over.
+#[cfg(test)]
+pub(super) mod fakes {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::anyhow;
+
+    use super::{AdcSample, PhSensor, TdsSensor, TemperatureReading, TemperatureSensor, convert};
+
+    /// Reads the millidegrees set, or fails like an unplugged sensor while there are none.
+    #[derive(Clone)]
+    pub struct FakeThermometer {
+        id: String,
+        millis: Arc<Mutex<Option<i32>>>,
+    }
+
+    impl FakeThermometer {
+        pub fn new(id: &str, millis: i32) -> Self {
+            Self {
+                id: id.to_owned(),
+                millis: Arc::new(Mutex::new(Some(millis))),
+            }
+        }
+
+        pub fn set(&self, millis: Option<i32>) {
+            *self.millis.lock().unwrap() = millis;
+        }
+    }
+
+    impl TemperatureSensor for FakeThermometer {
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn read(&self) -> anyhow::Result<TemperatureReading> {
+            let millis = self.millis.lock().unwrap().ok_or_else(|| anyhow!("No such device"))?;
+
+            Ok(TemperatureReading {
+                celsius: convert::millis_to_celsius(millis),
+                millis: Some(millis),
+            })
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct FakeTds(Arc<Mutex<f64>>);
+
+    impl FakeTds {
+        pub fn new(voltage: f64) -> Self {
+            Self(Arc::new(Mutex::new(voltage)))
+        }
+
+        pub fn set(&self, voltage: f64) {
+            *self.0.lock().unwrap() = voltage;
+        }
+    }
+
+    impl TdsSensor for FakeTds {
+        fn read_sample(&self) -> anyhow::Result<AdcSample> {
+            Ok(AdcSample {
+                voltage: *self.0.lock().unwrap(),
+                counts: None,
+            })
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct FakePh(Arc<Mutex<f64>>);
+
+    impl FakePh {
+        pub fn new(voltage: f64) -> Self {
+            Self(Arc::new(Mutex::new(voltage)))
+        }
+    }
+
+    impl PhSensor for FakePh {
+        fn read_voltage(&self) -> anyhow::Result<f64> {
+            Ok(*self.0.lock().unwrap())
+        }
+    }
+}
@@ -0,0 +1,365 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ads1x1x::{Ads1x1x, DataRate12Bit, DataRate16Bit, FullScaleRange, ModeChangeError, TargetAddr, channel};
+use anyhow::anyhow;
+use linux_embedded_hal::{
+    I2cdev,
+    nb::{self, block},
+};
+use logger::log::{error, info, warn};
+
+use super::{AdcSample, AuxSensor, PhSensor, TdsSensor};
+use crate::{
+    config::{AdcChannel, AdcChip, AdcConfig},
+    errors::ErrorLog,
+    measurements::convert,
+};
+
+type Ads1115 = ads1x1x::Ads1x1x<
+    linux_embedded_hal::I2cdev,
+    ads1x1x::ic::Ads1115,
+    ads1x1x::ic::Resolution16Bit,
+    ads1x1x::mode::OneShot,
+>;
+
+type Ads1015 = ads1x1x::Ads1x1x<
+    linux_embedded_hal::I2cdev,
+    ads1x1x::ic::Ads1015,
+    ads1x1x::ic::Resolution12Bit,
+    ads1x1x::mode::OneShot,
+>;
+
+type Ads1115Continuous = ads1x1x::Ads1x1x<
+    linux_embedded_hal::I2cdev,
+    ads1x1x::ic::Ads1115,
+    ads1x1x::ic::Resolution16Bit,
+    ads1x1x::mode::Continuous,
+>;
+
+type Ads1015Continuous = ads1x1x::Ads1x1x<
+    linux_embedded_hal::I2cdev,
+    ads1x1x::ic::Ads1015,
+    ads1x1x::ic::Resolution12Bit,
+    ads1x1x::mode::Continuous,
+>;
+
+/// A TDS probe on an input of the ADC.
+pub(super) struct Ads1x15Tds(Arc<Mutex<Adc>>, AdcChannel);
+
+/// The pH probe on the configured input of the ADC.
+pub(super) struct Ads1x15Ph(Arc<Mutex<Adc>>);
+
+/// An auxiliary input of the ADC.
+pub(super) struct Ads1x15Aux(Arc<Mutex<Adc>>, AdcChannel);
+
+/// Opens the ADC the probes are wired to, with a TDS probe on each of `tds_channels`. In continuous mode, only the
+/// first TDS probe can be read.
+pub(super) fn open(
+    i2c_bus: &Path,
+    config: &AdcConfig,
+    tds_channels: &[AdcChannel],
+    continuous: bool,
+    errors: Arc<ErrorLog>,
+) -> anyhow::Result<(Vec<Ads1x15Tds>, Ads1x15Ph, Vec<Ads1x15Aux>)> {
+    let continuous = tds_channels.first().copied().filter(|_| continuous);
+    let adc = Arc::new(Mutex::new(Adc {
+        device: Some(Adc::open(i2c_bus, config, continuous)?),
+        i2c_bus: i2c_bus.to_owned(),
+        config: config.clone(),
+        continuous,
+        next_conversion_at: Adc::first_conversion_at(config),
+        consecutive_failures: 0,
+        errors,
+    }));
+    info!(
+        sensor = "adc";
+        "Opened {} at {:#04x} on {}: ±{} V, {} SPS, {}, TDS on {}, pH on {}",
+        config.chip,
+        config.address,
+        i2c_bus.display(),
+        config.range,
+        config.data_rate,
+        if continuous.is_some() { "continuous" } else { "one-shot" },
+        tds_channels.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "),
+        config.ph_channel
+    );
+
+    for aux in &config.aux {
+        info!(sensor = "adc"; "Reading {} on {}", aux.name, aux.channel);
+    }
+
+    let aux = config
+        .aux
+        .iter()
+        .map(|aux| Ads1x15Aux(adc.clone(), aux.channel))
+        .collect();
+    let tds = tds_channels
+        .iter()
+        .map(|&channel| Ads1x15Tds(adc.clone(), channel))
+        .collect();
+    Ok((tds, Ads1x15Ph(adc), aux))
+}
+
+impl TdsSensor for Ads1x15Tds {
+    fn read_sample(&self) -> anyhow::Result<AdcSample> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).read_sample(self.1)
+    }
+}
+
+impl PhSensor for Ads1x15Ph {
+    fn read_voltage(&self) -> anyhow::Result<f64> {
+        let mut adc = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let channel = adc.config.ph_channel;
+
+        adc.read_sample(channel).map(|sample| sample.voltage)
+    }
+}
+
+impl AuxSensor for Ads1x15Aux {
+    fn read_voltage(&self) -> anyhow::Result<f64> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .read_sample(self.1)
+            .map(|sample| sample.voltage)
+    }
+}
+
+/// The two chips differ in resolution and data rates only, but the driver has a type for each, and for each mode.
+enum Device {
+    Ads1115(Ads1115),
+    Ads1015(Ads1015),
+    /// Converting a TDS channel over and over.
+    Ads1115Continuous(Ads1115Continuous),
+    Ads1015Continuous(Ads1015Continuous),
+}
+
+impl Device {
+    fn read(&mut self, channel: AdcChannel) -> nb::Result<i16, ads1x1x::Error<linux_embedded_hal::I2CError>> {
+        match self {
+            Self::Ads1115(device) => match channel {
+                AdcChannel::A0 => device.read(channel::SingleA0),
+                AdcChannel::A1 => device.read(channel::SingleA1),
+                AdcChannel::A2 => device.read(channel::SingleA2),
+                AdcChannel::A3 => device.read(channel::SingleA3),
+            },
+            Self::Ads1015(device) => match channel {
+                AdcChannel::A0 => device.read(channel::SingleA0),
+                AdcChannel::A1 => device.read(channel::SingleA1),
+                AdcChannel::A2 => device.read(channel::SingleA2),
+                AdcChannel::A3 => device.read(channel::SingleA3),
+            },
+            // The channel was selected when the device was opened.
+            Self::Ads1115Continuous(device) => device.read().map_err(nb::Error::Other),
+            Self::Ads1015Continuous(device) => device.read().map_err(nb::Error::Other),
+        }
+    }
+}
+
+/// The ADC together with what is needed to recover it from I2C errors.
+struct Adc {
+    i2c_bus: PathBuf,
+    config: AdcConfig,
+    device: Option<Device>,
+    /// The channel converted over and over in continuous mode.
+    continuous: Option<AdcChannel>,
+    /// When the conversion register next holds a result not read yet, in continuous mode.
+    next_conversion_at: Instant,
+    consecutive_failures: u32,
+    errors: Arc<ErrorLog>,
+}
+
+impl Adc {
+    const RETRIES: u32 = 2;
+    const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+    /// The device is re-opened every this many consecutive failures, as the chip sometimes gets stuck.
+    const REOPEN_AFTER: u32 = 5;
+
+    fn open(i2c_bus: &Path, config: &AdcConfig, continuous: Option<AdcChannel>) -> anyhow::Result<Device> {
+        let address = match config.address {
+            0x48 => TargetAddr::Gnd,
+            0x49 => TargetAddr::Vdd,
+            0x4a => TargetAddr::Sda,
+            0x4b => TargetAddr::Scl,
+            address => return Err(anyhow!("Invalid ADC address {address:#04x}")),
+        };
+        // The values have been validated along with the rest of the config.
+        let range = match config.range {
+            6.144 => FullScaleRange::Within6_144V,
+            4.096 => FullScaleRange::Within4_096V,
+            2.048 => FullScaleRange::Within2_048V,
+            1.024 => FullScaleRange::Within1_024V,
+            0.512 => FullScaleRange::Within0_512V,
+            _ => FullScaleRange::Within0_256V,
+        };
+
+        let dev = I2cdev::new(i2c_bus)?;
+        match config.chip {
+            AdcChip::Ads1115 => {
+                let data_rate = match config.data_rate {
+                    8 => DataRate16Bit::Sps8,
+                    16 => DataRate16Bit::Sps16,
+                    32 => DataRate16Bit::Sps32,
+                    64 => DataRate16Bit::Sps64,
+                    128 => DataRate16Bit::Sps128,
+                    250 => DataRate16Bit::Sps250,
+                    475 => DataRate16Bit::Sps475,
+                    _ => DataRate16Bit::Sps860,
+                };
+                let mut adc = Ads1x1x::new_ads1115(dev, address);
+                adc.set_full_scale_range(range).map_err(|e| anyhow!("{e:?}"))?;
+                adc.set_data_rate(data_rate).map_err(|e| anyhow!("{e:?}"))?;
+                let Some(channel) = continuous else {
+                    return Ok(Device::Ads1115(adc));
+                };
+
+                let mut adc = adc
+                    .into_continuous()
+                    .map_err(|ModeChangeError::I2C(e, _)| anyhow!("{e:?}"))?;
+                match channel {
+                    AdcChannel::A0 => adc.select_channel(channel::SingleA0),
+                    AdcChannel::A1 => adc.select_channel(channel::SingleA1),
+                    AdcChannel::A2 => adc.select_channel(channel::SingleA2),
+                    AdcChannel::A3 => adc.select_channel(channel::SingleA3),
+                }
+                .map_err(|e| anyhow!("{e:?}"))?;
+
+                Ok(Device::Ads1115Continuous(adc))
+            }
+            AdcChip::Ads1015 => {
+                let data_rate = match config.data_rate {
+                    128 => DataRate12Bit::Sps128,
+                    250 => DataRate12Bit::Sps250,
+                    490 => DataRate12Bit::Sps490,
+                    920 => DataRate12Bit::Sps920,
+                    1600 => DataRate12Bit::Sps1600,
+                    2400 => DataRate12Bit::Sps2400,
+                    _ => DataRate12Bit::Sps3300,
+                };
+                let mut adc = Ads1x1x::new_ads1015(dev, address);
+                adc.set_full_scale_range(range).map_err(|e| anyhow!("{e:?}"))?;
+                adc.set_data_rate(data_rate).map_err(|e| anyhow!("{e:?}"))?;
+                let Some(channel) = continuous else {
+                    return Ok(Device::Ads1015(adc));
+                };
+
+                let mut adc = adc
+                    .into_continuous()
+                    .map_err(|ModeChangeError::I2C(e, _)| anyhow!("{e:?}"))?;
+                match channel {
+                    AdcChannel::A0 => adc.select_channel(channel::SingleA0),
+                    AdcChannel::A1 => adc.select_channel(channel::SingleA1),
+                    AdcChannel::A2 => adc.select_channel(channel::SingleA2),
+                    AdcChannel::A3 => adc.select_channel(channel::SingleA3),
+                }
+                .map_err(|e| anyhow!("{e:?}"))?;
+
+                Ok(Device::Ads1015Continuous(adc))
+            }
+        }
+    }
+
+    /// The conversion under way when the channel is selected still uses the previous one, so the first result on the
+    /// TDS channel is only in after the one following it.
+    fn first_conversion_at(config: &AdcConfig) -> Instant {
+        Instant::now() + config.conversion_time() * 2
+    }
+
+    /// Takes a conversion of `channel`, along with the voltage it comes to.
+    ///
+    /// In continuous mode, it sleeps until a conversion not read yet is in, so that every sample of a burst is a
+    /// conversion of its own.
+    fn read_sample(&mut self, channel: AdcChannel) -> anyhow::Result<AdcSample> {
+        if let Some(continuous) = self.continuous {
+            if channel != continuous {
+                return Err(anyhow!("{channel} cannot be read while the ADC converts {continuous}"));
+            }
+            thread::sleep(self.next_conversion_at.saturating_duration_since(Instant::now()));
+        }
+        let raw_value = self.read(|device| device.read(channel))?;
+        self.next_conversion_at = Instant::now() + self.config.conversion_time();
+
+        Ok(AdcSample {
+            voltage: convert::adc_voltage(raw_value, self.config.range, self.config.chip.max_raw_value()),
+            counts: Some(raw_value),
+        })
+    }
+
+    fn reopen(&mut self) {
+        info!(
+            sensor = "adc", consecutive_failures = self.consecutive_failures;
+            "Re-opening ADC after {} consecutive failures",
+            self.consecutive_failures
+        );
+
+        // Close the old handle before opening a new one.
+        self.device = None;
+        // Opening sets the mode up again, as a chip that got stuck may well have been reset to its defaults.
+        match Self::open(&self.i2c_bus, &self.config, self.continuous) {
+            Ok(device) => {
+                self.device = Some(device);
+                self.next_conversion_at = Self::first_conversion_at(&self.config);
+            }
+            Err(e) => {
+                error!(sensor = "adc"; "Failed to re-open ADC: {e:?}");
+                self.errors
+                    .error("measurements", format!("Failed to re-open ADC: {e:#}"));
+            }
+        }
+    }
+
+    /// Runs a conversion, retrying a few times on I2C errors.
+    fn read<E: Debug>(&mut self, mut convert: impl FnMut(&mut Device) -> nb::Result<i16, E>) -> anyhow::Result<i16> {
+        let mut attempt = 0;
+        loop {
+            let result = match &mut self.device {
+                Some(device) => block!(convert(device)).map_err(|e| anyhow!("{e:?}")),
+                None => Err(anyhow!("ADC is not open")),
+            };
+            match result {
+                Ok(value) => {
+                    if self.consecutive_failures > 0 {
+                        info!(
+                            sensor = "adc", consecutive_failures = self.consecutive_failures;
+                            "ADC recovered after {} consecutive failures",
+                            self.consecutive_failures
+                        );
+                        self.consecutive_failures = 0;
+                    }
+                    return Ok(value);
+                }
+                Err(e) => {
+                    self.consecutive_failures += 1;
+                    warn!(
+                        sensor = "adc", consecutive_failures = self.consecutive_failures;
+                        "ADC conversion failed ({} consecutive failures): {e:?}",
+                        self.consecutive_failures
+                    );
+                    // Without the count, so that a run of failures is kept as one event
+                    self.errors
+                        .warning("measurements", format!("ADC conversion failed: {e:#}"));
+                    if self.consecutive_failures.is_multiple_of(Self::REOPEN_AFTER) {
+                        self.reopen();
+                    }
+                    if attempt >= Self::RETRIES {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    thread::sleep(Self::RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::Write;
+
+use chrono::Utc;
+
+use crate::state::AppState;
+
+/// Renders all metrics in the Prometheus text exposition format.
+pub(crate) async fn render(state: &AppState) -> String {
+    let mut tanks = Vec::with_capacity(state.tanks.len());
+    for tank in state.tanks.iter() {
+        if let Some(m) = tank.measurements.get().await {
+            tanks.push((tank.name.as_str(), m));
+        }
+    }
+    let aux = state
+        .default_tank()
+        .measurements
+        .get()
+        .await
+        .map(|m| m.aux)
+        .unwrap_or_default();
+    let signal = state.signal.get().await;
+    let system = state.system.get().await;
+    let now = Utc::now();
+
+    let mut out = String::new();
+    // Labelled only when there is more than one tank, so that the series of a single tank stay as they were. The
+    // names are restricted by the config to characters that need no escaping in a label.
+    let labelled = state.tanks.len() > 1;
+    if !tanks.is_empty() {
+        let tank_gauges = [
+            ("cobitis_temperature_celsius", "Water temperature."),
+            ("cobitis_tds_ppm", "Total dissolved solids."),
+            ("cobitis_ec_microsiemens_per_cm", "Electrical conductivity at 25 °C."),
+            ("cobitis_measurement_age_seconds", "Age of the latest measurement."),
+        ];
+        for (i, (name, help)) in tank_gauges.into_iter().enumerate() {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge");
+            for (tank, m) in &tanks {
+                let value = [m.temperature, m.tds, m.ec, (now - m.timestamp).as_seconds_f64()][i];
+                if labelled {
+                    let _ = writeln!(out, "{name}{{tank=\"{tank}\"}} {value}");
+                } else {
+                    let _ = writeln!(out, "{name} {value}");
+                }
+            }
+        }
+    }
+
+    let mut gauges: Vec<(&str, &str, f64)> = Vec::new();
+    if let Some(s) = signal {
+        gauges.extend([
+            ("cobitis_signal_quality", "Wireless link quality (0-1).", s.quality),
+            (
+                "cobitis_signal_associated",
+                "Whether the interface is connected to an access point.",
+                f64::from(u8::from(s.associated)),
+            ),
+            (
+                "cobitis_signal_age_seconds",
+                "Age of the latest signal reading.",
+                (now - s.timestamp).as_seconds_f64(),
+            ),
+        ]);
+        let optional = [
+            (
+                "cobitis_signal_rssi_dbm",
+                "Received signal strength.",
+                s.rssi.map(f64::from),
+            ),
+            ("cobitis_signal_bitrate_mbps", "Negotiated transmit bitrate.", s.bitrate),
+            ("cobitis_signal_frequency_mhz", "Channel frequency.", s.frequency),
+        ];
+        gauges.extend(
+            optional
+                .into_iter()
+                .filter_map(|(name, help, value)| value.map(|v| (name, help, v))),
+        );
+    }
+
+    if let Some(s) = system {
+        let optional = [
+            ("cobitis_cpu_temperature_celsius", "CPU temperature.", s.cpu_temperature),
+            ("cobitis_load1", "Load average over 1 minute.", s.load.map(|l| l.one)),
+            ("cobitis_load5", "Load average over 5 minutes.", s.load.map(|l| l.five)),
+            (
+                "cobitis_load15",
+                "Load average over 15 minutes.",
+                s.load.map(|l| l.fifteen),
+            ),
+            (
+                "cobitis_memory_total_bytes",
+                "Total memory.",
+                s.memory.map(|m| m.total_bytes as f64),
+            ),
+            (
+                "cobitis_memory_available_bytes",
+                "Memory available without swapping.",
+                s.memory.map(|m| m.available_bytes as f64),
+            ),
+            (
+                "cobitis_disk_total_bytes",
+                "Size of the filesystem holding the state directory.",
+                s.disk.map(|d| d.total_bytes as f64),
+            ),
+            (
+                "cobitis_disk_available_bytes",
+                "Space available on the filesystem holding the state directory.",
+                s.disk.map(|d| d.available_bytes as f64),
+            ),
+            (
+                "cobitis_under_voltage",
+                "Whether the board is under-voltage.",
+                s.throttled.map(|t| f64::from(u8::from(t.under_voltage))),
+            ),
+            (
+                "cobitis_throttled",
+                "Whether the CPU is throttled.",
+                s.throttled.map(|t| f64::from(u8::from(t.throttled))),
+            ),
+        ];
+        gauges.extend(
+            optional
+                .into_iter()
+                .filter_map(|(name, help, value)| value.map(|v| (name, help, v))),
+        );
+    }
+
+    let counters = [
+        (
+            "cobitis_measurement_errors_total",
+            "Failed sensor reads.",
+            &state.health.measurements,
+        ),
+        (
+            "cobitis_signal_errors_total",
+            "Failed signal reads.",
+            &state.health.signal,
+        ),
+        (
+            "cobitis_display_errors_total",
+            "Failed display draws.",
+            &state.health.display,
+        ),
+    ];
+
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}");
+    }
+    if !aux.is_empty() {
+        for (name, help, voltage) in [
+            (
+                "cobitis_aux_value",
+                "Auxiliary ADC input with its scale and offset applied.",
+                false,
+            ),
+            ("cobitis_aux_voltage_volts", "Voltage on an auxiliary ADC input.", true),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge");
+            for (input, v) in &aux {
+                let value = if voltage { v.voltage } else { v.value };
+                let _ = writeln!(out, "{name}{{input=\"{input}\"}} {value}");
+            }
+        }
+    }
+    for (name, help, value) in counters {
+        let value = value.snapshot().total_failures;
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}");
+    }
+    let rejections = state.api_rejections.get();
+    let spikes = state.sampling.rejections();
+    for (name, help, value) in [
+        (
+            "cobitis_api_rate_limited_total",
+            "API requests turned away by the per-client rate limit.",
+            rejections.rate_limited,
+        ),
+        (
+            "cobitis_api_overloaded_total",
+            "API requests turned away by the cap on open requests.",
+            rejections.overloaded,
+        ),
+        (
+            "cobitis_rejected_temperatures_total",
+            "Temperature readings rejected by the spike filter.",
+            spikes.temperature,
+        ),
+        (
+            "cobitis_rejected_tds_total",
+            "TDS readings rejected by the spike filter.",
+            spikes.tds,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}");
+    }
+    let failures = state.notifications.failures();
+    if !failures.is_empty() {
+        let name = "cobitis_notification_failures_total";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Alert notifications dropped after failing to be sent.\n# TYPE {name} counter"
+        );
+        for (notifier, value) in failures {
+            let _ = writeln!(out, "{name}{{notifier=\"{}\"}} {value}", notifier.to_lowercase());
+        }
+    }
+
+    out
+}
@@ -0,0 +1,225 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{fs, time::Duration};
+
+use logger::log::{error, info, warn};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use serde_json::json;
+use tokio::{
+    select,
+    time::{MissedTickBehavior, interval, sleep, timeout},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::MqttConfig, state::AppState};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+struct Context {
+    client: AsyncClient,
+    hostname: String,
+    topic_prefix: String,
+    status_topic: String,
+}
+
+impl Context {
+    fn new(config: &MqttConfig) -> (Self, rumqttc::EventLoop) {
+        let hostname =
+            fs::read_to_string("/proc/sys/kernel/hostname").map_or_else(|_| "cobitis".into(), |s| s.trim().to_owned());
+        let topic_prefix = config
+            .topic_prefix
+            .clone()
+            .unwrap_or_else(|| format!("cobitis/{hostname}"));
+        let status_topic = format!("{topic_prefix}/status");
+        let client_id = config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("cobitis-{hostname}"));
+
+        let mut options = MqttOptions::new(client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&status_topic, "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 16);
+        let ctx = Self {
+            client,
+            hostname,
+            topic_prefix,
+            status_topic,
+        };
+
+        (ctx, eventloop)
+    }
+
+    fn publish(&self, topic: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self.client.try_publish(topic, QoS::AtLeastOnce, retain, payload) {
+            warn!("Failed to queue MQTT message for {topic}: {e:?}");
+        }
+    }
+
+    fn publish_json(&self, topic: &str, value: &impl Serialize) {
+        match serde_json::to_vec(value) {
+            Ok(payload) => self.publish(topic, false, payload),
+            Err(e) => error!("Failed to serialize MQTT payload for {topic}: {e:?}"),
+        }
+    }
+
+    /// Returns the topic the measurements of `tank` are published to.
+    fn tank_topic(&self, tank: &str) -> String {
+        format!("{}/tanks/{tank}/measurements", self.topic_prefix)
+    }
+
+    /// Publishes Home Assistant MQTT discovery payloads for the temperature, TDS, and conductivity sensors, with a
+    /// set of them for each tank when there is more than one.
+    fn announce(&self, discovery_prefix: &str, tanks: &[&str]) {
+        let node_id = self.hostname.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let device = json!({
+            "identifiers": [format!("cobitis_{node_id}")],
+            "name": format!("Cobitis {}", self.hostname),
+            "model": "Cobitis",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+
+        let sources: Vec<_> = match tanks {
+            [_] => vec![(
+                String::new(),
+                String::new(),
+                format!("{}/measurements", self.topic_prefix),
+            )],
+            tanks => tanks
+                .iter()
+                .map(|tank| (format!("{tank}_"), format!("{tank} "), self.tank_topic(tank)))
+                .collect(),
+        };
+        for (id_prefix, name_prefix, state_topic) in &sources {
+            self.announce_sensors(discovery_prefix, &node_id, &device, id_prefix, name_prefix, state_topic);
+        }
+    }
+
+    fn announce_sensors(
+        &self,
+        discovery_prefix: &str,
+        node_id: &str,
+        device: &serde_json::Value,
+        id_prefix: &str,
+        name_prefix: &str,
+        state_topic: &str,
+    ) {
+        for (key, name, unit, device_class) in [
+            ("temperature", "Temperature", "°C", Some("temperature")),
+            ("tds", "TDS", "ppm", None),
+            ("ec", "Conductivity", "µS/cm", Some("conductivity")),
+        ] {
+            let mut payload = json!({
+                "name": format!("{name_prefix}{name}"),
+                "unique_id": format!("cobitis_{node_id}_{id_prefix}{key}"),
+                "state_topic": state_topic,
+                "value_template": format!("{{{{ value_json.{key} }}}}"),
+                "unit_of_measurement": unit,
+                "state_class": "measurement",
+                "availability_topic": self.status_topic,
+                "device": device,
+            });
+            if let Some(device_class) = device_class {
+                payload["device_class"] = device_class.into();
+            }
+
+            let topic = format!("{discovery_prefix}/sensor/cobitis_{node_id}/{id_prefix}{key}/config");
+            self.publish(&topic, true, payload.to_string());
+        }
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.mqtt else {
+        return Ok(());
+    };
+
+    let (ctx, mut eventloop) = Context::new(config);
+
+    let mut interval = interval(Duration::from_secs(1));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let mut backoff = MIN_BACKOFF;
+    let mut connected = false;
+    let tank_names: Vec<_> = state.tanks.iter().map(|tank| tank.name.as_str()).collect();
+    let default_tank = &state.default_tank().name;
+    let mut last_measurements = vec![None; state.tanks.len()];
+    let mut last_signal = None;
+
+    loop {
+        select! {
+            event = eventloop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker {}:{}", config.host, config.port);
+                    connected = true;
+                    backoff = MIN_BACKOFF;
+                    ctx.publish(&ctx.status_topic, true, "online");
+                    if config.homeassistant_discovery {
+                        ctx.announce(&config.discovery_prefix, &tank_names);
+                    }
+
+                    // Republish the current values after every (re)connection.
+                    last_measurements.fill(None);
+                    last_signal = None;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("MQTT connection error, retrying in {}s: {e:?}", backoff.as_secs());
+                    connected = false;
+                    select! {
+                        () = sleep(backoff) => {}
+                        () = shutdown.cancelled() => return Ok(()),
+                    }
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            },
+            () = shutdown.cancelled() => break,
+            _ = interval.tick(), if connected => {
+                // The default tank keeps its topic from before there were tanks.
+                for (tank, last) in state.tanks.iter().zip(&mut last_measurements) {
+                    let Some(m) = tank.measurements.get().await else {
+                        continue;
+                    };
+                    if *last == Some(m.timestamp) {
+                        continue;
+                    }
+                    *last = Some(m.timestamp);
+                    if &tank.name == default_tank {
+                        ctx.publish_json(&format!("{}/measurements", ctx.topic_prefix), &m);
+                    }
+                    if state.tanks.len() > 1 {
+                        ctx.publish_json(&ctx.tank_topic(&tank.name), &m);
+                    }
+                }
+                if let Some(s) = state.signal.get().await
+                    && last_signal != Some(s.timestamp)
+                {
+                    last_signal = Some(s.timestamp);
+                    ctx.publish_json(&format!("{}/signal", ctx.topic_prefix), &s);
+                }
+            }
+        }
+    }
+
+    if connected {
+        // Announce the shutdown explicitly, since a clean disconnect does not trigger the last will.
+        ctx.publish(&ctx.status_topic, true, "offline");
+        let _ = ctx.client.try_disconnect();
+        let _ = timeout(Duration::from_secs(1), async {
+            while eventloop.poll().await.is_ok() {}
+        })
+        .await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    net::Ipv4Addr,
+    process::Command,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use logger::log::warn;
+use regex::Regex;
+
+/// Addresses are looked up again after this long, so that a new DHCP lease shows up.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+static RX_INET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^[0-9]+:\s+(\S+)\s+inet ([0-9.]+)/").unwrap());
+
+static CACHE: Mutex<Option<(Instant, Option<Ipv4Addr>)>> = Mutex::new(None);
+
+/// Returns the IPv4 address of `interface`, or of the first interface other than loopback when not given.
+///
+/// Blocks while running `ip` when the cached address is old.
+pub(crate) fn ipv4_address(interface: Option<&str>) -> Option<Ipv4Addr> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((fetched_at, address)) = *cache
+        && fetched_at.elapsed() < REFRESH_INTERVAL
+    {
+        return address;
+    }
+
+    let address = lookup(interface).unwrap_or_else(|e| {
+        warn!("Failed to look up the IP address: {e:?}");
+        None
+    });
+    *cache = Some((Instant::now(), address));
+
+    address
+}
+
+fn lookup(interface: Option<&str>) -> anyhow::Result<Option<Ipv4Addr>> {
+    let output = Command::new("ip").args(["-4", "-o", "addr", "show"]).output()?;
+    let raw = String::from_utf8(output.stdout)?;
+
+    let address = RX_INET
+        .captures_iter(&raw)
+        .filter(|caps| interface.is_none_or(|interface| &caps[1] == interface))
+        .filter_map(|caps| caps[2].parse::<Ipv4Addr>().ok())
+        .find(|address| !address.is_loopback());
+
+    Ok(address)
+}
@@ -0,0 +1,361 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use futures_util::future::BoxFuture;
+use logger::log::{error, info, warn};
+use reqwest::StatusCode;
+use tokio::{select, sync::broadcast::error::RecvError, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    alerts::{Alert, AlertEvent},
+    config::{AlertMetric, Config},
+    state::AppState,
+};
+
+pub(crate) mod email;
+mod telegram;
+mod webhook;
+
+const RETRIES: u32 = 3;
+/// Delay before the first retry, which doubles with each one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// An alert firing or clearing, along with the message telling of it.
+struct Notification<'a> {
+    alert: &'a Alert,
+    fired: bool,
+    text: String,
+}
+
+/// Why a notification could not be sent.
+enum SendError {
+    /// May go through when tried again, such as after a timeout or a 5xx response.
+    Transient(anyhow::Error),
+    /// Would fail the same way again, such as after a 4xx response.
+    Permanent(anyhow::Error),
+}
+
+impl SendError {
+    /// Returns the error for an unsuccessful response, which is worth retrying when rate limited or a server error.
+    fn from_status(status: StatusCode) -> Self {
+        let e = anyhow!("Server returned {status}");
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Self::Transient(e)
+        } else {
+            Self::Permanent(e)
+        }
+    }
+}
+
+/// A channel that alert notifications are delivered to.
+trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), SendError>>;
+}
+
+fn notifiers(config: &Config, notifications: &Notifications) -> anyhow::Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(telegram) = &config.notifications.telegram {
+        notifiers.push(Box::new(telegram::Telegram::new(telegram)?));
+    }
+    if let Some(webhook) = &config.notifications.webhook {
+        notifiers.push(Box::new(webhook::Webhook::new(webhook)?));
+    }
+    if config.notifications.email.is_some() {
+        notifiers.push(Box::new(email::Email::new(&notifications.outbox)));
+    }
+
+    Ok(notifiers)
+}
+
+/// What the notifiers share with the rest of the service.
+pub(crate) struct Notifications {
+    /// Notifications dropped after failing to be sent, by the name of the notifier.
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    outbox: email::Outbox,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self {
+            failures: Mutex::new(BTreeMap::new()),
+            outbox: email::Outbox::new(),
+        }
+    }
+
+    /// Returns how many notifications each notifier in use has failed to send.
+    pub fn failures(&self) -> BTreeMap<&'static str, u64> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn count_failure(&self, notifier: &'static str) {
+        *self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(notifier)
+            .or_insert(0) += 1;
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let notifications = &state.notifications;
+    let notifiers = notifiers(&config, notifications)?;
+    if notifiers.is_empty() || config.alerts.is_empty() {
+        return Ok(());
+    }
+    {
+        // Counted from zero, so that there are series before anything fails
+        let mut failures = notifications.failures.lock().unwrap_or_else(|e| e.into_inner());
+        for notifier in &notifiers {
+            failures.entry(notifier.name()).or_insert(0);
+        }
+    }
+
+    let multiple_tanks = state.tanks.len() > 1;
+    let mut rx = state.alerts.subscribe();
+    let cooldown = config.notifications.cooldown();
+    // When each alert was last notified as fired. Only those are notified when they clear.
+    let mut notified: HashMap<String, Instant> = HashMap::new();
+
+    loop {
+        let event = select! {
+            event = rx.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Skipped {n} alert events while notifying");
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            () = shutdown.cancelled() => return Ok(()),
+        };
+
+        let notification = match &event {
+            AlertEvent::Fired(alert) => {
+                if notified.get(&alert.name).is_some_and(|at| at.elapsed() < cooldown) {
+                    info!("Not notifying {} again within the cooldown", alert.name);
+                    continue;
+                }
+                notified.insert(alert.name.clone(), Instant::now());
+                Notification {
+                    alert,
+                    fired: true,
+                    text: fired_message(alert, multiple_tanks),
+                }
+            }
+            AlertEvent::Cleared(alert) => {
+                if !notified.contains_key(&alert.name) {
+                    continue;
+                }
+                Notification {
+                    alert,
+                    fired: false,
+                    text: cleared_message(alert, multiple_tanks),
+                }
+            }
+        };
+
+        for notifier in &notifiers {
+            select! {
+                () = deliver(notifications, notifier.as_ref(), &notification) => {}
+                () = shutdown.cancelled() => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Sends `notification`, retrying a few times with a growing delay unless it failed for good, before dropping it.
+async fn deliver(notifications: &Notifications, notifier: &dyn Notifier, notification: &Notification<'_>) {
+    let what = format!("notify via {}", notifier.name());
+    if let Err(e) = retry(&what, RETRIES, || notifier.send(notification)).await {
+        error!(
+            "Failed to notify via {}, dropping the notification: {e:?}",
+            notifier.name()
+        );
+        notifications.count_failure(notifier.name());
+    }
+}
+
+/// Runs `attempt` until it succeeds, retrying up to `retries` times with a delay that doubles each time unless it
+/// failed for good. `what` is told in the logs, as in "Failed to {what}".
+async fn retry<F: Future<Output = Result<(), SendError>>>(
+    what: &str,
+    retries: u32,
+    mut attempt: impl FnMut() -> F,
+) -> anyhow::Result<()> {
+    let mut delay = RETRY_DELAY;
+    let mut retried = 0;
+    loop {
+        match attempt().await {
+            Ok(()) => return Ok(()),
+            Err(SendError::Transient(e)) if retried < retries => {
+                retried += 1;
+                warn!(
+                    "Failed to {what} (attempt {retried}), retrying in {}s: {e:?}",
+                    delay.as_secs()
+                );
+                sleep(delay).await;
+                delay *= 2;
+            }
+            Err(SendError::Transient(e) | SendError::Permanent(e)) => return Err(e),
+        }
+    }
+}
+
+/// Decimals the values of `metric` are told with.
+fn decimals(metric: AlertMetric) -> usize {
+    match metric {
+        AlertMetric::Temperature | AlertMetric::TemperatureTrend | AlertMetric::Flow => 1,
+        AlertMetric::Ph => 2,
+        AlertMetric::Tds | AlertMetric::WaterLevel | AlertMetric::WaterChange => 0,
+    }
+}
+
+fn describe(metric: AlertMetric, value: f64) -> String {
+    match metric {
+        AlertMetric::Temperature => format!("temperature {value:.1} °C"),
+        AlertMetric::TemperatureTrend => format!("temperature changing {value:+.1} °C/h"),
+        AlertMetric::Tds => format!("TDS {value:.0} ppm"),
+        AlertMetric::Ph => format!("pH {value:.2}"),
+        AlertMetric::WaterLevel if value < 0.5 => "water level low".to_owned(),
+        AlertMetric::WaterLevel => "water level ok".to_owned(),
+        AlertMetric::Flow => format!("flow {value:.1} L/min"),
+        AlertMetric::WaterChange if value > 0.0 => "water change overdue".to_owned(),
+        AlertMetric::WaterChange => "water changed".to_owned(),
+    }
+}
+
+/// Names the tank of `alert` when there is more than one to tell it apart from.
+fn tank(alert: &Alert, multiple_tanks: bool) -> String {
+    if multiple_tanks {
+        format!("Tank {}:", alert.tank)
+    } else {
+        "Tank".to_owned()
+    }
+}
+
+fn fired_message(alert: &Alert, multiple_tanks: bool) -> String {
+    format!(
+        "⚠ {} {}, {} {:.*}",
+        tank(alert, multiple_tanks),
+        describe(alert.metric, alert.value),
+        alert.condition,
+        decimals(alert.metric),
+        alert.threshold
+    )
+}
+
+fn cleared_message(alert: &Alert, multiple_tanks: bool) -> String {
+    format!(
+        "✅ {} {}, back to normal",
+        tank(alert, multiple_tanks),
+        describe(alert.metric, alert.value)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::config::AlertCondition;
+
+    fn alert(tank: &str) -> Alert {
+        Alert {
+            name: "hot".to_owned(),
+            tank: tank.to_owned(),
+            metric: AlertMetric::Temperature,
+            condition: AlertCondition::Above,
+            threshold: 28.5,
+            critical: false,
+            value: 29.3,
+            started_at: Utc::now(),
+            cleared_at: None,
+        }
+    }
+
+    #[test]
+    fn messages_leave_out_a_single_tank() {
+        let alert = alert("tank");
+        assert_eq!(fired_message(&alert, false), "⚠ Tank temperature 29.3 °C, above 28.5");
+        assert_eq!(
+            cleared_message(&alert, false),
+            "✅ Tank temperature 29.3 °C, back to normal"
+        );
+    }
+
+    #[test]
+    fn messages_name_the_tank_among_several() {
+        let alert = alert("shrimp");
+        assert_eq!(
+            fired_message(&alert, true),
+            "⚠ Tank shrimp: temperature 29.3 °C, above 28.5"
+        );
+        assert_eq!(
+            cleared_message(&alert, true),
+            "✅ Tank shrimp: temperature 29.3 °C, back to normal"
+        );
+    }
+
+    #[test]
+    fn thresholds_are_told_as_precisely_as_the_values() {
+        let alert = |metric, threshold, value| Alert {
+            metric,
+            threshold,
+            value,
+            ..alert("tank")
+        };
+
+        assert_eq!(
+            fired_message(&alert(AlertMetric::Temperature, 28.0, 29.3), false),
+            "⚠ Tank temperature 29.3 °C, above 28.0"
+        );
+        assert_eq!(
+            fired_message(&alert(AlertMetric::Tds, 300.0, 312.0), false),
+            "⚠ Tank TDS 312 ppm, above 300"
+        );
+        assert_eq!(
+            fired_message(&alert(AlertMetric::Ph, 7.5, 7.62), false),
+            "⚠ Tank pH 7.62, above 7.50"
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_at_once_on_a_permanent_error() {
+        let mut attempts = 0;
+        let result = retry("test", RETRIES, || {
+            attempts += 1;
+            async { Err(SendError::Permanent(anyhow!("Rejected"))) }
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Rejected");
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn retry_stops_at_the_retries_given() {
+        let mut attempts = 0;
+        let result = retry("test", 0, || {
+            attempts += 1;
+            async { Err(SendError::Transient(anyhow!("Timed out"))) }
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Timed out");
+        assert_eq!(attempts, 1);
+    }
+}
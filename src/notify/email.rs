@@ -0,0 +1,302 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{fmt::Write as _, time::Duration};
+
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use futures_util::future::BoxFuture;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use logger::log::{error, info};
+use tokio::{
+    select,
+    sync::{Mutex, mpsc},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{Notification, Notifications, Notifier, SendError, retry};
+use crate::{
+    config::{EmailConfig, SmtpTls},
+    measurements::{self, Stats},
+    state::AppState,
+    timezone,
+};
+
+/// Emails that can wait for the server at once. Any more are dropped.
+const OUTBOX_CAPACITY: usize = 16;
+
+/// An email to send to every address configured.
+struct Outgoing {
+    subject: String,
+    body: String,
+}
+
+/// Emails waiting for the worker, which sends them one at a time so that a slow server holds nothing else up.
+pub(super) struct Outbox {
+    tx: mpsc::Sender<Outgoing>,
+    /// Held by the worker while it runs.
+    rx: Mutex<mpsc::Receiver<Outgoing>>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(OUTBOX_CAPACITY);
+        Self { tx, rx: Mutex::new(rx) }
+    }
+}
+
+/// Hands alerts over to the worker to email.
+pub(super) struct Email(mpsc::Sender<Outgoing>);
+
+impl Email {
+    pub fn new(outbox: &Outbox) -> Self {
+        Self(outbox.tx.clone())
+    }
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let outgoing = Outgoing {
+                subject: notification.text.clone(),
+                body: format!(
+                    "{}\n\nAt {}",
+                    notification.text,
+                    timezone::now().format("%Y-%m-%d %H:%M")
+                ),
+            };
+            self.0
+                .try_send(outgoing)
+                .map_err(|_| SendError::Permanent(anyhow!("Too many emails are waiting to be sent")))
+        })
+    }
+}
+
+/// Connection to the server, along with the addresses.
+struct Sender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    retries: u32,
+}
+
+impl Sender {
+    fn new(config: &EmailConfig) -> anyhow::Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)?,
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.server)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.server),
+        };
+        let mut builder = builder.port(config.port()).timeout(Some(config.timeout()));
+        if let Some((username, password)) = config.username.clone().zip(config.password.clone()) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to: config.to.iter().map(|to| to.parse()).collect::<Result<_, _>>()?,
+            retries: config.retries,
+        })
+    }
+
+    /// Sends `outgoing`, retrying a few times with a growing delay unless the server turned it down for good. An
+    /// email dropped is counted in `notifications`.
+    async fn send(&self, outgoing: &Outgoing, notifications: &Notifications) {
+        let message = self
+            .to
+            .iter()
+            .fold(Message::builder().from(self.from.clone()), |builder, to| {
+                builder.to(to.clone())
+            })
+            .subject(&outgoing.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(outgoing.body.clone());
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Failed to build email {:?}, dropping it: {e:?}", outgoing.subject);
+                notifications.count_failure("email");
+                return;
+            }
+        };
+
+        let result = retry("send email", self.retries, || async {
+            self.transport.send(message.clone()).await.map(drop).map_err(|e| {
+                if e.is_permanent() {
+                    SendError::Permanent(e.into())
+                } else {
+                    SendError::Transient(e.into())
+                }
+            })
+        })
+        .await;
+        match result {
+            Ok(()) => info!("Sent email {:?}", outgoing.subject),
+            Err(e) => {
+                error!("Failed to send email {:?}, dropping it: {e:?}", outgoing.subject);
+                notifications.count_failure("email");
+            }
+        }
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(email) = &config.notifications.email else {
+        return Ok(());
+    };
+
+    let sender = Sender::new(email)?;
+    let mut outbox = state.notifications.outbox.rx.lock().await;
+    let mut summary_at = email.summary_at.and_then(next_time);
+    info!("Sending email through {}:{}", email.server, email.port());
+
+    loop {
+        let outgoing = select! {
+            Some(outgoing) = outbox.recv() => outgoing,
+            () = sleep(until(summary_at)), if summary_at.is_some() => {
+                summary_at = email.summary_at.and_then(next_time);
+                summary(&state)
+            }
+            () = shutdown.cancelled() => return Ok(()),
+        };
+
+        // Shutdown cancels sending, since the retries could take a while.
+        select! {
+            () = sender.send(&outgoing, &state.notifications) => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Returns the next time it is `time` of day in the configured zone. A time skipped by a clock change is skipped.
+fn next_time(time: NaiveTime) -> Option<DateTime<Utc>> {
+    let now = timezone::now();
+    let today = now.date_naive();
+
+    [
+        Some(today),
+        today.succ_opt(),
+        today.succ_opt().and_then(|d| d.succ_opt()),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|date| timezone::to_utc(date.and_time(time)))
+    .find(|&at| at > now)
+}
+
+/// Returns how long there is until `at`.
+fn until(at: Option<DateTime<Utc>>) -> Duration {
+    at.and_then(|at| (at - Utc::now()).to_std().ok()).unwrap_or_default()
+}
+
+/// Writes up the statistics of each tank since midnight, and the alerts that fired over the last day.
+fn summary(state: &AppState) -> Outgoing {
+    let date = timezone::now().date_naive();
+    let multiple_tanks = state.tanks.len() > 1;
+    let mut body = format!("Tank summary of {date}\n");
+    for tank in state.tanks.iter() {
+        let today = measurements::daily_stats(tank).today;
+        body.push('\n');
+        if multiple_tanks {
+            let _ = writeln!(body, "{}:", tank.name);
+        }
+        for (name, stats, unit, precision) in [
+            ("Temperature", &today.temperature, "°C", 1),
+            ("TDS", &today.tds, "ppm", 0),
+        ] {
+            let value = |value: Option<f64>| value.map_or_else(|| "-".to_owned(), |v| format!("{v:.precision$}"));
+            let Stats { min, max, mean, .. } = *stats;
+            let _ = writeln!(
+                body,
+                "{name}: min {} {unit}, max {} {unit}, average {} {unit}",
+                value(min),
+                value(max),
+                value(mean)
+            );
+        }
+    }
+
+    let since = Utc::now() - TimeDelta::days(1);
+    let alerts = &state.alerts;
+    let mut fired: Vec<_> = alerts
+        .history()
+        .into_iter()
+        .chain(alerts.active())
+        .filter(|alert| alert.started_at >= since)
+        .collect();
+    fired.sort_by_key(|alert| alert.started_at);
+    if fired.is_empty() {
+        body.push_str("\nNo alerts fired over the last 24 hours.\n");
+    } else {
+        body.push_str("\nAlerts over the last 24 hours:\n");
+        for alert in fired {
+            let at = |t: DateTime<Utc>| timezone::localize(t).format("%m-%d %H:%M").to_string();
+            let name = if multiple_tanks {
+                format!("{} ({})", alert.name, alert.tank)
+            } else {
+                alert.name.clone()
+            };
+            let _ = match alert.cleared_at {
+                Some(cleared_at) => writeln!(body, "- {} {}, cleared {}", at(alert.started_at), name, at(cleared_at)),
+                None => writeln!(body, "- {} {name}, still active", at(alert.started_at)),
+            };
+        }
+    }
+
+    Outgoing {
+        subject: format!("Tank summary of {date}"),
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::{AdcChannel, Config, TankConfig};
+
+    #[test]
+    fn summary_of_a_single_tank() {
+        let state = AppState::new(Arc::new(Config::default()));
+
+        let body = summary(&state).body;
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines[2], "Temperature: min - °C, max - °C, average - °C");
+        assert_eq!(lines[3], "TDS: min - ppm, max - ppm, average - ppm");
+    }
+
+    #[test]
+    fn summary_has_a_section_for_each_tank() {
+        let tank = |name: &str, tds_channel| TankConfig {
+            name: name.to_owned(),
+            temperature_sensor: Some(format!("28-{name}")),
+            tds_channel,
+        };
+        let mut config = Config::default();
+        config.measurements.tanks = vec![tank("main", AdcChannel::A0), tank("shrimp", AdcChannel::A2)];
+        let state = AppState::new(Arc::new(config));
+
+        let body = summary(&state).body;
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines[2], "main:");
+        assert!(lines[3].starts_with("Temperature: "), "{body}");
+        assert!(lines[4].starts_with("TDS: "), "{body}");
+        assert_eq!(lines[6], "shrimp:");
+        assert!(lines[7].starts_with("Temperature: "), "{body}");
+        assert!(lines[8].starts_with("TDS: "), "{body}");
+    }
+}
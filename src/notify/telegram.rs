@@ -0,0 +1,57 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use serde_json::json;
+
+use super::{Notification, Notifier, SendError};
+use crate::config::TelegramConfig;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends messages to a chat through the Telegram Bot API.
+pub(super) struct Telegram {
+    client: Client,
+    url: String,
+    chat_id: String,
+}
+
+impl Telegram {
+    pub fn new(config: &TelegramConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(TIMEOUT).build()?,
+            url: format!("https://api.telegram.org/bot{}/sendMessage", config.bot_token),
+            chat_id: config.chat_id.clone(),
+        })
+    }
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .json(&json!({ "chat_id": self.chat_id, "text": notification.text }))
+                .send()
+                .await
+                // The error would contain the URL, and with it the bot token.
+                .map_err(|e| SendError::Transient(anyhow!("{}", e.without_url())))?;
+            if !response.status().is_success() {
+                return Err(SendError::from_status(response.status()));
+            }
+
+            Ok(())
+        })
+    }
+}
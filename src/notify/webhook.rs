@@ -0,0 +1,108 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::anyhow;
+use futures_util::future::BoxFuture;
+use reqwest::{
+    Client, Method,
+    header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde_json::{Value, json};
+
+use super::{Notification, Notifier, SendError};
+use crate::config::{AlertWebhookConfig, BodyTemplate, TemplateField, WebhookMethod};
+
+/// Sends alerts as JSON to an HTTP endpoint.
+pub(super) struct Webhook {
+    client: Client,
+    url: String,
+    method: Method,
+    headers: HeaderMap,
+    body: Option<BodyTemplate>,
+}
+
+impl Webhook {
+    pub fn new(config: &AlertWebhookConfig) -> anyhow::Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+
+        Ok(Self {
+            client: Client::builder().timeout(config.timeout()).build()?,
+            url: config.url.clone(),
+            method: match config.method {
+                WebhookMethod::Post => Method::POST,
+                WebhookMethod::Put => Method::PUT,
+                WebhookMethod::Patch => Method::PATCH,
+            },
+            headers,
+            body: config.body.clone(),
+        })
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let body = match &self.body {
+                Some(template) => template.render(|field| fill(notification, field)),
+                None => {
+                    let alert = notification.alert;
+                    json!({
+                        "name": alert.name,
+                        "tank": alert.tank,
+                        "metric": alert.metric,
+                        "value": alert.value,
+                        "threshold": alert.threshold,
+                        "state": state(notification),
+                    })
+                    .to_string()
+                }
+            };
+
+            let response = self
+                .client
+                .request(self.method.clone(), &self.url)
+                .headers(self.headers.clone())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+                .await
+                // The URL may well hold a secret, such as the name of an ntfy.sh topic.
+                .map_err(|e| SendError::Transient(anyhow!("{}", e.without_url())))?;
+            if !response.status().is_success() {
+                return Err(SendError::from_status(response.status()));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+fn state(notification: &Notification) -> &'static str {
+    if notification.fired { "fired" } else { "cleared" }
+}
+
+/// Returns what `field` is filled in with, escaped to go inside a JSON string unless it is a number.
+fn fill(notification: &Notification, field: TemplateField) -> String {
+    let alert = notification.alert;
+    let text = match field {
+        TemplateField::Name => alert.name.clone(),
+        TemplateField::Tank => alert.tank.clone(),
+        TemplateField::Metric => alert.metric.to_string(),
+        TemplateField::Value => return alert.value.to_string(),
+        TemplateField::Threshold => return alert.threshold.to_string(),
+        TemplateField::State => state(notification).to_owned(),
+    };
+
+    // Quoted as a whole, then the quotes taken off again
+    let quoted = Value::from(text).to_string();
+    quoted[1..quoted.len() - 1].to_owned()
+}
@@ -0,0 +1,120 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use logger::log::{error, info, warn};
+use serde::Serialize;
+use tokio::{select, sync::broadcast::error::RecvError, task};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::ReadingsLogConfig, measurements::Measurements, state::AppState};
+
+/// A line of the log, which names the tank the measurements are of.
+#[derive(Serialize)]
+struct Line<'a> {
+    tank: &'a str,
+    #[serde(flatten)]
+    measurements: &'a Measurements,
+}
+
+/// Appends measurements to a JSON Lines file, rotating it by size.
+struct Writer {
+    config: ReadingsLogConfig,
+    file: Option<BufWriter<File>>,
+}
+
+impl Writer {
+    fn append(&mut self, line: &Line) -> anyhow::Result<()> {
+        // The file may have been deleted or moved away underneath us, in which case a new one is started.
+        let size = fs::metadata(&self.config.path).map(|meta| meta.len()).ok();
+        if size.is_none() {
+            self.file = None;
+        }
+        if size.is_some_and(|size| size >= self.config.max_size_bytes) {
+            self.file = None;
+            self.rotate()?;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(dir) = self.config.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(&self.config.path)?;
+                self.file.insert(BufWriter::new(file))
+            }
+        };
+
+        serde_json::to_writer(&mut *file, line)?;
+        file.write_all(b"\n")?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Shifts `<path>.1` to `<path>.2` and so on, dropping the oldest, then moves the current file to `<path>.1`.
+    fn rotate(&self) -> anyhow::Result<()> {
+        let rotated = |n: usize| {
+            let mut path = self.config.path.clone().into_os_string();
+            path.push(format!(".{n}"));
+            PathBuf::from(path)
+        };
+
+        if self.config.keep_files == 0 {
+            fs::remove_file(&self.config.path)?;
+            return Ok(());
+        }
+
+        let _ = fs::remove_file(rotated(self.config.keep_files));
+        for n in (1..self.config.keep_files).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        fs::rename(&self.config.path, rotated(1))?;
+        info!("Rotated readings log {}", self.config.path.display());
+
+        Ok(())
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.readings_log else {
+        return Ok(());
+    };
+
+    let mut rx = state.subscribe_tanks();
+    let mut writer = Writer {
+        config: config.clone(),
+        file: None,
+    };
+    info!("Appending readings to {}", config.path.display());
+
+    loop {
+        select! {
+            (tank, m) = rx.recv() => match m {
+                Ok(m) => {
+                    // The writer moves into the blocking task and comes back, so the file stays open between lines.
+                    writer = task::spawn_blocking(move || {
+                        if let Err(e) = writer.append(&Line { tank: &tank, measurements: &m }) {
+                            error!("Failed to append to readings log: {e:?}");
+                            writer.file = None;
+                        }
+                        writer
+                    })
+                    .await?;
+                }
+                Err(RecvError::Lagged(n)) => warn!("Dropped {n} measurements of tank {tank} while logging"),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
@@ -4,94 +4,562 @@
 // https://opensource.org/licenses/MIT
 
 use std::{
-    process::Command,
-    sync::{Arc, LazyLock},
+    fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Duration,
 };
 
 use anyhow::anyhow;
 use chrono::serde::ts_milliseconds;
 use chrono::{DateTime, Utc};
-use logger::log::error;
+use logger::log::{error, info};
 use regex::Regex;
 use serde::Serialize;
 use tokio::{
-    sync::RwLock,
+    process::Command,
+    select,
+    sync::watch,
     task,
-    time::{MissedTickBehavior, interval},
+    time::{Interval, MissedTickBehavior, interval, timeout},
 };
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Copy, Serialize)]
+use crate::{config::SignalConfig, hardware::Device, simulation, state::AppState};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub(crate) struct Signal {
+    /// Milliseconds since the Unix epoch.
     #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
     pub timestamp: DateTime<Utc>,
+    /// Whether the interface is connected to an access point. The quality is 0 when not.
+    pub associated: bool,
     pub quality: f64,
+    /// Received signal strength in dBm, when the driver reports it.
+    pub rssi: Option<i32>,
+    /// Network the interface is associated with. `None` for a hidden SSID.
+    pub ssid: Option<String>,
+    /// Negotiated transmit bitrate in Mbit/s.
+    pub bitrate: Option<f64>,
+    /// Channel frequency in MHz.
+    pub frequency: Option<f64>,
 }
 
 impl Signal {
-    fn new(level: f64) -> Self {
+    fn disconnected() -> Self {
         Self {
             timestamp: Utc::now(),
-            quality: level,
+            associated: false,
+            quality: 0.0,
+            rssi: None,
+            ssid: None,
+            bitrate: None,
+            frequency: None,
         }
     }
 }
 
-static LATEST: LazyLock<RwLock<Option<Signal>>> = LazyLock::new(|| RwLock::new(None));
+/// How the signal is polled, which the API may change while the worker runs.
+pub(crate) struct Polling {
+    interval: watch::Sender<Duration>,
+    /// Set when there is no wireless interface to monitor.
+    disabled: AtomicBool,
+}
+
+impl Polling {
+    pub fn new(config: &SignalConfig) -> Self {
+        Self {
+            interval: watch::channel(config.interval()).0,
+            disabled: AtomicBool::new(false),
+        }
+    }
 
-pub(crate) async fn latest() -> Option<Signal> {
-    *LATEST.read().await
+    /// Returns the effective polling interval, which starts out as the configured one.
+    pub fn interval(&self) -> Duration {
+        *self.interval.borrow()
+    }
+
+    /// Changes the polling interval of the running worker.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval.send_replace(interval);
+    }
+
+    /// Returns whether there is no wireless interface to monitor, as on an Ethernet-only install.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    /// Marks the signal as not monitored, for want of a wireless interface.
+    pub fn disable(&self) {
+        self.disabled.store(true, Ordering::Relaxed);
+    }
 }
 
+const PROC_NET_WIRELESS: &str = "/proc/net/wireless";
+
+/// A read gives up after this long, since a wedged driver can make `iwconfig` hang indefinitely.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the kernel lists the network interfaces.
+const NET_DEVICES: &str = "/sys/class/net";
+
+/// Full scale of the link quality in `/proc/net/wireless`, as used by the Raspberry Pi drivers.
+const MAX_LINK_QUALITY: f64 = 70.0;
+
 struct Context {
+    interface: String,
+    /// Whether the signal is made up rather than read from `interface`.
+    simulated: bool,
     rx_quality: Regex,
+    rx_level: Regex,
+    rx_ssid: Regex,
+    rx_bitrate: Regex,
+    rx_frequency: Regex,
+    rx_iw_ssid: Regex,
+    rx_iw_bitrate: Regex,
+    rx_iw_frequency: Regex,
 }
 
 impl Context {
-    async fn new() -> anyhow::Result<Arc<Self>> {
+    async fn new(interface: String, simulated: bool) -> anyhow::Result<Arc<Self>> {
         task::spawn_blocking(move || {
             let rx_quality = Regex::new(r"Link Quality=\s*([0-9]+)\s*/\s*([0-9]+)").unwrap();
-            Ok(Arc::new(Self { rx_quality }))
+            let rx_level = Regex::new(r"Signal level=\s*(-[0-9]+) dBm").unwrap();
+            let rx_ssid = Regex::new(r#"ESSID:"([^"]+)""#).unwrap();
+            let rx_bitrate = Regex::new(r"Bit Rate[=:]\s*([0-9.]+) Mb/s").unwrap();
+            let rx_frequency = Regex::new(r"Frequency[=:]\s*([0-9.]+) GHz").unwrap();
+            let rx_iw_ssid = Regex::new(r"(?m)^\s*SSID: (.+)$").unwrap();
+            let rx_iw_bitrate = Regex::new(r"(?m)^\s*tx bitrate: ([0-9.]+) MBit/s").unwrap();
+            let rx_iw_frequency = Regex::new(r"(?m)^\s*freq: ([0-9.]+)").unwrap();
+            Ok(Arc::new(Self {
+                interface,
+                simulated,
+                rx_quality,
+                rx_level,
+                rx_ssid,
+                rx_bitrate,
+                rx_frequency,
+                rx_iw_ssid,
+                rx_iw_bitrate,
+                rx_iw_frequency,
+            }))
         })
         .await?
     }
 }
 
-pub(crate) async fn worker() -> anyhow::Result<()> {
-    let mut interval = interval(Duration::from_secs(30));
-    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let mut interval_rx = state.signal_polling.interval.subscribe();
+    let mut interval = ticker(*interval_rx.borrow_and_update());
+
+    let interface = match &config.signal.interface {
+        _ if config.simulate => simulation::INTERFACE.to_owned(),
+        Some(interface) => interface.clone(),
+        None => match task::spawn_blocking(find_interface).await?? {
+            Some(interface) => {
+                info!("Monitoring wireless interface {interface}");
+                interface
+            }
+            None => {
+                info!("No wireless interface found, signal monitoring is disabled");
+                state.hardware.record(|report| {
+                    report.wireless = Device::missing(format!("wireless interface: none found under {NET_DEVICES}"));
+                });
+                state.signal_polling.disable();
+                state.health.signal.initialized();
+                return Ok(());
+            }
+        },
+    };
+
+    if !config.simulate {
+        record_interface(&state, &interface).await?;
+    }
 
-    let ctx = Context::new().await?;
+    let ctx = Context::new(interface, config.simulate)
+        .await
+        .inspect_err(|_| state.health.signal.failure())?;
+    state.health.signal.initialized();
 
     loop {
-        interval.tick().await;
+        select! {
+            _ = interval.tick() => {}
+            Ok(()) = interval_rx.changed() => {
+                let period = *interval_rx.borrow_and_update();
+                let interval_secs = period.as_secs();
+                info!(interval_secs; "Polling interval changed to {interval_secs}s");
+                interval = ticker(period);
+                continue;
+            }
+            () = shutdown.cancelled() => return Ok(()),
+        }
 
-        if let Err(e) = update(&ctx).await {
-            error!("Failed to update signal level: {e:?}");
+        match update(&state, &ctx).await {
+            Ok(()) => state.health.signal.success(),
+            Err(e) => {
+                state.health.signal.failure();
+                let consecutive_failures = state.health.signal.snapshot().consecutive_failures;
+                error!(
+                    interface = ctx.interface.as_str(), consecutive_failures;
+                    "Failed to update signal level: {e:?}"
+                );
+                state
+                    .errors
+                    .error("signal", format!("Failed to update signal level: {e:#}"));
+            }
         }
     }
 }
 
-async fn update(ctx: &Arc<Context>) -> anyhow::Result<()> {
+/// Records the interface about to be polled, along with whether it is there at all.
+async fn record_interface(state: &AppState, interface: &str) -> anyhow::Result<()> {
+    let path = Path::new(NET_DEVICES).join(interface);
+    let device = if task::spawn_blocking(move || path.exists()).await? {
+        Device::found(format!("{interface}: polling"))
+    } else {
+        Device::missing(format!("{interface}: not found under {NET_DEVICES}"))
+    };
+    state.hardware.record(|report| {
+        report.wireless = device;
+        report.wireless_interface = Some(interface.to_owned());
+    });
+
+    Ok(())
+}
+
+/// Returns the name of the first wireless network interface, if any.
+fn find_interface() -> anyhow::Result<Option<String>> {
+    let mut interfaces: Vec<String> = fs::read_dir(NET_DEVICES)?
+        .flatten()
+        .filter(|entry| entry.path().join("wireless").exists())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    interfaces.sort();
+
+    Ok(interfaces.into_iter().next())
+}
+
+fn ticker(period: Duration) -> Interval {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+async fn update(state: &AppState, ctx: &Arc<Context>) -> anyhow::Result<()> {
     let signal = read(ctx).await?;
-    *LATEST.write().await = Some(signal);
+    state.signal.set(signal).await;
 
     Ok(())
 }
 
-async fn read(ctx: &Arc<Context>) -> anyhow::Result<Signal> {
-    let ctx = ctx.clone();
-    task::spawn_blocking(move || {
-        let output = Command::new("iwconfig").args(["wlan0"]).output()?;
-        let raw = String::from_utf8(output.stdout)?;
-        let Some(caps) = ctx.rx_quality.captures(&raw) else {
-            return Err(anyhow!("Invalid format"));
-        };
-        let num: i32 = caps[1].parse().unwrap();
-        let denom: i32 = caps[2].parse().unwrap();
-        let quality = (f64::from(num) / f64::from(denom) * 100.0).round() / 100.0;
+async fn read(ctx: &Context) -> anyhow::Result<Signal> {
+    if ctx.simulated {
+        return read_simulated();
+    }
+
+    let read = async {
+        // `iwconfig` is deprecated and missing from recent images, so it is only a fallback.
+        match read_native(ctx).await {
+            Ok(signal) => Ok(signal),
+            Err(e) => read_iwconfig(ctx)
+                .await
+                .map_err(|fallback_error| anyhow!("{PROC_NET_WIRELESS}: {e:#}; iwconfig: {fallback_error:#}")),
+        }
+    };
+
+    // Dropping the read on timeout kills any command still running.
+    timeout(READ_TIMEOUT, read)
+        .await
+        .map_err(|_| anyhow!("Timed out after {}s", READ_TIMEOUT.as_secs()))?
+}
+
+/// Runs a command and returns its standard output. The child is killed when the returned future is dropped.
+async fn run(program: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(program).args(args).kill_on_drop(true).output().await?;
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+async fn read_native(ctx: &Context) -> anyhow::Result<Signal> {
+    let raw = tokio::fs::read_to_string(PROC_NET_WIRELESS).await?;
+    let (link, level) =
+        parse_proc_net_wireless(&raw, &ctx.interface).ok_or_else(|| anyhow!("No statistics for {}", ctx.interface))?;
+
+    // The rest is not in /proc/net/wireless, and `iw` may not be installed either.
+    let link_info = run("iw", &["dev", &ctx.interface, "link"]).await.unwrap_or_default();
+
+    // Without `iw`, a link quality of 0 is the best hint of being disconnected.
+    let associated = if link_info.is_empty() {
+        link > 0.0
+    } else {
+        !link_info.starts_with("Not connected")
+    };
+    if !associated {
+        return Ok(Signal::disconnected());
+    }
+
+    Ok(Signal {
+        timestamp: Utc::now(),
+        associated,
+        quality: normalize_quality(link, MAX_LINK_QUALITY)?,
+        rssi: level,
+        ssid: ctx
+            .rx_iw_ssid
+            .captures(&link_info)
+            .map(|caps| caps[1].trim().to_owned()),
+        bitrate: capture_number(&ctx.rx_iw_bitrate, &link_info),
+        frequency: capture_number(&ctx.rx_iw_frequency, &link_info),
+    })
+}
+
+/// Makes up a steady connection with a little fluctuation, for development without a wireless interface.
+fn read_simulated() -> anyhow::Result<Signal> {
+    let rssi = -52 - i32::try_from(simulation::below(8)).unwrap_or_default();
+
+    Ok(Signal {
+        timestamp: Utc::now(),
+        associated: true,
+        // As many drivers derive it, the link quality is the level in dBm plus 110.
+        quality: normalize_quality(f64::from(rssi + 110), MAX_LINK_QUALITY)?,
+        rssi: Some(rssi),
+        ssid: Some("cobitis-simulated".to_owned()),
+        bitrate: Some(72.2),
+        frequency: Some(2437.0),
+    })
+}
+
+/// Parses the link quality and the signal level in dBm of `interface` out of `/proc/net/wireless`.
+///
+/// ```text
+/// Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+///  face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+///  wlan0: 0000   58.  -52.  -256        0      0      0      0     12        0
+/// ```
+fn parse_proc_net_wireless(raw: &str, interface: &str) -> Option<(f64, Option<i32>)> {
+    raw.lines().skip(2).find_map(|line| {
+        let (name, stats) = line.split_once(':')?;
+        if name.trim() != interface {
+            return None;
+        }
+
+        let mut fields = stats.split_whitespace().skip(1).map(|v| v.trim_end_matches('.'));
+        let link: f64 = fields.next()?.parse().ok()?;
+        // Drivers that do not report dBm leave a non-negative level here.
+        let level = fields.next()?.parse::<i32>().ok().filter(|v| *v < 0);
+
+        Some((link, level))
+    })
+}
+
+async fn read_iwconfig(ctx: &Context) -> anyhow::Result<Signal> {
+    let raw = run("iwconfig", &[&ctx.interface]).await?;
+
+    parse_iwconfig(ctx, &raw)
+}
 
-        Ok(Signal::new(quality))
+/// Parses the output of `iwconfig <interface>`.
+///
+/// A disconnected interface reports `ESSID:off/any` and drops or zeroes the quality line:
+///
+/// ```text
+/// wlan0     IEEE 802.11  ESSID:off/any
+///           Mode:Managed  Access Point: Not-Associated   Tx-Power=31 dBm
+/// ```
+fn parse_iwconfig(ctx: &Context, raw: &str) -> anyhow::Result<Signal> {
+    // Nothing is written to the standard output for an interface without wireless extensions.
+    if raw.trim().is_empty() {
+        return Err(anyhow!("Invalid format"));
+    }
+    if raw.contains("ESSID:off/any") || raw.contains("Not-Associated") {
+        return Ok(Signal::disconnected());
+    }
+    let Some(caps) = ctx.rx_quality.captures(raw) else {
+        return Ok(Signal::disconnected());
+    };
+    let link: f64 = caps[1].parse()?;
+    if link == 0.0 {
+        return Ok(Signal::disconnected());
+    }
+    let quality = normalize_quality(link, caps[2].parse()?)?;
+
+    let rssi = ctx.rx_level.captures(raw).and_then(|caps| caps[1].parse().ok());
+    let ssid = ctx.rx_ssid.captures(raw).map(|caps| caps[1].to_owned());
+
+    Ok(Signal {
+        timestamp: Utc::now(),
+        associated: true,
+        quality,
+        rssi,
+        ssid,
+        bitrate: capture_number(&ctx.rx_bitrate, raw),
+        frequency: capture_number(&ctx.rx_frequency, raw).map(|ghz| (ghz * 1000.0).round()),
     })
-    .await?
+}
+
+/// Scales a link quality of `link` out of `full_scale` into 0-1, rounded to two decimals.
+///
+/// Some drivers report a little over full scale under a strong signal, such as `72/70`, which is clamped.
+fn normalize_quality(link: f64, full_scale: f64) -> anyhow::Result<f64> {
+    if !link.is_finite() || !full_scale.is_finite() || full_scale <= 0.0 {
+        return Err(anyhow!("Invalid link quality {link}/{full_scale}"));
+    }
+
+    Ok(((link / full_scale).clamp(0.0, 1.0) * 100.0).round() / 100.0)
+}
+
+/// Parses the first capture group of `rx` as a number, if it matches at all.
+fn capture_number(rx: &Regex, raw: &str) -> Option<f64> {
+    rx.captures(raw).and_then(|caps| caps[1].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IWCONFIG_CONNECTED: &str = "\
+wlan0     IEEE 802.11  ESSID:\"tank-room\"
+          Mode:Managed  Frequency:2.437 GHz  Access Point: B8:27:EB:12:34:56
+          Bit Rate=72.2 Mb/s   Tx-Power=31 dBm
+          Retry short limit:7   RTS thr:off   Fragment thr:off
+          Power Management:on
+          Link Quality=58/70  Signal level=-52 dBm
+          Rx invalid nwid:0  Rx invalid crypt:0  Rx invalid frag:0
+          Tx excessive retries:0  Invalid misc:12   Missed beacon:0
+";
+
+    const IWCONFIG_DISCONNECTED: &str = "\
+wlan0     IEEE 802.11  ESSID:off/any
+          Mode:Managed  Access Point: Not-Associated   Tx-Power=31 dBm
+          Retry short limit:7   RTS thr:off   Fragment thr:off
+          Power Management:on
+";
+
+    async fn parse(raw: &str) -> anyhow::Result<Signal> {
+        let ctx = Context::new("wlan0".to_owned(), false).await.unwrap();
+        parse_iwconfig(&ctx, raw)
+    }
+
+    const PROC_NET_WIRELESS_FIXTURE: &str = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+  eth1: 0000    0     0     0        0      0      0      0      0        0
+ wlan0: 0000   58.  -52.  -256        0      0      0      0     12        0
+";
+
+    #[test]
+    fn proc_net_wireless_with_trailing_dots() {
+        assert_eq!(
+            parse_proc_net_wireless(PROC_NET_WIRELESS_FIXTURE, "wlan0"),
+            Some((58.0, Some(-52)))
+        );
+    }
+
+    #[test]
+    fn proc_net_wireless_without_a_level_in_dbm() {
+        assert_eq!(
+            parse_proc_net_wireless(PROC_NET_WIRELESS_FIXTURE, "eth1"),
+            Some((0.0, None))
+        );
+    }
+
+    #[test]
+    fn proc_net_wireless_without_the_interface() {
+        assert_eq!(parse_proc_net_wireless(PROC_NET_WIRELESS_FIXTURE, "wlan1"), None);
+        // The interface is not matched on a prefix of its name.
+        assert_eq!(parse_proc_net_wireless(PROC_NET_WIRELESS_FIXTURE, "wlan"), None);
+    }
+
+    #[test]
+    fn proc_net_wireless_without_interfaces() {
+        let headers: String = PROC_NET_WIRELESS_FIXTURE
+            .lines()
+            .take(2)
+            .map(|l| format!("{l}\n"))
+            .collect();
+        assert_eq!(parse_proc_net_wireless(&headers, "wlan0"), None);
+    }
+
+    #[test]
+    fn quality_of_no_link() {
+        assert_eq!(normalize_quality(0.0, 70.0).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn quality_at_full_scale() {
+        assert_eq!(normalize_quality(70.0, 70.0).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn quality_over_full_scale_is_clamped() {
+        assert_eq!(normalize_quality(72.0, 70.0).unwrap(), 1.0);
+        assert_eq!(normalize_quality(-3.0, 70.0).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn quality_of_another_scale_is_rounded() {
+        assert_eq!(normalize_quality(55.0, 94.0).unwrap(), 0.59);
+    }
+
+    #[test]
+    fn quality_without_a_scale_is_an_error() {
+        assert!(normalize_quality(55.0, 0.0).is_err());
+        assert!(normalize_quality(f64::NAN, 70.0).is_err());
+        assert!(normalize_quality(55.0, f64::INFINITY).is_err());
+    }
+
+    #[tokio::test]
+    async fn iwconfig_over_full_scale_is_clamped() {
+        let raw = IWCONFIG_CONNECTED.replace("Link Quality=58/70", "Link Quality=72/70");
+        assert_eq!(parse(&raw).await.unwrap().quality, 1.0);
+    }
+
+    #[tokio::test]
+    async fn iwconfig_of_a_connected_interface() {
+        let signal = parse(IWCONFIG_CONNECTED).await.unwrap();
+
+        assert!(signal.associated);
+        assert!((signal.quality - 0.83).abs() < 1e-9, "{}", signal.quality);
+        assert_eq!(signal.rssi, Some(-52));
+        assert_eq!(signal.ssid.as_deref(), Some("tank-room"));
+        assert_eq!(signal.bitrate, Some(72.2));
+        assert_eq!(signal.frequency, Some(2437.0));
+    }
+
+    #[tokio::test]
+    async fn iwconfig_of_a_disconnected_interface() {
+        let signal = parse(IWCONFIG_DISCONNECTED).await.unwrap();
+
+        assert!(!signal.associated);
+        assert_eq!(signal.quality, 0.0);
+        assert_eq!(signal.ssid, None);
+    }
+
+    #[tokio::test]
+    async fn iwconfig_without_a_quality_line_is_disconnected() {
+        let raw = IWCONFIG_CONNECTED.replace("          Link Quality=58/70  Signal level=-52 dBm\n", "");
+        let signal = parse(&raw).await.unwrap();
+
+        assert!(!signal.associated);
+        assert_eq!(signal.quality, 0.0);
+    }
+
+    #[tokio::test]
+    async fn iwconfig_with_zero_quality_is_disconnected() {
+        let raw = IWCONFIG_CONNECTED.replace("Link Quality=58/70", "Link Quality=0/70");
+        let signal = parse(&raw).await.unwrap();
+
+        assert!(!signal.associated);
+        assert_eq!(signal.rssi, None);
+    }
+
+    #[tokio::test]
+    async fn iwconfig_without_output_is_an_error() {
+        assert!(parse("").await.is_err());
+    }
 }
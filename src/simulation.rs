@@ -0,0 +1,78 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+#[cfg(feature = "display")]
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+use std::{
+    f64,
+    hash::{BuildHasher, Hasher, RandomState},
+    time::Duration,
+};
+
+use chrono::Utc;
+
+#[cfg(feature = "display")]
+use crate::display::Framebuffer;
+
+/// Name the simulated wireless interface goes by.
+pub(crate) const INTERFACE: &str = "sim0";
+
+/// Returns the ID the simulated thermal sensor of the `index`th tank goes by, in the format of a DS18B20.
+pub(crate) fn sensor_id(index: usize) -> String {
+    format!("28-{index:012x}")
+}
+
+/// Where the display writes its frames to instead of a panel.
+#[cfg(feature = "display")]
+static FRAME_PATH: LazyLock<PathBuf> = LazyLock::new(|| env::temp_dir().join("cobitis").join("display.png"));
+
+/// Returns a point on a sine wave of `period`, between -1 and 1, following the wall clock.
+pub(crate) fn wave(period: Duration) -> f64 {
+    let secs = Utc::now().timestamp_millis() as f64 / 1000.0;
+
+    (secs / period.as_secs_f64() * f64::consts::TAU).sin()
+}
+
+/// Returns a random number between -1 and 1.
+pub(crate) fn noise() -> f64 {
+    // 53 random bits make up the whole mantissa.
+    (random() >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+/// Returns a random number below `bound`.
+pub(crate) fn below(bound: u64) -> u64 {
+    random() % bound.max(1)
+}
+
+/// Randomly keyed hashers are as random as this needs to be, without a dependency for it.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Returns the file the display writes its frames to instead of a panel.
+#[cfg(feature = "display")]
+pub(crate) fn frame_path() -> &'static Path {
+    &FRAME_PATH
+}
+
+/// Writes `frame` to [`frame_path`], replacing the previous one.
+#[cfg(feature = "display")]
+pub(crate) fn write_frame(frame: &Framebuffer) -> anyhow::Result<()> {
+    let path = frame_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Renamed into place, so that an image viewer watching the file never sees half of it.
+    let tmp = path.with_extension("png.tmp");
+    fs::write(&tmp, frame.to_png()?)?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
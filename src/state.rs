@@ -0,0 +1,182 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use axum::extract::FromRef;
+use futures_util::future;
+use tokio::sync::{
+    RwLock,
+    broadcast::{self, error::RecvError},
+};
+
+#[cfg(feature = "display")]
+use crate::display;
+use crate::{
+    alerts::Alerts,
+    api, buzzer, calibration,
+    config::Config,
+    errors::ErrorLog,
+    flow,
+    hardware::Hardware,
+    health, maintenance,
+    measurements::{self, DailyStatsPair, Measurements},
+    notify,
+    signal::{self, Signal},
+    storage,
+    system::System,
+    thermostat, water_change, water_level,
+};
+
+/// The readings and the state shared between the workers and the API, built once in `main` and handed to each of
+/// them.
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub config: Arc<Config>,
+    /// Every tank, in the order configured.
+    pub tanks: Arc<[Tank]>,
+    pub signal: Arc<Feed<Signal>>,
+    pub hardware: Arc<Hardware>,
+    pub health: Arc<health::Workers>,
+    pub alerts: Arc<Alerts>,
+    pub errors: Arc<ErrorLog>,
+    pub system: Arc<Feed<System>>,
+    pub sampling: Arc<measurements::Sampling>,
+    pub signal_polling: Arc<signal::Polling>,
+    pub calibration: Arc<calibration::Store>,
+    pub maintenance: Arc<maintenance::Tracker>,
+    pub thermostat: Arc<thermostat::Thermostat>,
+    pub water_level: Arc<water_level::WaterLevel>,
+    pub water_change: Arc<water_change::Reminder>,
+    pub flow: Arc<flow::FlowMeter>,
+    pub buzzer: Arc<buzzer::Buzzer>,
+    pub database: Arc<storage::Database>,
+    pub notifications: Arc<notify::Notifications>,
+    pub api_rejections: Arc<api::RejectionCounter>,
+    #[cfg(feature = "display")]
+    pub display: Arc<display::Controls>,
+}
+
+impl AppState {
+    pub fn new(config: Arc<Config>) -> Self {
+        let tanks = config
+            .measurements
+            .tanks()
+            .into_iter()
+            .map(|tank| Tank {
+                name: tank.name,
+                measurements: Arc::new(Feed::new()),
+                history: Arc::new(RwLock::new(VecDeque::new())),
+                daily_stats: Mutex::new(None),
+            })
+            .collect();
+
+        Self {
+            tanks,
+            signal: Arc::new(Feed::new()),
+            hardware: Arc::new(Hardware::new(&config)),
+            health: Arc::new(health::Workers::new()),
+            alerts: Arc::new(Alerts::new()),
+            errors: Arc::new(ErrorLog::new(config.recent_errors)),
+            system: Arc::new(Feed::new()),
+            sampling: Arc::new(measurements::Sampling::new(&config.measurements)),
+            signal_polling: Arc::new(signal::Polling::new(&config.signal)),
+            calibration: Arc::new(calibration::Store::new()),
+            maintenance: Arc::new(maintenance::Tracker::new()),
+            thermostat: Arc::new(thermostat::Thermostat::new()),
+            water_level: Arc::new(water_level::WaterLevel::new()),
+            water_change: Arc::new(water_change::Reminder::new()),
+            flow: Arc::new(flow::FlowMeter::new()),
+            buzzer: Arc::new(buzzer::Buzzer::new()),
+            database: Arc::new(storage::Database::new()),
+            notifications: Arc::new(notify::Notifications::new()),
+            api_rejections: Arc::new(api::RejectionCounter::new()),
+            #[cfg(feature = "display")]
+            display: Arc::new(display::Controls::new()),
+            config,
+        }
+    }
+
+    /// Returns the tank served at `/measurements`, which the probes other than the thermal sensors and TDS belong to.
+    pub fn default_tank(&self) -> &Tank {
+        &self.tanks[self.config.measurements.default_tank_index()]
+    }
+
+    pub fn tank(&self, name: &str) -> Option<&Tank> {
+        self.tanks.iter().find(|tank| tank.name == name)
+    }
+
+    /// Subscribes to the measurements of every tank.
+    pub fn subscribe_tanks(&self) -> TankUpdates {
+        TankUpdates(
+            self.tanks
+                .iter()
+                .map(|tank| (tank.name.clone(), tank.measurements.subscribe()))
+                .collect(),
+        )
+    }
+}
+
+/// The readings of a single tank.
+pub(crate) struct Tank {
+    pub name: String,
+    pub measurements: Arc<Feed<Measurements>>,
+    /// Recorded measurements in chronological order.
+    pub history: Arc<RwLock<VecDeque<Measurements>>>,
+    /// Statistics of today and yesterday.
+    pub daily_stats: Mutex<Option<DailyStatsPair>>,
+}
+
+/// New measurements of every tank, along with the name of the tank.
+pub(crate) struct TankUpdates(Vec<(String, broadcast::Receiver<Measurements>)>);
+
+impl TankUpdates {
+    /// Waits for the next measurements of any of the tanks. Cancel safe, like the receivers it waits on.
+    pub async fn recv(&mut self) -> (String, Result<Measurements, RecvError>) {
+        let (result, index, _) = future::select_all(self.0.iter_mut().map(|(_, rx)| Box::pin(rx.recv()))).await;
+
+        (self.0[index].0.clone(), result)
+    }
+}
+
+/// Lets the handlers that only need the config keep extracting it alone.
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+/// The latest value of a reading, along with a channel every new one is sent on.
+pub(crate) struct Feed<T> {
+    value: RwLock<Option<T>>,
+    updates: broadcast::Sender<T>,
+}
+
+impl<T: Clone> Feed<T> {
+    fn new() -> Self {
+        Self {
+            value: RwLock::new(None),
+            updates: broadcast::channel(16).0,
+        }
+    }
+
+    pub async fn get(&self) -> Option<T> {
+        self.value.read().await.clone()
+    }
+
+    /// Stores `value` as the latest one and sends it to the subscribers.
+    pub async fn set(&self, value: T) {
+        *self.value.write().await = Some(value.clone());
+        let _ = self.updates.send(value);
+    }
+
+    /// Subscribes to every new value as it is stored.
+    pub fn subscribe(&self) -> broadcast::Receiver<T> {
+        self.updates.subscribe()
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use logger::log::{error, info, warn};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use tokio::{
+    select,
+    sync::broadcast::error::RecvError,
+    task,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::StorageConfig, measurements::Measurements, signal::Signal, state::AppState};
+
+const HOUR_MILLIS: i64 = 3_600_000;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS measurements (
+        tank TEXT,
+        timestamp INTEGER NOT NULL,
+        temperature REAL NOT NULL,
+        temperatures TEXT,
+        tds REAL NOT NULL,
+        ec REAL,
+        tds_voltage REAL,
+        ph REAL,
+        downsampled INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS measurements_timestamp ON measurements (timestamp);
+    CREATE TABLE IF NOT EXISTS signal (
+        timestamp INTEGER NOT NULL,
+        associated INTEGER NOT NULL,
+        quality REAL NOT NULL,
+        rssi REAL,
+        downsampled INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS signal_timestamp ON signal (timestamp);
+";
+
+/// The history database, which the worker writes to and the API reads back from.
+pub(crate) struct Database {
+    /// `None` when storage is disabled or not opened yet.
+    connection: Mutex<Option<Connection>>,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Self {
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Whether the database is open, so that history can be read from it.
+    pub fn is_enabled(&self) -> bool {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    fn set(&self, connection: Option<Connection>) {
+        *self.connection.lock().unwrap_or_else(|e| e.into_inner()) = connection;
+    }
+}
+
+/// Runs `f` on the database in a blocking task. Returns `None` when storage is not available.
+async fn with_db<T, F>(db: &Arc<Database>, f: F) -> anyhow::Result<Option<T>>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+{
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        let mut connection = db.connection.lock().unwrap_or_else(|e| e.into_inner());
+        connection.as_mut().map(f).transpose()
+    })
+    .await?
+}
+
+/// Returns stored measurements of `tank` taken between `from` and `to` in chronological order, or `None` when
+/// storage is disabled.
+///
+/// When `limit` is given, only the most recent `limit` of them are returned.
+pub(crate) async fn history(
+    db: &Arc<Database>,
+    tank: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<usize>,
+) -> anyhow::Result<Option<Vec<Measurements>>> {
+    let from = from.map_or(i64::MIN, |t| t.timestamp_millis());
+    let to = to.map_or(i64::MAX, |t| t.timestamp_millis());
+    let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+    let tank = tank.to_owned();
+
+    with_db(db, move |db| {
+        let mut statement = db.prepare_cached(
+            "SELECT timestamp, temperature, temperatures, tds, tds_voltage, ph, ec FROM measurements
+             WHERE tank = ?4 AND timestamp >= ?1 AND timestamp <= ?2 ORDER BY timestamp DESC LIMIT ?3",
+        )?;
+        let mut rows = statement
+            .query_map(params![from, to, limit, tank], measurements_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.reverse();
+
+        Ok(rows)
+    })
+    .await
+}
+
+/// Reads stored measurements of a tank in chronological order a page at a time, so that a long range is never held
+/// in memory at once.
+pub(crate) struct HistoryPages {
+    db: Arc<Database>,
+    tank: Arc<str>,
+    from: i64,
+    to: i64,
+    /// Counted back from the most recent row, and only applied when reading the first page.
+    limit: Option<usize>,
+    /// `(timestamp, rowid)` of the last row read.
+    after: Option<(i64, i64)>,
+}
+
+impl HistoryPages {
+    const PAGE_SIZE: i64 = 1000;
+
+    pub fn new(
+        db: Arc<Database>,
+        tank: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    ) -> Self {
+        Self {
+            db,
+            tank: tank.into(),
+            from: from.map_or(i64::MIN, |t| t.timestamp_millis()),
+            to: to.map_or(i64::MAX, |t| t.timestamp_millis()),
+            limit,
+            after: None,
+        }
+    }
+
+    /// Returns the next page, which is empty once every row has been read or when storage is disabled.
+    pub async fn next(&mut self) -> anyhow::Result<Vec<Measurements>> {
+        let (tank, from, to, limit, after) = (self.tank.clone(), self.from, self.to, self.limit, self.after);
+        let (rows, after) = with_db(&self.db, move |db| {
+            let after = match (after, limit) {
+                (Some(after), _) => Some(after),
+                // Start just before the `limit`th most recent row.
+                (None, Some(limit)) => db
+                    .prepare_cached(
+                        "SELECT timestamp, rowid FROM measurements
+                         WHERE tank = ?4 AND timestamp >= ?1 AND timestamp <= ?2
+                         ORDER BY timestamp DESC, rowid DESC LIMIT 1 OFFSET ?3",
+                    )?
+                    .query_row(
+                        params![
+                            from,
+                            to,
+                            i64::try_from(limit.saturating_sub(1)).unwrap_or(i64::MAX),
+                            &*tank
+                        ],
+                        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? - 1)),
+                    )
+                    .optional()?,
+                (None, None) => None,
+            };
+            let (after_timestamp, after_rowid) = after.unwrap_or((i64::MIN, i64::MIN));
+
+            let mut statement = db.prepare_cached(
+                "SELECT timestamp, temperature, temperatures, tds, tds_voltage, ph, ec, rowid FROM measurements
+                 WHERE tank = ?6 AND timestamp >= ?1 AND timestamp <= ?2 AND (timestamp, rowid) > (?3, ?4)
+                 ORDER BY timestamp, rowid LIMIT ?5",
+            )?;
+            let mut last = after;
+            let rows = statement
+                .query_map(
+                    params![from, to, after_timestamp, after_rowid, Self::PAGE_SIZE, &*tank],
+                    |row| {
+                        last = Some((row.get(0)?, row.get(7)?));
+                        measurements_from_row(row)
+                    },
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok((rows, last))
+        })
+        .await?
+        .unwrap_or_default();
+
+        self.after = after;
+
+        Ok(rows)
+    }
+}
+
+fn measurements_from_row(row: &Row) -> rusqlite::Result<Measurements> {
+    let temperatures: Option<String> = row.get(2)?;
+    let tds = row.get(3)?;
+
+    Ok(Measurements {
+        timestamp: DateTime::from_timestamp_millis(row.get(0)?).unwrap_or_default(),
+        temperature: row.get(1)?,
+        temperature_raw: None,
+        temperatures: temperatures
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        tds,
+        tds_raw: None,
+        // Rows from before conductivity was stored were all derived with the NaCl factor of 0.5.
+        ec: row.get::<_, Option<f64>>(6)?.unwrap_or(tds * 2.0),
+        tds_voltage: row.get(4)?,
+        ph: row.get(5)?,
+        flow_rate: None,
+        flow_volume: None,
+        water_level_ok: None,
+        aux: BTreeMap::new(),
+        debug: None,
+    })
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.storage else {
+        return Ok(());
+    };
+
+    let db = &state.database;
+    let mut measurements_rx = state.subscribe_tanks();
+    let mut signal_rx = state.signal.subscribe();
+    open(db, config.path.clone(), state.default_tank().name.clone()).await?;
+    info!("Storing history in {}", config.path.display());
+
+    let mut maintenance = interval(config.maintenance_interval());
+    maintenance.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let result = loop {
+        select! {
+            (tank, m) = measurements_rx.recv() => match m {
+                Ok(m) => {
+                    if let Err(e) = with_db(db, move |db| insert_measurements(db, &tank, &m)).await {
+                        error!("Failed to store measurements: {e:?}");
+                    }
+                }
+                Err(RecvError::Lagged(n)) => warn!("Dropped {n} measurements of tank {tank} while storing"),
+                Err(RecvError::Closed) => break Ok(()),
+            },
+            s = signal_rx.recv() => match s {
+                Ok(s) => {
+                    if let Err(e) = with_db(db, move |db| insert_signal(db, &s)).await {
+                        error!("Failed to store signal level: {e:?}");
+                    }
+                }
+                Err(RecvError::Lagged(n)) => warn!("Dropped {n} signal readings while storing"),
+                Err(RecvError::Closed) => break Ok(()),
+            },
+            _ = maintenance.tick() => {
+                let config = config.clone();
+                if let Err(e) = with_db(db, move |db| maintain(db, &config, Utc::now())).await {
+                    error!("Failed to prune stored history: {e:?}");
+                }
+            }
+            () = shutdown.cancelled() => break Ok(()),
+        }
+    };
+
+    // Close the database so that a restarted worker opens it afresh.
+    db.set(None);
+
+    result
+}
+
+async fn open(db: &Arc<Database>, path: PathBuf, default_tank: String) -> anyhow::Result<()> {
+    let db = db.clone();
+    task::spawn_blocking(move || {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(&path)?;
+        // Fewer fsyncs on the SD card, at the cost of losing the last few rows on a power cut.
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        migrate(&connection, &default_tank)?;
+        db.set(Some(connection));
+
+        Ok(())
+    })
+    .await?
+}
+
+/// Brings a database created by an older version up to the current schema. Rows from before the tank was stored
+/// are all of `default_tank`.
+fn migrate(db: &Connection, default_tank: &str) -> anyhow::Result<()> {
+    let has_ec = db
+        .prepare("SELECT 1 FROM pragma_table_info('measurements') WHERE name = 'ec'")?
+        .exists([])?;
+    if !has_ec {
+        info!("Adding the ec column to the measurements table");
+        db.execute_batch("ALTER TABLE measurements ADD COLUMN ec REAL")?;
+    }
+    let has_tank = db
+        .prepare("SELECT 1 FROM pragma_table_info('measurements') WHERE name = 'tank'")?
+        .exists([])?;
+    if !has_tank {
+        info!("Adding the tank column to the measurements table");
+        db.execute_batch("ALTER TABLE measurements ADD COLUMN tank TEXT")?;
+    }
+    let untagged = db.execute("UPDATE measurements SET tank = ?1 WHERE tank IS NULL", [default_tank])?;
+    if untagged > 0 {
+        info!("Assigned {untagged} stored measurements to tank {default_tank}");
+    }
+    db.execute_batch("CREATE INDEX IF NOT EXISTS measurements_tank_timestamp ON measurements (tank, timestamp)")?;
+
+    Ok(())
+}
+
+fn insert_measurements(db: &mut Connection, tank: &str, m: &Measurements) -> anyhow::Result<()> {
+    let temperatures = (!m.temperatures.is_empty())
+        .then(|| serde_json::to_string(&m.temperatures))
+        .transpose()?;
+    db.prepare_cached(
+        "INSERT INTO measurements (tank, timestamp, temperature, temperatures, tds, ec, tds_voltage, ph)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?
+    .execute(params![
+        tank,
+        m.timestamp.timestamp_millis(),
+        m.temperature,
+        temperatures,
+        m.tds,
+        m.ec,
+        m.tds_voltage,
+        m.ph,
+    ])?;
+
+    Ok(())
+}
+
+fn insert_signal(db: &mut Connection, s: &Signal) -> anyhow::Result<()> {
+    db.prepare_cached("INSERT INTO signal (timestamp, associated, quality, rssi) VALUES (?1, ?2, ?3, ?4)")?
+        .execute(params![s.timestamp.timestamp_millis(), s.associated, s.quality, s.rssi])?;
+
+    Ok(())
+}
+
+/// Deletes rows past the retention period and replaces those past the downsampling age with hourly averages.
+fn maintain(db: &mut Connection, config: &StorageConfig, now: DateTime<Utc>) -> anyhow::Result<()> {
+    let cutoff = |age: Duration| {
+        now.checked_sub_signed(TimeDelta::from_std(age).unwrap_or(TimeDelta::MAX))
+            .map_or(i64::MIN, |t| t.timestamp_millis())
+    };
+
+    let tx = db.transaction()?;
+    let mut deleted = 0;
+    for table in ["measurements", "signal"] {
+        deleted += tx.execute(
+            &format!("DELETE FROM {table} WHERE timestamp < ?1"),
+            [cutoff(config.retention())],
+        )?;
+    }
+
+    let mut downsampled = 0;
+    if let Some(age) = config.downsample_after() {
+        // Align to the hour so that a bucket is never split between two runs.
+        let cutoff = cutoff(age).div_euclid(HOUR_MILLIS) * HOUR_MILLIS;
+        tx.execute(
+            "INSERT INTO measurements (tank, timestamp, temperature, tds, ec, tds_voltage, ph, downsampled)
+             SELECT tank, timestamp / ?2 * ?2 AS hour, AVG(temperature), AVG(tds), AVG(ec), AVG(tds_voltage),
+                 AVG(ph), 1
+             FROM measurements WHERE downsampled = 0 AND timestamp < ?1 GROUP BY tank, hour",
+            [cutoff, HOUR_MILLIS],
+        )?;
+        downsampled += tx.execute(
+            "DELETE FROM measurements WHERE downsampled = 0 AND timestamp < ?1",
+            [cutoff],
+        )?;
+        tx.execute(
+            "INSERT INTO signal (timestamp, associated, quality, rssi, downsampled)
+             SELECT timestamp / ?2 * ?2 AS hour, MAX(associated), AVG(quality), AVG(rssi), 1
+             FROM signal WHERE downsampled = 0 AND timestamp < ?1 GROUP BY hour",
+            [cutoff, HOUR_MILLIS],
+        )?;
+        downsampled += tx.execute("DELETE FROM signal WHERE downsampled = 0 AND timestamp < ?1", [cutoff])?;
+    }
+    tx.commit()?;
+
+    if deleted > 0 || downsampled > 0 {
+        info!("Pruned {deleted} and downsampled {downsampled} stored rows");
+    }
+
+    Ok(())
+}
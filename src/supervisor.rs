@@ -0,0 +1,121 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::time::{Duration, Instant};
+
+use logger::log::{error, info};
+use tokio::{select, task::JoinHandle, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::state::AppState;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A worker that has run at least this long is considered healthy again, and its next failure restarts quickly.
+const STABLE_PERIOD: Duration = Duration::from_secs(60);
+
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { next: MIN_BACKOFF }
+    }
+
+    /// Returns how long to wait before restarting a worker that failed after running for `ran_for`.
+    fn next_delay(&mut self, ran_for: Duration) -> Duration {
+        if ran_for >= STABLE_PERIOD {
+            self.next = MIN_BACKOFF;
+        }
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+/// Spawns a worker and restarts it with exponential backoff whenever it fails or panics.
+///
+/// Supervision ends when the worker returns `Ok(())` or `shutdown` is cancelled.
+pub(crate) fn spawn<F>(
+    name: &'static str,
+    worker: impl Fn(AppState, CancellationToken) -> F + Send + 'static,
+    state: AppState,
+    shutdown: CancellationToken,
+) -> JoinHandle<()>
+where
+    F: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new();
+
+        loop {
+            let started = Instant::now();
+            let result = tokio::spawn(worker(state.clone(), shutdown.clone())).await;
+            if shutdown.is_cancelled() {
+                return;
+            }
+
+            match result {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => {
+                    error!(worker = name; "Worker {name} failed: {e:?}");
+                    state.errors.error(name, format!("Worker failed: {e:#}"));
+                }
+                Err(e) => {
+                    error!(worker = name; "Worker {name} panicked: {e:?}");
+                    state.errors.error(name, format!("Worker panicked: {e}"));
+                }
+            }
+
+            let delay = backoff.next_delay(started.elapsed());
+            info!("Restarting worker {name} in {}s", delay.as_secs());
+
+            select! {
+                () = sleep(delay) => {}
+                () = shutdown.cancelled() => return,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUICK: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn backoff_doubles_from_a_second() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<_> = (0..4).map(|_| backoff.next_delay(QUICK).as_secs()).collect();
+
+        assert_eq!(delays, [1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn backoff_stops_at_a_minute() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<_> = (0..9).map(|_| backoff.next_delay(QUICK).as_secs()).collect();
+
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+    }
+
+    #[test]
+    fn backoff_starts_over_after_running_stably() {
+        let mut backoff = Backoff::new();
+        for _ in 0..5 {
+            backoff.next_delay(QUICK);
+        }
+
+        assert_eq!(
+            backoff.next_delay(STABLE_PERIOD - Duration::from_millis(1)).as_secs(),
+            32
+        );
+        assert_eq!(backoff.next_delay(STABLE_PERIOD).as_secs(), 1);
+        assert_eq!(backoff.next_delay(QUICK).as_secs(), 2);
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{collections::BTreeSet, fs, io, path::Path, time::Duration};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc, serde::ts_milliseconds};
+use logger::log::{info, warn};
+use serde::Serialize;
+use tokio::{
+    process::Command,
+    select, task,
+    time::{MissedTickBehavior, interval, timeout},
+};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::{errors::ErrorLog, state::AppState};
+
+/// Vitals of the board. Each value is read on its own, and is `None` when it could not be.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct System {
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub timestamp: DateTime<Utc>,
+    /// CPU temperature in °C.
+    pub cpu_temperature: Option<f64>,
+    pub load: Option<LoadAverage>,
+    pub memory: Option<Usage>,
+    /// Usage of the filesystem the state directory is on.
+    pub disk: Option<Usage>,
+    /// Only available on a Raspberry Pi.
+    pub throttled: Option<Throttled>,
+}
+
+/// Number of runnable processes, averaged over 1, 5 and 15 minutes.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct Usage {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Throttling as reported by the VideoCore firmware.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct Throttled {
+    /// The raw flags, e.g. `0x50005`.
+    pub flags: u32,
+    pub under_voltage: bool,
+    pub frequency_capped: bool,
+    pub throttled: bool,
+    pub soft_temperature_limit: bool,
+    /// Whether any of the above has happened since boot.
+    pub occurred: bool,
+}
+
+impl Throttled {
+    fn from_flags(flags: u32) -> Self {
+        Self {
+            flags,
+            under_voltage: flags & 0x1 != 0,
+            frequency_capped: flags & 0x2 != 0,
+            throttled: flags & 0x4 != 0,
+            soft_temperature_limit: flags & 0x8 != 0,
+            occurred: flags & 0xf_0000 != 0,
+        }
+    }
+}
+
+const THERMAL_ZONE: &str = "/sys/class/thermal/thermal_zone0/temp";
+const PROC_LOADAVG: &str = "/proc/loadavg";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+/// Exposed by the firmware driver of recent Raspberry Pi kernels, in place of running `vcgencmd`.
+const SYSFS_THROTTLED: &str = "/sys/devices/platform/soc/soc:firmware/get_throttled";
+
+/// A command gives up after this long, since `df` can hang on a dead network mount.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let mut interval = interval(config.system.interval());
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    // Names of the values that failed last time, so that each failure is logged once rather than at every read.
+    let mut failing = BTreeSet::new();
+    loop {
+        select! {
+            _ = interval.tick() => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+
+        let system = read(&config.state_dir, &state.errors, &mut failing).await?;
+        state.system.set(system).await;
+    }
+}
+
+async fn read(state_dir: &Path, errors: &ErrorLog, failing: &mut BTreeSet<&'static str>) -> anyhow::Result<System> {
+    let (cpu_temperature, load, memory) =
+        task::spawn_blocking(|| (read_cpu_temperature(), read_load_average(), read_memory())).await?;
+
+    Ok(System {
+        timestamp: Utc::now(),
+        cpu_temperature: check("CPU temperature", cpu_temperature, errors, failing),
+        load: check("load average", load, errors, failing),
+        memory: check("memory usage", memory, errors, failing),
+        disk: check("disk usage", read_disk(state_dir).await, errors, failing),
+        throttled: check("throttling", read_throttled().await, errors, failing).flatten(),
+    })
+}
+
+/// Returns the value read, logging when it starts or stops failing.
+fn check<T>(
+    name: &'static str,
+    result: anyhow::Result<T>,
+    errors: &ErrorLog,
+    failing: &mut BTreeSet<&'static str>,
+) -> Option<T> {
+    match result {
+        Ok(value) => {
+            if failing.remove(name) {
+                info!("Reading {name} again");
+            }
+            Some(value)
+        }
+        Err(e) => {
+            if failing.insert(name) {
+                warn!("Failed to read {name}: {e:?}");
+                errors.warning("system", format!("Failed to read {name}: {e:#}"));
+            }
+            None
+        }
+    }
+}
+
+fn read_cpu_temperature() -> anyhow::Result<f64> {
+    let millis: f64 = fs::read_to_string(THERMAL_ZONE)?.trim().parse()?;
+
+    Ok((millis / 100.0).round() / 10.0)
+}
+
+fn read_load_average() -> anyhow::Result<LoadAverage> {
+    let raw = fs::read_to_string(PROC_LOADAVG)?;
+    let mut fields = raw.split_whitespace().map(str::parse::<f64>);
+    let (Some(Ok(one)), Some(Ok(five)), Some(Ok(fifteen))) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(anyhow!("Invalid format"));
+    };
+
+    Ok(LoadAverage { one, five, fifteen })
+}
+
+fn read_memory() -> anyhow::Result<Usage> {
+    let raw = fs::read_to_string(PROC_MEMINFO)?;
+    let field = |name: &str| {
+        raw.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().strip_suffix("kB")?.trim().parse::<u64>().ok())
+            .map(|kib| kib * 1024)
+            .ok_or_else(|| anyhow!("No {name} in {PROC_MEMINFO}"))
+    };
+
+    Ok(Usage {
+        total_bytes: field("MemTotal")?,
+        available_bytes: field("MemAvailable")?,
+    })
+}
+
+/// Reads the usage of the filesystem `path` is on through `df`, as std has no `statvfs`.
+async fn read_disk(path: &Path) -> anyhow::Result<Usage> {
+    let raw = run("df", &["-Pk", &path.to_string_lossy()]).await?;
+    // The POSIX format has a header, then the filesystem, 1024-blocks, used, available, capacity and mount point.
+    let fields: Vec<&str> = raw.lines().nth(1).unwrap_or_default().split_whitespace().collect();
+    let (Some(Ok(total)), Some(Ok(available))) = (
+        fields.get(1).map(|v| v.parse::<u64>()),
+        fields.get(3).map(|v| v.parse::<u64>()),
+    ) else {
+        return Err(anyhow!("Invalid output of df: {raw:?}"));
+    };
+
+    Ok(Usage {
+        total_bytes: total * 1024,
+        available_bytes: available * 1024,
+    })
+}
+
+/// Returns `None` on boards other than a Raspberry Pi, which have no such flags.
+async fn read_throttled() -> anyhow::Result<Option<Throttled>> {
+    let raw = match tokio::fs::read_to_string(SYSFS_THROTTLED).await {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match run("vcgencmd", &["get_throttled"]).await {
+            Ok(raw) => raw,
+            Err(e)
+                if e.downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::NotFound) =>
+            {
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    // The sysfs file holds the bare hex value, and `vcgencmd` prints `throttled=0x50005`.
+    let hex = raw.trim();
+    let hex = hex.strip_prefix("throttled=").unwrap_or(hex);
+    let flags = u32::from_str_radix(hex.trim_start_matches("0x"), 16)
+        .map_err(|e| anyhow!("Invalid throttled flags {hex:?}: {e}"))?;
+
+    Ok(Some(Throttled::from_flags(flags)))
+}
+
+/// Runs a command and returns its standard output. The child is killed when it times out.
+async fn run(program: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = timeout(
+        COMMAND_TIMEOUT,
+        Command::new(program).args(args).kill_on_drop(true).output(),
+    )
+    .await
+    .map_err(|_| anyhow!("{program} timed out after {}s", COMMAND_TIMEOUT.as_secs()))??;
+    if !output.status.success() {
+        return Err(anyhow!("{program} exited with {}", output.status));
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
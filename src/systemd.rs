@@ -0,0 +1,151 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    env,
+    fmt::Write,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    process,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use logger::log::info;
+use tokio::{
+    select,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{health::Health, state::AppState};
+
+/// How often the workers are checked for having got through their setup.
+const INIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the status line is updated when no watchdog is configured.
+const STATUS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The notification socket systemd passes in `$NOTIFY_SOCKET`.
+struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// Returns `None` when not run by systemd.
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        let socket = UnixDatagram::unbound()?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Some(Self { socket, addr }))
+    }
+
+    fn send(&self, message: &str) -> anyhow::Result<()> {
+        self.socket
+            .send_to_addr(message.as_bytes(), &self.addr)
+            .map_err(|e| anyhow!("Failed to notify systemd: {e}"))?;
+
+        Ok(())
+    }
+}
+
+/// Returns the interval systemd expects watchdog pings within, if it expects them from this process.
+fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(process::id())
+    {
+        return None;
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Summarizes the latest readings for `systemctl status`.
+async fn status(state: &AppState) -> String {
+    let Some(m) = state.default_tank().measurements.get().await else {
+        return "Waiting for the first measurement".to_owned();
+    };
+
+    let mut status = format!("{:.1} °C, {:.0} ppm", m.temperature, m.tds);
+    if let Some(s) = state.signal.get().await
+        && s.associated
+    {
+        let _ = write!(status, ", Wi-Fi {:.0}%", s.quality * 100.0);
+    }
+
+    status
+}
+
+/// Tells systemd when the service is ready, keeps its status line current, and pings its watchdog while
+/// measurements keep coming in. Does nothing when not run by systemd.
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(notifier) = Notifier::from_env()? else {
+        return Ok(());
+    };
+
+    // Ready once every worker with devices to open has opened them.
+    let awaited: Vec<&Health> = [
+        (&state.health.measurements, config.measurements.enabled),
+        (&state.health.signal, config.signal.enabled),
+        (&state.health.display, config.display.is_enabled()),
+    ]
+    .into_iter()
+    .filter_map(|(health, enabled)| enabled.then_some(health))
+    .collect();
+    let mut poll = interval(INIT_POLL_INTERVAL);
+    while !awaited.iter().all(|health| health.snapshot().initialized) {
+        select! {
+            _ = poll.tick() => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+    notifier.send(&format!("READY=1\nSTATUS={}", status(&state).await))?;
+    info!("Notified systemd of readiness");
+
+    let watchdog = watchdog_timeout();
+    if let Some(timeout) = watchdog {
+        info!("Pinging the systemd watchdog every {}s", (timeout / 2).as_secs_f64());
+    }
+    let mut ticker = interval(watchdog.map_or(STATUS_INTERVAL, |timeout| timeout / 2));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        select! {
+            _ = ticker.tick() => {}
+            () = shutdown.cancelled() => break,
+        }
+
+        let mut message = format!("STATUS={}", status(&state).await);
+        // Withholding the ping from a stuck sensor loop lets the watchdog restart the service.
+        if watchdog.is_some()
+            && (!config.measurements.enabled
+                || state
+                    .health
+                    .measurements
+                    .snapshot()
+                    .succeeded_within(Utc::now(), config.measurements.stale_after()))
+        {
+            message.push_str("\nWATCHDOG=1");
+        }
+        notifier.send(&message)?;
+    }
+
+    notifier.send("STOPPING=1")
+}
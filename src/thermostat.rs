@@ -0,0 +1,342 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use logger::log::{error, info, warn};
+use serde::Serialize;
+use tokio::{
+    select,
+    sync::{Notify, watch},
+    task,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::{config::ThermostatConfig, gpio, state::AppState};
+
+/// Readings outside of this range are taken as a sensor fault, such as the 85 °C a DS18B20 reports after a reset.
+const PLAUSIBLE_TEMPERATURE: std::ops::Range<f64> = 0.0..50.0;
+
+/// How often the fail-safes are checked between readings.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why the heater is forced off regardless of the temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FailSafe {
+    /// No reading for too long.
+    Stale,
+    /// The latest read failed or returned an implausible value.
+    Invalid,
+    /// The heater ran for too long without a break. Cleared once the temperature rises past the band.
+    MaxOnTime,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(as = ThermostatStatus)]
+pub(crate) struct Status {
+    pub enabled: bool,
+    pub target: f64,
+    pub hysteresis: f64,
+    pub heating: bool,
+    pub fail_safe: Option<FailSafe>,
+    pub temperature: Option<f64>,
+    /// Milliseconds since the Unix epoch. `None` while the heater is off.
+    #[serde(with = "ts_milliseconds_option")]
+    #[schema(value_type = Option<i64>)]
+    pub on_since: Option<DateTime<Utc>>,
+    /// Statistics are counted from this time, which is when the service started. Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub stats_since: DateTime<Utc>,
+    pub on_secs_total: f64,
+    /// Fraction of the time the heater has been on.
+    pub duty_cycle: f64,
+    /// Number of times the heater has been switched on.
+    pub cycles: u64,
+}
+
+struct State {
+    enabled: bool,
+    target: f64,
+    hysteresis: f64,
+    heating: bool,
+    fail_safe: Option<FailSafe>,
+    temperature: Option<f64>,
+    on_since: Option<(Instant, DateTime<Utc>)>,
+    stats_since: (Instant, DateTime<Utc>),
+    on_total: Duration,
+    cycles: u64,
+}
+
+impl State {
+    fn new(config: &ThermostatConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            target: config.target,
+            hysteresis: config.hysteresis,
+            heating: false,
+            fail_safe: None,
+            temperature: None,
+            on_since: None,
+            stats_since: (Instant::now(), Utc::now()),
+            on_total: Duration::ZERO,
+            cycles: 0,
+        }
+    }
+
+    fn status(&self) -> Status {
+        let on_total = self.on_total + self.on_since.map_or(Duration::ZERO, |(since, _)| since.elapsed());
+        let elapsed = self.stats_since.0.elapsed().as_secs_f64();
+
+        Status {
+            enabled: self.enabled,
+            target: self.target,
+            hysteresis: self.hysteresis,
+            heating: self.heating,
+            fail_safe: self.fail_safe,
+            temperature: self.temperature,
+            on_since: self.on_since.map(|(_, at)| at),
+            stats_since: self.stats_since.1,
+            on_secs_total: on_total.as_secs_f64(),
+            duty_cycle: if elapsed > 0.0 {
+                on_total.as_secs_f64() / elapsed
+            } else {
+                0.0
+            },
+            cycles: self.cycles,
+        }
+    }
+
+    fn set_fail_safe(&mut self, fail_safe: Option<FailSafe>) {
+        if fail_safe == self.fail_safe {
+            return;
+        }
+        match fail_safe {
+            Some(reason) => warn!("Heater forced off: {reason:?}"),
+            None => info!("Heater fail-safe cleared"),
+        }
+        self.fail_safe = fail_safe;
+    }
+
+    fn switch(&mut self, heating: bool) -> bool {
+        if heating == self.heating {
+            return heating;
+        }
+
+        let temperature = self
+            .temperature
+            .map_or_else(|| "-".to_owned(), |t| format!("{t:.1} °C"));
+        info!("Heater {} at {temperature}", if heating { "on" } else { "off" });
+        if heating {
+            self.on_since = Some((Instant::now(), Utc::now()));
+            self.cycles += 1;
+        } else if let Some((since, _)) = self.on_since.take() {
+            self.on_total += since.elapsed();
+        }
+        self.heating = heating;
+
+        heating
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    at: Instant,
+    /// `None` when the read failed.
+    temperature: Option<f64>,
+}
+
+/// The heater control, fed by the measurements and adjusted through the API.
+pub(crate) struct Thermostat {
+    /// `None` while the thermostat is not configured.
+    state: Mutex<Option<State>>,
+    /// Wakes the worker to act on changed settings right away.
+    wake: Notify,
+    readings: watch::Sender<Option<Reading>>,
+}
+
+impl Thermostat {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(None),
+            wake: Notify::new(),
+            readings: watch::channel(None).0,
+        }
+    }
+
+    /// Passes the result of a measurement cycle to the thermostat, which acts on it right away.
+    pub fn feed(&self, temperature: Option<f64>) {
+        self.readings.send_replace(Some(Reading {
+            at: Instant::now(),
+            temperature,
+        }));
+    }
+
+    /// Returns the current state, or `None` when the thermostat is not configured.
+    pub fn status(&self) -> Option<Status> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(State::status)
+    }
+
+    /// Changes the settings at runtime. Returns the new state, or `None` when the thermostat is not configured.
+    pub fn configure(&self, target: Option<f64>, enabled: Option<bool>) -> Option<Status> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = state.as_mut()?;
+        if let Some(target) = target {
+            info!("Thermostat target changed from {} to {target}", state.target);
+            state.target = target;
+        }
+        if let Some(enabled) = enabled
+            && enabled != state.enabled
+        {
+            info!("Thermostat {}", if enabled { "enabled" } else { "disabled" });
+            state.enabled = enabled;
+            // Re-enabling is how a heater that hit the on-time limit is put back to work.
+            if enabled && state.fail_safe == Some(FailSafe::MaxOnTime) {
+                state.set_fail_safe(None);
+            }
+        }
+        self.wake.notify_one();
+
+        Some(state.status())
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.thermostat else {
+        return Ok(());
+    };
+
+    let thermostat = &state.thermostat;
+    thermostat
+        .state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| State::new(config));
+
+    let relay = {
+        let config = config.clone();
+        task::spawn_blocking(move || Relay::open(&config)).await??
+    };
+
+    let mut readings = thermostat.readings.subscribe();
+    let mut watchdog = interval(WATCHDOG_INTERVAL);
+    watchdog.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    // Decisions follow the measurement cadence. The watchdog only makes sure the fail-safes kick in when readings
+    // stop coming.
+    let result = loop {
+        select! {
+            Ok(()) = readings.changed() => {}
+            _ = watchdog.tick() => {}
+            () = thermostat.wake.notified() => {}
+            () = shutdown.cancelled() => break Ok(()),
+        }
+
+        let reading = *readings.borrow_and_update();
+        let heating = {
+            let mut state = thermostat.state.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(state) = state.as_mut() else {
+                break Ok(());
+            };
+            control(state, config, reading)
+        };
+        if let Err(e) = relay.set(heating) {
+            break Err(e);
+        }
+    };
+
+    if let Err(e) = relay.set(false) {
+        error!("Failed to switch the heater off: {e:?}");
+    }
+    if let Some(state) = thermostat.state.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        state.switch(false);
+    }
+
+    result
+}
+
+/// Decides whether the heater should be on, updating the state to match.
+fn control(state: &mut State, config: &ThermostatConfig, reading: Option<Reading>) -> bool {
+    let temperature = match reading {
+        Some(reading) if reading.at.elapsed() < config.stale_after() => match reading.temperature {
+            Some(t) if PLAUSIBLE_TEMPERATURE.contains(&t) => Ok(t),
+            _ => Err(FailSafe::Invalid),
+        },
+        _ => Err(FailSafe::Stale),
+    };
+    state.temperature = temperature.ok();
+
+    if !state.enabled {
+        return state.switch(false);
+    }
+    let temperature = match temperature {
+        Ok(t) => t,
+        Err(reason) => {
+            // Keep the on-time limit latched through a sensor fault.
+            if state.fail_safe != Some(FailSafe::MaxOnTime) {
+                state.set_fail_safe(Some(reason));
+            }
+            return state.switch(false);
+        }
+    };
+
+    let (on_below, off_above) = (state.target - state.hysteresis, state.target + state.hysteresis);
+    if state.fail_safe == Some(FailSafe::MaxOnTime) && temperature <= off_above {
+        return state.switch(false);
+    }
+    state.set_fail_safe(None);
+
+    if state
+        .on_since
+        .is_some_and(|(since, _)| since.elapsed() >= config.max_on())
+    {
+        error!(
+            "Heater has been on for {}s without reaching {off_above} °C, switching it off",
+            config.max_on().as_secs()
+        );
+        state.set_fail_safe(Some(FailSafe::MaxOnTime));
+        return state.switch(false);
+    }
+
+    let heating = if temperature < on_below {
+        true
+    } else if temperature > off_above {
+        false
+    } else {
+        state.heating
+    };
+    state.switch(heating)
+}
+
+/// The heater relay, which is switched off when opened.
+struct Relay {
+    output: gpio::Output,
+    active_low: bool,
+}
+
+impl Relay {
+    fn open(config: &ThermostatConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            output: gpio::Output::open(&config.gpio_chip, config.pin, config.active_low, "cobitis-heater")?,
+            active_low: config.active_low,
+        })
+    }
+
+    fn set(&self, on: bool) -> anyhow::Result<()> {
+        self.output.set(on != self.active_low)
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Zone the display and the daily statistics go by, when it is not that of the system.
+static ZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Sets the zone once at startup. The local zone of the system is used without one.
+pub(crate) fn init(zone: Option<Tz>) {
+    if let Some(zone) = zone {
+        let _ = ZONE.set(zone);
+    }
+}
+
+/// Returns `timestamp` in the zone.
+pub(crate) fn localize(timestamp: DateTime<Utc>) -> DateTime<FixedOffset> {
+    match ZONE.get() {
+        Some(zone) => timestamp.with_timezone(zone).fixed_offset(),
+        None => timestamp.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Returns the current time in the zone.
+pub(crate) fn now() -> DateTime<FixedOffset> {
+    localize(Utc::now())
+}
+
+/// Returns the instant a wall-clock time in the zone stands for, the earlier one when the clocks are turned back.
+pub(crate) fn to_utc(local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match ZONE.get() {
+        Some(zone) => zone.from_local_datetime(&local).earliest().map(|t| t.to_utc()),
+        None => Local.from_local_datetime(&local).earliest().map(|t| t.to_utc()),
+    }
+}
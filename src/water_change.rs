@@ -0,0 +1,160 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context as _;
+use chrono::{DateTime, TimeDelta, Utc, serde::ts_milliseconds};
+use logger::log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{config::Config, timezone};
+
+const FILE_NAME: &str = "water_change.toml";
+
+/// When the next water change is due.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub(crate) struct WaterChange {
+    pub interval_days: u32,
+    /// The last change recorded, or when the reminder was first set up until one is. Milliseconds since the Unix
+    /// epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub last_changed_at: DateTime<Utc>,
+    /// Milliseconds since the Unix epoch.
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64)]
+    pub due_at: DateTime<Utc>,
+    /// Days from today until the day it is due in the configured zone, negative once that day has passed.
+    pub days_remaining: i64,
+    pub overdue: bool,
+}
+
+/// What is written to disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    changed_at: DateTime<Utc>,
+}
+
+struct State {
+    interval_days: u32,
+    changed_at: DateTime<Utc>,
+    path: PathBuf,
+}
+
+impl State {
+    fn due_at(&self) -> DateTime<Utc> {
+        self.changed_at + TimeDelta::days(i64::from(self.interval_days))
+    }
+
+    fn status(&self, now: DateTime<Utc>) -> WaterChange {
+        let due_at = self.due_at();
+        let today = timezone::localize(now).date_naive();
+
+        WaterChange {
+            interval_days: self.interval_days,
+            last_changed_at: self.changed_at,
+            due_at,
+            days_remaining: (timezone::localize(due_at).date_naive() - today).num_days(),
+            overdue: now >= due_at,
+        }
+    }
+}
+
+/// The water change reminder, whose due date the API and the alerts look up.
+pub(crate) struct Reminder {
+    /// `None` unless the reminder is configured.
+    state: Mutex<Option<State>>,
+}
+
+impl Reminder {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Loads the last change recorded. Must be called once at startup.
+    pub fn load(&self, config: &Config) -> anyhow::Result<()> {
+        let Some(water_change) = &config.water_change else {
+            return Ok(());
+        };
+
+        let path = config.state_dir.join(FILE_NAME);
+        let changed_at = if path.exists() {
+            let raw = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let record: Record =
+                toml::from_str(&raw).with_context(|| format!("Malformed water change file {}", path.display()))?;
+            record.changed_at
+        } else {
+            // Counting starts the first time around, and is kept so that a restart does not start it over
+            let now = Utc::now();
+            if let Err(e) = save(&path, &Record { changed_at: now }) {
+                warn!("Failed to save the start of the water change reminder: {e:?}");
+            }
+            now
+        };
+
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = Some(State {
+            interval_days: water_change.interval_days,
+            changed_at,
+            path,
+        });
+
+        Ok(())
+    }
+
+    /// Returns when the next water change is due, or `None` unless the reminder is configured.
+    pub fn status(&self) -> Option<WaterChange> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.as_ref().map(|state| state.status(Utc::now()))
+    }
+
+    /// Returns how many days past due the water change is at `now`, negative until it is due.
+    pub fn overdue_days(&self, now: DateTime<Utc>) -> Option<f64> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .as_ref()
+            .map(|state| (now - state.due_at()).as_seconds_f64() / TimeDelta::days(1).as_seconds_f64())
+    }
+
+    /// Records a water change made now and writes it to disk, so that the next one is due a full interval from now
+    /// even when this one was late. Returns `None` unless the reminder is configured.
+    pub fn record(&self) -> anyhow::Result<Option<WaterChange>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(state) = state.as_mut() else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        save(&state.path, &Record { changed_at: now })?;
+        state.changed_at = now;
+        let status = state.status(now);
+        info!(
+            "Water change recorded, the next one is due in {} days",
+            status.days_remaining
+        );
+
+        Ok(Some(status))
+    }
+}
+
+fn save(path: &Path, record: &Record) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Written to a temporary file first, like the calibration
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, toml::to_string(record)?).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
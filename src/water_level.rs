@@ -0,0 +1,104 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use logger::log::{error, info, warn};
+use tokio::{
+    select, task,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{gpio, state::AppState};
+
+/// The position of the float switch, as last settled.
+pub(crate) struct WaterLevel {
+    ok: Mutex<Option<bool>>,
+}
+
+impl WaterLevel {
+    pub fn new() -> Self {
+        Self { ok: Mutex::new(None) }
+    }
+
+    /// Returns whether the water is up to the float switch, or `None` when there is no switch or it has not settled
+    /// yet.
+    pub fn is_ok(&self) -> Option<bool> {
+        *self.ok.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, ok: bool) {
+        if ok {
+            info!("Water level is ok");
+        } else {
+            warn!("Water level is low");
+        }
+        *self.ok.lock().unwrap_or_else(|e| e.into_inner()) = Some(ok);
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(water_level) = &config.water_level else {
+        return Ok(());
+    };
+    if config.simulate {
+        state.water_level.set(true);
+        return Ok(());
+    }
+
+    // Without the pin there is nothing to retry, so the switch is left unread.
+    let opened = {
+        let c = water_level.clone();
+        task::spawn_blocking(move || gpio::Input::open(&c.gpio_chip, c.pin, c.active_low, c.pull_up, "cobitis-level"))
+            .await?
+    };
+    let input = Arc::new(match opened {
+        Ok(input) => input,
+        Err(e) => {
+            error!("Water level disabled: {e:?}");
+            state
+                .errors
+                .error("water_level", format!("Water level disabled: {e:#}"));
+            return Ok(());
+        }
+    });
+
+    let mut tick = interval(water_level.poll_interval());
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // A position the switch moved to, and since when, until it has stayed there for the debounce time.
+    let mut pending: Option<(bool, Instant)> = None;
+
+    loop {
+        select! {
+            _ = tick.tick() => {}
+            () = shutdown.cancelled() => return Ok(()),
+        }
+
+        let active = task::spawn_blocking({
+            let input = input.clone();
+            move || input.get()
+        })
+        .await??;
+
+        if Some(active) == state.water_level.is_ok() {
+            pending = None;
+            continue;
+        }
+        match pending {
+            Some((position, since)) if position == active => {
+                if since.elapsed() >= water_level.debounce() {
+                    state.water_level.set(active);
+                    pending = None;
+                }
+            }
+            _ => pending = Some((active, Instant::now())),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright © 2025 Akira Miyakoda
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::anyhow;
+use logger::log::{error, info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use tokio::{select, sync::broadcast::error::RecvError, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::WebhookConfig, measurements::Measurements, state::AppState};
+
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+struct Context {
+    client: Client,
+    config: WebhookConfig,
+    /// Last measurements posted of each tank, which changes are measured against.
+    last_posted: BTreeMap<String, Measurements>,
+}
+
+impl Context {
+    /// Whether `m` differs enough from the last posted measurements of `tank` to be worth posting.
+    fn has_changed(&self, tank: &str, m: &Measurements) -> bool {
+        let Some(last) = self.last_posted.get(tank) else {
+            return true;
+        };
+
+        !self.config.on_change
+            || (m.temperature - last.temperature).abs() > self.config.temperature_delta
+            || (m.tds - last.tds).abs() > self.config.tds_delta
+    }
+
+    /// Posts `body`, retrying a few times before giving up. Measurements name their tank in `X-Cobitis-Tank`.
+    async fn post(&self, event: &str, tank: Option<&str>, body: &impl Serialize) -> anyhow::Result<()> {
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(&self.config.url)
+                .header("X-Cobitis-Event", event)
+                .json(body);
+            if let Some(tank) = tank {
+                request = request.header("X-Cobitis-Tank", tank);
+            }
+            if let Some(token) = &self.config.bearer_token {
+                request = request.bearer_auth(token);
+            }
+
+            let result = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => Err(anyhow!("Server returned {}", response.status())),
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Err(e) if attempt < self.config.retries => {
+                    attempt += 1;
+                    warn!("Failed to post {event} to webhook (attempt {attempt}): {e:?}");
+                    sleep(RETRY_DELAY).await;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+pub(crate) async fn worker(state: AppState, shutdown: CancellationToken) -> anyhow::Result<()> {
+    let config = state.config.clone();
+    let Some(config) = &config.webhook else {
+        return Ok(());
+    };
+
+    let mut measurements_rx = state.subscribe_tanks();
+    let mut signal_rx = state.signal.subscribe();
+    let mut ctx = Context {
+        client: Client::builder().timeout(config.timeout()).build()?,
+        config: config.clone(),
+        last_posted: BTreeMap::new(),
+    };
+    info!("Posting readings to {}", config.url);
+
+    loop {
+        select! {
+            (tank, m) = measurements_rx.recv() => match m {
+                Ok(m) if ctx.has_changed(&tank, &m) => {
+                    // Shutdown cancels posting, since the retries could take a while.
+                    select! {
+                        result = ctx.post("measurements", Some(&tank), &m) => match result {
+                            Ok(()) => {
+                                ctx.last_posted.insert(tank, m);
+                            }
+                            Err(e) => error!(
+                                "Failed to post measurements of tank {tank} to webhook, dropping them: {e:?}"
+                            ),
+                        },
+                        () = shutdown.cancelled() => return Ok(()),
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => warn!("Skipped {n} measurements of tank {tank} while posting to webhook"),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            s = signal_rx.recv(), if config.include_signal => match s {
+                Ok(s) => {
+                    select! {
+                        result = ctx.post("signal", None, &s) => if let Err(e) = result {
+                            error!("Failed to post signal level to webhook, dropping it: {e:?}");
+                        },
+                        () = shutdown.cancelled() => return Ok(()),
+                    }
+                }
+                Err(RecvError::Lagged(n)) => warn!("Skipped {n} signal readings while posting to webhook"),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            () = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU16, Ordering},
+    };
+
+    use axum::{Router, extract::State, http::StatusCode, routing::post};
+    use chrono::{TimeDelta, Utc};
+    use serde_json::Value;
+    use tokio::{net::TcpListener, sync::mpsc, task, time::timeout};
+
+    use super::*;
+    use crate::config::Config;
+
+    /// A webhook receiver that hands over the body of every request and answers with the status it is set to.
+    struct Receiver {
+        url: String,
+        bodies: mpsc::UnboundedReceiver<Value>,
+        status: Arc<AtomicU16>,
+    }
+
+    impl Receiver {
+        async fn start() -> Self {
+            let (tx, bodies) = mpsc::unbounded_channel();
+            let status = Arc::new(AtomicU16::new(200));
+            let app = Router::new()
+                .route(
+                    "/hook",
+                    post(
+                        async |State((tx, status)): State<(mpsc::UnboundedSender<Value>, Arc<AtomicU16>)>,
+                               body: axum::Json<Value>| {
+                            let _ = tx.send(body.0);
+                            StatusCode::from_u16(status.load(Ordering::Relaxed)).unwrap()
+                        },
+                    ),
+                )
+                .with_state((tx, status.clone()));
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+
+            Self { url, bodies, status }
+        }
+
+        async fn next_temperature(&mut self) -> f64 {
+            let body = timeout(Duration::from_secs(10), self.bodies.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            body["temperature"].as_f64().unwrap()
+        }
+    }
+
+    /// Starts the worker posting to `receiver`, and returns the state to feed it measurements through.
+    async fn start(receiver: &Receiver, config: WebhookConfig) -> (AppState, CancellationToken) {
+        let state = AppState::new(Arc::new(Config {
+            webhook: Some(WebhookConfig {
+                url: receiver.url.clone(),
+                ..config
+            }),
+            ..Config::default()
+        }));
+        let shutdown = CancellationToken::new();
+        tokio::spawn(worker(state.clone(), shutdown.clone()));
+        // Lets the worker subscribe before anything is measured.
+        task::yield_now().await;
+
+        (state, shutdown)
+    }
+
+    async fn measure(state: &AppState, seconds: i64, temperature: f64) {
+        let timestamp = Utc::now() + TimeDelta::seconds(seconds);
+        state
+            .default_tank()
+            .measurements
+            .set(Measurements::sample(timestamp, temperature, 150.0))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn posts_only_changes_past_the_delta() {
+        let mut receiver = Receiver::start().await;
+        let config = WebhookConfig {
+            on_change: true,
+            temperature_delta: 0.2,
+            ..WebhookConfig::default()
+        };
+        let (state, shutdown) = start(&receiver, config).await;
+
+        measure(&state, 0, 24.5).await;
+        assert_eq!(receiver.next_temperature().await, 24.5);
+        // Within the delta of the last posted one, even after a second small change
+        measure(&state, 10, 24.6).await;
+        measure(&state, 20, 24.7).await;
+        measure(&state, 30, 24.8).await;
+        assert_eq!(receiver.next_temperature().await, 24.8);
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retries() {
+        let mut receiver = Receiver::start().await;
+        receiver.status.store(500, Ordering::Relaxed);
+        let config = WebhookConfig {
+            retries: 1,
+            ..WebhookConfig::default()
+        };
+        let (state, shutdown) = start(&receiver, config).await;
+
+        measure(&state, 0, 24.5).await;
+        assert_eq!(receiver.next_temperature().await, 24.5);
+        assert_eq!(receiver.next_temperature().await, 24.5);
+
+        // The failed measurements are dropped, and the next ones posted as usual.
+        receiver.status.store(200, Ordering::Relaxed);
+        measure(&state, 10, 24.6).await;
+        assert_eq!(receiver.next_temperature().await, 24.6);
+        assert!(receiver.bodies.try_recv().is_err());
+
+        shutdown.cancel();
+    }
+}